@@ -0,0 +1,80 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rvim_core::{FileType, Row};
+use std::fmt::Write;
+
+const WIDTH: usize = 80;
+const HEIGHT: usize = 240;
+
+// A screenful of highlighted Rust, taken from this crate's own source.
+fn screen() -> Vec<Row> {
+    let file_type = FileType::from("row.rs");
+    let mut in_comment = false;
+    include_str!("../src/row.rs")
+        .lines()
+        .cycle()
+        .take(HEIGHT)
+        .map(|line| {
+            let mut row = Row::from(line);
+            in_comment = row.highlight(file_type.highlighting_options(), &None, in_comment);
+            row
+        })
+        .collect()
+}
+
+// Stands in for the color codes the editor writes before each run.
+const COLOR: &str = "\u{1b}[38;2;255;255;255m";
+
+// What the editor does for every row of a frame, into buffers reused from
+// frame to frame.
+fn draw(rows: &[Row], line: &mut String, frame: &mut String) {
+    frame.clear();
+    for (number, row) in rows.iter().enumerate() {
+        line.clear();
+        let _ = write!(line, "{number:^4}|");
+        row.for_each_segment(0, WIDTH, |_, text| {
+            line.push_str(COLOR);
+            line.push_str(text);
+        });
+        frame.push_str(line);
+        frame.push_str("\r\n");
+    }
+}
+
+// The same with a `String` per row and per run, as before `for_each_segment`.
+fn draw_allocating(rows: &[Row], frame: &mut String) {
+    frame.clear();
+    for (number, row) in rows.iter().enumerate() {
+        let mut line = format!("{number:^4}|");
+        for (_, text) in row.segments(0, WIDTH) {
+            line.push_str(COLOR);
+            line.push_str(&text);
+        }
+        frame.push_str(&line);
+        frame.push_str("\r\n");
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let rows = screen();
+    c.bench_function("row_segments", |b| {
+        b.iter(|| {
+            for row in &rows {
+                row.for_each_segment(0, WIDTH, |hl_type, text| {
+                    black_box((hl_type, text));
+                });
+            }
+        });
+    });
+    let mut group = c.benchmark_group("draw_rows_80x240");
+    let (mut line, mut frame) = (String::new(), String::new());
+    group.bench_function("reused_buffers", |b| {
+        b.iter(|| draw(black_box(&rows), &mut line, &mut frame));
+    });
+    group.bench_function("allocating", |b| {
+        b.iter(|| draw_allocating(black_box(&rows), &mut frame));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
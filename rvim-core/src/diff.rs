@@ -0,0 +1,263 @@
+//! Line diffs between two versions of a buffer, as hunks.
+//!
+//! ```
+//! use rvim_core::diff::{self, Hunk, Stats};
+//!
+//! let old = ["a", "b", "c", "d"];
+//! let new = ["a", "B", "c", "d", "e"];
+//! let hunks = diff::hunks(&old, &new);
+//! assert_eq!(hunks[0], Hunk { old_start: 1, old_len: 1, new_start: 1, new_len: 1 });
+//! assert_eq!(Stats::from(hunks.as_slice()), Stats { added: 1, modified: 1, removed: 0 });
+//! ```
+
+/// A run of lines that differ: `old_len` lines from `old_start` in the old
+/// version were replaced by `new_len` lines from `new_start` in the new one.
+/// Either length may be 0 for a pure insertion or deletion.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+/// Changed lines counted the way gutter signs show them: where a hunk
+/// replaces lines, the overlap counts as modified and the rest as added or
+/// removed.
+#[derive(PartialEq, Clone, Copy, Default, Debug)]
+pub struct Stats {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+}
+
+impl From<&[Hunk]> for Stats {
+    fn from(hunks: &[Hunk]) -> Self {
+        let mut stats = Self::default();
+        for hunk in hunks {
+            let modified = hunk.old_len.min(hunk.new_len);
+            stats.modified = stats.modified.saturating_add(modified);
+            stats.added = stats
+                .added
+                .saturating_add(hunk.new_len.saturating_sub(modified));
+            stats.removed = stats
+                .removed
+                .saturating_add(hunk.old_len.saturating_sub(modified));
+        }
+        stats
+    }
+}
+
+impl Stats {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Edit {
+    Keep,
+    Insert,
+    Delete,
+}
+
+/// The hunks turning `old` into `new`, in order, using Myers' shortest edit
+/// script. Lines both versions start or end with are skipped up front, so
+/// the cost grows with the size of the change rather than of the file, and
+/// the memory it takes grows with the number of lines, not with the square.
+/// Past a bounded amount of work it stops looking for the shortest script,
+/// and what it has not matched up yet is one hunk.
+///
+/// ```
+/// use rvim_core::diff::{self, Hunk};
+///
+/// let old: Vec<String> = (0..20_000).map(|i| format!("line {i}")).collect();
+/// let mut new = old.clone();
+/// for line in new.iter_mut().step_by(500) {
+///     line.push('!');
+/// }
+/// assert_eq!(diff::hunks(&old, &new).len(), 40);
+/// assert_eq!(
+///     diff::hunks(&old, &[] as &[String]),
+///     [Hunk { old_start: 0, old_len: 20_000, new_start: 0, new_len: 0 }]
+/// );
+/// let other: Vec<String> = (0..20_000).map(|i| format!("other {i}")).collect();
+/// assert_eq!(
+///     diff::hunks(&old, &other),
+///     [Hunk { old_start: 0, old_len: 20_000, new_start: 0, new_len: 20_000 }]
+/// );
+/// ```
+pub fn hunks<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_middle = &old_rest[..old_rest.len().saturating_sub(suffix)];
+    let new_middle = &new_rest[..new_rest.len().saturating_sub(suffix)];
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut x, mut y) = (prefix, prefix);
+    for edit in edit_script(old_middle, new_middle) {
+        if edit == Edit::Keep {
+            x = x.saturating_add(1);
+            y = y.saturating_add(1);
+            continue;
+        }
+        let extends_last = hunks.last().is_some_and(|hunk| {
+            hunk.old_start.saturating_add(hunk.old_len) == x
+                && hunk.new_start.saturating_add(hunk.new_len) == y
+        });
+        if !extends_last {
+            hunks.push(Hunk {
+                old_start: x,
+                old_len: 0,
+                new_start: y,
+                new_len: 0,
+            });
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            continue;
+        };
+        if edit == Edit::Insert {
+            hunk.new_len = hunk.new_len.saturating_add(1);
+            y = y.saturating_add(1);
+        } else {
+            hunk.old_len = hunk.old_len.saturating_add(1);
+            x = x.saturating_add(1);
+        }
+    }
+    hunks
+}
+
+// How many steps the search for a shortest edit script may take, so two
+// versions with little in common cost no more than a moment to compare.
+const SEARCH_STEPS: usize = 4_000_000;
+
+// Myers' algorithm in linear space: the middle snake of an optimal path
+// splits the problem in two, each solved the same way, so only the two
+// frontiers of the search are ever kept rather than one for every `d`.
+// Once the search has taken `SEARCH_STEPS`, what is left to compare is
+// replaced whole.
+fn edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut script = Vec::with_capacity(old.len().max(new.len()));
+    let mut steps = SEARCH_STEPS;
+    diff(old, new, &mut script, &mut steps);
+    script
+}
+
+fn diff<T: PartialEq>(old: &[T], new: &[T], script: &mut Vec<Edit>, steps: &mut usize) {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old = &old[..old.len().saturating_sub(suffix)];
+    let new = &new[..new.len().saturating_sub(suffix)];
+    script.extend(std::iter::repeat_n(Edit::Keep, prefix));
+    // both ends differ, so a path takes at least two edits and each half
+    // of it takes fewer than the whole
+    let snake = (!old.is_empty() && !new.is_empty())
+        .then(|| middle_snake(old, new, steps))
+        .flatten();
+    if let Some((start, end)) = snake {
+        diff(&old[..start.0], &new[..start.1], script, steps);
+        script.extend(std::iter::repeat_n(Edit::Keep, end.0 - start.0));
+        diff(&old[end.0..], &new[end.1..], script, steps);
+    } else {
+        script.extend(std::iter::repeat_n(Edit::Delete, old.len()));
+        script.extend(std::iter::repeat_n(Edit::Insert, new.len()));
+    }
+    script.extend(std::iter::repeat_n(Edit::Keep, suffix));
+}
+
+// Where an optimal path crosses the middle of its edits, as the start and
+// end of the run of equal lines there: searching forward from the start
+// and back from the end, `forward[k]` and `backward[k]` are the furthest
+// `x` each has reached on diagonal `k = x - y`, the backward one counted
+// from the ends, until the two meet. None once it runs out of `steps`.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn middle_snake<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    steps: &mut usize,
+) -> Option<((usize, usize), (usize, usize))> {
+    let (old_len, new_len) = (old.len() as isize, new.len() as isize);
+    let delta = old_len - new_len;
+    let max = (old_len + new_len + 1) / 2;
+    let index = |k: isize| (k + max + 1) as usize;
+    let mut forward = vec![0_isize; 2 * max as usize + 3];
+    let mut backward = vec![0_isize; 2 * max as usize + 3];
+    let point = |x: isize, y: isize| (x as usize, y as usize);
+    for d in 0..=max {
+        // a step for each diagonal both ways, and for each line compared
+        // along one below
+        *steps = steps.checked_sub(2 * d as usize + 2)?;
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let (start_x, start_y) = (x, x - k);
+            let mut y = start_y;
+            while x < old_len && y < new_len && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            *steps = steps.checked_sub((x - start_x) as usize)?;
+            forward[index(k)] = x;
+            let back_k = delta - k;
+            if delta % 2 != 0
+                && (-(d - 1)..=d - 1).contains(&back_k)
+                && x + backward[index(back_k)] >= old_len
+            {
+                return Some((point(start_x, start_y), point(x, y)));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let (end_x, end_y) = (x, x - k);
+            let mut y = end_y;
+            while x < old_len
+                && y < new_len
+                && old[(old_len - x - 1) as usize] == new[(new_len - y - 1) as usize]
+            {
+                x += 1;
+                y += 1;
+            }
+            *steps = steps.checked_sub((x - end_x) as usize)?;
+            backward[index(k)] = x;
+            let forward_k = delta - k;
+            if delta % 2 == 0
+                && (-d..=d).contains(&forward_k)
+                && x + forward[index(forward_k)] >= old_len
+            {
+                return Some((
+                    point(old_len - x, new_len - y),
+                    point(old_len - end_x, new_len - end_y),
+                ));
+            }
+        }
+    }
+    // the searches always meet by `max`
+    None
+}
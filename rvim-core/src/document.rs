@@ -0,0 +1,576 @@
+use crate::highlighting;
+use crate::undo::{self, Splice, UndoBranch, UndoHistory};
+use crate::FileType;
+use crate::HighlightingOptions;
+use crate::Pattern;
+use crate::Position;
+use crate::Row;
+use crate::SearchDirection;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Error, Write};
+
+// How lines end in the file, kept so saving writes them back the same way.
+#[derive(Default, PartialEq, Clone, Copy, Debug)]
+pub enum LineEnding {
+    #[default]
+    Unix,
+    Dos,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unix => write!(f, "unix"),
+            Self::Dos => write!(f, "dos"),
+        }
+    }
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Self::Unix => b"\n",
+            Self::Dos => b"\r\n",
+        }
+    }
+}
+
+// What the file looked like on disk when it was last read or written.
+#[derive(Clone, Copy, Debug)]
+pub struct FileInfo {
+    pub size: u64,
+    pub read_only: bool,
+}
+
+impl FileInfo {
+    fn read(file_name: &str) -> Option<Self> {
+        let metadata = fs::metadata(file_name).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            read_only: metadata.permissions().readonly(),
+        })
+    }
+}
+
+// How often `Snapshot::write` reports its progress, in bytes.
+const PROGRESS_INTERVAL: usize = 1 << 20;
+
+// How many lines at either end of a file are looked through for a modeline.
+const MODELINES: usize = 5;
+
+// The text of a document at one revision, so it can be written out while
+// the document itself keeps changing.
+pub struct Snapshot {
+    pub file_name: String,
+    pub revision: u64,
+    lines: Vec<String>,
+    line_ending: LineEnding,
+    missing_final_newline: bool,
+}
+
+impl Snapshot {
+    // Writes the file, calling `progress` with the bytes written so far and
+    // the total every so often and once at the end.
+    pub fn write<F: FnMut(usize, usize)>(&self, mut progress: F) -> Result<(), Error> {
+        let ending = self.line_ending.as_bytes();
+        let total = self
+            .lines
+            .iter()
+            .map(|line| line.len().saturating_add(ending.len()))
+            .sum();
+        let mut file = BufWriter::new(fs::File::create(&self.file_name)?);
+        let (mut written, mut reported) = (0_usize, 0_usize);
+        let last = self.lines.len().saturating_sub(1);
+        for (index, line) in self.lines.iter().enumerate() {
+            file.write_all(line.as_bytes())?;
+            if index < last || !self.missing_final_newline {
+                file.write_all(ending)?;
+            }
+            written = written.saturating_add(line.len().saturating_add(ending.len()));
+            if written.saturating_sub(reported) >= PROGRESS_INTERVAL {
+                progress(written, total);
+                reported = written;
+            }
+        }
+        file.flush()?;
+        progress(total, total);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Document {
+    rows: Vec<Row>,
+    pub file_name: Option<String>,
+    dirty: bool,
+    file_type: FileType,
+    // Whether `set_file_type` chose the type, so the file name no longer
+    // decides it.
+    file_type_set: bool,
+    revision: u64,
+    line_ending: LineEnding,
+    file_info: Option<FileInfo>,
+    read_only: bool,
+    // The file did not end in a newline, and is written back the same way.
+    missing_final_newline: bool,
+    history: UndoHistory,
+    // Where each mark is, kept on its line as lines are added and deleted
+    // above it.
+    marks: BTreeMap<char, Position>,
+}
+
+// A buffer with no file behind it.
+impl From<&str> for Document {
+    fn from(text: &str) -> Self {
+        Self {
+            rows: text.lines().map(Row::from).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Document {
+    pub fn open(filename: &str) -> Result<Self, std::io::Error> {
+        let contents = fs::read_to_string(filename)?;
+        let mut rows = Vec::new();
+        for value in contents.lines() {
+            rows.push(Row::from(value));
+        }
+        let file_type = detect_file_type(filename, &rows);
+        let line_ending = match contents.find('\n') {
+            Some(end) if contents[..end].ends_with('\r') => LineEnding::Dos,
+            _ => LineEnding::Unix,
+        };
+        Ok(Self {
+            rows,
+            file_name: Some(filename.to_string()),
+            dirty: false,
+            file_type,
+            file_type_set: false,
+            revision: 0,
+            line_ending,
+            file_info: FileInfo::read(filename),
+            read_only: false,
+            missing_final_newline: !contents.is_empty() && !contents.ends_with('\n'),
+            history: UndoHistory::default(),
+            marks: BTreeMap::new(),
+        })
+    }
+    // Files are always read as UTF-8.
+    pub fn encoding(&self) -> &'static str {
+        "utf-8"
+    }
+    pub fn is_missing_final_newline(&self) -> bool {
+        self.missing_final_newline
+    }
+    pub fn add_final_newline(&mut self) {
+        self.missing_final_newline = false;
+    }
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+    pub fn file_info(&self) -> Option<FileInfo> {
+        self.file_info
+    }
+    // A read-only document ignores every edit.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    pub fn file_type(&self) -> String {
+        self.file_type.name()
+    }
+    /// Highlights the document as the type called `name`, as
+    /// [`FileType::named`] finds it, whatever its file is called; an empty
+    /// name for none. Returns false when there is no such type.
+    pub fn set_file_type(&mut self, name: &str) -> bool {
+        let file_type = if name.is_empty() {
+            FileType::default()
+        } else {
+            let Some(file_type) = FileType::named(name) else {
+                return false;
+            };
+            file_type
+        };
+        self.file_type = file_type;
+        self.file_type_set = true;
+        self.reset_highlighting();
+        true
+    }
+    pub fn row(&self, index: usize) -> Option<&Row> {
+        self.rows.get(index)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn delete_line(&mut self, y: usize) {
+        if y >= self.rows.len() || self.read_only {
+            return;
+        }
+        let removed = self.rows.remove(y);
+        self.revision = self.revision.wrapping_add(1);
+        self.changed(
+            &Position { x: 0, y },
+            y,
+            vec![removed.as_str().to_string()],
+            Vec::new(),
+        );
+    }
+
+    // Replaces rows `start..end` with `lines`, clamping the range to the document.
+    pub fn replace_lines(&mut self, start: usize, end: usize, lines: &[String]) {
+        if self.read_only {
+            return;
+        }
+        let end = end.min(self.rows.len());
+        let start = start.min(end);
+        let removed = self.lines(start, end);
+        self.rows.splice(
+            start..end,
+            lines.iter().map(|line| Row::from(line.as_str())),
+        );
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        self.unhighlight_rows(start);
+        self.changed(&Position { x: 0, y: start }, start, removed, lines.to_vec());
+    }
+
+    pub fn insert_newline(&mut self, at: &Position) {
+        if at.y > self.rows.len() || self.read_only {
+            return;
+        }
+        self.revision = self.revision.wrapping_add(1);
+        let removed = self.lines(at.y, at.y.saturating_add(1));
+        if at.y == self.rows.len() {
+            self.rows.push(Row::default());
+        } else {
+            let current_row = &mut self.rows[at.y];
+            let new_row = current_row.split(at.x);
+            self.rows.insert(at.y + 1, new_row);
+        }
+        let inserted = self.lines(at.y, at.y.saturating_add(2));
+        self.changed(at, at.y, removed, inserted);
+    }
+
+    pub fn insert(&mut self, at: &Position, c: char) {
+        if at.y > self.rows.len() || self.read_only {
+            return;
+        }
+        self.dirty = true;
+        if c == '\n' {
+            self.insert_newline(at);
+        } else {
+            self.revision = self.revision.wrapping_add(1);
+            let removed = self.lines(at.y, at.y.saturating_add(1));
+            if at.y == self.rows.len() {
+                let mut row = Row::default();
+                row.insert(0, c);
+                self.rows.push(row);
+            } else {
+                let row = &mut self.rows[at.y];
+                row.insert(at.x, c);
+            }
+            let inserted = self.lines(at.y, at.y.saturating_add(1));
+            self.changed(at, at.y, removed, inserted);
+        }
+        self.unhighlight_rows(at.y);
+    }
+
+    fn unhighlight_rows(&mut self, start: usize) {
+        let start = start.saturating_sub(1);
+        for row in self.rows.iter_mut().skip(start) {
+            row.is_highlighted = false;
+        }
+    }
+    pub fn delete(&mut self, at: &Position) {
+        let len = self.rows.len();
+        if at.y >= len || self.read_only {
+            return;
+        }
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        let joins = at.x == self.rows[at.y].len() && at.y + 1 < len;
+        let removed = self.lines(at.y, at.y + if joins { 2 } else { 1 });
+        if joins {
+            let next_row = self.rows.remove(at.y + 1);
+            let row = &mut self.rows[at.y];
+            row.append(&next_row);
+        } else {
+            let row = &mut self.rows[at.y];
+            row.delete(at.x);
+        }
+        let inserted = self.lines(at.y, at.y + 1);
+        self.changed(at, at.y, removed, inserted);
+        self.unhighlight_rows(at.y);
+    }
+    // Keeps the change of the lines from `start`, made at `at`, for undo,
+    // moves the marks on the lines after it, and puts `.` at it.
+    fn changed(
+        &mut self,
+        at: &Position,
+        start: usize,
+        removed: Vec<String>,
+        inserted: Vec<String>,
+    ) {
+        move_marks(&mut self.marks, start, removed.len(), inserted.len());
+        self.marks.insert('.', at.clone());
+        self.history.record(at, start, removed, inserted);
+    }
+    // The text of rows `start..end`, as far as the document goes.
+    fn lines(&self, start: usize, end: usize) -> Vec<String> {
+        self.rows
+            .iter()
+            .take(end)
+            .skip(start)
+            .map(|row| row.as_str().to_string())
+            .collect()
+    }
+    /// Takes back the last change, together with the ones made along with
+    /// it, and returns where it was made; None with nothing to undo.
+    pub fn undo(&mut self) -> Option<Position> {
+        self.travel(UndoHistory::undo)
+    }
+    /// Makes the last change undone again.
+    pub fn redo(&mut self) -> Option<Position> {
+        self.travel(UndoHistory::redo)
+    }
+    /// Goes back to the state of the text before the current one in the
+    /// order they were made, across branches of the undo tree.
+    pub fn earlier(&mut self) -> Option<Position> {
+        self.travel(UndoHistory::earlier)
+    }
+    /// Goes on to the state of the text made after the current one.
+    pub fn later(&mut self) -> Option<Position> {
+        self.travel(UndoHistory::later)
+    }
+    /// The ends of the branches of the undo tree, oldest first.
+    pub fn undo_branches(&self) -> Vec<UndoBranch> {
+        self.history.branches()
+    }
+    // Goes through the undo history with `go`, moving the marks along
+    // with the lines it puts back.
+    fn travel<F>(&mut self, go: F) -> Option<Position>
+    where
+        F: FnOnce(&mut UndoHistory, &mut Splice) -> Option<(Position, usize)>,
+    {
+        if self.read_only {
+            return None;
+        }
+        let (rows, marks) = (&mut self.rows, &mut self.marks);
+        let (position, first) = go(&mut self.history, &mut |start, count, lines| {
+            undo::splice(rows, start, count, lines);
+            move_marks(marks, start, count, lines.len());
+        })?;
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        self.unhighlight_rows(first);
+        Some(position)
+    }
+    /// Puts the mark `name` at `at`. Marks stay on their line as lines are
+    /// added or deleted above them, and go with a line deleted; every
+    /// change puts the mark `.` where it was made.
+    ///
+    /// ```
+    /// use rvim_core::{Document, Position};
+    ///
+    /// let mut document = Document::from("one\ntwo\nthree");
+    /// document.set_mark('a', &Position { x: 1, y: 2 });
+    /// document.delete_line(0);
+    /// assert_eq!(document.mark('a'), Some(&Position { x: 1, y: 1 }));
+    /// assert_eq!(document.mark('.'), Some(&Position { x: 0, y: 0 }));
+    /// document.delete_line(1);
+    /// assert_eq!(document.mark('a'), None);
+    /// ```
+    pub fn set_mark(&mut self, name: char, at: &Position) {
+        self.marks.insert(name, at.clone());
+    }
+    /// Where the mark `name` is, if it is set.
+    pub fn mark(&self, name: char) -> Option<&Position> {
+        self.marks.get(&name)
+    }
+    /// Ends the changes undone together: the next one starts another step.
+    pub fn end_undo_step(&mut self) {
+        self.history.close();
+    }
+    pub fn save(&mut self) -> Result<(), Error> {
+        if let Some(snapshot) = self.snapshot() {
+            snapshot.write(|_, _| ())?;
+            self.saved(snapshot.revision);
+        }
+        Ok(())
+    }
+    // None when there is no file name to write to.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        Some(Snapshot {
+            file_name: self.file_name.clone()?,
+            revision: self.revision,
+            lines: self
+                .rows
+                .iter()
+                .map(|row| row.as_str().to_string())
+                .collect(),
+            line_ending: self.line_ending,
+            missing_final_newline: self.missing_final_newline,
+        })
+    }
+    // Follows the file to `file_name` once it has been moved there on disk.
+    pub fn rename(&mut self, file_name: &str) {
+        self.file_name = Some(file_name.to_string());
+        if !self.file_type_set {
+            self.file_type = detect_file_type(file_name, &self.rows);
+        }
+        self.file_info = FileInfo::read(file_name);
+        self.reset_highlighting();
+    }
+    // Records that the snapshot taken at `revision` is on disk. The document
+    // stays modified if it changed in the meantime.
+    pub fn saved(&mut self, revision: u64) {
+        let Some(file_name) = &self.file_name else {
+            return;
+        };
+        if !self.file_type_set {
+            self.file_type = detect_file_type(file_name, &self.rows);
+        }
+        self.file_info = FileInfo::read(file_name);
+        if self.revision == revision {
+            self.dirty = false;
+        }
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    // Goes up with every change to the text, so callers can tell whether
+    // something derived from it is out of date.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
+        if at.y >= self.rows.len() {
+            return None;
+        }
+        let mut position = Position { x: at.x, y: at.y };
+
+        let start = if direction == SearchDirection::Forward {
+            at.y
+        } else {
+            0
+        };
+        let end = if direction == SearchDirection::Forward {
+            self.rows.len()
+        } else {
+            at.y.saturating_add(1)
+        };
+        for _ in start..end {
+            if let Some(row) = self.rows.get(position.y) {
+                if let Some(x) = row.find(pattern, position.x, direction) {
+                    position.x = x;
+                    return Some(position);
+                }
+                if direction == SearchDirection::Forward {
+                    position.y = position.y.saturating_add(1);
+                    position.x = 0;
+                } else {
+                    position.y = position.y.saturating_sub(1);
+                    position.x = self.rows[position.y].len();
+                }
+            } else {
+                return None;
+            }
+        }
+        None
+    }
+    // Every match of `pattern` in document order, found as the iterator is
+    // advanced rather than all up front.
+    pub fn matches<'a>(&'a self, pattern: &'a Pattern) -> impl Iterator<Item = Position> + 'a {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(move |(y, row)| row.matches(pattern).map(move |x| Position { x, y }))
+    }
+    pub fn highlighting_options(&self) -> &HighlightingOptions {
+        self.file_type.highlighting_options()
+    }
+    // Applies highlighting computed elsewhere to the rows from `start` on.
+    pub fn set_highlighting(&mut self, start: usize, rows: Vec<Vec<highlighting::Type>>) {
+        for (row, highlighting) in self.rows.iter_mut().skip(start).zip(rows) {
+            row.set_highlighting(highlighting);
+        }
+    }
+    // Marks every row to be highlighted again, e.g. for a new search word.
+    pub fn reset_highlighting(&mut self) {
+        for row in &mut self.rows {
+            row.is_highlighted = false;
+        }
+    }
+    pub fn highlight(&mut self, word: &Option<Pattern>, until: Option<usize>) {
+        let mut start_with_comment = false;
+        let until = if let Some(until) = until {
+            if until.saturating_add(1) < self.rows.len() {
+                until.saturating_add(1)
+            } else {
+                self.rows.len()
+            }
+        } else {
+            self.rows.len()
+        };
+        for row in &mut self.rows[..until] {
+            start_with_comment = row.highlight(
+                self.file_type.highlighting_options(),
+                word,
+                start_with_comment,
+            );
+        }
+    }
+}
+
+// The type a modeline in the first or last lines names, or else the type
+// of `file_name` by its name or extension, or else by what its first line
+// gives away.
+fn detect_file_type(file_name: &str, rows: &[Row]) -> FileType {
+    let mut modelines = rows
+        .iter()
+        .take(MODELINES)
+        .chain(rows.iter().rev().take(MODELINES));
+    if let Some(file_type) = modelines.find_map(|row| FileType::from_modeline(row.as_str())) {
+        return file_type;
+    }
+    let file_type = FileType::from(file_name);
+    if file_type.name() != FileType::default().name() {
+        return file_type;
+    }
+    rows.first()
+        .and_then(|row| FileType::from_first_line(row.as_str()))
+        .unwrap_or(file_type)
+}
+
+// Moves `marks` for `removed` lines from `start` replaced with `inserted`
+// ones: the marks after them by the lines added or deleted, and the ones on
+// them to the last line left in their place, or away with none left.
+fn move_marks(marks: &mut BTreeMap<char, Position>, start: usize, removed: usize, inserted: usize) {
+    marks.retain(|_, mark| {
+        let Some(offset) = mark.y.checked_sub(start) else {
+            return true;
+        };
+        if offset >= removed {
+            mark.y = mark.y.saturating_add(inserted).saturating_sub(removed);
+        } else if inserted == 0 {
+            return false;
+        } else {
+            mark.y = start.saturating_add(offset.min(inserted.saturating_sub(1)));
+        }
+        true
+    });
+}
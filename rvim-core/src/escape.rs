@@ -0,0 +1,114 @@
+/// The backslash escapes a language allows in its strings.
+///
+/// ```
+/// use rvim_core::Escapes;
+///
+/// let len = |escapes: Escapes, text: &str| {
+///     escapes.sequence_len(&text.chars().collect::<Vec<_>>())
+/// };
+/// assert_eq!(len(Escapes::Rust, r"\n"), Some(2));
+/// assert_eq!(len(Escapes::Rust, r"\u{1F600}!"), Some(9));
+/// assert_eq!(len(Escapes::Rust, r"\q"), None);
+/// assert_eq!(len(Escapes::C, r"\x41"), Some(4));
+/// assert_eq!(len(Escapes::C, r"\101"), Some(4));
+/// ```
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Escapes {
+    /// A backslash only keeps the next character from ending the string.
+    #[default]
+    None,
+    C,
+    Rust,
+    Python,
+    JavaScript,
+    Json,
+}
+
+impl Escapes {
+    /// How many characters the escape at the start of `chars`, a
+    /// backslash, takes up, or None when the language does not allow it.
+    /// A backslash at the end of `chars` takes up just itself.
+    pub fn sequence_len(self, chars: &[char]) -> Option<usize> {
+        let Some(&next) = chars.get(1) else {
+            return Some(1);
+        };
+        let hex = |from: usize, min: usize, max: usize| {
+            let digits = hex_digits(chars, from, max);
+            (digits >= min).then_some(from.saturating_add(digits))
+        };
+        match self {
+            Self::None => Some(2),
+            Self::C => match next {
+                'n' | 't' | 'r' | '\\' | '"' | '\'' | '?' | 'a' | 'b' | 'f' | 'v' | 'e' => Some(2),
+                '0'..='7' => Some(1 + octal_digits(chars, 1, 3)),
+                'x' => hex(2, 1, usize::MAX),
+                'u' => hex(2, 4, 4),
+                'U' => hex(2, 8, 8),
+                _ => None,
+            },
+            Self::Rust => match next {
+                'n' | 't' | 'r' | '\\' | '"' | '\'' | '0' => Some(2),
+                // up to 0x7f, as a byte escape can only be ASCII
+                'x' => {
+                    let ascii = matches!(chars.get(2), Some('0'..='7'));
+                    hex(2, 2, 2).filter(|_| ascii)
+                }
+                'u' => braced_hex(chars, 6),
+                _ => None,
+            },
+            Self::Python => match next {
+                'n' | 't' | 'r' | '\\' | '"' | '\'' | 'a' | 'b' | 'f' | 'v' => Some(2),
+                '0'..='7' => Some(1 + octal_digits(chars, 1, 3)),
+                'x' => hex(2, 2, 2),
+                'u' => hex(2, 4, 4),
+                'U' => hex(2, 8, 8),
+                'N' => {
+                    let name = chars.get(3..)?.iter().position(|&c| c == '}')?;
+                    (chars.get(2) == Some(&'{') && name > 0).then_some(name + 4)
+                }
+                _ => None,
+            },
+            // any other character stands for itself
+            Self::JavaScript => match next {
+                'x' => hex(2, 2, 2),
+                'u' if chars.get(2) == Some(&'{') => braced_hex(chars, 6),
+                'u' => hex(2, 4, 4),
+                _ => Some(2),
+            },
+            Self::Json => match next {
+                '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' => Some(2),
+                'u' => hex(2, 4, 4),
+                _ => None,
+            },
+        }
+    }
+}
+
+// How many hex digits there are in a row from `from`, up to `max`.
+fn hex_digits(chars: &[char], from: usize, max: usize) -> usize {
+    chars
+        .iter()
+        .skip(from)
+        .take(max)
+        .take_while(|c| c.is_ascii_hexdigit())
+        .count()
+}
+
+fn octal_digits(chars: &[char], from: usize, max: usize) -> usize {
+    chars
+        .iter()
+        .skip(from)
+        .take(max)
+        .take_while(|c| matches!(c, '0'..='7'))
+        .count()
+}
+
+// A `\u{…}` escape with one to `max` hex digits.
+fn braced_hex(chars: &[char], max: usize) -> Option<usize> {
+    if chars.get(2) != Some(&'{') {
+        return None;
+    }
+    let digits = hex_digits(chars, 3, max);
+    let closing = 3 + digits;
+    (digits > 0 && chars.get(closing) == Some(&'}')).then_some(closing + 1)
+}
@@ -1,15 +1,37 @@
+use crate::Escapes;
+
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct HighlightingOptions {
     numbers: bool,
     strings: bool,
+    // The backslash escapes strings allow.
+    escapes: Escapes,
     characters: bool,
+    // Lifetimes such as `'a`, as in Rust.
+    lifetimes: bool,
     comments: bool,
+    // What starts a comment that runs to the end of the line.
+    comment_leaders: Vec<String>,
+    // What starts a documentation comment, which is told apart from the
+    // others. Doubling its last character makes a plain comment again.
+    doc_comment_leaders: Vec<String>,
     multiline_comments: bool,
+    // Keywords match in any case, as in SQL.
+    ignore_case: bool,
+    // Function names and capitalized type names are picked out.
+    identifiers: bool,
+    // Attributes such as `#[derive(Debug)]`, as in Rust.
+    attributes: bool,
+    // Directives such as `#include`, as in C.
+    preprocessor: bool,
+    // Variables and command substitutions such as `$HOME`, `${HOME}` and
+    // `$(pwd)`, in strings too.
+    shell_variables: bool,
     primary_keywords: Vec<String>,
     secondary_keywords: Vec<String>,
 }
@@ -23,6 +45,66 @@ impl Default for FileType {
     }
 }
 
+// A file name of each type `FileType::from` knows, to find one by name.
+const EXAMPLES: [&str; 26] = [
+    ".toml",
+    ".gitignore",
+    ".ini",
+    ".hs",
+    ".sh",
+    ".r",
+    ".json",
+    ".cs",
+    ".c",
+    ".cpp",
+    ".rs",
+    ".js",
+    ".rb",
+    ".py",
+    ".java",
+    ".go",
+    ".mk",
+    ".dockerfile",
+    ".cmake",
+    ".html",
+    ".xml",
+    ".env",
+    ".sql",
+    ".lua",
+    ".ts",
+    ".md",
+];
+
+// Files named for what they are rather than by extension, and a file name
+// of their type.
+const NAMES: [(&str, &str); 6] = [
+    ("Makefile", ".mk"),
+    ("makefile", ".mk"),
+    ("GNUmakefile", ".mk"),
+    ("Dockerfile", ".dockerfile"),
+    ("Containerfile", ".dockerfile"),
+    ("CMakeLists.txt", ".cmake"),
+];
+
+// The interpreters a `#!` line can name, before any version number, and a
+// file name of the type of script they run.
+const INTERPRETERS: [(&str, &str); 14] = [
+    ("sh", ".sh"),
+    ("bash", ".sh"),
+    ("dash", ".sh"),
+    ("ksh", ".sh"),
+    ("zsh", ".sh"),
+    ("python", ".py"),
+    ("ruby", ".rb"),
+    ("node", ".js"),
+    ("Rscript", ".r"),
+    ("runghc", ".hs"),
+    ("runhaskell", ".hs"),
+    ("go", ".go"),
+    ("lua", ".lua"),
+    ("luajit", ".lua"),
+];
+
 macro_rules! str_vec {
     ($($x:expr),*) => (vec![$($x.to_string()),*]);
 }
@@ -34,16 +116,119 @@ impl FileType {
     pub fn highlighting_options(&self) -> &HighlightingOptions {
         &self.hl_opts
     }
+    /// The type called `name`, ignoring case, or whose files end in
+    /// `.{name}`, so both `rust` and `rs` are Rust; None for a type without
+    /// highlighting.
+    ///
+    /// ```
+    /// use rvim_core::FileType;
+    ///
+    /// assert_eq!(FileType::named("rs").map(|file_type| file_type.name()).as_deref(), Some("Rust"));
+    /// assert!(FileType::named("cobol").is_none());
+    /// ```
+    pub fn named(name: &str) -> Option<Self> {
+        EXAMPLES
+            .iter()
+            .find(|example| {
+                example.strip_prefix('.') == Some(name)
+                    || Self::from(example).name.eq_ignore_ascii_case(name)
+            })
+            .map(|example| Self::from(example))
+    }
+    /// The type of script a `#!` line runs, directly or through `env`, as
+    /// in `#!/bin/bash` or `#!/usr/bin/env python3`.
+    pub fn from_shebang(line: &str) -> Option<Self> {
+        let mut words = line.strip_prefix("#!")?.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            // past options such as `-S`
+            program = words.find(|word| !word.starts_with('-'))?;
+        }
+        let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        INTERPRETERS
+            .iter()
+            .find(|(interpreter, _)| *interpreter == program)
+            .map(|(_, example)| Self::from(example))
+    }
+    /// The type a file's first line gives away: the script a `#!` line
+    /// runs, an HTML doctype or an XML declaration.
+    pub fn from_first_line(line: &str) -> Option<Self> {
+        let start = line.trim_start().to_ascii_lowercase();
+        if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            Some(Self::from(".html"))
+        } else if start.starts_with("<?xml") {
+            Some(Self::from(".xml"))
+        } else {
+            Self::from_shebang(line)
+        }
+    }
+    /// The type a modeline in `line` names, as in Vim's `vim: set ft=sh:`
+    /// or Emacs' `-*- mode: python -*-`.
+    ///
+    /// ```
+    /// use rvim_core::FileType;
+    ///
+    /// let name = |line| FileType::from_modeline(line).map(|file_type| file_type.name());
+    /// assert_eq!(name("# vim: set ft=ruby ts=2:").as_deref(), Some("Ruby"));
+    /// assert_eq!(name("// -*- mode: go; tab-width: 4 -*-").as_deref(), Some("Golang"));
+    /// assert_eq!(name("see index: ft=c"), None);
+    /// ```
+    pub fn from_modeline(line: &str) -> Option<Self> {
+        if let Some((_, rest)) = line.split_once("-*-") {
+            let (settings, _) = rest.split_once("-*-")?;
+            // a lone word is the mode
+            if !settings.contains(':') {
+                return Self::named(settings.trim());
+            }
+            return settings
+                .split(';')
+                .filter_map(|setting| setting.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("mode"))
+                .and_then(|(_, mode)| Self::named(mode.trim()));
+        }
+        // the marker starts the line or a word in it
+        let settings = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+            line.match_indices(marker)
+                .find(|&(at, _)| {
+                    line.get(..at).is_some_and(|before| {
+                        before.is_empty() || before.ends_with(char::is_whitespace)
+                    })
+                })
+                .and_then(|(at, _)| line.get(at.saturating_add(marker.len())..))
+        })?;
+        settings
+            .split(|c: char| c == ':' || c.is_whitespace())
+            .filter_map(|setting| setting.split_once('='))
+            .find(|(name, _)| matches!(*name, "ft" | "filetype" | "syn" | "syntax"))
+            .and_then(|(_, value)| Self::named(value))
+    }
     pub fn from(file_name: &str) -> Self {
+        let base_name = file_name.rsplit('/').next().unwrap_or(file_name);
+        if let Some((_, example)) = NAMES.iter().find(|(name, _)| *name == base_name) {
+            return Self::from(example);
+        }
+        // `.env.local` and the like
+        if base_name.starts_with(".env.") {
+            return Self::from(".env");
+        }
         if file_name.ends_with(".toml") {
             return Self {
                 name: String::from("TOML"),
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec!["true", "false"],
                     secondary_keywords: str_vec!["[", "]"],
                 },
@@ -54,9 +239,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: false,
                     strings: false,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![],
                     secondary_keywords: str_vec![],
                 },
@@ -70,10 +264,19 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec![";", "#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: false,
-                    primary_keywords: str_vec![";", " ", "#"],
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![],
                     secondary_keywords: str_vec![],
                 },
             };
@@ -83,9 +286,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["--"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "case",
                         "class",
@@ -129,9 +341,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: true,
                     primary_keywords: str_vec![
                         "case", "do", "done", "elif", "else", "esac", "fi", "for", "function",
                         "if", "in", "select", "then", "time", "until", "while"
@@ -187,9 +408,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "for",
                         "in",
@@ -221,9 +451,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::Json,
                     characters: true,
+                    lifetimes: false,
                     comments: false,
+                    comment_leaders: str_vec![],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec!["true", "false", "null"],
                     secondary_keywords: str_vec!["[", "]", "{", "}"],
                 },
@@ -234,9 +473,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::C,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: true,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "abstract",
                         "as",
@@ -404,14 +652,22 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::C,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: true,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "auto", "break", "case", "const", "continue", "default", "do", "enum",
                         "extern", "for", "goto", "if", "register", "return", "sizeof", "static",
-                        "struct", "switch", "typedef", "union", "void", "volatile", "while",
-                        "#include", "#ifndef", "#if", "#endif", "#define", "#undef"
+                        "struct", "switch", "typedef", "union", "void", "volatile", "while"
                     ],
                     secondary_keywords: str_vec![
                         "char",
@@ -441,9 +697,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::C,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: true,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "alignas",
                         "alignof",
@@ -565,9 +830,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::Rust,
                     characters: true,
+                    lifetimes: true,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec!["///", "//!"],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: true,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "as", "break", "const", "continue", "crate", "else", "enum", "extern",
                         "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mut",
@@ -588,9 +862,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::JavaScript,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "async",
                         "await",
@@ -638,9 +921,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::None,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "__ENCODING__",
                         "__LINE",
@@ -691,9 +983,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::Python,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "and", "as", "assert", "break", "class", "continue", "def", "del", "elif",
                         "else", "except", "False", "finally", "for", "from", "global", "if",
@@ -709,9 +1010,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::C,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "abstract",
                         "continue",
@@ -771,9 +1081,18 @@ impl FileType {
                 hl_opts: HighlightingOptions {
                     numbers: true,
                     strings: true,
+                    escapes: Escapes::C,
                     characters: true,
+                    lifetimes: false,
                     comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
                     multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
                     primary_keywords: str_vec![
                         "break",
                         "default",
@@ -869,6 +1188,408 @@ impl FileType {
                     ],
                 },
             };
+        } else if file_name.ends_with(".mk") {
+            return Self {
+                name: String::from("Make"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: false,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: true,
+                    primary_keywords: str_vec![
+                        "ifeq", "ifneq", "ifdef", "ifndef", "else", "endif", "include", "define",
+                        "endef", "export", "unexport", "override", "vpath"
+                    ],
+                    secondary_keywords: str_vec![
+                        ".PHONY",
+                        ".DEFAULT",
+                        ".SUFFIXES",
+                        "$@",
+                        "$<",
+                        "$^"
+                    ],
+                },
+            };
+        } else if file_name.ends_with(".dockerfile") {
+            return Self {
+                name: String::from("Dockerfile"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: true,
+                    primary_keywords: str_vec![
+                        "FROM",
+                        "AS",
+                        "RUN",
+                        "CMD",
+                        "LABEL",
+                        "EXPOSE",
+                        "ENV",
+                        "ADD",
+                        "COPY",
+                        "ENTRYPOINT",
+                        "VOLUME",
+                        "USER",
+                        "WORKDIR",
+                        "ARG",
+                        "ONBUILD",
+                        "STOPSIGNAL",
+                        "HEALTHCHECK",
+                        "SHELL"
+                    ],
+                    secondary_keywords: str_vec![],
+                },
+            };
+        } else if file_name.ends_with(".cmake") {
+            return Self {
+                name: String::from("CMake"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![
+                        "if",
+                        "elseif",
+                        "else",
+                        "endif",
+                        "foreach",
+                        "endforeach",
+                        "while",
+                        "endwhile",
+                        "function",
+                        "endfunction",
+                        "macro",
+                        "endmacro",
+                        "return"
+                    ],
+                    secondary_keywords: str_vec![
+                        "cmake_minimum_required",
+                        "project",
+                        "set",
+                        "unset",
+                        "option",
+                        "add_executable",
+                        "add_library",
+                        "add_subdirectory",
+                        "target_link_libraries",
+                        "target_include_directories",
+                        "find_package",
+                        "include",
+                        "install",
+                        "message"
+                    ],
+                },
+            };
+        } else if file_name.ends_with(".html") || file_name.ends_with(".htm") {
+            return Self {
+                name: String::from("HTML"),
+                hl_opts: HighlightingOptions {
+                    numbers: false,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: false,
+                    comment_leaders: str_vec![],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![],
+                    secondary_keywords: str_vec![],
+                },
+            };
+        } else if file_name.ends_with(".xml") || file_name.ends_with(".svg") {
+            return Self {
+                name: String::from("XML"),
+                hl_opts: HighlightingOptions {
+                    numbers: false,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: false,
+                    comment_leaders: str_vec![],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![],
+                    secondary_keywords: str_vec![],
+                },
+            };
+        } else if file_name.ends_with(".env") {
+            return Self {
+                name: String::from("Env"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: true,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["#"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec!["export"],
+                    secondary_keywords: str_vec![],
+                },
+            };
+        } else if file_name.ends_with(".sql") {
+            return Self {
+                name: String::from("SQL"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["--"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: true,
+                    ignore_case: true,
+                    identifiers: false,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![
+                        "SELECT",
+                        "FROM",
+                        "WHERE",
+                        "INSERT",
+                        "INTO",
+                        "VALUES",
+                        "UPDATE",
+                        "SET",
+                        "DELETE",
+                        "CREATE",
+                        "ALTER",
+                        "DROP",
+                        "TABLE",
+                        "INDEX",
+                        "VIEW",
+                        "JOIN",
+                        "INNER",
+                        "LEFT",
+                        "RIGHT",
+                        "OUTER",
+                        "ON",
+                        "AS",
+                        "AND",
+                        "OR",
+                        "NOT",
+                        "NULL",
+                        "IS",
+                        "IN",
+                        "LIKE",
+                        "BETWEEN",
+                        "EXISTS",
+                        "GROUP",
+                        "BY",
+                        "ORDER",
+                        "HAVING",
+                        "LIMIT",
+                        "OFFSET",
+                        "UNION",
+                        "ALL",
+                        "DISTINCT",
+                        "CASE",
+                        "WHEN",
+                        "THEN",
+                        "ELSE",
+                        "END",
+                        "BEGIN",
+                        "COMMIT",
+                        "ROLLBACK",
+                        "PRIMARY",
+                        "KEY",
+                        "FOREIGN",
+                        "REFERENCES",
+                        "DEFAULT",
+                        "UNIQUE",
+                        "WITH"
+                    ],
+                    secondary_keywords: str_vec![
+                        "INT",
+                        "INTEGER",
+                        "BIGINT",
+                        "SMALLINT",
+                        "DECIMAL",
+                        "NUMERIC",
+                        "REAL",
+                        "FLOAT",
+                        "BOOLEAN",
+                        "CHAR",
+                        "VARCHAR",
+                        "TEXT",
+                        "DATE",
+                        "TIME",
+                        "TIMESTAMP",
+                        "BLOB",
+                        "TRUE",
+                        "FALSE"
+                    ],
+                },
+            };
+        } else if file_name.ends_with(".lua") {
+            return Self {
+                name: String::from("Lua"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::None,
+                    characters: false,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["--"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: false,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![
+                        "and", "break", "do", "else", "elseif", "end", "for", "function", "goto",
+                        "if", "in", "local", "not", "or", "repeat", "return", "then", "until",
+                        "while"
+                    ],
+                    secondary_keywords: str_vec!["nil", "true", "false", "self"],
+                },
+            };
+        } else if file_name.ends_with(".ts") || file_name.ends_with(".tsx") {
+            return Self {
+                name: String::from("TypeScript"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    escapes: Escapes::JavaScript,
+                    characters: true,
+                    lifetimes: false,
+                    comments: true,
+                    comment_leaders: str_vec!["//"],
+                    doc_comment_leaders: str_vec![],
+                    multiline_comments: true,
+                    ignore_case: false,
+                    identifiers: true,
+                    attributes: false,
+                    preprocessor: false,
+                    shell_variables: false,
+                    primary_keywords: str_vec![
+                        "abstract",
+                        "as",
+                        "async",
+                        "await",
+                        "break",
+                        "case",
+                        "catch",
+                        "class",
+                        "const",
+                        "continue",
+                        "declare",
+                        "default",
+                        "delete",
+                        "do",
+                        "else",
+                        "enum",
+                        "export",
+                        "extends",
+                        "finally",
+                        "for",
+                        "from",
+                        "function",
+                        "if",
+                        "implements",
+                        "import",
+                        "in",
+                        "instanceof",
+                        "interface",
+                        "keyof",
+                        "let",
+                        "namespace",
+                        "new",
+                        "private",
+                        "protected",
+                        "public",
+                        "readonly",
+                        "return",
+                        "super",
+                        "switch",
+                        "this",
+                        "throw",
+                        "try",
+                        "type",
+                        "typeof",
+                        "var",
+                        "void",
+                        "while",
+                        "yield"
+                    ],
+                    secondary_keywords: str_vec![
+                        "any",
+                        "boolean",
+                        "never",
+                        "null",
+                        "number",
+                        "object",
+                        "string",
+                        "symbol",
+                        "undefined",
+                        "unknown",
+                        "true",
+                        "false"
+                    ],
+                },
+            };
+        } else if file_name.ends_with(".md") || file_name.ends_with(".markdown") {
+            return Self {
+                name: String::from("Markdown"),
+                hl_opts: HighlightingOptions::default(),
+            };
         }
         Self::default()
     }
@@ -881,6 +1602,9 @@ impl HighlightingOptions {
     pub fn strings(&self) -> bool {
         self.strings
     }
+    pub fn escapes(&self) -> Escapes {
+        self.escapes
+    }
     pub fn characters(&self) -> bool {
         self.characters
     }
@@ -893,6 +1617,30 @@ impl HighlightingOptions {
     pub fn secondary_keywords(&self) -> &Vec<String> {
         &self.secondary_keywords
     }
+    pub fn comment_leaders(&self) -> &[String] {
+        &self.comment_leaders
+    }
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+    pub fn lifetimes(&self) -> bool {
+        self.lifetimes
+    }
+    pub fn doc_comment_leaders(&self) -> &[String] {
+        &self.doc_comment_leaders
+    }
+    pub fn attributes(&self) -> bool {
+        self.attributes
+    }
+    pub fn preprocessor(&self) -> bool {
+        self.preprocessor
+    }
+    pub fn shell_variables(&self) -> bool {
+        self.shell_variables
+    }
+    pub fn identifiers(&self) -> bool {
+        self.identifiers
+    }
     pub fn multiline_comments(&self) -> bool {
         self.multiline_comments
     }
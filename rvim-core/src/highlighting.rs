@@ -0,0 +1,34 @@
+/// The syntax group a single grapheme of a [`Row`](crate::Row) belongs to.
+///
+/// Frontends decide how each group is displayed.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Type {
+    None,
+    Number,
+    Match,
+    String,
+    /// An escape sequence in a string, such as `\n`.
+    Escape,
+    /// An escape the language does not allow.
+    InvalidEscape,
+    Character,
+    Comment,
+    /// A documentation comment, such as Rust's `///`.
+    DocComment,
+    MultilineComment,
+    PrimaryKeywords,
+    SecondaryKeywords,
+    /// The name of a function where it is called or defined.
+    Function,
+    /// A capitalized name, taken to be a type.
+    Type,
+    /// An attribute, such as `#[derive(Debug)]`.
+    Attribute,
+    /// A lifetime, such as `'a`.
+    Lifetime,
+    /// A preprocessor directive, such as `#include`.
+    Preprocessor,
+    /// A shell variable or command substitution, such as `$HOME` or
+    /// `$(pwd)`.
+    Variable,
+}
@@ -0,0 +1,58 @@
+//! The editing engine behind rvim.
+//!
+//! This crate holds everything that does not need a terminal: buffers
+//! ([`Document`] and its [`Row`]s, with their undo history), filetype
+//! detection, syntax highlighting, search [`Pattern`]s, cursor motions, text
+//! objects and line diffs. Frontends own the screen and turn the highlighting
+//! [`Type`](highlighting::Type)s into colors.
+//!
+//! ```
+//! use rvim_core::{motion, Document, Position};
+//!
+//! let mut document = Document::default();
+//! for (x, c) in "let x = 1;".chars().enumerate() {
+//!     document.insert(&Position { x, y: 0 }, c);
+//! }
+//! let next = motion::word_forward(&document, &Position::default());
+//! assert_eq!(next, Position { x: 4, y: 0 });
+//! ```
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::case_sensitive_file_extension_comparisons,
+    clippy::too_many_lines,
+    clippy::struct_excessive_bools
+)]
+pub mod diff;
+mod document;
+mod escape;
+mod filetype;
+pub mod highlighting;
+pub mod motion;
+mod pattern;
+mod row;
+pub mod textobject;
+mod undo;
+
+pub use document::{Document, FileInfo, LineEnding, Snapshot};
+pub use escape::Escapes;
+pub use filetype::FileType;
+pub use filetype::HighlightingOptions;
+pub use pattern::{Pattern, SearchFlags};
+pub use row::Row;
+pub use undo::UndoBranch;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+/// A grapheme position in a [`Document`]: `x` is the column and `y` the
+/// 0-based line.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+}
@@ -0,0 +1,172 @@
+//! Cursor motions that only depend on the contents of a [`Document`].
+
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+#[derive(PartialEq, Clone, Copy)]
+pub(crate) enum Class {
+    // An empty line, which `w` and `b` stop on like a word of its own.
+    Empty,
+    Blank,
+    Word,
+    Punctuation,
+}
+
+/// The start of the next word after `at`, like vim's `w`.
+///
+/// A word is either a run of letters, digits and underscores or a run of
+/// other non-blank characters. Stays on the last character of the document
+/// when there is no next word.
+pub fn word_forward(document: &Document, at: &Position) -> Position {
+    let start = class_at(document, at);
+    let mut position = at.clone();
+    let mut seen_blank = false;
+    while let Some(next) = next(document, &position) {
+        seen_blank |= next.y != position.y;
+        position = next;
+        match class_at(document, &position) {
+            Class::Empty => return position,
+            Class::Blank => seen_blank = true,
+            class if seen_blank || class != start => return position,
+            _ => (),
+        }
+    }
+    position
+}
+
+/// The start of the word before `at`, like vim's `b`.
+pub fn word_backward(document: &Document, at: &Position) -> Position {
+    let mut position = at.clone();
+    while let Some(before) = previous(document, &position) {
+        position = before;
+        match class_at(document, &position) {
+            Class::Empty => return position,
+            Class::Blank => (),
+            class => {
+                while let Some(before) = previous(document, &position)
+                    .filter(|p| p.y == position.y && class_at(document, p) == class)
+                {
+                    position = before;
+                }
+                return position;
+            }
+        }
+    }
+    position
+}
+
+fn class_at(document: &Document, at: &Position) -> Class {
+    let Some(row) = document.row(at.y) else {
+        return Class::Empty;
+    };
+    if row.is_empty() {
+        return Class::Empty;
+    }
+    row.get(at.x)
+        .and_then(|grapheme| grapheme.chars().next())
+        .map_or(Class::Blank, class_of)
+}
+
+pub(crate) fn class_of(c: char) -> Class {
+    if c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else if c.is_whitespace() {
+        Class::Blank
+    } else {
+        Class::Punctuation
+    }
+}
+
+pub(crate) fn next(document: &Document, at: &Position) -> Option<Position> {
+    let len = document.row(at.y)?.len();
+    if at.x.saturating_add(1) < len {
+        return Some(Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        });
+    }
+    let y = at.y.saturating_add(1);
+    document.row(y).map(|_| Position { x: 0, y })
+}
+
+pub(crate) fn previous(document: &Document, at: &Position) -> Option<Position> {
+    if at.x > 0 {
+        let len = document.row(at.y).map_or(0, Row::len);
+        return Some(Position {
+            x: at.x.min(len).saturating_sub(1),
+            y: at.y,
+        });
+    }
+    let y = at.y.checked_sub(1)?;
+    let len = document.row(y)?.len();
+    Some(Position {
+        x: len.saturating_sub(1),
+        y,
+    })
+}
+
+/// A search for a character in the line, as vim's `f`, `t`, `F` and `T`
+/// start it, kept so that `;` and `,` can repeat it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CharSearch {
+    pub target: char,
+    pub forward: bool,
+    /// Stops just short of the character, as `t` and `T` do.
+    pub till: bool,
+}
+
+impl CharSearch {
+    /// The same search the other way, as `,` repeats it.
+    #[must_use]
+    pub fn reversed(self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..self
+        }
+    }
+    /// Where in `row` the search from `x` lands on the `count`th match, or
+    /// None when there are not that many. `repeat` is for `;` and `,`,
+    /// which take a `t` or `T` past a match right next to `x` rather than
+    /// leave it where it is.
+    ///
+    /// ```
+    /// use rvim_core::motion::CharSearch;
+    /// use rvim_core::Row;
+    ///
+    /// let row = Row::from("a,b,c");
+    /// let f = CharSearch { target: ',', forward: true, till: false };
+    /// assert_eq!(f.find(&row, 0, 2, false), Some(3));
+    /// assert_eq!(f.find(&row, 4, 1, false), None);
+    /// assert_eq!(f.reversed().find(&row, 4, 1, false), Some(3));
+    /// let t = CharSearch { till: true, ..f };
+    /// assert_eq!(t.find(&row, 0, 1, false), Some(0));
+    /// assert_eq!(t.find(&row, 0, 1, true), Some(2));
+    /// ```
+    pub fn find(self, row: &Row, x: usize, count: usize, repeat: bool) -> Option<usize> {
+        let mut target = [0; 4];
+        let target: &str = self.target.encode_utf8(&mut target);
+        let is_match = |at: &usize| row.get(*at) == Some(target);
+        let skip = usize::from(self.till && repeat);
+        let nth = count.saturating_sub(1);
+        if self.forward {
+            let from = x.saturating_add(1).saturating_add(skip);
+            let found = (from..row.len()).filter(is_match).nth(nth)?;
+            Some(if self.till {
+                found.saturating_sub(1)
+            } else {
+                found
+            })
+        } else {
+            let found = (0..x.saturating_sub(skip))
+                .rev()
+                .filter(is_match)
+                .nth(nth)?;
+            Some(if self.till {
+                found.saturating_add(1)
+            } else {
+                found
+            })
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! What a search looks for: text matched as it is or as a regular
+//! expression, with or without regard to case, and anywhere or only as a
+//! whole word.
+
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// How the text of a [`Pattern`] is matched.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SearchFlags {
+    /// The text is a regular expression rather than literal text.
+    pub regex: bool,
+    pub ignore_case: bool,
+    /// Only matches without a letter, digit or `_` on either side count.
+    pub whole_word: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    // Plain text compared exactly, which needs no regular expression.
+    Literal,
+    Regex(Regex),
+}
+
+/// A search, ready to be matched against lines.
+///
+/// ```
+/// use rvim_core::{Pattern, SearchFlags};
+///
+/// let flags = SearchFlags { ignore_case: true, whole_word: true, ..SearchFlags::default() };
+/// let pattern = Pattern::new("let", flags).unwrap();
+/// assert_eq!(pattern.find_at("letter LET", 0), Some(7..10));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    text: String,
+    flags: SearchFlags,
+    matcher: Matcher,
+}
+
+impl Pattern {
+    /// Fails with a description of the problem when `text` is not a valid
+    /// regular expression.
+    pub fn new(text: &str, flags: SearchFlags) -> Result<Self, String> {
+        if flags == SearchFlags::default() {
+            return Ok(Self::literal(text));
+        }
+        let mut source = if flags.regex {
+            text.to_string()
+        } else {
+            regex::escape(text)
+        };
+        if flags.whole_word {
+            source = format!(r"\b(?:{source})\b");
+        }
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(flags.ignore_case)
+            .build()
+            .map_err(|error| error.to_string())?;
+        Ok(Self {
+            text: text.to_string(),
+            flags,
+            matcher: Matcher::Regex(regex),
+        })
+    }
+    /// `text` matched exactly as it is.
+    pub fn literal(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            flags: SearchFlags::default(),
+            matcher: Matcher::Literal,
+        }
+    }
+    /// The text the pattern was made from.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn flags(&self) -> SearchFlags {
+        self.flags
+    }
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+    pub fn is_match(&self, haystack: &str) -> bool {
+        match &self.matcher {
+            Matcher::Literal => haystack.contains(&self.text),
+            Matcher::Regex(regex) => regex.is_match(haystack),
+        }
+    }
+    /// The bytes of the first match starting at or after the byte `start`.
+    pub fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        match &self.matcher {
+            Matcher::Literal => {
+                let found = haystack
+                    .get(start..)?
+                    .find(&self.text)?
+                    .saturating_add(start);
+                Some(found..found.saturating_add(self.text.len()))
+            }
+            Matcher::Regex(regex) => regex.find_at(haystack, start).map(|found| found.range()),
+        }
+    }
+    /// The bytes of the last match starting before the byte `end`.
+    pub fn find_before(&self, haystack: &str, end: usize) -> Option<Range<usize>> {
+        match &self.matcher {
+            Matcher::Literal => haystack
+                .match_indices(&self.text)
+                .take_while(|(found, _)| *found < end)
+                .last()
+                .map(|(found, text)| found..found.saturating_add(text.len())),
+            Matcher::Regex(regex) => regex
+                .find_iter(haystack)
+                .take_while(|found| found.start() < end)
+                .last()
+                .map(|found| found.range()),
+        }
+    }
+}
+
+// Two patterns are the same search when made the same way; the compiled
+// expression follows from that.
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text && self.flags == other.flags
+    }
+}
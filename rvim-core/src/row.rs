@@ -0,0 +1,995 @@
+use crate::highlighting;
+use crate::Escapes;
+use crate::HighlightingOptions;
+use crate::Pattern;
+use crate::SearchDirection;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Graphemes between two entries of `Row::checkpoints`.
+const CHECKPOINT_INTERVAL: usize = 256;
+// How many columns a tab is drawn across.
+const TAB_WIDTH: usize = 2;
+// Only this many characters of a row are highlighted, so a minified file
+// with one huge line stays fast; the rest is shown plain.
+const MAX_HIGHLIGHT_LEN: usize = 3000;
+// The keywords a function's name follows where it is defined.
+const DEFINERS: [&str; 4] = ["fn", "def", "func", "function"];
+
+#[derive(Default)]
+pub struct Row {
+    string: String,
+    highlighting: Vec<highlighting::Type>,
+    pub is_highlighted: bool,
+    len: usize,
+    // The byte offset of every `CHECKPOINT_INTERVAL`th grapheme, so finding
+    // a column in a long line does not walk it from the start. Empty for
+    // ASCII rows, where graphemes and bytes line up.
+    checkpoints: Vec<usize>,
+    // How many screen columns the row takes, and whether that is one for
+    // every grapheme, so columns and grapheme indexes are the same.
+    width: usize,
+    one_column_each: bool,
+}
+
+impl From<&str> for Row {
+    fn from(slice: &str) -> Self {
+        Self::from(String::from(slice))
+    }
+}
+
+impl From<String> for Row {
+    fn from(string: String) -> Self {
+        let mut row = Self {
+            string,
+            ..Self::default()
+        };
+        row.reindex();
+        row
+    }
+}
+
+impl Row {
+    /// Splits the graphemes `start..end` into runs sharing one highlighting
+    /// type. Tabs are expanded to two spaces.
+    pub fn segments(&self, start: usize, end: usize) -> Vec<(highlighting::Type, String)> {
+        let mut segments: Vec<(highlighting::Type, String)> = Vec::new();
+        self.for_each_segment(start, end, |highlighting_type, text| {
+            match segments.last_mut() {
+                Some((current, segment)) if *current == highlighting_type => segment.push_str(text),
+                _ => segments.push((highlighting_type, text.to_string())),
+            }
+        });
+        segments
+    }
+    /// Like [`segments`](Self::segments), but hands each run to `f` as a
+    /// slice of the row instead of allocating it. A tab is a run of its own.
+    pub fn for_each_segment<F>(&self, start: usize, end: usize, mut f: F)
+    where
+        F: FnMut(highlighting::Type, &str),
+    {
+        // the highlighting type and byte range of the run so far
+        let mut run: Option<(highlighting::Type, usize, usize)> = None;
+        let mut step = |index: usize, byte: usize, grapheme: &str| {
+            let highlighting_type = self
+                .highlighting
+                .get(index)
+                .copied()
+                .unwrap_or(highlighting::Type::None);
+            let grapheme_end = byte.saturating_add(grapheme.len());
+            match run {
+                Some((current, run_start, _))
+                    if current == highlighting_type && grapheme != "\t" =>
+                {
+                    run = Some((current, run_start, grapheme_end));
+                }
+                _ => {
+                    if let Some((current, run_start, run_end)) = run.take() {
+                        f(
+                            current,
+                            self.string.get(run_start..run_end).unwrap_or_default(),
+                        );
+                    }
+                    if grapheme == "\t" {
+                        f(highlighting_type, &" ".repeat(TAB_WIDTH));
+                    } else {
+                        run = Some((highlighting_type, byte, grapheme_end));
+                    }
+                }
+            }
+        };
+        // an ASCII row has no checkpoints and one byte per grapheme, which
+        // saves finding the grapheme boundaries
+        if self.checkpoints.is_empty() {
+            for index in start..end.min(self.len) {
+                step(
+                    index,
+                    index,
+                    self.string.get(index..=index).unwrap_or_default(),
+                );
+            }
+        } else {
+            let offset = self.byte_index(start);
+            for (index, (byte, grapheme)) in self
+                .string
+                .get(offset..)
+                .unwrap_or_default()
+                .grapheme_indices(true)
+                .enumerate()
+                .take(end.saturating_sub(start))
+            {
+                step(
+                    index.saturating_add(start),
+                    byte.saturating_add(offset),
+                    grapheme,
+                );
+            }
+        }
+        if let Some((current, run_start, run_end)) = run {
+            f(
+                current,
+                self.string.get(run_start..run_end).unwrap_or_default(),
+            );
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn insert(&mut self, at: usize, c: char) {
+        let byte = self.byte_index(at);
+        self.string.insert(byte, c);
+        self.reindex();
+    }
+    pub fn get(&self, at: usize) -> Option<&str> {
+        if at >= self.len() {
+            return None;
+        }
+        self.string
+            .get(self.byte_index(at)..)?
+            .graphemes(true)
+            .next()
+    }
+    pub fn delete(&mut self, at: usize) {
+        let Some(grapheme_len) = self.get(at).map(str::len) else {
+            return;
+        };
+        let byte = self.byte_index(at);
+        self.string
+            .replace_range(byte..byte.saturating_add(grapheme_len), "");
+        self.reindex();
+    }
+    pub fn append(&mut self, new: &Self) {
+        self.string.push_str(&new.string);
+        self.reindex();
+    }
+    #[must_use]
+    pub fn split(&mut self, at: usize) -> Self {
+        let byte = self.byte_index(at);
+        let rest = self.string.split_off(byte);
+        self.reindex();
+        self.is_highlighted = false;
+        Self::from(rest)
+    }
+    // Recounts the graphemes and columns and rebuilds the checkpoints after
+    // a change.
+    fn reindex(&mut self) {
+        self.checkpoints.clear();
+        if self.string.is_ascii() {
+            self.len = self.string.len();
+            let tabs = self.string.bytes().filter(|&byte| byte == b'\t').count();
+            self.width = self
+                .len
+                .saturating_add(tabs.saturating_mul(TAB_WIDTH.saturating_sub(1)));
+            self.one_column_each = self.width == self.len;
+            return;
+        }
+        self.len = 0;
+        self.width = 0;
+        for (byte, grapheme) in self.string.grapheme_indices(true) {
+            if self.len.is_multiple_of(CHECKPOINT_INTERVAL) {
+                self.checkpoints.push(byte);
+            }
+            self.len = self.len.saturating_add(1);
+            self.width = self.width.saturating_add(grapheme_width(grapheme));
+        }
+        self.one_column_each = self.width == self.len;
+    }
+    /// How many screen columns the row takes.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    /// The screen column, counted from the start of the row, that the
+    /// grapheme `at` is drawn from. Tabs and wide characters take two
+    /// columns; past the end each grapheme counts as one.
+    ///
+    /// ```
+    /// use rvim_core::Row;
+    ///
+    /// let row = Row::from("\t日本x");
+    /// assert_eq!(row.column(1), 2);
+    /// assert_eq!(row.column(3), 6);
+    /// assert_eq!(row.column(5), 8);
+    /// assert_eq!(row.index_at_column(5), 2);
+    /// assert_eq!(row.index_at_column(6), 3);
+    /// ```
+    pub fn column(&self, at: usize) -> usize {
+        if self.one_column_each {
+            return at;
+        }
+        if at >= self.len {
+            return self.width.saturating_add(at.saturating_sub(self.len));
+        }
+        self.string
+            .graphemes(true)
+            .take(at)
+            .map(grapheme_width)
+            .sum()
+    }
+    /// The grapheme drawn in screen `column`, the inverse of
+    /// [`column`](Self::column): either half of a wide character gives that
+    /// character.
+    pub fn index_at_column(&self, column: usize) -> usize {
+        if self.one_column_each {
+            return column;
+        }
+        if column >= self.width {
+            return self.len.saturating_add(column.saturating_sub(self.width));
+        }
+        let mut start = 0_usize;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            start = start.saturating_add(grapheme_width(grapheme));
+            if start > column {
+                return index;
+            }
+        }
+        self.len
+    }
+    /// The byte offset where the grapheme `at` starts, or the length of the
+    /// row for `at` past its end.
+    ///
+    /// ```
+    /// use rvim_core::{Pattern, Row, SearchDirection};
+    ///
+    /// let row = Row::from(format!("{}x", "é".repeat(1000)));
+    /// assert_eq!(row.byte_index(600), 1200);
+    /// let x = Pattern::literal("x");
+    /// assert_eq!(row.find(&x, 0, SearchDirection::Forward), Some(1000));
+    /// assert_eq!(row.get(1000), Some("x"));
+    /// ```
+    pub fn byte_index(&self, at: usize) -> usize {
+        if at >= self.len {
+            return self.string.len();
+        }
+        if self.checkpoints.is_empty() {
+            return at;
+        }
+        let checkpoint = at / CHECKPOINT_INTERVAL;
+        let from = self.checkpoints.get(checkpoint).copied().unwrap_or(0);
+        self.string
+            .get(from..)
+            .unwrap_or_default()
+            .grapheme_indices(true)
+            .nth(at % CHECKPOINT_INTERVAL)
+            .map_or(self.string.len(), |(byte, _)| byte.saturating_add(from))
+    }
+    // The grapheme that the byte offset `byte` falls in.
+    fn grapheme_index(&self, byte: usize) -> usize {
+        if self.checkpoints.is_empty() {
+            return byte.min(self.len);
+        }
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|&start| start <= byte)
+            .saturating_sub(1);
+        let from = self.checkpoints.get(checkpoint).copied().unwrap_or(0);
+        let within = self
+            .string
+            .get(from..)
+            .unwrap_or_default()
+            .grapheme_indices(true)
+            .take_while(|(start, _)| start.saturating_add(from) <= byte)
+            .count()
+            .saturating_sub(1);
+        checkpoint
+            .saturating_mul(CHECKPOINT_INTERVAL)
+            .saturating_add(within)
+    }
+    /// The highlighting type of each grapheme, as of the last
+    /// [`highlight`](Self::highlight).
+    pub fn highlighting(&self) -> &[highlighting::Type] {
+        &self.highlighting
+    }
+    /// Takes highlighting computed elsewhere, such as on another thread,
+    /// for the row's current text.
+    pub fn set_highlighting(&mut self, highlighting: Vec<highlighting::Type>) {
+        self.highlighting = highlighting;
+        self.is_highlighted = true;
+    }
+    /// Whether a multiline comment is still open at the end of the row, so
+    /// the next row starts inside it.
+    pub fn ends_in_comment(&self) -> bool {
+        self.highlighting.last() == Some(&highlighting::Type::MultilineComment)
+            && !self.string.ends_with("*/")
+    }
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        self.string.as_bytes()
+    }
+    // The grapheme index of every match of `query`, left to right.
+    pub fn matches<'a>(&'a self, pattern: &'a Pattern) -> impl Iterator<Item = usize> + 'a {
+        std::iter::successors(self.find(pattern, 0, SearchDirection::Forward), move |x| {
+            self.find(pattern, x.saturating_add(1), SearchDirection::Forward)
+        })
+    }
+    pub fn find(&self, pattern: &Pattern, at: usize, direction: SearchDirection) -> Option<usize> {
+        self.find_match(pattern, at, direction)
+            .map(|found| found.start)
+    }
+    // The graphemes of the first match from `at` in `direction`.
+    fn find_match(
+        &self,
+        pattern: &Pattern,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<Range<usize>> {
+        if at > self.len || pattern.is_empty() {
+            return None;
+        }
+        let byte = self.byte_index(at);
+        let found = if direction == SearchDirection::Forward {
+            pattern.find_at(&self.string, byte)
+        } else {
+            pattern.find_before(&self.string, byte)
+        }?;
+        let end = if found.end >= self.string.len() {
+            self.len
+        } else {
+            self.grapheme_index(found.end)
+        };
+        Some(self.grapheme_index(found.start)..end)
+    }
+
+    fn highlight_match(&mut self, word: Option<&Pattern>) {
+        if let Some(word) = word {
+            let mut index = 0;
+            while let Some(search_match) = self.find_match(word, index, SearchDirection::Forward) {
+                if search_match.start >= MAX_HIGHLIGHT_LEN {
+                    break;
+                }
+                for hl_type in self
+                    .highlighting
+                    .iter_mut()
+                    .take(search_match.end)
+                    .skip(search_match.start)
+                {
+                    *hl_type = highlighting::Type::Match;
+                }
+                // a match of nothing at all still moves on
+                index = search_match.end.max(search_match.start.saturating_add(1));
+            }
+        }
+    }
+
+    fn highlight_str(
+        &mut self,
+        index: &mut usize,
+        substring: &str,
+        chars: &[char],
+        hl_type: highlighting::Type,
+        ignore_case: bool,
+    ) -> bool {
+        if substring.is_empty() {
+            return false;
+        }
+        for (substring_index, c) in substring.chars().enumerate() {
+            if let Some(next_char) = chars.get(index.saturating_add(substring_index)) {
+                if *next_char != c && !(ignore_case && next_char.eq_ignore_ascii_case(&c)) {
+                    return false;
+                }
+            } else {
+                return false;
+            }
+        }
+        for _ in 0..substring.len() {
+            self.highlighting.push(hl_type);
+            *index += 1;
+        }
+        true
+    }
+    fn highlight_keywords(
+        &mut self,
+        index: &mut usize,
+        chars: &[char],
+        keywords: &[String],
+        hl_type: highlighting::Type,
+        ignore_case: bool,
+    ) -> bool {
+        if *index > 0 {
+            let prev_char = chars[*index - 1];
+            if !is_separator(prev_char) {
+                return false;
+            }
+        }
+        for word in keywords {
+            if *index < chars.len().saturating_sub(word.len()) {
+                let next_char = chars[*index + word.len()];
+                if !is_separator(next_char) {
+                    continue;
+                }
+            }
+
+            if self.highlight_str(index, word, chars, hl_type, ignore_case) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn highlight_primary_keywords(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        self.highlight_keywords(
+            index,
+            chars,
+            opts.primary_keywords(),
+            highlighting::Type::PrimaryKeywords,
+            opts.ignore_case(),
+        )
+    }
+    fn highlight_secondary_keywords(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        self.highlight_keywords(
+            index,
+            chars,
+            opts.secondary_keywords(),
+            highlighting::Type::SecondaryKeywords,
+            opts.ignore_case(),
+        )
+    }
+
+    // A word that is not a keyword: a function's name when a `(` follows
+    // it or it is being defined, a type's when it is capitalized but not
+    // all in capitals, and nothing in particular otherwise.
+    fn highlight_identifier(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        if !opts.identifiers() || !(c.is_alphabetic() || c == '_') {
+            return false;
+        }
+        let (before, rest) = chars.split_at((*index).min(chars.len()));
+        if before.last().is_some_and(|c| !is_separator(*c)) {
+            return false;
+        }
+        let len = rest.iter().take_while(|c| is_word(c)).count();
+        let word = rest.get(..len).unwrap_or_default();
+        let mut previous: Vec<char> = before
+            .iter()
+            .rev()
+            .skip_while(|c| c.is_whitespace())
+            .take_while(|c| is_word(c))
+            .copied()
+            .collect();
+        previous.reverse();
+        let previous: String = previous.into_iter().collect();
+        let hl_type = if rest.get(len) == Some(&'(') || DEFINERS.contains(&previous.as_str()) {
+            highlighting::Type::Function
+        } else if c.is_uppercase() && word.iter().any(|c| c.is_lowercase()) {
+            highlighting::Type::Type
+        } else {
+            highlighting::Type::None
+        };
+        for _ in 0..len {
+            self.highlighting.push(hl_type);
+        }
+        *index = index.saturating_add(len);
+        true
+    }
+    fn highlight_char(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.characters() && c == '\'' {
+            if let Some(next_char) = chars.get(index.saturating_add(1)) {
+                let closing_index = if *next_char == '\\' {
+                    index.saturating_add(3)
+                } else {
+                    index.saturating_add(2)
+                };
+                if let Some(closing_char) = chars.get(closing_index) {
+                    if *closing_char == '\'' {
+                        for _ in 0..=closing_index.saturating_sub(*index) {
+                            self.highlighting.push(highlighting::Type::Character);
+                            *index += 1;
+                        }
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn highlight_comment(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        let starts_comment = |leader: &String| {
+            leader
+                .chars()
+                .enumerate()
+                .all(|(offset, c)| chars.get(index.saturating_add(offset)) == Some(&c))
+        };
+        let starts_doc_comment = |leader: &String| {
+            let after = chars.get(index.saturating_add(leader.chars().count()));
+            starts_comment(leader) && after != leader.chars().last().as_ref()
+        };
+        if !opts.comments() {
+            return false;
+        }
+        let hl_type = if opts.doc_comment_leaders().iter().any(starts_doc_comment) {
+            highlighting::Type::DocComment
+        } else if opts.comment_leaders().iter().any(starts_comment) {
+            highlighting::Type::Comment
+        } else {
+            return false;
+        };
+        for _ in *index..chars.len() {
+            self.highlighting.push(hl_type);
+            *index += 1;
+        }
+        true
+    }
+    // `'` and a name after it that `highlight_char` did not take for a
+    // character.
+    fn highlight_lifetime(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        let starts_name = |c: &char| c.is_alphabetic() || *c == '_';
+        if !opts.lifetimes()
+            || c != '\''
+            || !chars.get(index.saturating_add(1)).is_some_and(starts_name)
+        {
+            return false;
+        }
+        let len = chars
+            .iter()
+            .skip(index.saturating_add(1))
+            .take_while(|c| c.is_alphanumeric() || **c == '_')
+            .count();
+        for _ in 0..=len {
+            self.highlighting.push(highlighting::Type::Lifetime);
+        }
+        *index = index.saturating_add(len).saturating_add(1);
+        true
+    }
+    // `#[…]` or `#![…]`, to the `]` that closes it or the end of the line.
+    fn highlight_attribute(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        let rest = chars.get(*index..).unwrap_or_default();
+        if !opts.attributes()
+            || !(rest.starts_with(&['#', '[']) || rest.starts_with(&['#', '!', '[']))
+        {
+            return false;
+        }
+        let mut depth = 0_usize;
+        let len = rest
+            .iter()
+            .position(|&c| {
+                match c {
+                    '[' => depth = depth.saturating_add(1),
+                    ']' => depth = depth.saturating_sub(1),
+                    _ => return false,
+                }
+                depth == 0
+            })
+            .map_or(rest.len(), |closing| closing.saturating_add(1));
+        for _ in 0..len {
+            self.highlighting.push(highlighting::Type::Attribute);
+        }
+        *index = index.saturating_add(len);
+        true
+    }
+    // A directive such as `#include` or `# define` first on its line, and
+    // the `<header>` an include names.
+    fn highlight_preprocessor(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        let (before, rest) = chars.split_at((*index).min(chars.len()));
+        if !opts.preprocessor()
+            || rest.first() != Some(&'#')
+            || !before.iter().all(|c| c.is_whitespace())
+        {
+            return false;
+        }
+        let spaces = |from: usize| {
+            rest.iter()
+                .skip(from)
+                .take_while(|c| c.is_whitespace())
+                .count()
+        };
+        let gap = spaces(1);
+        let name: String = rest
+            .iter()
+            .skip(gap.saturating_add(1))
+            .take_while(|c| c.is_alphanumeric() || **c == '_')
+            .collect();
+        let len = gap.saturating_add(1).saturating_add(name.chars().count());
+        for _ in 0..len {
+            self.highlighting.push(highlighting::Type::Preprocessor);
+        }
+        *index = index.saturating_add(len);
+        if matches!(name.as_str(), "include" | "include_next" | "import") {
+            let gap = spaces(len);
+            let header = rest.get(len.saturating_add(gap)..).unwrap_or_default();
+            if let (Some('<'), Some(closing)) =
+                (header.first(), header.iter().position(|&c| c == '>'))
+            {
+                for _ in 0..gap {
+                    self.highlighting.push(highlighting::Type::None);
+                }
+                for _ in 0..=closing {
+                    self.highlighting.push(highlighting::Type::String);
+                }
+                *index = index
+                    .saturating_add(gap)
+                    .saturating_add(closing)
+                    .saturating_add(1);
+            }
+        }
+        true
+    }
+    fn highlight_variable(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+    ) -> bool {
+        let rest = chars.get(*index..).unwrap_or_default();
+        if !opts.shell_variables() || rest.first() != Some(&'$') {
+            return false;
+        }
+        let Some(len) = variable_len(rest) else {
+            return false;
+        };
+        for _ in 0..len {
+            self.highlighting.push(highlighting::Type::Variable);
+        }
+        *index = index.saturating_add(len);
+        true
+    }
+    fn highlight_multiline_comment(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.comments() && c == '/' && *index < chars.len() {
+            if let Some(next_char) = chars.get(index.saturating_add(1)) {
+                if *next_char == '*' {
+                    let closing_index =
+                        if let Some(closing_index) = self.string[*index + 2..].find("*/") {
+                            *index + closing_index + 4
+                        } else {
+                            chars.len()
+                        };
+                    for _ in *index..closing_index {
+                        self.highlighting.push(highlighting::Type::MultilineComment);
+                        *index += 1;
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn highlight_string(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.strings() && c == '"' {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+            while let Some(next_char) = chars.get(*index) {
+                let rest = chars.get(*index..).unwrap_or_default();
+                let variable = if *next_char == '$' && opts.shell_variables() {
+                    variable_len(rest)
+                } else {
+                    None
+                };
+                // a backslash keeps a quote from ending the string either way
+                let (len, hl_type) = if let Some(len) = variable {
+                    (len, highlighting::Type::Variable)
+                } else if *next_char != '\\' {
+                    (1, highlighting::Type::String)
+                } else if opts.escapes() == Escapes::None {
+                    (2, highlighting::Type::String)
+                } else if let Some(len) = opts.escapes().sequence_len(rest) {
+                    (len, highlighting::Type::Escape)
+                } else {
+                    (2, highlighting::Type::InvalidEscape)
+                };
+                let end = index.saturating_add(len).min(chars.len());
+                for _ in *index..end {
+                    self.highlighting.push(hl_type);
+                }
+                *index = end;
+                if *next_char == '"' {
+                    break;
+                }
+            }
+            return true;
+        }
+        false
+    }
+    fn highlight_number(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.numbers() && c.is_ascii_digit() {
+            if *index > 0 {
+                let prev_char = chars[*index - 1];
+                if !is_separator(prev_char) {
+                    return false;
+                }
+            }
+            let end = index.saturating_add(number_len(chars.get(*index..).unwrap_or_default()));
+            for _ in *index..end {
+                self.highlighting.push(highlighting::Type::Number);
+            }
+            *index = end;
+            return true;
+        }
+        false
+    }
+    /// Highlights the row as `opts` says, with the matches of `word` on
+    /// top. `start_with_comment` is whether a multiline comment is open at
+    /// its start; returns whether one still is at its end.
+    ///
+    /// ```
+    /// use rvim_core::highlighting::Type;
+    /// use rvim_core::{FileType, Row};
+    ///
+    /// let mut row = Row::from("Select 1 -- one");
+    /// row.highlight(FileType::from("q.sql").highlighting_options(), &None, false);
+    /// assert_eq!(row.highlighting().first(), Some(&Type::PrimaryKeywords));
+    /// assert_eq!(row.highlighting().last(), Some(&Type::Comment));
+    ///
+    /// let mut row = Row::from(r#""\t\q""#);
+    /// row.highlight(FileType::from("main.rs").highlighting_options(), &None, false);
+    /// let escapes = [Type::Escape, Type::Escape, Type::InvalidEscape, Type::InvalidEscape];
+    /// assert_eq!(row.highlighting().get(1..5), Some(escapes.as_slice()));
+    ///
+    /// let mut row = Row::from("0xFF_u8 1e-9");
+    /// row.highlight(FileType::from("main.rs").highlighting_options(), &None, false);
+    /// let numbers = row.highlighting().iter().filter(|&&hl_type| hl_type == Type::Number);
+    /// assert_eq!(numbers.count(), 11);
+    ///
+    /// let mut row = Row::from("fn main() -> Option<u8>");
+    /// row.highlight(FileType::from("main.rs").highlighting_options(), &None, false);
+    /// assert_eq!(row.highlighting().get(3), Some(&Type::Function));
+    /// assert_eq!(row.highlighting().get(13), Some(&Type::Type));
+    ///
+    /// let mut row = Row::from("#[derive(Debug)] struct S<'a>(&'a str); /// S");
+    /// row.highlight(FileType::from("main.rs").highlighting_options(), &None, false);
+    /// assert_eq!(row.highlighting().get(15), Some(&Type::Attribute));
+    /// assert_eq!(row.highlighting().get(26), Some(&Type::Lifetime));
+    /// assert_eq!(row.highlighting().last(), Some(&Type::DocComment));
+    ///
+    /// let mut row = Row::from("#include <stdio.h>");
+    /// row.highlight(FileType::from("main.c").highlighting_options(), &None, false);
+    /// assert_eq!(row.highlighting().first(), Some(&Type::Preprocessor));
+    /// assert_eq!(row.highlighting().last(), Some(&Type::String));
+    ///
+    /// let mut row = Row::from(r#"echo "$HOME/${dir}: $(ls)""#);
+    /// row.highlight(FileType::from("run.sh").highlighting_options(), &None, false);
+    /// let variables = row.highlighting().iter().filter(|&&hl_type| hl_type == Type::Variable);
+    /// assert_eq!(variables.count(), 16);
+    /// ```
+    pub fn highlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        word: &Option<Pattern>,
+        start_with_comment: bool,
+    ) -> bool {
+        let chars: Vec<char> = self.string.chars().take(MAX_HIGHLIGHT_LEN).collect();
+        if self.is_highlighted && word.is_none() {
+            return self.ends_in_comment();
+        }
+        self.highlighting = Vec::new();
+        let mut index = 0;
+        let mut in_ml_comment = start_with_comment;
+        if in_ml_comment {
+            let closing_index = if let Some(closing_index) = self.string.find("*/") {
+                closing_index + 2
+            } else {
+                chars.len()
+            };
+            for _ in 0..closing_index {
+                self.highlighting.push(highlighting::Type::MultilineComment);
+            }
+            index = closing_index;
+        }
+        while let Some(c) = chars.get(index) {
+            if self.highlight_multiline_comment(&mut index, opts, *c, &chars) {
+                in_ml_comment = true;
+                continue;
+            }
+            in_ml_comment = false;
+            if self.highlight_char(&mut index, opts, *c, &chars)
+                || self.highlight_lifetime(&mut index, opts, *c, &chars)
+                || self.highlight_comment(&mut index, opts, &chars)
+                || self.highlight_preprocessor(&mut index, opts, &chars)
+                || self.highlight_variable(&mut index, opts, &chars)
+                || self.highlight_attribute(&mut index, opts, &chars)
+                || self.highlight_primary_keywords(&mut index, opts, &chars)
+                || self.highlight_secondary_keywords(&mut index, opts, &chars)
+                || self.highlight_identifier(&mut index, opts, *c, &chars)
+                || self.highlight_string(&mut index, opts, *c, &chars)
+                || self.highlight_number(&mut index, opts, *c, &chars)
+            {
+                continue;
+            }
+            self.highlighting.push(highlighting::Type::None);
+            index += 1;
+        }
+        self.highlight_match(word.as_ref());
+        if in_ml_comment && !self.string.ends_with("*/") {
+            return true;
+        }
+        self.is_highlighted = true;
+        false
+    }
+}
+
+// How many characters the shell variable at the start of `chars`, a `$`,
+// takes up: `$NAME`, `${…}`, `$(…)` to the bracket that closes it or the
+// end of the line, or a special one such as `$1` or `$?`. None when
+// nothing that makes a variable follows the `$`.
+fn variable_len(chars: &[char]) -> Option<usize> {
+    let opening = *chars.get(1)?;
+    let closing = match opening {
+        '{' => '}',
+        '(' => ')',
+        c if c.is_alphabetic() || c == '_' => {
+            let name = chars
+                .iter()
+                .skip(1)
+                .take_while(|c| c.is_alphanumeric() || **c == '_')
+                .count();
+            return Some(name.saturating_add(1));
+        }
+        c if c.is_ascii_digit() || "@*#?$!-<^%".contains(c) => return Some(2),
+        _ => return None,
+    };
+    let mut depth = 0_usize;
+    let len = chars
+        .iter()
+        .skip(1)
+        .position(|&c| {
+            if c == opening {
+                depth = depth.saturating_add(1);
+            } else if c == closing {
+                depth = depth.saturating_sub(1);
+            }
+            depth == 0
+        })
+        .map_or(chars.len(), |closing| closing.saturating_add(2));
+    Some(len)
+}
+
+// How many characters the number at the start of `chars` takes up: a
+// `0x`, `0o` or `0b` literal, or digits with an optional fraction and
+// exponent, with `_` between digits and any suffix such as `u32` or `f64`.
+fn number_len(chars: &[char]) -> usize {
+    let digits = |from: usize, radix: u32| {
+        chars
+            .iter()
+            .skip(from)
+            .take_while(|&&c| c.is_digit(radix) || c == '_')
+            .count()
+    };
+    let radix = match chars.get(..2) {
+        Some(['0', 'x' | 'X']) => 16,
+        Some(['0', 'o' | 'O']) => 8,
+        Some(['0', 'b' | 'B']) => 2,
+        _ => 10,
+    };
+    let mut len = if radix == 10 {
+        digits(0, 10)
+    } else {
+        2 + digits(2, radix)
+    };
+    if radix == 10 {
+        // not the `.` of a range or a method call, as in `1..2` or `1.max(2)`
+        let method = |c: &char| *c == '.' || *c == '_' || c.is_alphabetic();
+        if chars.get(len) == Some(&'.') && !chars.get(len + 1).is_some_and(method) {
+            len += 1 + digits(len + 1, 10);
+        }
+        if matches!(chars.get(len), Some('e' | 'E')) {
+            let sign = usize::from(matches!(chars.get(len + 1), Some('+' | '-')));
+            if chars.get(len + 1 + sign).is_some_and(char::is_ascii_digit) {
+                len += 1 + sign + digits(len + 1 + sign, 10);
+            }
+        }
+    }
+    len + chars
+        .iter()
+        .skip(len)
+        .take_while(|&&c| c.is_ascii_alphanumeric() || c == '_')
+        .count()
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_ascii_punctuation() || c.is_ascii_whitespace()
+}
+
+// How many screen columns `grapheme` is drawn across: two for a tab or an
+// East Asian wide character, such as a CJK ideograph or most emoji.
+fn grapheme_width(grapheme: &str) -> usize {
+    match grapheme.chars().next() {
+        Some('\t') => TAB_WIDTH,
+        Some(c) if is_wide(c) => 2,
+        _ => 1,
+    }
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(
+        u32::from(c),
+        0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD
+    )
+}
@@ -0,0 +1,327 @@
+//! Text objects: the stretches of text vim's `i` and `a` pick for an
+//! operator or a visual selection to act on, as in `diw`, `ci"` or `dap`.
+
+use crate::motion::{self, class_of, Class};
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+/// What follows `i` or `a`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextObject {
+    /// `w`: a word, a run of letters, digits and underscores or of other
+    /// non-blank characters, or the blanks between words.
+    Word,
+    /// `"`, `'` and `` ` ``: a string in those quotes on the line.
+    Quote(char),
+    /// `(`, `[`, `{` and `<`: what is between a pair of the brackets,
+    /// over lines if need be. The closing brackets, `b` and `B` name them
+    /// too.
+    Brackets(char, char),
+    /// `p`: a paragraph, the lines between empty ones.
+    Paragraph,
+}
+
+/// Where a text object lies: from `start` up to but not including `end`,
+/// or when `linewise` the lines from `start`'s to `end`'s whole.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Extent {
+    pub start: Position,
+    pub end: Position,
+    pub linewise: bool,
+}
+
+impl Extent {
+    fn characters(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+    fn lines(first: usize, last: usize) -> Self {
+        Self {
+            start: Position { x: 0, y: first },
+            end: Position { x: 0, y: last },
+            linewise: true,
+        }
+    }
+}
+
+impl TextObject {
+    /// The object the key typed after `i` or `a` names.
+    pub fn from_key(key: char) -> Option<Self> {
+        match key {
+            'w' => Some(Self::Word),
+            '"' | '\'' | '`' => Some(Self::Quote(key)),
+            '(' | ')' | 'b' => Some(Self::Brackets('(', ')')),
+            '[' | ']' => Some(Self::Brackets('[', ']')),
+            '{' | '}' | 'B' => Some(Self::Brackets('{', '}')),
+            '<' | '>' => Some(Self::Brackets('<', '>')),
+            'p' => Some(Self::Paragraph),
+            _ => None,
+        }
+    }
+    /// Where the object at `at` lies, or None when there is none there.
+    /// `around` takes in what surrounds it as `a` does: the blanks after a
+    /// word or string (or before it, when there are none after), the
+    /// brackets themselves, or the empty lines after a paragraph. `count`
+    /// takes in that many words or paragraphs, or brackets that many
+    /// levels out.
+    ///
+    /// ```
+    /// use rvim_core::textobject::TextObject;
+    /// use rvim_core::{Document, Position};
+    ///
+    /// let mut document = Document::default();
+    /// for (x, c) in "call(a, \"b c\")".chars().enumerate() {
+    ///     document.insert(&Position { x, y: 0 }, c);
+    /// }
+    /// let at = Position { x: 10, y: 0 };
+    /// let columns = |object: TextObject, around: bool| {
+    ///     let extent = object.find(&document, &at, around, 1).unwrap();
+    ///     (extent.start.x, extent.end.x)
+    /// };
+    /// assert_eq!(columns(TextObject::Word, false), (10, 11));
+    /// assert_eq!(columns(TextObject::Quote('"'), false), (9, 12));
+    /// assert_eq!(columns(TextObject::Brackets('(', ')'), false), (5, 13));
+    /// assert_eq!(columns(TextObject::Brackets('(', ')'), true), (4, 14));
+    /// ```
+    pub fn find(
+        self,
+        document: &Document,
+        at: &Position,
+        around: bool,
+        count: usize,
+    ) -> Option<Extent> {
+        let count = count.max(1);
+        match self {
+            Self::Word => word(document.row(at.y)?, at, around, count),
+            Self::Quote(quote) => string(document.row(at.y)?, at, quote, around),
+            Self::Brackets(open, close) => brackets(document, at, (open, close), around, count),
+            Self::Paragraph => paragraph(document, at.y, around, count),
+        }
+    }
+}
+
+// The first character of each grapheme of `row`.
+fn chars(row: &Row) -> Vec<char> {
+    (0..row.len())
+        .filter_map(|x| row.get(x)?.chars().next())
+        .collect()
+}
+
+fn is_blank(c: char) -> bool {
+    class_of(c) == Class::Blank
+}
+
+// `count` runs of word characters, other characters or blanks from the one
+// at `at`; with `around`, a word and the blanks after it count as one.
+fn word(row: &Row, at: &Position, around: bool, count: usize) -> Option<Extent> {
+    let chars = chars(row);
+    let x = at.x.min(chars.len().checked_sub(1)?);
+    let class = class_of(*chars.get(x)?);
+    let same = |c: &char| class_of(*c) == class;
+    let mut start = x.saturating_sub(chars.get(..x)?.iter().rev().take_while(|c| same(c)).count());
+    let runs = if around {
+        count.saturating_mul(2)
+    } else {
+        count
+    };
+    let mut end = start;
+    for _ in 0..runs {
+        let Some(&first) = chars.get(end) else {
+            break;
+        };
+        let class = class_of(first);
+        end = end.saturating_add(
+            chars
+                .get(end..)?
+                .iter()
+                .take_while(|c| class_of(**c) == class)
+                .count(),
+        );
+    }
+    // at the end of the line, the blanks before the word go instead
+    let ends_in_word = end > 0 && !chars.get(end - 1).copied().is_some_and(is_blank);
+    if around && class != Class::Blank && ends_in_word {
+        let blanks = chars
+            .get(..start)?
+            .iter()
+            .rev()
+            .take_while(|c| is_blank(**c));
+        start = start.saturating_sub(blanks.count());
+    }
+    Some(Extent::characters(
+        Position { x: start, y: at.y },
+        Position { x: end, y: at.y },
+    ))
+}
+
+// The string in `quote`s around `at`, or the first after it on the line.
+// Quotes pair up from the start of the line, skipping any after a
+// backslash.
+fn string(row: &Row, at: &Position, quote: char, around: bool) -> Option<Extent> {
+    let chars = chars(row);
+    let mut quotes = Vec::new();
+    let mut x = 0;
+    while let Some(&c) = chars.get(x) {
+        if c == '\\' {
+            x = x.saturating_add(2);
+            continue;
+        }
+        if c == quote {
+            quotes.push(x);
+        }
+        x = x.saturating_add(1);
+    }
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| at.x <= close)?;
+    let (mut start, mut end) = if around {
+        (open, close.saturating_add(1))
+    } else {
+        (open.saturating_add(1), close)
+    };
+    if around {
+        let after = chars
+            .get(end..)?
+            .iter()
+            .take_while(|c| is_blank(**c))
+            .count();
+        if after > 0 {
+            end = end.saturating_add(after);
+        } else {
+            let before = chars
+                .get(..start)?
+                .iter()
+                .rev()
+                .take_while(|c| is_blank(**c));
+            start = start.saturating_sub(before.count());
+        }
+    }
+    Some(Extent::characters(
+        Position { x: start, y: at.y },
+        Position { x: end, y: at.y },
+    ))
+}
+
+fn char_at(document: &Document, at: &Position) -> Option<char> {
+    document.row(at.y)?.get(at.x)?.chars().next()
+}
+
+// What is between the `count`th pair of brackets around `at`. Inside
+// brackets on lines of their own, the lines between them go whole.
+fn brackets(
+    document: &Document,
+    at: &Position,
+    (open, close): (char, char),
+    around: bool,
+    count: usize,
+) -> Option<Extent> {
+    // back to the opening bracket, skipping pairs closed before `at`; on a
+    // closing bracket, its own pair is the one
+    let mut depth = 0_usize;
+    let mut levels = 0;
+    let mut position = at.clone();
+    let opening = loop {
+        match char_at(document, &position) {
+            Some(c) if c == close && position != *at => depth = depth.saturating_add(1),
+            Some(c) if c == open && depth > 0 => depth -= 1,
+            Some(c) if c == open => {
+                levels += 1;
+                if levels == count {
+                    break position;
+                }
+            }
+            _ => (),
+        }
+        position = motion::previous(document, &position)?;
+    };
+    let mut position = opening.clone();
+    let closing = loop {
+        position = motion::next(document, &position)?;
+        match char_at(document, &position) {
+            Some(c) if c == open => depth = depth.saturating_add(1),
+            Some(c) if c == close && depth > 0 => depth -= 1,
+            Some(c) if c == close => break position,
+            _ => (),
+        }
+    };
+    if around {
+        let end = Position {
+            x: closing.x.saturating_add(1),
+            y: closing.y,
+        };
+        return Some(Extent::characters(opening, end));
+    }
+    let mut start = Position {
+        x: opening.x.saturating_add(1),
+        y: opening.y,
+    };
+    if closing.y > start.y && start.x >= document.row(start.y).map_or(0, Row::len) {
+        start = Position {
+            x: 0,
+            y: start.y.saturating_add(1),
+        };
+    }
+    let before_closing = document
+        .row(closing.y)
+        .is_some_and(|row| chars(row).into_iter().take(closing.x).all(is_blank));
+    if closing.y > start.y && before_closing {
+        let last = closing.y.saturating_sub(1);
+        if start.x == 0 {
+            return Some(Extent::lines(start.y, last));
+        }
+        let end = Position {
+            x: document.row(last).map_or(0, Row::len),
+            y: last,
+        };
+        return Some(Extent::characters(start, end));
+    }
+    Some(Extent::characters(start, closing))
+}
+
+// `count` runs of lines with text or of blank ones from the one on line
+// `y`; with `around`, a paragraph and the blank lines after it count as
+// one.
+fn paragraph(document: &Document, y: usize, around: bool, count: usize) -> Option<Extent> {
+    let blank = |y: usize| {
+        document
+            .row(y)
+            .is_none_or(|row| row.as_str().trim().is_empty())
+    };
+    let y = y.min(document.len().checked_sub(1)?);
+    let starts_blank = blank(y);
+    let mut first = y;
+    while first > 0 && blank(first - 1) == starts_blank {
+        first -= 1;
+    }
+    let runs = if around {
+        count.saturating_mul(2)
+    } else {
+        count
+    };
+    let mut last = y;
+    for run in 0..runs {
+        if run > 0 {
+            if last.saturating_add(1) >= document.len() {
+                break;
+            }
+            last = last.saturating_add(1);
+        }
+        let kind = blank(last);
+        while last.saturating_add(1) < document.len() && blank(last.saturating_add(1)) == kind {
+            last = last.saturating_add(1);
+        }
+    }
+    // at the end of the text, the blank lines before the paragraph go
+    // instead
+    if around && !starts_blank && !blank(last) {
+        while first > 0 && blank(first - 1) {
+            first -= 1;
+        }
+    }
+    Some(Extent::lines(first, last))
+}
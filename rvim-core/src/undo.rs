@@ -0,0 +1,276 @@
+use crate::Position;
+use crate::Row;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+// How many steps can be undone; older ones are forgotten.
+const UNDO_LEVELS: usize = 1000;
+
+// Puts the lines of a change back or makes it again: replaces `count` rows
+// from `start` with `lines`.
+pub type Splice<'a> = dyn FnMut(usize, usize, &[String]) + 'a;
+
+// One change to the text: the lines from `start` that were `removed`, and
+// the ones `inserted` in their place.
+struct Change {
+    start: usize,
+    removed: Vec<String>,
+    inserted: Vec<String>,
+}
+
+// The changes undone and redone together, such as everything typed in one
+// visit to insert mode, and where the first of them was made. A step leads
+// from the state of the text it was made in, its parent, to a state of its
+// own, numbered in the order they were made.
+struct Step {
+    number: usize,
+    parent: usize,
+    changes: Vec<Change>,
+    position: Position,
+    time: Instant,
+}
+
+/// The end of a branch of the undo tree, as `:undolist` lists them.
+pub struct UndoBranch {
+    /// The number of the state the branch ends in.
+    pub number: usize,
+    /// How many steps lead to it from the oldest state kept.
+    pub changes: usize,
+    /// When the last of them was made.
+    pub time: Instant,
+}
+
+/// The edits made to a [`Document`](crate::Document), grouped into steps
+/// to undo and redo. The steps make a tree: a change made after undoing
+/// starts a branch of its own rather than losing what was undone, and
+/// every state stays in reach in the order it was made.
+#[derive(Default)]
+pub struct UndoHistory {
+    // Oldest first.
+    steps: Vec<Step>,
+    // The number of the state the text is in, 0 for the original text.
+    current: usize,
+    // The oldest state kept, once older ones are forgotten.
+    root: usize,
+    // The step redo makes from each state: the one last undone or made
+    // from it.
+    redo: HashMap<usize, usize>,
+    // Whether the current step still takes more changes.
+    open: bool,
+}
+
+impl UndoHistory {
+    // Adds the change of the lines from `start`, made at `position`, to the
+    // open step, or starts a new one. Typing on one line makes a single
+    // change rather than one for each character.
+    pub fn record(
+        &mut self,
+        position: &Position,
+        start: usize,
+        removed: Vec<String>,
+        inserted: Vec<String>,
+    ) {
+        if !self.open {
+            let number = self
+                .steps
+                .last()
+                .map_or(self.root, |step| step.number)
+                .saturating_add(1);
+            self.steps.push(Step {
+                number,
+                parent: self.current,
+                changes: Vec::new(),
+                position: position.clone(),
+                time: Instant::now(),
+            });
+            self.redo.insert(self.current, number);
+            self.current = number;
+            self.open = true;
+            self.forget();
+        }
+        // an open step is the one last made
+        let Some(step) = self.steps.last_mut() else {
+            return;
+        };
+        step.time = Instant::now();
+        match step.changes.last_mut() {
+            Some(last)
+                if last.start == start
+                    && last.inserted.len() == 1
+                    && removed.len() == 1
+                    && inserted.len() == 1 =>
+            {
+                last.inserted = inserted;
+            }
+            _ => step.changes.push(Change {
+                start,
+                removed,
+                inserted,
+            }),
+        }
+    }
+    // Ends the open step, so the next change starts another.
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+    // Takes back the step that led to the current state through `splice`,
+    // returning where it was made and the first line it touched, or None
+    // in the oldest state.
+    pub fn undo(&mut self, splice: &mut Splice) -> Option<(Position, usize)> {
+        self.open = false;
+        self.undo_step(splice)
+    }
+    // Makes the step last undone from the current state again.
+    pub fn redo(&mut self, splice: &mut Splice) -> Option<(Position, usize)> {
+        self.open = false;
+        let next = *self.redo.get(&self.current)?;
+        self.redo_step(splice, next)
+    }
+    // Goes back to the state made before the current one, whichever
+    // branch it is on.
+    pub fn earlier(&mut self, splice: &mut Splice) -> Option<(Position, usize)> {
+        self.open = false;
+        let target = self
+            .steps
+            .iter()
+            .rev()
+            .map(|step| step.number)
+            .find(|&number| number < self.current)
+            .or_else(|| (self.current != self.root).then_some(self.root))?;
+        self.go_to(splice, target)
+    }
+    // Goes on to the state made after the current one.
+    pub fn later(&mut self, splice: &mut Splice) -> Option<(Position, usize)> {
+        self.open = false;
+        let target = self
+            .steps
+            .iter()
+            .map(|step| step.number)
+            .find(|&number| number > self.current)?;
+        self.go_to(splice, target)
+    }
+    // The last state of every branch, oldest first.
+    pub fn branches(&self) -> Vec<UndoBranch> {
+        let parents: HashSet<usize> = self.steps.iter().map(|step| step.parent).collect();
+        self.steps
+            .iter()
+            .filter(|step| !parents.contains(&step.number))
+            .map(|step| UndoBranch {
+                number: step.number,
+                changes: self.path(step.number).len(),
+                time: step.time,
+            })
+            .collect()
+    }
+    fn step(&self, number: usize) -> Option<&Step> {
+        self.steps
+            .binary_search_by_key(&number, |step| step.number)
+            .ok()
+            .and_then(|index| self.steps.get(index))
+    }
+    // The steps from the oldest state kept to `number`, last first.
+    fn path(&self, number: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut state = number;
+        while let Some(step) = self.step(state) {
+            path.push(state);
+            state = step.parent;
+        }
+        path
+    }
+    // Undoes steps until `target` is ahead, then makes the steps to it.
+    fn go_to(&mut self, splice: &mut Splice, target: usize) -> Option<(Position, usize)> {
+        let path = self.path(target);
+        let mut moved: Option<(Position, usize)> = None;
+        let mut made = |(position, first): (Position, usize)| {
+            let first = moved.as_ref().map_or(first, |(_, line)| first.min(*line));
+            moved = Some((position, first));
+        };
+        while self.current != self.root && !path.contains(&self.current) {
+            let Some(undone) = self.undo_step(splice) else {
+                break;
+            };
+            made(undone);
+        }
+        let ahead = path.iter().position(|&state| state == self.current);
+        let ahead = path.get(..ahead.unwrap_or(path.len())).unwrap_or_default();
+        for &next in ahead.iter().rev() {
+            let Some(redone) = self.redo_step(splice, next) else {
+                break;
+            };
+            made(redone);
+        }
+        moved
+    }
+    fn undo_step(&mut self, splice: &mut Splice) -> Option<(Position, usize)> {
+        let step = self.step(self.current)?;
+        for change in step.changes.iter().rev() {
+            splice(change.start, change.inserted.len(), &change.removed);
+        }
+        let undone = (step.position.clone(), first_line(step));
+        let parent = step.parent;
+        self.redo.insert(parent, self.current);
+        self.current = parent;
+        Some(undone)
+    }
+    // Makes `next`, a step from the current state.
+    fn redo_step(&mut self, splice: &mut Splice, next: usize) -> Option<(Position, usize)> {
+        let step = self.step(next)?;
+        for change in &step.changes {
+            splice(change.start, change.removed.len(), &change.inserted);
+        }
+        let redone = (step.position.clone(), first_line(step));
+        self.redo.insert(self.current, next);
+        self.current = next;
+        Some(redone)
+    }
+    // Drops the oldest steps past the limit. When the current state came
+    // from the oldest step it becomes the oldest state kept, and the
+    // branches that start before it go; otherwise its own branch goes.
+    fn forget(&mut self) {
+        while self.steps.len() > UNDO_LEVELS {
+            let Some(oldest) = self.steps.first().map(|step| step.number) else {
+                return;
+            };
+            let rooted = self.path(self.current).contains(&oldest);
+            let mut gone = HashSet::new();
+            for step in &self.steps {
+                let dropped = if rooted {
+                    step.parent == self.root && step.number != oldest
+                } else {
+                    step.number == oldest
+                };
+                if dropped || gone.contains(&step.parent) {
+                    gone.insert(step.number);
+                }
+            }
+            if rooted {
+                self.redo.remove(&self.root);
+                self.root = oldest;
+                self.steps.retain(|step| step.number != oldest);
+            }
+            self.steps.retain(|step| !gone.contains(&step.number));
+            self.redo
+                .retain(|state, next| !gone.contains(state) && !gone.contains(next));
+        }
+    }
+}
+
+// The first line the changes of `step` touch.
+fn first_line(step: &Step) -> usize {
+    step.changes
+        .iter()
+        .map(|change| change.start)
+        .min()
+        .unwrap_or(0)
+}
+
+// Replaces `count` rows from `start` with `lines`.
+pub fn splice(rows: &mut Vec<Row>, start: usize, count: usize, lines: &[String]) {
+    let start = start.min(rows.len());
+    let end = start.saturating_add(count).min(rows.len());
+    rows.splice(
+        start..end,
+        lines.iter().map(|line| Row::from(line.as_str())),
+    );
+}
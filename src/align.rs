@@ -0,0 +1,177 @@
+// `:Align {delimiter}`: lines up the delimiters in a block of lines, one
+// column per occurrence. A markdown table is reformatted as a whole.
+
+use std::fmt::Write;
+
+// Delimiters that stay against the text before them, as in `a,  b`; the
+// rest get a space on either side, as in `a   = b`.
+const TRAILING: &[&str] = &[",", ";", ":"];
+
+pub fn align(lines: &[&str], delimiter: &str) -> Vec<String> {
+    if delimiter == "|" && is_table(lines) {
+        return align_table(lines);
+    }
+    let split: Vec<Option<Vec<&str>>> = lines
+        .iter()
+        .map(|line| {
+            line.contains(delimiter).then(|| {
+                line.split(delimiter)
+                    .enumerate()
+                    .map(|(index, part)| {
+                        // the first column keeps its indentation
+                        if index == 0 {
+                            part.trim_end()
+                        } else {
+                            part.trim()
+                        }
+                    })
+                    .collect()
+            })
+        })
+        .collect();
+    let mut widths: Vec<usize> = Vec::new();
+    for parts in split.iter().flatten() {
+        // the last column is not padded
+        for (index, part) in parts.iter().enumerate().rev().skip(1) {
+            if widths.len() <= index {
+                widths.resize(index.saturating_add(1), 0);
+            }
+            if let Some(width) = widths.get_mut(index) {
+                *width = (*width).max(part.chars().count());
+            }
+        }
+    }
+    let trailing = TRAILING.contains(&delimiter);
+    lines
+        .iter()
+        .zip(split)
+        .map(|(line, parts)| {
+            let Some(parts) = parts else {
+                return (*line).to_string();
+            };
+            let last = parts.len().saturating_sub(1);
+            let mut aligned = String::new();
+            for (index, part) in parts.iter().enumerate() {
+                if index == last {
+                    aligned.push_str(part);
+                    break;
+                }
+                let width = widths.get(index).copied().unwrap_or(0);
+                let padding = " ".repeat(width.saturating_sub(part.chars().count()));
+                if trailing {
+                    let _ = write!(aligned, "{part}{delimiter}{padding} ");
+                } else {
+                    let _ = write!(aligned, "{part}{padding} {delimiter} ");
+                }
+            }
+            aligned.trim_end().to_string()
+        })
+        .collect()
+}
+
+// Every line is a row between pipes, and the second is the row of dashes
+// under the header.
+fn is_table(lines: &[&str]) -> bool {
+    lines.iter().all(|line| line.trim_start().starts_with('|'))
+        && lines
+            .get(1)
+            .is_some_and(|line| cells(line).iter().all(|cell| alignment(cell).is_some()))
+}
+
+// The cells between the pipes of a table row.
+fn cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+// What a cell of the separator row, such as `:---:`, says about its column.
+fn alignment(cell: &str) -> Option<Alignment> {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+        return None;
+    }
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Some(Alignment::Center),
+        (false, true) => Some(Alignment::Right),
+        _ => Some(Alignment::Left),
+    }
+}
+
+// Pads every cell to the width of its column, in the column's alignment,
+// and redraws the separator row to match.
+fn align_table(lines: &[&str]) -> Vec<String> {
+    let indent: String = lines
+        .first()
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+        .unwrap_or_default();
+    let rows: Vec<Vec<&str>> = lines.iter().map(|line| cells(line)).collect();
+    let separator = rows.get(1).cloned().unwrap_or_default();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let alignments: Vec<Alignment> = (0..columns)
+        .map(|column| {
+            separator
+                .get(column)
+                .and_then(|cell| alignment(cell))
+                .unwrap_or(Alignment::Left)
+        })
+        .collect();
+    // three dashes at least, as markdown needs
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .enumerate()
+                .filter(|(index, _)| *index != 1)
+                .filter_map(|(_, row)| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+    rows.iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let cells: Vec<String> = widths
+                .iter()
+                .zip(&alignments)
+                .enumerate()
+                .map(|(column, (&width, &alignment))| {
+                    if index == 1 {
+                        return separator_cell(width, alignment);
+                    }
+                    let cell = row.get(column).copied().unwrap_or_default();
+                    pad(cell, width, alignment)
+                })
+                .collect();
+            format!("{indent}| {} |", cells.join(" | "))
+        })
+        .collect()
+}
+
+fn separator_cell(width: usize, alignment: Alignment) -> String {
+    let dashes = |count: usize| "-".repeat(count);
+    match alignment {
+        Alignment::Left => dashes(width),
+        Alignment::Center => format!(":{}:", dashes(width.saturating_sub(2))),
+        Alignment::Right => format!("{}:", dashes(width.saturating_sub(1))),
+    }
+}
+
+fn pad(cell: &str, width: usize, alignment: Alignment) -> String {
+    let room = width.saturating_sub(cell.chars().count());
+    let (before, after) = match alignment {
+        Alignment::Left => (0, room),
+        Alignment::Right => (room, 0),
+        Alignment::Center => (room / 2, room.saturating_sub(room / 2)),
+    };
+    format!("{}{cell}{}", " ".repeat(before), " ".repeat(after))
+}
@@ -0,0 +1,41 @@
+#[derive(Default)]
+pub struct ArgList {
+    files: Vec<String>,
+}
+
+impl ArgList {
+    pub fn from(files: &[String]) -> Self {
+        let mut arglist = Self::default();
+        for file in files {
+            arglist.add(file);
+        }
+        arglist
+    }
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+    pub fn add(&mut self, file: &str) {
+        if !self.files.iter().any(|existing| existing == file) {
+            self.files.push(file.to_string());
+        }
+    }
+    // Renames every entry with `rename`.
+    pub fn rename<F: Fn(&str) -> String>(&mut self, rename: F) {
+        for file in &mut self.files {
+            *file = rename(file);
+        }
+    }
+    // `*` removes every entry, anything else must match a file name exactly.
+    pub fn delete(&mut self, pattern: &str) -> bool {
+        let len = self.files.len();
+        if pattern == "*" {
+            self.files.clear();
+        } else {
+            self.files.retain(|file| file != pattern);
+        }
+        self.files.len() != len
+    }
+}
@@ -0,0 +1,110 @@
+// Lines set aside with a note by `:BookmarkAdd`, shown with a sign in the
+// gutter and picked from with `:Bookmarks`. Each project, the directory the
+// editor was started in, keeps its own in `bookmarks/` in the state
+// directory, in a file named after its path with `%` for each `/`, one
+// bookmark per line:
+//
+//     {line}	{file}	{note}
+//
+// Lines count from 0, and files are full paths.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Shown in the gutter of a bookmarked line.
+pub const SIGN: char = '*';
+
+pub struct Bookmark {
+    pub file_name: String,
+    pub line: usize,
+    pub note: String,
+}
+
+#[derive(Default)]
+pub struct Bookmarks {
+    // By file, then line.
+    list: Vec<Bookmark>,
+    // Where they are written as they change; None keeps them in memory, as
+    // in tests.
+    path: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    // The bookmarks of `project`, from under the state directory `dir`. A
+    // missing or unreadable file starts without any, and lines that do not
+    // make sense are skipped.
+    pub fn load(dir: &Path, project: &Path) -> Self {
+        let name = project.display().to_string().replace('/', "%");
+        let path = dir.join("bookmarks").join(name);
+        let list = fs::read_to_string(&path)
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| {
+                        let mut fields = line.splitn(3, '\t');
+                        Some(Bookmark {
+                            line: fields.next()?.parse().ok()?,
+                            file_name: fields.next()?.to_string(),
+                            note: fields.next().unwrap_or_default().to_string(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut bookmarks = Self {
+            list,
+            path: Some(path),
+        };
+        bookmarks.sort();
+        bookmarks
+    }
+    pub fn list(&self) -> &[Bookmark] {
+        &self.list
+    }
+    // Bookmarks `line` of `file_name`, replacing the note of one already
+    // there.
+    pub fn add(&mut self, file_name: &str, line: usize, note: &str) {
+        self.remove(file_name, line);
+        self.list.push(Bookmark {
+            file_name: file_name.to_string(),
+            line,
+            note: note.to_string(),
+        });
+        self.sort();
+        self.save();
+    }
+    // Returns whether there was one to remove.
+    pub fn remove(&mut self, file_name: &str, line: usize) -> bool {
+        let len = self.list.len();
+        self.list
+            .retain(|bookmark| bookmark.file_name != file_name || bookmark.line != line);
+        let removed = self.list.len() < len;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+    fn sort(&mut self) {
+        self.list
+            .sort_by(|a, b| (a.file_name.as_str(), a.line).cmp(&(b.file_name.as_str(), b.line)));
+    }
+    // Failing to write only loses them for the next run, so it is not worth
+    // interrupting the edit for.
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let mut text = String::new();
+        for bookmark in &self.list {
+            let _ = writeln!(
+                text,
+                "{}\t{}\t{}",
+                bookmark.line, bookmark.file_name, bookmark.note
+            );
+        }
+        let _ = fs::write(path, text);
+    }
+}
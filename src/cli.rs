@@ -0,0 +1,52 @@
+// Command line arguments:
+// `rvim [--listen {socket}] [--server {socket}] [--remote | --remote-wait]
+//       [--startuptime {file}] [--log {file}] [--pager] [FILE]...`
+#[derive(Default)]
+pub struct Arguments {
+    pub files: Vec<String>,
+    pub listen: Option<String>,
+    pub server: Option<String>,
+    // Some(true) for --remote-wait, Some(false) for --remote.
+    pub remote: Option<bool>,
+    pub startuptime: Option<String>,
+    pub log: Option<String>,
+    // Read-only with less-style keys, reading standard input without a
+    // file.
+    pub pager: bool,
+}
+
+impl Arguments {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Self {
+        let mut arguments = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "--listen" {
+                arguments.listen = args.next();
+            } else if let Some(path) = arg.strip_prefix("--listen=") {
+                arguments.listen = Some(path.to_string());
+            } else if arg == "--server" {
+                arguments.server = args.next();
+            } else if let Some(path) = arg.strip_prefix("--server=") {
+                arguments.server = Some(path.to_string());
+            } else if arg == "--remote" {
+                arguments.remote = Some(false);
+            } else if arg == "--remote-wait" {
+                arguments.remote = Some(true);
+            } else if arg == "--startuptime" {
+                arguments.startuptime = args.next();
+            } else if let Some(path) = arg.strip_prefix("--startuptime=") {
+                arguments.startuptime = Some(path.to_string());
+            } else if arg == "--log" {
+                arguments.log = args.next();
+            } else if let Some(path) = arg.strip_prefix("--log=") {
+                arguments.log = Some(path.to_string());
+            } else if arg == "--pager" {
+                arguments.pager = true;
+            } else if arg == "--" {
+                arguments.files.extend(args.by_ref());
+            } else {
+                arguments.files.push(arg);
+            }
+        }
+        arguments
+    }
+}
@@ -0,0 +1,219 @@
+// The system clipboard behind the `"+` and `"*` registers, reached through
+// whichever command-line tool the system has: pbcopy on macOS, wl-copy
+// under Wayland, xclip or xsel under X. `"+` is the clipboard and `"*` the
+// primary selection, where there is one; elsewhere both are the clipboard.
+//
+// Without a tool, as over SSH, `set clipboard` can have yanks sent as an
+// OSC 52 escape sequence instead, for the terminal to put on the clipboard
+// of the machine it runs on. Pasting that way is not supported, so puts
+// get what was last yanked in the editor.
+
+use crate::formatter;
+use crate::health;
+use std::env;
+use std::fmt;
+use std::io::Write;
+use std::process::Stdio;
+use std::time::Duration;
+
+// How long a tool may take before it is given up on.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// A tool, the environment variable that says there is a display for it,
+// and the commands that copy to and paste from the clipboard and the
+// primary selection.
+struct Tool {
+    program: &'static str,
+    display: Option<&'static str>,
+    copy: [&'static str; 2],
+    paste: [&'static str; 2],
+}
+
+// In the order they are tried.
+const TOOLS: [Tool; 4] = [
+    Tool {
+        program: "pbcopy",
+        display: None,
+        copy: ["pbcopy", "pbcopy"],
+        paste: ["pbpaste", "pbpaste"],
+    },
+    Tool {
+        program: "wl-copy",
+        display: Some("WAYLAND_DISPLAY"),
+        copy: ["wl-copy", "wl-copy --primary"],
+        paste: ["wl-paste --no-newline", "wl-paste --no-newline --primary"],
+    },
+    Tool {
+        program: "xclip",
+        display: Some("DISPLAY"),
+        copy: [
+            "xclip -quiet -i -selection clipboard",
+            "xclip -quiet -i -selection primary",
+        ],
+        paste: [
+            "xclip -o -selection clipboard",
+            "xclip -o -selection primary",
+        ],
+    },
+    Tool {
+        program: "xsel",
+        display: Some("DISPLAY"),
+        copy: ["xsel --clipboard --input", "xsel --primary --input"],
+        paste: ["xsel --clipboard --output", "xsel --primary --output"],
+    },
+];
+
+// `clipboard`: how `"+` and `"*` reach the system clipboard.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    // A tool, or OSC 52 when there is none.
+    Auto,
+    // Only a tool.
+    Tool,
+    // Only OSC 52, even with a tool.
+    Osc52,
+}
+
+impl Provider {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "tool" => Ok(Self::Tool),
+            "osc52" => Ok(Self::Osc52),
+            _ => Err(format!("Invalid argument: clipboard={value}")),
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Auto => "auto",
+            Self::Tool => "tool",
+            Self::Osc52 => "osc52",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl Selection {
+    // The selection register `name` stands for, if it is `+` or `*`.
+    pub fn of_register(name: Option<char>) -> Option<Self> {
+        match name {
+            Some('+') => Some(Self::Clipboard),
+            Some('*') => Some(Self::Primary),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Clipboard {
+    // The tool's name, and its copy and paste commands for each selection.
+    tool: Option<(String, [String; 2], [String; 2])>,
+}
+
+impl Clipboard {
+    // The first tool that is installed and has a display to work with.
+    pub fn detect() -> Self {
+        let tool = TOOLS.iter().find(|tool| {
+            tool.display
+                .is_none_or(|display| env::var_os(display).is_some_and(|value| !value.is_empty()))
+                && health::find_executable(tool.program).is_some()
+        });
+        Self {
+            tool: tool.map(|tool| {
+                (
+                    tool.program.to_string(),
+                    tool.copy.map(String::from),
+                    tool.paste.map(String::from),
+                )
+            }),
+        }
+    }
+    // The name of the tool found, if any.
+    pub fn tool(&self) -> Option<&str> {
+        self.tool.as_ref().map(|(name, _, _)| name.as_str())
+    }
+    pub fn copy(&self, selection: Selection, text: &str) -> Result<(), String> {
+        let Some((_, copy, _)) = &self.tool else {
+            return Err(String::from("No clipboard tool found"));
+        };
+        let command = &copy[selection as usize];
+        // the tool may stay around to serve the selection, so nothing waits
+        // on its output
+        let mut child = formatter::shell(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| format!("{command}: {error}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|error| format!("{command}: {error}"))?;
+        }
+        let status = formatter::wait(&mut child, TIMEOUT)?;
+        if !status.success() {
+            return Err(format!("{command}: {status}"));
+        }
+        Ok(())
+    }
+    pub fn paste(&self, selection: Selection) -> Result<String, String> {
+        let Some((_, _, paste)) = &self.tool else {
+            return Err(String::from("No clipboard tool found"));
+        };
+        let command = &paste[selection as usize];
+        formatter::run(command, "", TIMEOUT).map_err(|error| format!("{command}: {error}"))
+    }
+}
+
+// The OSC 52 sequence that has the terminal put `text` on `selection`.
+pub fn osc52(selection: Selection, text: &str) -> String {
+    let target = match selection {
+        Selection::Clipboard => 'c',
+        Selection::Primary => 'p',
+    };
+    format!("\u{1b}]52;{target};{}\u{7}", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3).saturating_mul(4));
+    for chunk in bytes.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|index| chunk.get(index).copied().unwrap_or(0));
+        let group = u32::from(a) << 16 | u32::from(b) << 8 | u32::from(c);
+        for (index, shift) in [18, 12, 6, 0].into_iter().enumerate() {
+            if index <= chunk.len() {
+                let sextet = (group >> shift) & 0x3f;
+                encoded.push(char::from(
+                    ALPHABET.get(sextet as usize).copied().unwrap_or(b'='),
+                ));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Copies with `copy` and pastes with `paste`, shell commands run for
+// either selection, so tests can stand in for the system clipboard.
+#[cfg(test)]
+impl Clipboard {
+    pub fn with_commands(copy: &str, paste: &str) -> Self {
+        Self {
+            tool: Some((
+                String::from("test"),
+                [copy, copy].map(String::from),
+                [paste, paste].map(String::from),
+            )),
+        }
+    }
+}
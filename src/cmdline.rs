@@ -0,0 +1,83 @@
+// The line typed at a prompt, such as `:` or `/`, with a cursor that can be
+// moved through it so text can be added or taken away anywhere.
+
+use crate::editor::is_word_char;
+
+#[derive(Default)]
+pub struct CommandLine {
+    text: Vec<char>,
+    // Where typed text goes, as an index into `text`.
+    cursor: usize,
+}
+
+impl CommandLine {
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+    // Replaces the whole line, with the cursor after it.
+    pub fn set(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+    pub fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.text.insert(self.cursor, c);
+            self.cursor = self.cursor.saturating_add(1);
+        }
+    }
+    // Backspace: the character before the cursor.
+    pub fn delete_before(&mut self) {
+        if self.cursor > 0 {
+            self.cursor = self.cursor.saturating_sub(1);
+            self.text.remove(self.cursor);
+        }
+    }
+    // Delete: the character under the cursor.
+    pub fn delete_under(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+    // Ctrl-W: the spaces before the cursor and the word before them, or the
+    // run of other characters if that is what comes first.
+    pub fn delete_word_before(&mut self) {
+        let before = self.text.get(..self.cursor).unwrap_or_default();
+        let spaces = before
+            .iter()
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        let before = before
+            .get(..before.len().saturating_sub(spaces))
+            .unwrap_or_default();
+        let is_word = before.last().is_some_and(|&c| is_word_char(c));
+        let word = before
+            .iter()
+            .rev()
+            .take_while(|&&c| !c.is_whitespace() && is_word_char(c) == is_word)
+            .count();
+        let start = self.cursor.saturating_sub(spaces.saturating_add(word));
+        self.text.drain(start..self.cursor);
+        self.cursor = start;
+    }
+    // Ctrl-U: everything before the cursor.
+    pub fn delete_to_start(&mut self) {
+        self.text.drain(..self.cursor);
+        self.cursor = 0;
+    }
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+    pub fn right(&mut self) {
+        self.cursor = self.cursor.saturating_add(1).min(self.text.len());
+    }
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+}
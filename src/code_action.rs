@@ -0,0 +1,50 @@
+// Code actions, such as adding a missing import, for the line or selection.
+// `gra` sends a `CodeAction` notification to the clients of the RPC
+// server; one that asks the language server hands the actions back with
+// `code_actions`, which lists them in the popup menu and applies the edits
+// of the one picked.
+
+use crate::Position;
+
+pub struct CodeAction {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+// Replaces the text from `start` up to `end` with `text`. Columns count
+// characters.
+pub struct TextEdit {
+    // None for the current buffer.
+    pub file: Option<String>,
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+}
+
+impl TextEdit {
+    // The lines to put in place of `first` up to `last`, the lines the edit
+    // starts and ends on.
+    pub fn replace(&self, first: &str, last: &str) -> Vec<String> {
+        let before: String = first.chars().take(self.start.x).collect();
+        let after: String = last.chars().skip(self.end.x).collect();
+        format!("{before}{}{after}", self.text)
+            .split('\n')
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// Applies `edits` to `lines`, which start at line `first` of the text: the
+// last edit first, so the places of the others stay the same.
+pub fn apply(lines: &mut Vec<String>, first: usize, edits: &[&TextEdit]) {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| (edit.start.y, edit.start.x));
+    for edit in edits.into_iter().rev() {
+        let start = edit.start.y.saturating_sub(first).min(lines.len());
+        let end = edit.end.y.saturating_sub(first).max(start);
+        let first_line = lines.get(start).cloned().unwrap_or_default();
+        let last_line = lines.get(end).cloned().unwrap_or_default();
+        let end = end.saturating_add(1).min(lines.len());
+        lines.splice(start..end, edit.replace(&first_line, &last_line));
+    }
+}
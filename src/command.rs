@@ -0,0 +1,522 @@
+use crate::editor::Mode;
+use crate::keymap;
+use std::fmt;
+use std::mem;
+
+pub enum Command {
+    Goto,
+    Write(Option<String>),
+    // `:q`, or `:qa` for every buffer; `:confirm` asks what to do about
+    // unsaved changes whatever the `confirm` option says.
+    Quit {
+        force: bool,
+        confirm: bool,
+    },
+    WriteQuit,
+    // `:wa`, or `:wqa` and `:xa` to quit after: writes every modified buffer.
+    WriteAll {
+        quit: bool,
+    },
+    Edit(Option<String>),
+    // `:ls`.
+    Buffers,
+    Args,
+    ArgAdd(Vec<String>),
+    ArgDelete(Vec<String>),
+    ArgDo(String),
+    Set(Vec<String>),
+    // `:ftset {filetype} {option}...`: options for files of one type.
+    FileTypeSet {
+        file_type: String,
+        assignments: Vec<String>,
+    },
+    Lua(String),
+    Define {
+        name: String,
+        definition: UserCommand,
+        force: bool,
+    },
+    ListUser,
+    DeleteUser(String),
+    User {
+        name: String,
+        argument: Option<String>,
+    },
+    Map {
+        modes: Vec<Mode>,
+        lhs: String,
+        rhs: String,
+        description: Option<String>,
+    },
+    ListMaps {
+        modes: Vec<Mode>,
+        prefix: String,
+    },
+    Unmap {
+        modes: Vec<Mode>,
+        lhs: String,
+    },
+    Highlight(Vec<String>),
+    Source(String),
+    CheckHealth,
+    Messages,
+    Outline,
+    // `:YankHistory`: the last yanks and deletes, to pick one to put.
+    YankHistory,
+    // `:DebugStart {program} [args]`, `:DebugStop`, `:DebugBreakpoint`,
+    // `:DebugContinue`, `:DebugStep` and `:DebugVariables`.
+    DebugStart(String),
+    DebugStop,
+    DebugBreakpoint,
+    DebugContinue,
+    DebugStep,
+    DebugVariables,
+    // `:Files [dir]`.
+    Files(Option<String>),
+    OldFiles,
+    // `:Recent`: the recently edited files, to pick one from.
+    Recent,
+    // `:undolist`: the ends of the branches of the undo tree.
+    UndoList,
+    // `:registers [names]` (or `:display`).
+    Registers(String),
+    // `:BookmarkAdd [note]`, `:BookmarkDelete` and `:Bookmarks` to pick one.
+    BookmarkAdd(String),
+    BookmarkDelete,
+    Bookmarks,
+    Preview,
+    // `:[range]Align {delimiter}`.
+    Align(String),
+    // `:Rename {name}`, from the current file's directory.
+    Rename(String),
+    // `:Delete`, or `:Delete!` without asking first.
+    Delete {
+        force: bool,
+    },
+    // `:Mkdir [dir]`, or the current file's directory without one.
+    Mkdir(Option<String>),
+    // `:cd [dir]`, or `:lcd [dir]` for this window only; home without one.
+    ChangeDir {
+        dir: Option<String>,
+        local: bool,
+    },
+    Pwd,
+    // `:[range]t {address}` or `:copy`: copies of the lines go after the
+    // line addressed, `0` for above the first.
+    Copy(Address),
+    // `:[range]m {address}`: the lines themselves go there.
+    Move(Address),
+    // `:[range]norm {keys}`: the keys typed in normal mode on each line.
+    Normal(String),
+}
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl ParseError {
+    fn unknown(input: &str) -> Self {
+        Self(format!("Not an editor command: {input}"))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Address {
+    Current,
+    Last,
+    Line(usize),
+}
+
+// A line range as typed before a command, e.g. `%`, `.,$` or `3,7`.
+// Lines are 1-based like in the status bar.
+#[derive(Clone, Copy)]
+pub struct Range {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl Range {
+    fn parse(input: &str) -> (Option<Self>, &str) {
+        if let Some(rest) = input.strip_prefix('%') {
+            let range = Self {
+                start: Address::Line(1),
+                end: Address::Last,
+            };
+            return (Some(range), rest);
+        }
+        let Some((start, rest)) = parse_address(input) else {
+            return (None, input);
+        };
+        if let Some((end, rest)) = rest.strip_prefix(',').and_then(parse_address) {
+            return (Some(Self { start, end }), rest);
+        }
+        (Some(Self { start, end: start }), rest)
+    }
+    pub fn resolve(&self, current: usize, last: usize) -> (usize, usize) {
+        let resolve = |address| match address {
+            Address::Current => current,
+            Address::Last => last,
+            Address::Line(line) => line,
+        };
+        let last = last.max(1);
+        let start = resolve(self.start).clamp(1, last);
+        let end = resolve(self.end).clamp(1, last);
+        (start.min(end), start.max(end))
+    }
+}
+
+fn parse_address(input: &str) -> Option<(Address, &str)> {
+    if let Some(rest) = input.strip_prefix('.') {
+        return Some((Address::Current, rest));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Some((Address::Last, rest));
+    }
+    let rest = input.trim_start_matches(|c: char| c.is_ascii_digit());
+    let line = input.get(..input.len().saturating_sub(rest.len()))?;
+    Some((Address::Line(line.parse().ok()?), rest))
+}
+
+// The address after `:t` or `:m`, where the lines go.
+fn parse_destination(argument: Option<&str>) -> Result<Address, ParseError> {
+    match argument.map(str::trim) {
+        None | Some("") => Err(ParseError("Argument required".to_string())),
+        Some(address) => match parse_address(address) {
+            Some((address, "")) => Ok(address),
+            _ => Err(ParseError(format!("Invalid address: {address}"))),
+        },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Nargs {
+    Zero,
+    One,
+    Any,
+    Optional,
+    AtLeastOne,
+}
+
+#[derive(Clone)]
+pub struct UserCommand {
+    pub nargs: Nargs,
+    pub range: bool,
+    pub replacement: String,
+}
+
+impl UserCommand {
+    // Fills in `<args>`, `<q-args>`, `<line1>` and `<line2>` in the replacement.
+    pub fn expand(
+        &self,
+        argument: Option<&str>,
+        line_range: Option<(usize, usize)>,
+    ) -> Result<String, ParseError> {
+        let argument = argument.unwrap_or_default();
+        match self.nargs {
+            Nargs::Zero if !argument.is_empty() => {
+                return Err(ParseError("Trailing characters".to_string()));
+            }
+            Nargs::One | Nargs::AtLeastOne if argument.is_empty() => {
+                return Err(ParseError("Argument required".to_string()));
+            }
+            _ => (),
+        }
+        if line_range.is_some() && !self.range {
+            return Err(ParseError("No range allowed".to_string()));
+        }
+        let (first, last) = line_range.unwrap_or((0, 0));
+        Ok(self
+            .replacement
+            .replace("<args>", argument)
+            .replace("<q-args>", &format!("{argument:?}"))
+            .replace("<line1>", &first.to_string())
+            .replace("<line2>", &last.to_string()))
+    }
+}
+
+impl fmt::Display for UserCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nargs = match self.nargs {
+            Nargs::Zero => "0",
+            Nargs::One => "1",
+            Nargs::Any => "*",
+            Nargs::Optional => "?",
+            Nargs::AtLeastOne => "+",
+        };
+        let range = if self.range { " -range" } else { "" };
+        write!(f, "-nargs={nargs}{range} {}", self.replacement)
+    }
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<(Option<Range>, Self), ParseError> {
+        let (range, input) = Range::parse(input.trim());
+        let input = input.trim();
+        if input.is_empty() {
+            return match range {
+                Some(_) => Ok((range, Self::Goto)),
+                None => Err(ParseError::unknown(input)),
+            };
+        }
+        // `:t.` and `:m$` need no space before the address
+        let address = ["t", "m"].into_iter().find_map(|name| {
+            input
+                .strip_prefix(name)
+                .filter(|rest| {
+                    rest.starts_with(|c: char| c == '.' || c == '$' || c.is_ascii_digit())
+                })
+                .map(|address| (name, address))
+        });
+        let (name, argument) = match (address, input.split_once(char::is_whitespace)) {
+            (Some((name, address)), _) => (name, Some(address.to_string())),
+            (None, Some((name, argument))) => (name, Some(argument.trim().to_string())),
+            (None, None) => (input, None),
+        };
+        let command = match name {
+            "w" | "write" => Self::Write(argument),
+            "q" | "quit" | "qa" | "qall" | "quita" | "quitall" => Self::Quit {
+                force: false,
+                confirm: false,
+            },
+            "q!" | "quit!" | "qa!" | "qall!" | "quita!" | "quitall!" | "!" => Self::Quit {
+                force: true,
+                confirm: false,
+            },
+            "conf" | "confirm" => match Self::parse(argument.as_deref().unwrap_or_default())? {
+                (None, Self::Quit { force, .. }) => Self::Quit {
+                    force,
+                    confirm: true,
+                },
+                _ => return Err(ParseError("Only quitting can be confirmed".to_string())),
+            },
+            "wq" | "x" | "exit" => Self::WriteQuit,
+            "wa" | "wall" => Self::WriteAll { quit: false },
+            "wqa" | "wqall" | "xa" | "xall" => Self::WriteAll { quit: true },
+            "e" | "edit" => Self::Edit(argument),
+            "ls" | "buffers" => Self::Buffers,
+            "ar" | "args" => Self::Args,
+            "arga" | "argadd" => Self::ArgAdd(split_arguments(argument.as_deref())),
+            "argd" | "argdelete" => Self::ArgDelete(split_arguments(argument.as_deref())),
+            "argdo" => match argument {
+                Some(command) if !command.is_empty() => Self::ArgDo(command),
+                _ => return Err(ParseError::unknown(input)),
+            },
+            "se" | "set" => Self::Set(split_options(argument.as_deref())),
+            "ftset" => {
+                let mut arguments = split_options(argument.as_deref()).into_iter();
+                match (arguments.next(), arguments.as_slice()) {
+                    (Some(file_type), [_, ..]) => Self::FileTypeSet {
+                        file_type,
+                        assignments: arguments.collect(),
+                    },
+                    _ => return Err(ParseError("Argument required".to_string())),
+                }
+            }
+            "lua" => Self::Lua(argument.unwrap_or_default()),
+            "so" | "source" => match argument {
+                Some(file_name) if !file_name.is_empty() => Self::Source(file_name),
+                _ => return Err(ParseError("Argument required".to_string())),
+            },
+            "checkh" | "checkhealth" => Self::CheckHealth,
+            "mes" | "messages" => Self::Messages,
+            "Outline" => Self::Outline,
+            "YankHistory" => Self::YankHistory,
+            "DebugStart" => Self::DebugStart(argument.unwrap_or_default()),
+            "DebugStop" => Self::DebugStop,
+            "DebugBreakpoint" => Self::DebugBreakpoint,
+            "DebugContinue" => Self::DebugContinue,
+            "DebugStep" => Self::DebugStep,
+            "DebugVariables" => Self::DebugVariables,
+            "Files" => Self::Files(argument.filter(|dir| !dir.is_empty())),
+            "ol" | "oldfiles" => Self::OldFiles,
+            "Recent" => Self::Recent,
+            "undol" | "undolist" => Self::UndoList,
+            "reg" | "registers" | "di" | "display" => Self::Registers(argument.unwrap_or_default()),
+            "BookmarkAdd" => Self::BookmarkAdd(argument.unwrap_or_default()),
+            "BookmarkDelete" => Self::BookmarkDelete,
+            "Bookmarks" => Self::Bookmarks,
+            "Preview" => Self::Preview,
+            "Rename" => match argument {
+                Some(name) if !name.is_empty() => Self::Rename(name),
+                _ => return Err(ParseError("Argument required".to_string())),
+            },
+            "Delete" => Self::Delete { force: false },
+            "Delete!" => Self::Delete { force: true },
+            "Mkdir" => Self::Mkdir(argument.filter(|dir| !dir.is_empty())),
+            "cd" | "chd" | "chdir" | "lcd" | "lch" | "lchdir" => Self::ChangeDir {
+                dir: argument.filter(|dir| !dir.is_empty()),
+                local: name.starts_with('l'),
+            },
+            "pw" | "pwd" => Self::Pwd,
+            "t" | "co" | "copy" => Self::Copy(parse_destination(argument.as_deref())?),
+            "m" | "mo" | "move" => Self::Move(parse_destination(argument.as_deref())?),
+            "norm" | "normal" => match argument {
+                Some(keys) if !keys.is_empty() => Self::Normal(keys),
+                _ => return Err(ParseError("Argument required".to_string())),
+            },
+            "Align" => match argument {
+                Some(delimiter) if !delimiter.is_empty() => Self::Align(delimiter),
+                _ => return Err(ParseError("Argument required".to_string())),
+            },
+            "hi" | "highlight" => Self::Highlight(split_arguments(argument.as_deref())),
+            "com" | "command" | "com!" | "command!" => match argument {
+                Some(definition) => parse_definition(&definition, name.ends_with('!'))?,
+                None => Self::ListUser,
+            },
+            "delc" | "delcommand" => match argument {
+                Some(name) => Self::DeleteUser(name),
+                None => return Err(ParseError("Argument required".to_string())),
+            },
+            "map" | "no" | "noremap" => parse_mapping("nv", argument.as_deref())?,
+            "nm" | "nmap" | "nn" | "nnoremap" => parse_mapping("n", argument.as_deref())?,
+            "im" | "imap" | "ino" | "inoremap" => parse_mapping("i", argument.as_deref())?,
+            "vm" | "vmap" | "vn" | "vnoremap" => parse_mapping("v", argument.as_deref())?,
+            "unm" | "unmap" => parse_unmap("nv", argument)?,
+            "nun" | "nunmap" => parse_unmap("n", argument)?,
+            "iu" | "iunmap" => parse_unmap("i", argument)?,
+            "vu" | "vunmap" => parse_unmap("v", argument)?,
+            _ if is_user_command_name(name) => Self::User {
+                name: name.to_string(),
+                argument,
+            },
+            _ => return Err(ParseError::unknown(input)),
+        };
+        Ok((range, command))
+    }
+    pub fn accepts_range(&self) -> bool {
+        matches!(
+            self,
+            Self::Goto
+                | Self::Align(_)
+                | Self::Copy(_)
+                | Self::Move(_)
+                | Self::Normal(_)
+                | Self::User { .. }
+        )
+    }
+}
+
+// Parses the part of `:command` after the command name:
+// `[-nargs={0,1,*,?,+}] [-range] {Name} {replacement}`.
+fn parse_definition(definition: &str, force: bool) -> Result<Command, ParseError> {
+    let mut nargs = Nargs::Zero;
+    let mut range = false;
+    let mut rest = definition.trim_start();
+    while let Some(attribute) = rest.strip_prefix('-') {
+        let (attribute, remaining) = attribute
+            .split_once(char::is_whitespace)
+            .unwrap_or((attribute, ""));
+        match attribute {
+            "range" => range = true,
+            "nargs=0" => nargs = Nargs::Zero,
+            "nargs=1" => nargs = Nargs::One,
+            "nargs=*" => nargs = Nargs::Any,
+            "nargs=?" => nargs = Nargs::Optional,
+            "nargs=+" => nargs = Nargs::AtLeastOne,
+            _ => return Err(ParseError(format!("Invalid attribute: -{attribute}"))),
+        }
+        rest = remaining.trim_start();
+    }
+    let (name, replacement) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if !is_user_command_name(name) {
+        return Err(ParseError(
+            "User defined commands must start with an uppercase letter".to_string(),
+        ));
+    }
+    if replacement.trim().is_empty() {
+        return Err(ParseError("Replacement required".to_string()));
+    }
+    Ok(Command::Define {
+        name: name.to_string(),
+        definition: UserCommand {
+            nargs,
+            range,
+            replacement: replacement.trim().to_string(),
+        },
+        force,
+    })
+}
+
+// Parses `[-desc={description}] {lhs} {rhs}`. A description with spaces
+// goes in double quotes. Without a right-hand side, lists the mappings that
+// start with `lhs`.
+fn parse_mapping(modes: &str, argument: Option<&str>) -> Result<Command, ParseError> {
+    let modes = keymap::parse_modes(modes);
+    let mut rest = argument.unwrap_or_default().trim_start();
+    let mut description = None;
+    if let Some(desc) = rest.strip_prefix("-desc=") {
+        let (text, remaining) = match desc.strip_prefix('"') {
+            Some(quoted) => quoted
+                .split_once('"')
+                .ok_or_else(|| ParseError("Missing quote in -desc".to_string()))?,
+            None => desc.split_once(char::is_whitespace).unwrap_or((desc, "")),
+        };
+        description = Some(text.to_string());
+        rest = remaining.trim_start();
+    }
+    match rest.split_once(char::is_whitespace) {
+        Some((lhs, rhs)) if !rhs.trim().is_empty() => Ok(Command::Map {
+            modes,
+            lhs: lhs.to_string(),
+            rhs: rhs.trim().to_string(),
+            description,
+        }),
+        _ => Ok(Command::ListMaps {
+            modes,
+            prefix: rest.trim().to_string(),
+        }),
+    }
+}
+
+fn parse_unmap(modes: &str, argument: Option<String>) -> Result<Command, ParseError> {
+    match argument {
+        Some(lhs) if !lhs.is_empty() => Ok(Command::Unmap {
+            modes: keymap::parse_modes(modes),
+            lhs,
+        }),
+        _ => Err(ParseError("Argument required".to_string())),
+    }
+}
+
+fn is_user_command_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+// Like `split_arguments`, but `\ ` keeps a blank inside a value, as in
+// `:set formatters=sort\ -u`.
+fn split_options(argument: Option<&str>) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut option = String::new();
+    let mut chars = argument.unwrap_or_default().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(|next| next.is_whitespace()) => {
+                option.extend(chars.next());
+            }
+            _ if c.is_whitespace() => {
+                if !option.is_empty() {
+                    options.push(mem::take(&mut option));
+                }
+            }
+            _ => option.push(c),
+        }
+    }
+    if !option.is_empty() {
+        options.push(option);
+    }
+    options
+}
+
+fn split_arguments(argument: Option<&str>) -> Vec<String> {
+    argument
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,56 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// `$XDG_CONFIG_HOME/rvim`, falling back to `~/.config/rvim`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_home.join("rvim"))
+}
+
+// `$XDG_STATE_HOME/rvim`, falling back to `~/.local/state/rvim`: what the
+// editor keeps between runs, such as the files edited recently.
+pub fn state_dir() -> Option<PathBuf> {
+    let state_home = env::var("XDG_STATE_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".local/state"))
+        })?;
+    Some(state_home.join("rvim"))
+}
+
+// `file_name` from the root, so it names the same file from any directory.
+pub fn full_path(file_name: &str) -> String {
+    let path = Path::new(file_name);
+    let path = fs::canonicalize(path)
+        .or_else(|_| env::current_dir().map(|cwd| cwd.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf());
+    path.display().to_string()
+}
+
+// `file_name`, relative to `from`, as named from `to`: relative to it when
+// the file is under it, and from the root otherwise.
+pub fn move_path(file_name: &str, from: &Path, to: &Path) -> String {
+    let path = from.join(file_name);
+    path.strip_prefix(to).unwrap_or(&path).display().to_string()
+}
+
+// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
@@ -0,0 +1,32 @@
+// What happens when the editor panics: the terminal is put back the way it
+// was so the message can be read, and the buffer, if it has unsaved
+// changes, is written next to its file as `{file}.rvim-crash`.
+
+use crossterm::terminal::disable_raw_mode;
+use std::io::{stdout, Write};
+use std::panic;
+use std::thread;
+
+// Where a buffer without a file name is written.
+const UNNAMED: &str = "untitled";
+
+pub fn install_hook() {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // a worker thread going down leaves the editor running
+        if thread::current().name() != Some("main") {
+            report(info);
+            return;
+        }
+        let _ = disable_raw_mode();
+        // clears the screen and shows the cursor again
+        let mut stdout = stdout();
+        let _ = stdout.write_all(b"\x1b[2J\x1b[H\x1b[?25h");
+        let _ = stdout.flush();
+        report(info);
+    }));
+}
+
+pub fn recovery_path(file_name: Option<&str>) -> String {
+    format!("{}.rvim-crash", file_name.unwrap_or(UNNAMED))
+}
@@ -0,0 +1,149 @@
+use crate::event::{Event, EventSender};
+use crate::formatter;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Tells apart the messages of an adapter from those of one before it.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// A connection to a debug adapter, spoken to in the Debug Adapter
+// Protocol: JSON messages, each after a `Content-Length` header. What the
+// adapter sends is read on a thread of its own and posted as
+// `Event::Debug`, so it is handled on the main thread between keypresses.
+pub struct Adapter {
+    id: usize,
+    writer: Box<dyn Write + Send>,
+    child: Option<Child>,
+    seq: u64,
+    // The command of each request still waiting for its response, by seq.
+    pending: HashMap<u64, String>,
+}
+
+impl Adapter {
+    // Starts the adapter `command` with the shell and talks to it over its
+    // standard input and output.
+    pub fn spawn(command: &str, events: EventSender) -> io::Result<Self> {
+        let mut child = formatter::shell(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "no pipes"));
+        };
+        let mut adapter = Self::connect(stdout, stdin, events);
+        adapter.child = Some(child);
+        Ok(adapter)
+    }
+    pub fn connect<R: Read + Send + 'static, W: Write + Send + 'static>(
+        reader: R,
+        writer: W,
+        events: EventSender,
+    ) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                let message = Some(message);
+                if !events.send(Event::Debug {
+                    adapter: id,
+                    message,
+                }) {
+                    return;
+                }
+            }
+            events.send(Event::Debug {
+                adapter: id,
+                message: None,
+            });
+        });
+        Self {
+            id,
+            writer: Box::new(writer),
+            child: None,
+            seq: 0,
+            pending: HashMap::new(),
+        }
+    }
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub fn request(&mut self, command: &str, arguments: &Value) -> io::Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        self.pending.insert(self.seq, command.to_string());
+        self.send(&json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }))
+    }
+    // Turns down a request the adapter made, such as `runInTerminal`.
+    pub fn refuse(&mut self, request: &Value) -> io::Result<()> {
+        self.seq = self.seq.wrapping_add(1);
+        self.send(&json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": "not supported",
+        }))
+    }
+    // The command of the request `response` answers.
+    pub fn answered(&mut self, response: &Value) -> Option<String> {
+        let seq = response["request_seq"].as_u64()?;
+        self.pending.remove(&seq)
+    }
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        write_message(&mut self.writer, message)
+    }
+}
+
+impl Drop for Adapter {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// The next message, or None once the adapter has closed its end.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
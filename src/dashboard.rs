@@ -0,0 +1,73 @@
+// The start screen shown with `set dashboard` while the editor has no file
+// and nothing has been typed: the version, shortcuts to get going, the
+// files edited recently and where to find help. `j` and `k` pick a
+// shortcut or file and Enter runs it.
+
+use crate::keymap::Action;
+
+// How many recent files are listed, one for each of the keys 1-9.
+pub const FILES: usize = 9;
+
+// What each shortcut shows and the mapping right-hand side it runs.
+const SHORTCUTS: [(&str, &str, &str); 5] = [
+    ("i", "Start typing", "<insert>"),
+    (":Files", "Find a file", ":Files"),
+    (":Recent", "Pick a recent file", ":Recent"),
+    (":nmap", "List the key bindings", ":nmap"),
+    (":q", "Quit", ":q"),
+];
+
+pub struct Item {
+    pub key: String,
+    label: String,
+    pub action: Action,
+    is_file: bool,
+}
+
+// The shortcuts, then the first recent files, in the order they are listed.
+pub fn items(files: &[String]) -> Vec<Item> {
+    let home = std::env::var("HOME").ok().filter(|home| !home.is_empty());
+    let shortcuts = SHORTCUTS.iter().filter_map(|&(key, label, rhs)| {
+        Some(Item {
+            key: key.to_string(),
+            label: label.to_string(),
+            action: Action::parse(rhs).ok()?,
+            is_file: false,
+        })
+    });
+    let files = files.iter().take(FILES).enumerate().map(|(index, file)| {
+        let number = index.saturating_add(1);
+        Item {
+            key: number.to_string(),
+            label: match home.as_deref().and_then(|home| file.strip_prefix(home)) {
+                Some(rest) if rest.starts_with('/') => format!("~{rest}"),
+                _ => file.clone(),
+            },
+            action: Action::Command(format!("e #<{number}")),
+            is_file: true,
+        }
+    });
+    shortcuts.chain(files).collect()
+}
+
+// The screen's lines, with `>` in front of the `selected` item. `more` is
+// whether there are more recent files than are listed.
+pub fn lines(version: &str, items: &[Item], selected: usize, more: bool) -> Vec<String> {
+    let mut lines = vec![format!("rvim -- version {version}"), String::new()];
+    let mut files = false;
+    for (index, item) in items.iter().enumerate() {
+        if item.is_file && !files {
+            lines.push(String::new());
+            lines.push(String::from("Recent files"));
+            files = true;
+        }
+        let marker = if index == selected { '>' } else { ' ' };
+        lines.push(format!("{marker} {:<8} {}", item.key, item.label));
+    }
+    if more {
+        lines.push(String::from("  :Recent for more"));
+    }
+    lines.push(String::new());
+    lines.push(String::from("See man rvim for help"));
+    lines
+}
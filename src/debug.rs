@@ -0,0 +1,255 @@
+// What the editor keeps for debugging: breakpoints, shown in the gutter,
+// and a session with a debug adapter, started by `:DebugStart`, with the
+// line the program stopped on and the variables there.
+//
+// Lines count from 0 here and from 1 in the protocol, and files are full
+// paths.
+
+use crate::dap::Adapter;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+// Shown in the gutter of a line with a breakpoint, and of the line the
+// program stopped on.
+pub const BREAKPOINT_SIGN: char = 'B';
+pub const STOPPED_SIGN: char = '>';
+
+// What the editor has to do after a message from the adapter.
+pub enum Update {
+    None,
+    // The program stopped on `line` of `file`, to be opened there.
+    Stopped(String, usize),
+    // The session is over, and why.
+    Ended(String),
+    Error(String),
+}
+
+struct Session {
+    adapter: Adapter,
+    // The arguments of `launch`, sent once the adapter is initialized.
+    launch: Value,
+    // The thread that stopped last, which `continue` and `next` run on.
+    thread: i64,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<String, BTreeSet<usize>>,
+    session: Option<Session>,
+    stopped: Option<(String, usize)>,
+    // `name = value` for each variable where the program stopped.
+    variables: Vec<String>,
+}
+
+impl Debugger {
+    // Sets a breakpoint on `line` of `file`, or takes away the one there,
+    // telling a running adapter. Returns whether there is one now.
+    pub fn toggle(&mut self, file: &str, line: usize) -> io::Result<bool> {
+        let lines = self.breakpoints.entry(file.to_string()).or_default();
+        let set = !lines.remove(&line);
+        if set {
+            lines.insert(line);
+        } else if lines.is_empty() {
+            self.breakpoints.remove(file);
+        }
+        if self.session.is_some() {
+            self.send_breakpoints(file)?;
+        }
+        Ok(set)
+    }
+    // The lines of `file` with a breakpoint.
+    pub fn breakpoints_in(&self, file: &str) -> BTreeSet<usize> {
+        self.breakpoints.get(file).cloned().unwrap_or_default()
+    }
+    // Starts debugging `program` with `args` in `cwd` through `adapter`.
+    pub fn start(
+        &mut self,
+        adapter: Adapter,
+        program: &str,
+        args: &[&str],
+        cwd: &str,
+    ) -> io::Result<()> {
+        self.end();
+        let mut session = Session {
+            adapter,
+            launch: json!({ "program": program, "args": args, "cwd": cwd }),
+            thread: 1,
+        };
+        session.adapter.request(
+            "initialize",
+            &json!({
+                "clientID": "rvim",
+                "clientName": "rvim",
+                "adapterID": "rvim",
+                "linesStartAt1": true,
+                "columnsStartAt1": true,
+                "pathFormat": "path",
+            }),
+        )?;
+        self.session = Some(session);
+        Ok(())
+    }
+    // `:DebugStop`: ends the program and the adapter with it.
+    pub fn end(&mut self) {
+        if let Some(session) = &mut self.session {
+            let _ = session
+                .adapter
+                .request("disconnect", &json!({ "terminateDebuggee": true }));
+        }
+        self.session = None;
+        self.stop(None);
+    }
+    pub fn is_running(&self) -> bool {
+        self.session.is_some()
+    }
+    // Runs the stopped program on with `command`: `continue` or `next`.
+    pub fn resume(&mut self, command: &str) -> io::Result<()> {
+        self.stop(None);
+        match &mut self.session {
+            Some(session) => session
+                .adapter
+                .request(command, &json!({ "threadId": session.thread })),
+            None => Ok(()),
+        }
+    }
+    // A message from `adapter`, or None once it has gone. Those of an
+    // adapter since replaced are dropped.
+    pub fn handle(&mut self, adapter: usize, message: Option<Value>) -> Update {
+        if self.session.as_ref().map(|session| session.adapter.id()) != Some(adapter) {
+            return Update::None;
+        }
+        let Some(message) = message else {
+            self.session = None;
+            self.stop(None);
+            return Update::Ended("The debug adapter exited".to_string());
+        };
+        let result = match message["type"].as_str() {
+            Some("response") => self.handle_response(&message),
+            Some("event") => self.handle_event(&message),
+            Some("request") => match &mut self.session {
+                Some(session) => session.adapter.refuse(&message).map(|()| Update::None),
+                None => Ok(Update::None),
+            },
+            _ => Ok(Update::None),
+        };
+        result.unwrap_or_else(|error| Update::Error(format!("Debug adapter: {error}")))
+    }
+    fn handle_response(&mut self, response: &Value) -> io::Result<Update> {
+        let Some(session) = &mut self.session else {
+            return Ok(Update::None);
+        };
+        let command = session.adapter.answered(response).unwrap_or_default();
+        if response["success"] != true {
+            let message = response["message"].as_str().unwrap_or("failed");
+            return Ok(Update::Error(format!("{command}: {message}")));
+        }
+        let body = &response["body"];
+        match command.as_str() {
+            "initialize" => {
+                let launch = session.launch.clone();
+                session.adapter.request("launch", &launch)?;
+            }
+            // the top frame is where the program stopped
+            "stackTrace" => {
+                let frame = &body["stackFrames"][0];
+                let (Some(path), Some(line)) =
+                    (frame["source"]["path"].as_str(), frame["line"].as_u64())
+                else {
+                    return Ok(Update::None);
+                };
+                session
+                    .adapter
+                    .request("scopes", &json!({ "frameId": frame["id"] }))?;
+                let line = usize::try_from(line).unwrap_or(0).saturating_sub(1);
+                self.stop(Some((path.to_string(), line)));
+                return Ok(Update::Stopped(path.to_string(), line));
+            }
+            "scopes" => {
+                for scope in body["scopes"].as_array().into_iter().flatten() {
+                    if scope["expensive"] != true {
+                        session.adapter.request(
+                            "variables",
+                            &json!({ "variablesReference": scope["variablesReference"] }),
+                        )?;
+                    }
+                }
+            }
+            "variables" => {
+                for variable in body["variables"].as_array().into_iter().flatten() {
+                    let name = variable["name"].as_str().unwrap_or_default();
+                    let value = variable["value"].as_str().unwrap_or_default();
+                    self.variables.push(format!("{name} = {value}"));
+                }
+            }
+            _ => (),
+        }
+        Ok(Update::None)
+    }
+    fn handle_event(&mut self, event: &Value) -> io::Result<Update> {
+        let body = &event["body"];
+        match event["event"].as_str() {
+            // ready for the breakpoints, then to run
+            Some("initialized") => {
+                let files: Vec<String> = self.breakpoints.keys().cloned().collect();
+                for file in files {
+                    self.send_breakpoints(&file)?;
+                }
+                if let Some(session) = &mut self.session {
+                    session.adapter.request("configurationDone", &json!({}))?;
+                }
+            }
+            Some("stopped") => {
+                if let Some(session) = &mut self.session {
+                    if let Some(thread) = body["threadId"].as_i64() {
+                        session.thread = thread;
+                    }
+                    session.adapter.request(
+                        "stackTrace",
+                        &json!({ "threadId": session.thread, "startFrame": 0, "levels": 1 }),
+                    )?;
+                }
+            }
+            Some("continued") => self.stop(None),
+            Some("terminated") => {
+                self.end();
+                return Ok(Update::Ended("The program exited".to_string()));
+            }
+            _ => (),
+        }
+        Ok(Update::None)
+    }
+    // Tells the adapter every breakpoint in `file`.
+    fn send_breakpoints(&mut self, file: &str) -> io::Result<()> {
+        let lines: Vec<Value> = self
+            .breakpoints_in(file)
+            .into_iter()
+            .map(|line| json!({ "line": line.saturating_add(1) }))
+            .collect();
+        match &mut self.session {
+            Some(session) => session.adapter.request(
+                "setBreakpoints",
+                &json!({ "source": { "path": file }, "breakpoints": lines }),
+            ),
+            None => Ok(()),
+        }
+    }
+    // The program stopped at `at`, or with None runs again.
+    fn stop(&mut self, at: Option<(String, usize)>) {
+        self.stopped = at;
+        self.variables.clear();
+    }
+    // The line of `file` the program stopped on, if it did there.
+    pub fn stopped_in(&self, file: &str) -> Option<usize> {
+        self.stopped
+            .as_ref()
+            .filter(|(stopped, _)| stopped == file)
+            .map(|(_, line)| *line)
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.is_some()
+    }
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+}
@@ -0,0 +1,123 @@
+use crate::theme::Group;
+use crate::Position;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "error" => Ok(Self::Error),
+            "warning" | "warn" => Ok(Self::Warning),
+            "info" => Ok(Self::Info),
+            "hint" => Ok(Self::Hint),
+            _ => Err(format!("Unknown severity: {name}")),
+        }
+    }
+    // What the sign column shows for it.
+    pub fn sign(self) -> char {
+        match self {
+            Self::Error => 'E',
+            Self::Warning => 'W',
+            Self::Info => 'I',
+            Self::Hint => 'H',
+        }
+    }
+    pub fn group(self) -> Group {
+        match self {
+            Self::Error => Group::DiagnosticError,
+            Self::Warning => Group::DiagnosticWarn,
+            Self::Info => Group::DiagnosticInfo,
+            Self::Hint => Group::DiagnosticHint,
+        }
+    }
+}
+
+// A problem a linter, compiler or script reported at a place in the buffer.
+pub struct Diagnostic {
+    pub position: Position,
+    pub severity: Severity,
+    pub message: String,
+}
+
+// The diagnostics for the current buffer, in buffer order. Whoever
+// reports them replaces the whole set each time.
+#[derive(Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn set(&mut self, mut items: Vec<Diagnostic>) {
+        items.sort_by_key(|item| (item.position.y, item.position.x, item.severity));
+        self.items = items;
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+    // The one shown for line `y`: the most severe, then the first.
+    pub fn on_line(&self, y: usize) -> Option<&Diagnostic> {
+        let start = self.items.partition_point(|item| item.position.y < y);
+        self.items
+            .get(start..)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|item| item.position.y == y)
+            .min_by_key(|item| item.severity)
+    }
+    // The first after `position`, or the last before it, wrapping around
+    // the buffer.
+    pub fn next(&self, position: &Position, forward: bool) -> Option<&Diagnostic> {
+        let at = |item: &Diagnostic| (item.position.y, item.position.x);
+        let here = (position.y, position.x);
+        if forward {
+            self.items
+                .iter()
+                .find(|item| at(item) > here)
+                .or_else(|| self.items.first())
+        } else {
+            self.items
+                .iter()
+                .rev()
+                .find(|item| at(item) < here)
+                .or_else(|| self.items.last())
+        }
+    }
+}
+
+// `diagnostics`: how they are shown, as text after the end of the line,
+// as a sign in place of the gutter bar, or not at all.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Display {
+    Inline,
+    Signs,
+    Off,
+}
+
+impl Display {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "inline" => Ok(Self::Inline),
+            "signs" => Ok(Self::Signs),
+            "off" => Ok(Self::Off),
+            _ => Err(format!("Invalid argument: diagnostics={value}")),
+        }
+    }
+}
+
+impl fmt::Display for Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Inline => "inline",
+            Self::Signs => "signs",
+            Self::Off => "off",
+        };
+        write!(f, "{name}")
+    }
+}
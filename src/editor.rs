@@ -1,43 +1,112 @@
+use crate::align;
+use crate::bookmarks::{self, Bookmarks};
+use crate::cli::Arguments;
+use crate::clipboard::{self, Clipboard, Provider, Selection};
+use crate::cmdline::CommandLine;
+use crate::code_action::{self, CodeAction, TextEdit};
+use crate::command::{Address, UserCommand};
+use crate::config;
+use crate::crash;
+use crate::dap::Adapter;
+use crate::dashboard;
+use crate::debug::{self, Debugger, Update};
+use crate::diagnostics::{self, Diagnostic, Diagnostics};
+use crate::error::{self, Error};
+use crate::event::{self, EventLoop, Timer};
+use crate::flash::Flash;
+use crate::format;
+use crate::formatter;
+use crate::git::{self, GitDiff};
+use crate::health;
+use crate::highlighter::Highlighter;
+use crate::history::{History, HistoryKind};
+use crate::keymap::{self, Action, Builtin, Keymaps, Lookup};
+use crate::logging::{self, Level};
+#[cfg(feature = "lua")]
+use crate::lua::Scripting;
+use crate::oldfiles::OldFiles;
+use crate::options::{Confirm, UndoGroup};
+use crate::pending::{Operator, Pending, Span};
+use crate::picker::Picker;
+use crate::popup::{self, Item, Popup};
+use crate::preview::{self, Preview};
+use crate::register::{self, Kind, Register, Registers};
+use crate::repeat::DotRepeat;
+#[cfg(unix)]
+use crate::rpc::{self, RpcServer};
+use crate::search::Searcher;
+use crate::semantic::{SemanticToken, SemanticTokens};
+use crate::shada::Shada;
+use crate::signature::{self, Signature};
+use crate::startup::StartupLog;
+use crate::statusline;
+use crate::tags;
+use crate::theme::{Group, Style, Theme};
+use crate::walk::{self, Walker};
+use crate::wrap::Layout;
+use crate::ArgList;
+use crate::Command;
 use crate::Document;
+use crate::Marks;
+use crate::Options;
+use crate::Pattern;
+use crate::Position;
 use crate::Row;
+use crate::SearchDirection;
+use crate::SearchFlags;
 use crate::Terminal;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use crossterm::style::Color;
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use rvim_core::motion::{self, CharSearch};
+use rvim_core::textobject::TextObject;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
-use std::fmt;
-use std::fs::File;
+use std::fmt::{self, Write as _};
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
-const STATUS_FG_COLOR: Color = Color::Rgb {
-    r: 63,
-    g: 63,
-    b: 63,
-};
-const STATUS_BG_COLOR: Color = Color::Rgb {
-    r: 239,
-    g: 239,
-    b: 239,
-};
-
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MAX_COMMAND_DEPTH: usize = 100;
+// The keys that label the other matches of `s{char}{char}`, nearest first.
+const SNEAK_LABELS: &str = "asdfghjklqwertyuiopzxcvbnm";
+// How many of the last keys of a command being typed `showcmd` shows.
+const SHOWCMD_WIDTH: usize = 10;
+// The most words insert-mode completion offers.
+const MAX_COMPLETIONS: usize = 100;
+// How long a message stays in the message bar.
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+// How long typing has to pause before the git diff counts are redone.
+const DIFF_DEBOUNCE: Duration = Duration::from_millis(300);
+// How many of the latest messages `:messages` shows.
+const MESSAGE_HISTORY: usize = 200;
 
-#[derive(PartialEq, Copy, Clone)]
-pub enum SearchDirection {
-    Forward,
-    Backward,
-}
-
-#[derive(Default, Clone, PartialEq)]
-pub struct Position {
-    pub x: usize,
-    pub y: usize,
+// The last `p` or `P`, or put from `:YankHistory`, which `<C-p>` right
+// after it swaps for the yank or delete before it in the history.
+struct LastPut {
+    before: bool,
+    count: usize,
+    indented: bool,
+    // Where the cursor was before the put.
+    cursor: Position,
+    // Where in the history what was put is, when it is there.
+    index: Option<usize>,
+    // The text as the put left it, which it still has to be in.
+    revision: u64,
 }
 
 struct StatusMessage {
     text: String,
     time: Instant,
+    is_error: bool,
+    // Prompts stay until they are answered.
+    expires: bool,
 }
 
 impl StatusMessage {
@@ -45,14 +114,92 @@ impl StatusMessage {
         Self {
             time: Instant::now(),
             text: message.into(),
+            is_error: false,
+            expires: true,
+        }
+    }
+    fn error<S: Into<String>>(message: S) -> Self {
+        Self {
+            is_error: true,
+            ..Self::from(message)
+        }
+    }
+    fn prompt<S: Into<String>>(message: S) -> Self {
+        Self {
+            expires: false,
+            ..Self::from(message)
         }
     }
+    // Errors stay until a key is pressed after them.
+    fn is_visible(&self) -> bool {
+        !self.expires || self.is_error || self.time.elapsed() < MESSAGE_TIMEOUT
+    }
+}
+
+// The text typed since insert mode was entered. Leaving insert mode types
+// it again until it is there `count` times, each on a new line of its own
+// after `o` and `O`, and after `c` on a block, on the block's other lines.
+#[derive(Default)]
+struct Insertion {
+    count: usize,
+    new_lines: bool,
+    text: String,
+    // The lines below the first of a changed block, and the screen column
+    // it started in.
+    block: Option<(Range<usize>, usize)>,
+}
+
+// Insert-mode completion of the word before the cursor, from `start` on.
+// `current` is what the word reads now, the typed part or a choice.
+struct Completion {
+    popup: Popup,
+    start: Position,
+    typed: String,
+    current: String,
+    // Where the cursor was left; moving it anywhere else ends completion.
+    cursor: Position,
+}
+
+// What takes the place of the gutter bar on a line.
+#[derive(Clone, Copy)]
+enum Sign<'a> {
+    None,
+    Diagnostic(&'a Diagnostic),
+    Bookmark,
+    Breakpoint,
+    // The line the program being debugged stopped on.
+    Stopped,
+}
+
+// The lines of the file with a sign other than a diagnostic's.
+struct Marked {
+    bookmarks: HashSet<usize>,
+    breakpoints: BTreeSet<usize>,
+    stopped: Option<usize>,
+}
+
+// A write running on a worker thread.
+struct Saving {
+    file_name: String,
+    percent: usize,
+    quit_after: bool,
+    // Why the buffer was written without formatting, if it was.
+    format_error: Option<String>,
+}
+
+// A buffer with unsaved changes left for another file with `hidden` set.
+struct HiddenBuffer {
+    document: Document,
+    cursor: Position,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Mode {
     Normal,
     Insert,
     Visual,
+    // After an operator, until what it acts on is typed.
+    OperatorPending,
 }
 
 impl fmt::Display for Mode {
@@ -61,6 +208,7 @@ impl fmt::Display for Mode {
             Self::Normal => write!(f, "normal mode"),
             Self::Insert => write!(f, "insert mode"),
             Self::Visual => write!(f, "visual mode"),
+            Self::OperatorPending => write!(f, "operator-pending mode"),
         }
     }
 }
@@ -68,739 +216,5152 @@ impl fmt::Display for Mode {
 pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
+    events: EventLoop,
     cursor_position: Position,
+    // The first row on screen, and the first screen column of each row
+    // shown when not wrapping.
     offset: Position,
     document: Document,
     status_message: StatusMessage,
-    highlighted_word: Option<String>,
+    // The lines of a message too long for the message line, drawn over
+    // the bottom of the screen until a key is pressed.
+    long_message: Option<Vec<String>>,
+    // The column of the cursor on the message line while a prompt reads
+    // what is typed.
+    prompt_cursor: Option<usize>,
+    // Every message shown, oldest first, for `:messages`.
+    messages: Vec<String>,
+    highlighted_word: Option<Pattern>,
+    highlighter: Highlighter,
+    last_search: Option<Pattern>,
+    // How `/` matches, toggled at its prompt and kept for the next search.
+    search_flags: SearchFlags,
+    searcher: Searcher,
+    // How many quit commands in a row there have been, and the document's
+    // revision at the last, for `confirm={n}`.
+    quit_presses: Option<(u64, usize)>,
+    // The cursor moves to the first match the running search finds.
+    search_jump: bool,
+    // The search whose match count the status bar shows while it is counted.
+    counting: Option<Pattern>,
     mode: Mode,
-    previous_characters: Vec<char>,
+    // Where the selection started, while in visual mode, and what it
+    // takes: characters after `v`, whole lines after `V` or a block after
+    // `Ctrl-V`.
+    visual_start: Position,
+    visual_kind: Kind,
+    insertion: Insertion,
+    completion: Option<Completion>,
+    // Open while `pick` reads the query for it.
+    picker: Option<Picker>,
+    diagnostics: Diagnostics,
+    debugger: Debugger,
+    // Shown while the arguments of its call are typed.
+    signature: Option<Signature>,
+    semantic_tokens: SemanticTokens,
+    // The text just yanked or put, lit up until `Timer::Flash`.
+    flash: Option<Flash>,
+    last_put: Option<LastPut>,
+    preview: Option<Preview>,
+    // Kept between `:Files` so only what changed is read again.
+    walker: Walker,
+    // Drawn over the other matches while `s{char}{char}` waits for a label.
+    sneak_labels: Vec<(Position, char)>,
+    // The normal mode command typed so far.
+    pending: Pending,
+    // When the latest key came, which `timeoutlen` counts from.
+    last_key: Instant,
+    // Where `gj` and `gk` last left the cursor and the screen column they
+    // keep to, so passing a short line does not lose it.
+    display_column: Option<(Position, usize)>,
+    // Where the cursor ended up after `j` or `k`, and the screen column they
+    // aim for; it no longer counts once the cursor moves any other way.
+    wanted_column: Option<(Position, usize)>,
+    // The last `f`, `t`, `F` or `T`, for `;` and `,`.
+    char_search: Option<CharSearch>,
+    // What was last yanked or deleted, and the named registers.
+    registers: Registers,
+    clipboard: Clipboard,
+    keymaps: Keymaps,
+    pending_keys: Vec<KeyEvent>,
+    dot_repeat: DotRepeat,
+    // Keys typed by `.`, `:normal` and `@` rather than the user, which
+    // whatever reads the next key takes first.
+    typeahead: VecDeque<Event>,
+    // While `:normal`, `@:` and `@` go over lines, their changes are undone
+    // together.
+    batching: bool,
+    // The register `q` is recording into and the keys typed so far.
+    recording: Option<(char, Vec<KeyEvent>)>,
+    // The registers `@` is running, innermost last, and the one it ran
+    // last, for `@@`.
+    running_macros: Vec<char>,
+    last_macro: Option<char>,
+    theme: Theme,
+    // Errors from the config files read at startup, for `:checkhealth`.
+    config_errors: Vec<String>,
+    git_diff: GitDiff,
+    last_edit: Instant,
+    marks: Marks,
+    alternate_file: Option<(String, Position)>,
+    hidden_buffers: Vec<HiddenBuffer>,
+    oldfiles: OldFiles,
+    bookmarks: Bookmarks,
+    // The line picked on the dashboard, counting its shortcuts and files.
+    dashboard_selected: usize,
+    command_history: History,
+    search_history: History,
+    // Where the session is kept on exit; None for none, as in tests.
+    shada_path: Option<PathBuf>,
+    // The window's own working directory, from `:lcd`, which relative
+    // paths are taken from instead of the editor's.
+    local_dir: Option<PathBuf>,
+    // The directory before the last `:cd`, for `:cd -`.
+    previous_dir: Option<PathBuf>,
+    arglist: ArgList,
+    options: Options,
+    user_commands: HashMap<String, UserCommand>,
+    command_depth: usize,
+    startup: Option<StartupLog>,
+    saving: Option<Saving>,
+    #[cfg(feature = "lua")]
+    scripting: Option<Scripting>,
+    #[cfg(unix)]
+    rpc: Option<RpcServer>,
 }
 
 impl Editor {
-    pub fn run(&mut self) {
-        enable_raw_mode().unwrap();
+    // Runs until the editor quits. A failure it cannot carry on from leaves
+    // the terminal as it was found before it is returned.
+    pub fn run(&mut self) -> Result<(), Error> {
+        enable_raw_mode().map_err(|error| Error::new("set up the terminal", error))?;
+        let result = self.run_loop();
+        if result.is_err() {
+            self.terminal.clear_screen();
+            self.terminal.cursor_show();
+            let _ = self.terminal.flush();
+        }
+        let restored =
+            disable_raw_mode().map_err(|error| Error::new("restore the terminal", error));
+        result.and(restored)
+    }
+    fn run_loop(&mut self) -> Result<(), Error> {
+        let draw = |error| Error::new("draw the screen", error);
+        self.highlight_visible_rows();
+        if let Some(mut startup) = self.startup.take() {
+            startup.mark("highlighting");
+            self.refresh_screen().map_err(draw)?;
+            startup.mark("first screen drawn");
+            if let Err(error) = startup.write() {
+                self.set_status(StatusMessage::error(format!(
+                    "Could not write startup times: {error}"
+                )));
+            }
+        }
+        // a burst of input, such as a paste, is drawn once per frame
+        let mut last_frame: Option<Instant> = None;
         loop {
-            if let Err(error) = self.refresh_screen() {
-                die(error);
+            let next_frame = last_frame.map(|last| last + self.frame_interval());
+            match next_frame {
+                Some(next_frame) if !self.should_quit && Instant::now() < next_frame => {
+                    self.events.set_timer(Timer::Frame, next_frame);
+                }
+                _ => {
+                    self.events.cancel_timer(Timer::Frame);
+                    self.refresh_screen().map_err(draw)?;
+                    last_frame = Some(Instant::now());
+                }
             }
             if self.should_quit {
                 break;
             }
-            if let Err(error) = self.process_keypress() {
-                die(error);
+            self.set_timers();
+            match self
+                .events
+                .next()
+                .and_then(|event| self.handle_event(event))
+            {
+                Ok(()) => (),
+                Err(error) if error::is_transient(&error) => {
+                    self.set_status(StatusMessage::error(error.to_string()));
+                }
+                Err(error) => return Err(Error::new("read input", error)),
+            }
+        }
+        self.wait_for_save()
+            .map_err(|error| Error::new("finish writing the file", error))?;
+        self.fire_event("BufUnload");
+        self.write_shada();
+        Ok(())
+    }
+    // After a panic: writes the buffer, if it has unsaved changes, to a
+    // recovery file beside its own, and says where.
+    pub fn write_recovery_file(&mut self) -> Option<Result<String, std::io::Error>> {
+        if !self.document.is_dirty() {
+            return None;
+        }
+        let path = crash::recovery_path(self.document.file_name.as_deref());
+        if self.document.file_name.is_none() {
+            self.document.file_name = Some(path.clone());
+        }
+        let mut snapshot = self.document.snapshot()?;
+        snapshot.file_name.clone_from(&path);
+        Some(snapshot.write(|_, _| {}).map(|()| path))
+    }
+    // Everything but input is handled the same way everywhere, including
+    // while a prompt waits for its answer.
+    fn handle_event(&mut self, event: event::Event) -> Result<(), std::io::Error> {
+        match event {
+            event::Event::Input(input) => {
+                let start = Instant::now();
+                self.record_macro_key(input);
+                self.process_keypress(input)?;
+                if self.status_message.time >= start {
+                    self.show_long_message()?;
+                }
+            }
+            event::Event::Timer(Timer::GitDiff) => self.git_diff.update(&self.document),
+            event::Event::Timer(Timer::KeyTimeout) => self.flush_pending_keys()?,
+            event::Event::Timer(Timer::Flash) => self.flash = None,
+            event::Event::Timer(Timer::MessageExpiry | Timer::Frame) => (),
+            event::Event::FileChanged(path) => {
+                if self.theme.path() == Some(&path) {
+                    self.reload_theme();
+                }
+            }
+            #[cfg(unix)]
+            event::Event::Rpc => self.process_rpc_requests(),
+            event::Event::Debug { adapter, message } => self.follow_debugger(adapter, message),
+            event::Event::SaveProgress(percent) => {
+                if let Some(saving) = &mut self.saving {
+                    saving.percent = percent;
+                }
+            }
+            event::Event::Saved { revision, result } => self.finish_save(revision, result),
+            event @ event::Event::SearchMatches { .. } => self.follow_search(event),
+            event::Event::Highlighted {
+                revision,
+                file_type,
+                word,
+                start,
+                rows,
+            } => {
+                self.highlighter.finish();
+                if revision == self.document.revision()
+                    && file_type == self.document.file_type()
+                    && word == self.highlighted_word
+                {
+                    self.document.set_highlighting(start, rows);
+                }
+            }
+        }
+        self.update_preview();
+        Ok(())
+    }
+    // Input that arrives while other work is handled, such as a prompt
+    // waiting for its next key.
+    fn read_input(&mut self) -> Result<Event, std::io::Error> {
+        if let Some(typed) = self.typeahead.pop_front() {
+            self.dot_repeat.record(typed);
+            return Ok(typed);
+        }
+        loop {
+            self.set_timers();
+            match self.events.next()? {
+                event::Event::Input(input) => {
+                    if let Event::Key(_) = input {
+                        self.dot_repeat.record(input);
+                    }
+                    self.record_macro_key(input);
+                    return Ok(input);
+                }
+                event => {
+                    self.handle_event(event)?;
+                    self.refresh_screen()?;
+                }
             }
         }
-        disable_raw_mode().unwrap();
     }
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let initial_status = String::from("HELP: `/` = find | `:w` = save | `:q` = quit");
-
-        let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(file_name);
-            if let Ok(doc) = doc {
-                doc
-            } else {
-                let _ = File::create(file_name);
-                Document::open(file_name).unwrap()
+    fn frame_interval(&self) -> Duration {
+        match u32::try_from(self.options.maxfps) {
+            Ok(fps) if fps > 0 => Duration::from_secs(1) / fps,
+            _ => Duration::ZERO,
+        }
+    }
+    // Redraws are only needed when something changes, so anything that
+    // changes by itself over time has a timer.
+    fn set_timers(&mut self) {
+        if self.status_message.expires
+            && !self.status_message.is_error
+            && self.status_message.is_visible()
+        {
+            let deadline = self.status_message.time + MESSAGE_TIMEOUT;
+            self.events.set_timer(Timer::MessageExpiry, deadline);
+        } else {
+            self.events.cancel_timer(Timer::MessageExpiry);
+        }
+        if self.pending_keys.is_empty() || !self.options.timeout {
+            self.events.cancel_timer(Timer::KeyTimeout);
+        } else {
+            let timeout = Duration::from_millis(self.options.timeoutlen as u64);
+            self.events
+                .set_timer(Timer::KeyTimeout, self.last_key + timeout);
+        }
+        match &self.flash {
+            Some(flash) => self.events.set_timer(Timer::Flash, flash.until),
+            None => self.events.cancel_timer(Timer::Flash),
+        }
+        self.events
+            .set_escape_timeout(Duration::from_millis(self.options.ttimeoutlen as u64));
+        if self.git_diff.is_stale(&self.document) {
+            self.events
+                .set_timer(Timer::GitDiff, self.last_edit + DIFF_DEBOUNCE);
+        } else {
+            self.events.cancel_timer(Timer::GitDiff);
+        }
+    }
+    fn reload_theme(&mut self) {
+        if let Err(error) = self.theme.reload_if_changed() {
+            self.set_status(StatusMessage::error(error));
+        }
+    }
+    #[cfg(unix)]
+    fn process_rpc_requests(&mut self) {
+        while let Some(request) = self.rpc.as_mut().and_then(RpcServer::next_request) {
+            let result = rpc::handle(self, &request);
+            if let Some(server) = self.rpc.as_mut() {
+                server.respond(&request, result);
+            }
+        }
+    }
+    pub fn new(arguments: &Arguments) -> Result<Self, Error> {
+        let mut startup = arguments.startuptime.as_deref().map(StartupLog::new);
+        let mut open_error = None;
+        let document = if arguments.pager && arguments.files.is_empty() {
+            let text = io::read_to_string(io::stdin())
+                .map_err(|error| Error::new("read standard input", error))?;
+            Document::from(text.as_str())
+        } else if let Some(file_name) = arguments.files.first() {
+            match Document::open(file_name) {
+                Ok(doc) => doc,
+                // a pager only shows files, it does not make them
+                Err(error) if arguments.pager => {
+                    let error = io::Error::new(error.kind(), format!("{file_name}: {error}"));
+                    return Err(Error::new("open the file", error));
+                }
+                Err(_) => {
+                    let _ = File::create(file_name);
+                    Document::open(file_name).unwrap_or_else(|error| {
+                        open_error = Some(format!("Could not open {file_name}: {error}"));
+                        Document::default()
+                    })
+                }
             }
         } else {
             Document::default()
         };
 
+        let mark = |startup: &mut Option<StartupLog>, phase| {
+            if let Some(startup) = startup {
+                startup.mark(phase);
+            }
+        };
+        mark(&mut startup, "opening the file");
+        let mut editor = Self::with_terminal(
+            arguments,
+            document,
+            Terminal::new().map_err(|error| Error::new("set up the terminal", error))?,
+            EventLoop::new(),
+        );
+        mark(&mut startup, "terminal setup");
+        #[cfg(unix)]
+        if let Some(path) = &arguments.listen {
+            match RpcServer::listen(path, editor.events.sender()) {
+                Ok(server) => {
+                    env::set_var(rpc::SERVER_ENV, path);
+                    editor.rpc = Some(server);
+                }
+                Err(error) => {
+                    editor.set_status(StatusMessage::error(format!(
+                        "Could not listen on {path}: {error}"
+                    )));
+                }
+            }
+        }
+        editor.git_diff.reload(&editor.document);
+        mark(&mut startup, "git diff");
+        editor.theme = Theme::new(config::config_dir().map(|dir| dir.join("theme")));
+        editor.reload_theme();
+        if let Some(path) = editor.theme.path() {
+            event::watch(path.clone(), editor.events.sender());
+        }
+        mark(&mut startup, "loading the theme");
+        if let Some(rvimrc) = config::config_dir().map(|dir| dir.join("rvimrc")) {
+            editor.config_errors = editor.source(&rvimrc).unwrap_or_default();
+        }
+        mark(&mut startup, "sourcing rvimrc");
+        #[cfg(feature = "lua")]
+        {
+            editor.status_message.is_error = false;
+            editor.with_scripting(Scripting::load_init);
+            if editor.status_message.is_error {
+                let error = format!("init.lua: {}", editor.status_message.text);
+                editor.config_errors.push(error);
+            }
+            mark(&mut startup, "running init.lua");
+        }
+        if let Some(path) = &arguments.log {
+            let _ = editor.options.set(&format!("logfile={path}"));
+        }
+        editor.open_log();
+        logging::info("startup", &format!("rvim {VERSION}"));
+        if let Some(dir) = config::state_dir() {
+            editor.oldfiles = OldFiles::load(dir.join("oldfiles"));
+            if let Ok(cwd) = env::current_dir() {
+                editor.bookmarks = Bookmarks::load(&dir, &cwd);
+            }
+            let path = dir.join("shada");
+            editor.restore_shada(Shada::read(&path));
+            editor.shada_path = Some(path);
+        }
+        if let Some(file_name) = editor.document.file_name.clone() {
+            editor.oldfiles.add(&file_name);
+            editor.restore_last_position();
+            editor.fire_event("BufRead");
+        }
+        mark(&mut startup, "BufRead autocommands");
+        editor.startup = startup;
+        if let Some(error) = open_error {
+            editor.set_status(StatusMessage::error(error));
+        }
+        if arguments.pager {
+            editor.start_pager();
+        }
+        Ok(editor)
+    }
+
+    // `--pager`: the text can only be read, `q` quits, and Space and `b`
+    // page through it as in less.
+    pub fn start_pager(&mut self) {
+        self.document.set_read_only(true);
+        self.options.dashboard = false;
+        for (lhs, rhs, description) in [
+            ("q", ":q!", "Quit"),
+            ("<Space>", "<page-down>", "Forward a screen"),
+            ("b", "<page-up>", "Back a screen"),
+        ] {
+            let _ = self
+                .keymaps
+                .bind(Mode::Normal, lhs, rhs, Some(description.to_string()));
+        }
+        self.status_message = StatusMessage::from("q = quit | Space, b = page | / = find");
+    }
+    // Everything but the startup side effects (config files, the RPC
+    // server and events), so tests can build an editor around a headless
+    // terminal.
+    fn with_terminal(
+        arguments: &Arguments,
+        document: Document,
+        terminal: Terminal,
+        events: EventLoop,
+    ) -> Self {
+        let initial_status = String::from("HELP: `/` = find | `:w` = save | `:q` = quit");
+        let mut options = Options::default();
+        options.use_file_type(&document.file_type());
         Self {
             should_quit: false,
-            terminal: Terminal::default().expect("Failed to initialize terminal"),
+            terminal,
+            events,
             document,
             cursor_position: Position::default(),
             offset: Position::default(),
             status_message: StatusMessage::from(initial_status),
+            long_message: None,
+            prompt_cursor: None,
+            messages: Vec::new(),
             highlighted_word: None,
+            highlighter: Highlighter::default(),
+            last_search: None,
+            search_flags: SearchFlags::default(),
+            quit_presses: None,
+            searcher: Searcher::default(),
+            search_jump: false,
+            counting: None,
             mode: Mode::Normal,
-            previous_characters: vec![],
+            visual_start: Position::default(),
+            visual_kind: Kind::Characters,
+            insertion: Insertion::default(),
+            completion: None,
+            picker: None,
+            preview: None,
+            walker: Walker::default(),
+            sneak_labels: Vec::new(),
+            diagnostics: Diagnostics::default(),
+            debugger: Debugger::default(),
+            signature: None,
+            semantic_tokens: SemanticTokens::default(),
+            flash: None,
+            last_put: None,
+            pending: Pending::default(),
+            last_key: Instant::now(),
+            display_column: None,
+            wanted_column: None,
+            char_search: None,
+            registers: Registers::default(),
+            clipboard: Clipboard::detect(),
+            keymaps: Keymaps::default(),
+            pending_keys: Vec::new(),
+            dot_repeat: DotRepeat::default(),
+            typeahead: VecDeque::new(),
+            batching: false,
+            recording: None,
+            running_macros: Vec::new(),
+            last_macro: None,
+            theme: Theme::new(None),
+            config_errors: Vec::new(),
+            git_diff: GitDiff::default(),
+            last_edit: Instant::now(),
+            marks: Marks::default(),
+            alternate_file: None,
+            hidden_buffers: Vec::new(),
+            oldfiles: OldFiles::default(),
+            bookmarks: Bookmarks::default(),
+            dashboard_selected: 0,
+            command_history: History::default(),
+            search_history: History::default(),
+            shada_path: None,
+            local_dir: None,
+            previous_dir: None,
+            arglist: ArgList::from(&arguments.files),
+            options,
+            user_commands: HashMap::new(),
+            command_depth: 0,
+            startup: None,
+            saving: None,
+            #[cfg(feature = "lua")]
+            scripting: Some(Scripting::new()),
+            #[cfg(unix)]
+            rpc: None,
         }
     }
 
     fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
+        let start = Instant::now();
+        self.terminal.begin_frame();
         if self.should_quit {
-            Terminal::clear_screen();
-        } else {
-            self.document.highlight(
-                &self.highlighted_word,
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
+            self.terminal.clear_screen();
+        } else {
+            self.highlighter.request(
+                &self.document,
+                self.highlighted_word.as_ref(),
+                self.offset.y,
+                self.terminal.size().height as usize,
+                self.events.sender(),
             );
             self.draw_rows();
             self.draw_status_bar();
             self.draw_message_bar();
-            if self.cursor_position.y == 0 {
-                Terminal::cursor_position(&Position {
-                    x: self.cursor_position.x.saturating_add(5),
-                    y: self.cursor_position.y.saturating_sub(self.offset.y),
-                });
+            // the terminal leaves room for the gutter on all but the first line
+            if let Some(x) = self.prompt_cursor {
+                let y = usize::from(self.terminal.size().height).saturating_add(1);
+                self.terminal.cursor_to(&Position { x, y });
             } else {
-                Terminal::cursor_position(&Position {
-                    x: self.cursor_position.x.saturating_sub(self.offset.x),
-                    y: self.cursor_position.y.saturating_sub(self.offset.y),
-                });
+                let Position { x, y } = self.cursor_screen_position();
+                let x = if y == 0 { x.saturating_add(5) } else { x };
+                self.terminal.cursor_position(&Position { x, y });
             }
         }
-        Terminal::cursor_show();
-        Terminal::flush()
+        self.terminal.cursor_show();
+        let flushed = self.terminal.flush();
+        if logging::enabled(Level::Debug) {
+            let elapsed = start.elapsed().as_micros();
+            logging::debug("render", &format!("frame drawn in {elapsed}us"));
+        }
+        flushed
+    }
+    // Highlights everything up to the bottom of the screen right away, for
+    // the first frame; rows that have not changed since are skipped.
+    fn highlight_visible_rows(&mut self) {
+        self.document.highlight(
+            &self.highlighted_word,
+            Some(
+                self.offset
+                    .y
+                    .saturating_add(self.terminal.size().height as usize),
+            ),
+        );
     }
     fn save(&mut self) {
+        self.start_save(false);
+    }
+    // Writes a snapshot of the buffer on a worker thread so the editor stays
+    // responsive; `Event::Saved` finishes the job.
+    fn start_save(&mut self, quit_after: bool) {
+        if self.document.is_read_only() {
+            self.set_status(StatusMessage::error("Cannot write a read-only buffer."));
+            return;
+        }
+        if self.is_still_saving() {
+            return;
+        }
         if self.document.file_name.is_none() {
             let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
             if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.".to_string());
+                self.set_status(StatusMessage::from("Save aborted.".to_string()));
                 return;
             }
             self.document.file_name = new_name;
         }
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_string());
-        } else {
-            self.status_message = StatusMessage::from("Error writing file!".to_string());
+        let format_error = self.format_for_write().err();
+        if let Some(error) = &format_error {
+            logging::warn("format", error);
         }
+        self.tidy_for_write();
+        self.fire_event("BufWritePre");
+        let Some(snapshot) = self.document.snapshot() else {
+            return;
+        };
+        self.saving = Some(Saving {
+            file_name: snapshot.file_name.clone(),
+            percent: 0,
+            quit_after,
+            format_error,
+        });
+        logging::info("file", &format!("writing {}", snapshot.file_name));
+        let sender = self.events.sender();
+        thread::spawn(move || {
+            let mut reported = 0;
+            let result = snapshot.write(|written, total| {
+                let percent = written.saturating_mul(100) / total.max(1);
+                if percent > reported {
+                    reported = percent;
+                    sender.send(event::Event::SaveProgress(percent));
+                }
+            });
+            sender.send(event::Event::Saved {
+                revision: snapshot.revision,
+                result: result.map_err(|error| error.to_string()),
+            });
+        });
     }
-    fn search(&mut self) {
-        let old_position = self.cursor_position.clone();
-        let mut direction = SearchDirection::Forward;
-        let query = self
-            .prompt(
-                "Search (ESC to cancel, Arrows to navigate): ",
-                |editor, key, query| {
-                    let mut moved = false;
-                    match key {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('n'),
-                            ..
-                        })
-                        | Event::Key(KeyEvent {
-                            code: KeyCode::Right,
-                            ..
-                        }) => {
-                            direction = SearchDirection::Forward;
-                            editor.move_cursor(Event::Key(KeyEvent {
-                                code: KeyCode::Right,
-                                modifiers: KeyModifiers::NONE,
-                            }));
-                            moved = true;
-                        }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('p'),
-                            ..
-                        })
-                        | Event::Key(KeyEvent {
-                            code: KeyCode::Left,
-                            ..
-                        }) => direction = SearchDirection::Backward,
-                        _ => direction = SearchDirection::Forward,
-                    }
-                    if let Some(position) =
-                        editor
-                            .document
-                            .find(&query, &editor.cursor_position, direction)
-                    {
-                        editor.cursor_position = position;
-                        editor.scroll();
-                    } else if moved {
-                        editor.move_cursor(Event::Key(KeyEvent {
-                            code: KeyCode::Left,
-                            modifiers: KeyModifiers::NONE,
-                        }));
+    // Pipes the buffer through `formatters`, changing only the lines they
+    // changed. On failure the buffer is left as it was, to be written
+    // unformatted.
+    fn format_for_write(&mut self) -> Result<(), String> {
+        if self.options.formatters.trim().is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = (0..self.document.len())
+            .filter_map(|index| self.document.row(index))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let mut text = lines.join("\n");
+        text.push('\n');
+        let timeout = Duration::from_millis(self.options.formattimeout as u64);
+        let formatted = formatter::run_chain(&self.options.formatters, &text, timeout)?;
+        let formatted: Vec<String> = formatted.lines().map(str::to_string).collect();
+        // from the bottom up, so the line numbers of the hunks above hold
+        for hunk in rvim_core::diff::hunks(&lines, &formatted).iter().rev() {
+            let replacement = formatted
+                .get(hunk.new_start..hunk.new_start.saturating_add(hunk.new_len))
+                .unwrap_or_default();
+            self.document.replace_lines(
+                hunk.old_start,
+                hunk.old_start.saturating_add(hunk.old_len),
+                replacement,
+            );
+        }
+        self.clamp_cursor();
+        Ok(())
+    }
+    // `trim_trailing_whitespace` and `ensure_final_newline`. Only the lines
+    // that differ from the file on disk are trimmed, so the write does not
+    // touch the rest.
+    fn tidy_for_write(&mut self) {
+        if self.options.ensure_final_newline {
+            self.document.add_final_newline();
+        }
+        if !self.options.trim_trailing_whitespace {
+            return;
+        }
+        let written = self
+            .document
+            .file_name
+            .as_ref()
+            .and_then(|file_name| fs::read_to_string(file_name).ok())
+            .unwrap_or_default();
+        let old: Vec<&str> = written.lines().collect();
+        let new: Vec<String> = (0..self.document.len())
+            .filter_map(|index| self.document.row(index))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let new_lines: Vec<&str> = new.iter().map(String::as_str).collect();
+        for hunk in rvim_core::diff::hunks(&old, &new_lines) {
+            for index in hunk.new_start..hunk.new_start.saturating_add(hunk.new_len) {
+                let Some(line) = new.get(index) else {
+                    continue;
+                };
+                let trimmed = line.trim_end();
+                if trimmed.len() != line.len() {
+                    self.document.replace_lines(
+                        index,
+                        index.saturating_add(1),
+                        &[trimmed.to_string()],
+                    );
+                }
+            }
+        }
+        self.clamp_cursor();
+    }
+    fn finish_save(&mut self, revision: u64, result: Result<(), String>) {
+        let Some(saving) = self.saving.take() else {
+            return;
+        };
+        match result {
+            Ok(()) if self.document.file_name.as_ref() == Some(&saving.file_name) => {
+                logging::info("file", &format!("wrote {}", saving.file_name));
+                self.document.saved(revision);
+                self.options.use_file_type(&self.document.file_type());
+                self.set_status(match &saving.format_error {
+                    Some(error) => {
+                        StatusMessage::error(format!("File saved without formatting: {error}"))
                     }
-                    editor.highlighted_word = Some(query.to_string());
-                },
-            )
-            .unwrap_or(None);
-
-        if query.is_none() {
-            self.cursor_position = old_position;
+                    None => StatusMessage::from("File saved successfully.".to_string()),
+                });
+                self.git_diff.reload(&self.document);
+                self.fire_event("BufWritePost");
+                // the error stays on screen rather than going with the editor
+                if saving.quit_after && saving.format_error.is_none() && !self.document.is_dirty() {
+                    self.quit(false, false);
+                }
+            }
+            Ok(()) => (),
+            Err(error) => {
+                self.set_status(StatusMessage::error(format!("Error writing file: {error}")));
+            }
+        }
+    }
+    // Handles events until the save in progress, if any, has finished.
+    fn wait_for_save(&mut self) -> Result<(), std::io::Error> {
+        while self.saving.is_some() {
+            let event = self.events.wait_for(|event| {
+                matches!(
+                    event,
+                    event::Event::Saved { .. } | event::Event::SaveProgress(_)
+                )
+            })?;
+            self.handle_event(event)?;
+        }
+        Ok(())
+    }
+    // Refuses anything that would touch the file while it is being written.
+    fn is_still_saving(&mut self) -> bool {
+        let Some(saving) = &self.saving else {
+            return false;
+        };
+        self.set_status(StatusMessage::error(format!(
+            "Still writing {} ({}%).",
+            saving.file_name, saving.percent
+        )));
+        true
+    }
+    // Opens `file_name`, or brings back its hidden buffer. A buffer with
+    // unsaved changes is only left behind, hidden, with `hidden` set.
+    fn open_file(&mut self, file_name: &str) -> bool {
+        if self.is_still_saving() {
+            return false;
+        }
+        let reloading = self.document.file_name.as_deref() == Some(file_name);
+        if self.document.is_dirty() && (!self.options.hidden || reloading) {
+            self.set_status(StatusMessage::error("WARNING! File has unsaved changes."));
+            return false;
+        }
+        let hidden = self
+            .hidden_buffers
+            .iter()
+            .position(|buffer| buffer.document.file_name.as_deref() == Some(file_name));
+        let cursor = self.cursor_position.clone();
+        let previous = if let Some(index) = hidden {
+            let buffer = self.hidden_buffers.remove(index);
+            let previous = self.replace_document(buffer.document);
+            self.cursor_position = buffer.cursor;
+            self.clamp_cursor();
+            previous
+        } else {
+            match Document::open(file_name) {
+                Ok(document) => {
+                    let lines = document.len();
+                    logging::info("file", &format!("read {file_name}, {lines} lines"));
+                    let previous = self.replace_document(document);
+                    self.restore_last_position();
+                    self.fire_event("BufRead");
+                    previous
+                }
+                Err(error) => {
+                    self.set_status(StatusMessage::error(format!(
+                        "Could not open {file_name}: {error}"
+                    )));
+                    return false;
+                }
+            }
+        };
+        if previous.is_dirty() {
+            self.hidden_buffers.push(HiddenBuffer {
+                document: previous,
+                cursor,
+            });
+        }
+        true
+    }
+    // Notes where the cursor is in the current file, to come back to when it
+    // is opened again.
+    fn remember_position(&mut self) {
+        if let Some(file_name) = &self.document.file_name {
+            let file_name = config::full_path(file_name);
+            self.marks
+                .set_last_position(&file_name, &self.cursor_position);
+        }
+    }
+    fn restore_last_position(&mut self) {
+        let Some(file_name) = &self.document.file_name else {
+            return;
+        };
+        if let Some(position) = self.marks.last_position(&config::full_path(file_name)) {
+            self.cursor_position = position.clone();
+            self.clamp_cursor();
             self.scroll();
         }
-        self.highlighted_word = None;
     }
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let event = Terminal::read_key()?;
-        match (&self.mode, event) {
-            // go to visual mode when Ctrl-V is pressed in normal mode
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('v'),
-                    modifiers: KeyModifiers::CONTROL,
-                }),
-            ) => self.mode = Mode::Visual,
-
-            // go to normal mode when Esc is pressed in Insert or Visual Mode
-            (
-                _,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc, ..
-                }),
-            ) => self.mode = Mode::Normal,
-
-            // go to insert mode when i is pressed.
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('i'),
-                    ..
-                }),
-            ) => {
-                self.mode = Mode::Insert;
-                Terminal::cursor_hide();
+    fn restore_shada(&mut self, shada: Shada) {
+        self.command_history = History::new(shada.command_history);
+        self.search_history = History::new(shada.search_history);
+        for (name, mark) in shada.global_marks {
+            self.marks.set_global(name, &mark.file_name, &mark.position);
+        }
+        for (file_name, position) in shada.last_positions {
+            self.marks.set_last_position(&file_name, &position);
+        }
+    }
+    fn write_shada(&mut self) {
+        let Some(path) = self.shada_path.clone() else {
+            return;
+        };
+        self.remember_position();
+        let shada = Shada {
+            command_history: self.command_history.entries().to_vec(),
+            search_history: self.search_history.entries().to_vec(),
+            global_marks: self
+                .marks
+                .globals()
+                .into_iter()
+                .map(|(name, mark)| (name, mark.clone()))
+                .collect(),
+            last_positions: self.marks.last_positions().to_vec(),
+        };
+        if let Err(error) = shada.write(&path) {
+            logging::warn(
+                "shada",
+                &format!("could not write {}: {error}", path.display()),
+            );
+        }
+    }
+    // Swaps in another buffer, returning the one it replaces; the file
+    // being left becomes the alternate file.
+    fn replace_document(&mut self, document: Document) -> Document {
+        self.fire_event("BufUnload");
+        self.remember_position();
+        if let Some(previous) = self.document.file_name.clone() {
+            self.alternate_file = Some((previous, self.cursor_position.clone()));
+        }
+        let previous = mem::replace(&mut self.document, document);
+        if let Some(file_name) = &self.document.file_name {
+            self.oldfiles.add(file_name);
+        }
+        self.options.use_file_type(&self.document.file_type());
+        self.diagnostics.clear();
+        self.semantic_tokens.clear();
+        self.preview = None;
+        self.searcher.forget_lines();
+        self.git_diff.reload(&self.document);
+        self.cursor_position = Position::default();
+        self.offset = Position::default();
+        previous
+    }
+    // Shows `message`, and keeps it for `:messages` unless it is a prompt
+    // or clears the message line.
+    fn set_status(&mut self, message: StatusMessage) {
+        if message.expires && !message.text.is_empty() {
+            if message.is_error {
+                logging::error("message", &message.text);
+            } else {
+                logging::info("message", &message.text);
             }
-
-            // go to insert mode one past cursor if a is pressed.
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('a'),
-                    ..
-                }),
-            ) => {
-                self.move_cursor(Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    modifiers: KeyModifiers::NONE,
-                }));
-                self.mode = Mode::Insert;
+            if self.messages.len() >= MESSAGE_HISTORY {
+                self.messages.remove(0);
             }
-
-            // go to insert mode at end of line if A is pressed.
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('A'),
-                    ..
-                }),
-            ) => {
-                self.cursor_position.x = self
-                    .document
-                    .row(self.cursor_position.y)
-                    .unwrap_or(&Row::default())
-                    .len();
-                self.mode = Mode::Insert;
+            self.messages.push(message.text.clone());
+        }
+        self.status_message = message;
+    }
+    // A message over several lines, or wider than the screen, is shown over
+    // the bottom of the screen a page at a time. Enter, Space or Esc go on
+    // from the last page; any other key goes on and is handled as usual.
+    fn show_long_message(&mut self) -> Result<(), std::io::Error> {
+        let width = self.screen_width();
+        let message = &self.status_message;
+        if !message.expires
+            || !(message.text.contains('\n') || message.text.chars().count() > width)
+        {
+            return Ok(());
+        }
+        let mut lines: Vec<String> = Vec::new();
+        for line in message.text.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                lines.push(String::new());
             }
-
-                        (
-                Mode::Normal,
+            lines.extend(
+                chars
+                    .chunks(width.max(1))
+                    .map(|chunk| chunk.iter().collect()),
+            );
+        }
+        let is_error = message.is_error;
+        let page_height = usize::from(self.terminal.size().height).max(1);
+        let pages: Vec<Vec<String>> = lines.chunks(page_height).map(<[String]>::to_vec).collect();
+        let last = pages.len().saturating_sub(1);
+        let mut key = None;
+        for (index, page) in pages.into_iter().enumerate() {
+            self.long_message = Some(page);
+            let prompt = if index < last {
+                "-- More --"
+            } else {
+                "Press ENTER or type command to continue"
+            };
+            self.status_message = StatusMessage {
+                is_error,
+                ..StatusMessage::prompt(prompt)
+            };
+            self.refresh_screen()?;
+            let input = self.read_input();
+            self.long_message = None;
+            self.status_message = StatusMessage::from(String::new());
+            let input = input?;
+            let stop = matches!(
+                input,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('b'),
+                    code: KeyCode::Esc | KeyCode::Char('q'),
                     ..
-                }),
-            ) => {
-                let width = self.terminal.size().width as usize;
-                let height = self.terminal.size().height as usize;
-
-                // keep moving right until you've seen both a space and a char.
-                let mut seen_char = false;
-                let mut seen_space = false;
-                let mut i = 0;
-                while i < 500 {
-                    if seen_char == true && seen_space == true {
-                        break;
-                    }
-                    let row = self.document.row(self.cursor_position.y);
-                    if row.is_some() {
-                        if let Some(c) = row.unwrap().get(self.cursor_position.x) {
-                            match c {
-                                " " | "\t" | "\n" => seen_space = true,
-                                _ => seen_char = true,
-                            }
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-
-                    self.move_cursor(Event::Key(KeyEvent {
-                        code: KeyCode::Left,
-                        modifiers: KeyModifiers::NONE,
-                    }));
-                    i += 1;
+                })
+            );
+            if index == last || stop {
+                key = Some(input);
+                break;
+            }
+        }
+        let Some(Event::Key(key)) = key else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Char(' ' | 'q') => Ok(()),
+            _ => {
+                let start = Instant::now();
+                self.process_keypress(Event::Key(key))?;
+                if self.status_message.time >= start {
+                    self.show_long_message()?;
                 }
+                Ok(())
             }
-
-            // either save if :w or go find next word.
-            // FIXME: Broken
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('w'),
-                    ..
-                }),
-            ) => {
-                // move cursor to the left until the character underneath is not a space?
-                if self.previous_characters.last() != Some(&':') {
-                    let width = self.terminal.size().width as usize;
-                    let height = self.terminal.size().height as usize;
-
-                    // keep moving right until you've seen both a space and a char.
-                    let mut seen_char = false;
-                    let mut seen_space = false;
-                    let mut i = 0;
-                    while i < 500 {
-                        if seen_char == true && seen_space == true {
-                            break;
-                        }
-                        let row = self.document.row(self.cursor_position.y);
-                        if row.is_some() {
-                            if let Some(c) = row.unwrap().get(self.cursor_position.x) {
-                                match c {
-                                    " " | "\t" | "\n" => seen_space = true,
-                                    _ => seen_char = true,
-                                }
-                            } else {
-                                break;
-                            }
-                        } else {
-                            break;
+        }
+    }
+    // Starts, stops or moves the log to follow `logfile` and `loglevel`.
+    fn open_log(&mut self) {
+        if let Err(error) = logging::open(&self.options.logfile, self.options.loglevel) {
+            let file = &self.options.logfile;
+            self.set_status(StatusMessage::error(format!(
+                "Could not open {file}: {error}"
+            )));
+        }
+    }
+    // `:messages`: the messages shown so far, in a read-only buffer.
+    fn show_messages(&mut self) {
+        if self.document.is_dirty() {
+            self.set_status(StatusMessage::error("WARNING! File has unsaved changes."));
+            return;
+        }
+        let mut document = Document::from(self.messages.join("\n").as_str());
+        document.set_read_only(true);
+        self.replace_document(document);
+        self.cursor_position.y = self.document.len().saturating_sub(1);
+        self.scroll();
+    }
+    // Shows the `:checkhealth` report in a read-only buffer of its own.
+    fn check_health(&mut self) {
+        if self.document.is_dirty() {
+            self.set_status(StatusMessage::error("WARNING! File has unsaved changes."));
+            return;
+        }
+        let report = health::report(
+            &self.config_errors,
+            self.theme.path().map(PathBuf::as_path),
+            self.theme.error(),
+        );
+        let mut document = Document::from(report.as_str());
+        document.set_read_only(true);
+        self.replace_document(document);
+    }
+    // `:Outline`: the symbols in the file as a tree to pick one from and
+    // jump to, starting at the one the cursor is in.
+    fn outline(&mut self) {
+        let Some(file_name) = self.document.file_name.clone() else {
+            self.set_status(StatusMessage::error("No file name"));
+            return;
+        };
+        let symbols = match tags::symbols(Path::new(&file_name)) {
+            Ok(symbols) => symbols,
+            Err(error) => {
+                self.set_status(StatusMessage::error(error));
+                return;
+            }
+        };
+        let mut symbols: Vec<(usize, tags::Tag)> = symbols
+            .into_iter()
+            .filter_map(|tag| Some((tag.locate(&self.document)?, tag)))
+            .collect();
+        if symbols.is_empty() {
+            self.set_status(StatusMessage::error("No symbols"));
+            return;
+        }
+        symbols.sort_by_key(|(y, _)| *y);
+        let current = symbols
+            .iter()
+            .rposition(|(y, _)| *y <= self.cursor_position.y)
+            .unwrap_or(0);
+        let items = symbols
+            .iter()
+            .map(|(y, tag)| Item {
+                text: format!("{}{}", "  ".repeat(tag.depth()), tag.name),
+                detail: format!("{} {}", tag.kind, y.saturating_add(1)),
+            })
+            .collect();
+        let chosen = self.pick("Outline: ", items, current).unwrap_or(None);
+        if let Some((y, _)) = chosen.and_then(|index| symbols.get(index)) {
+            self.cursor_position = Position { x: 0, y: *y };
+            self.clamp_cursor();
+            self.scroll();
+        }
+    }
+    // `:Rename {name}`: moves the file to `name`, taken from the directory
+    // the file is in, and the buffer with it. Git is told about files it
+    // tracks.
+    fn rename_file(&mut self, name: &str) {
+        if self.is_still_saving() {
+            return;
+        }
+        let Some(from) = self.document.file_name.clone() else {
+            self.set_status(StatusMessage::error("No file name."));
+            return;
+        };
+        let to = Path::new(&from)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(name);
+        let to = to.display().to_string();
+        if Path::new(&to).exists() {
+            self.set_status(StatusMessage::error(format!("File exists: {to}")));
+            return;
+        }
+        let moved = match git::rename(&from, &to) {
+            Ok(true) => Ok(()),
+            Ok(false) => fs::rename(&from, &to).map_err(|error| error.to_string()),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = moved {
+            self.set_status(StatusMessage::error(format!(
+                "Could not rename {from}: {error}"
+            )));
+            return;
+        }
+        logging::info("file", &format!("renamed {from} to {to}"));
+        self.document.rename(&to);
+        self.options.use_file_type(&self.document.file_type());
+        self.git_diff.reload(&self.document);
+        self.set_status(StatusMessage::from(format!("Renamed to {to}")));
+    }
+    // `:Delete`: removes the file from disk, once confirmed, and leaves an
+    // empty buffer in its place.
+    fn delete_file(&mut self, force: bool) -> Result<(), std::io::Error> {
+        if self.is_still_saving() {
+            return Ok(());
+        }
+        let Some(file_name) = self.document.file_name.clone() else {
+            self.set_status(StatusMessage::error("No file name."));
+            return Ok(());
+        };
+        if !force {
+            self.set_status(StatusMessage::prompt(format!(
+                "Delete \"{file_name}\"? [y]es, [n]o: "
+            )));
+            self.refresh_screen()?;
+            let answer = self.read_input()?;
+            self.set_status(StatusMessage::from(String::new()));
+            if !matches!(
+                answer,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y' | 'Y'),
+                    ..
+                })
+            ) {
+                return Ok(());
+            }
+        }
+        if let Err(error) = fs::remove_file(&file_name) {
+            self.set_status(StatusMessage::error(format!(
+                "Could not delete {file_name}: {error}"
+            )));
+            return Ok(());
+        }
+        logging::info("file", &format!("deleted {file_name}"));
+        self.replace_document(Document::default());
+        self.alternate_file = None;
+        self.set_status(StatusMessage::from(format!("Deleted {file_name}")));
+        Ok(())
+    }
+    // `:Mkdir [dir]`: creates `dir` and any directories above it, or the
+    // directory of a file being edited before it is first written.
+    fn make_directory(&mut self, dir: Option<&str>) {
+        let dir = if let Some(dir) = dir {
+            PathBuf::from(self.resolve_path(&self.expand_file_name(dir)))
+        } else if let Some(file) = self.document.file_name.as_deref() {
+            Path::new(file)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        } else {
+            self.set_status(StatusMessage::error("No file name."));
+            return;
+        };
+        if dir.as_os_str().is_empty() {
+            return;
+        }
+        let message = match fs::create_dir_all(&dir) {
+            Ok(()) => StatusMessage::from(format!("Created {}", dir.display())),
+            Err(error) => {
+                StatusMessage::error(format!("Could not create {}: {error}", dir.display()))
+            }
+        };
+        self.set_status(message);
+    }
+    // `:cd [dir]` and `:lcd [dir]`: `-` goes back to the directory before
+    // the last `:cd`, and no `dir` to the home directory. `:cd` also drops
+    // the window's own directory.
+    fn change_dir(&mut self, dir: Option<&str>, local: bool) {
+        let dir = match dir {
+            Some("-") if !local => {
+                let Some(dir) = self.previous_dir.clone() else {
+                    self.set_status(StatusMessage::error("No previous directory."));
+                    return;
+                };
+                dir
+            }
+            Some(dir) => PathBuf::from(self.resolve_path(&self.expand_file_name(dir))),
+            None => match env::var("HOME") {
+                Ok(home) => PathBuf::from(home),
+                Err(_) => return,
+            },
+        };
+        let dir = config::expand_home(&dir.display().to_string());
+        let Some(dir) = dir.canonicalize().ok().filter(|dir| dir.is_dir()) else {
+            let message = format!("No such directory: {}", dir.display());
+            self.set_status(StatusMessage::error(message));
+            return;
+        };
+        if local {
+            self.local_dir = Some(dir.clone());
+        } else {
+            let previous = env::current_dir().ok();
+            if let Err(error) = env::set_current_dir(&dir) {
+                let message = format!("Could not change to {}: {error}", dir.display());
+                self.set_status(StatusMessage::error(message));
+                return;
+            }
+            if let Some(previous) = &previous {
+                self.follow_dir(previous, &dir);
+            }
+            self.previous_dir = previous;
+            self.local_dir = None;
+        }
+        self.set_status(StatusMessage::from(dir.display().to_string()));
+    }
+    // Renames the files open, hidden, alternate and in the argument list
+    // after `:cd` from `from` to `to`, so relative names still name the
+    // same files.
+    fn follow_dir(&mut self, from: &Path, to: &Path) {
+        let rename = |file_name: &str| config::move_path(file_name, from, to);
+        let documents = Some(&mut self.document).into_iter().chain(
+            self.hidden_buffers
+                .iter_mut()
+                .map(|hidden| &mut hidden.document),
+        );
+        for document in documents {
+            if let Some(file_name) = &mut document.file_name {
+                *file_name = rename(file_name);
+            }
+        }
+        if let Some((file_name, _)) = &mut self.alternate_file {
+            *file_name = rename(file_name);
+        }
+        self.arglist.rename(rename);
+    }
+    // Where relative paths are taken from: the window's own directory, or
+    // the editor's.
+    fn working_dir(&self) -> PathBuf {
+        self.local_dir
+            .clone()
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_default()
+    }
+    // `path` taken from the window's own directory, when it has one and
+    // `path` is relative.
+    fn resolve_path(&self, path: &str) -> String {
+        match &self.local_dir {
+            Some(dir) if Path::new(path).is_relative() && !path.starts_with('~') => {
+                dir.join(path).display().to_string()
+            }
+            _ => path.to_string(),
+        }
+    }
+    // `%` as a file argument stands for the current file; `:h` after it
+    // takes its directory, `:t` its name without the directory and `:p` its
+    // full path, in the order given, as in `%:p:h`.
+    fn expand_file_name(&self, argument: &str) -> String {
+        // `#<N` is the Nth file of `:oldfiles`
+        if let Some(number) = argument.strip_prefix("#<") {
+            let file = number
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|index| self.oldfiles.files().get(index));
+            return file.map_or_else(|| argument.to_string(), Clone::clone);
+        }
+        let Some(modifiers) = argument.strip_prefix('%') else {
+            return argument.to_string();
+        };
+        let Some(file_name) = &self.document.file_name else {
+            return argument.to_string();
+        };
+        let mut path = PathBuf::from(file_name);
+        for modifier in modifiers.split(':').skip(1) {
+            path = match modifier {
+                "h" => path
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+                "t" => path.file_name().map(PathBuf::from).unwrap_or_default(),
+                "p" => fs::canonicalize(&path).unwrap_or(path),
+                _ => return argument.to_string(),
+            };
+        }
+        path.display().to_string()
+    }
+    // `:Files [dir]`: the files under `dir`, or the working directory, to
+    // pick one from and open.
+    fn find_file(&mut self, dir: Option<&str>) {
+        let Ok(cwd) = env::current_dir() else {
+            return;
+        };
+        let working_dir = self.working_dir();
+        let root = dir.map_or_else(
+            || working_dir.clone(),
+            |dir| working_dir.join(config::expand_home(dir)),
+        );
+        let settings = walk::Settings {
+            hidden: self.options.walkhidden,
+            max_file_size: (self.options.walkmaxsize as u64).saturating_mul(1024),
+        };
+        let files = self.walker.files(&root, settings);
+        if files.is_empty() {
+            self.set_status(StatusMessage::error("No files"));
+            return;
+        }
+        let items = files
+            .iter()
+            .map(|path| Item {
+                text: path
+                    .strip_prefix(&root)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string(),
+                detail: String::new(),
+            })
+            .collect();
+        let chosen = self.pick("Files: ", items, 0).unwrap_or(None);
+        if let Some(path) = chosen.and_then(|index| files.get(index)) {
+            let path = path.strip_prefix(&cwd).unwrap_or(path);
+            self.open_file(&path.display().to_string());
+        }
+    }
+    // `:Recent`: the recently edited files, newest first, to pick one from.
+    fn pick_recent_file(&mut self) {
+        let items = self
+            .oldfiles
+            .files()
+            .iter()
+            .map(|file| Item {
+                text: file.clone(),
+                detail: String::new(),
+            })
+            .collect();
+        if let Some(index) = self.pick("Recent: ", items, 0).unwrap_or(None) {
+            self.open_recent_file(index);
+        }
+    }
+    // `:registers [names]`: what each register holds, or only the ones
+    // named, on a line each with newlines shown as `^J`.
+    fn list_registers(&mut self, names: &str) {
+        let width = self.screen_width();
+        let mut lines = vec![String::from("Type Name Content")];
+        let file = self.register_contents(Some('%'));
+        let file = file.as_ref().map(|register| ('%', register));
+        for (name, register) in self.registers.list().into_iter().chain(file) {
+            if !names.is_empty() && !names.contains(name) {
+                continue;
+            }
+            let kind = register.kind.letter();
+            let line = format!("  {kind}  \"{name}   {}", register.text.replace('\n', "^J"));
+            lines.push(statusline::truncate(&line, width));
+        }
+        self.set_status(StatusMessage::from(lines.join("\n")));
+    }
+    // `:undolist`: the end of each branch of the undo tree, with how many
+    // changes lead to it and when the last was made.
+    fn list_undo_branches(&mut self) {
+        let branches = self.document.undo_branches();
+        if branches.is_empty() {
+            self.set_status(StatusMessage::from("Nothing to undo"));
+            return;
+        }
+        let mut lines = vec![String::from("number changes  when")];
+        for branch in branches {
+            let seconds = branch.time.elapsed().as_secs();
+            let when = match seconds {
+                0..=99 => format!("{seconds} seconds ago"),
+                100..=5999 => format!("{} minutes ago", seconds / 60),
+                _ => format!("{} hours ago", seconds / 3600),
+            };
+            lines.push(format!(
+                "{:>6} {:>7}  {when}",
+                branch.number, branch.changes
+            ));
+        }
+        self.set_status(StatusMessage::from(lines.join("\n")));
+    }
+    // `:BookmarkAdd [note]`: bookmarks the cursor line, or changes the note
+    // of its bookmark.
+    fn add_bookmark(&mut self, note: &str) {
+        let Some(file_name) = &self.document.file_name else {
+            self.set_status(StatusMessage::error("No file name"));
+            return;
+        };
+        let file_name = config::full_path(file_name);
+        self.bookmarks
+            .add(&file_name, self.cursor_position.y, note.trim());
+    }
+    // `:BookmarkDelete`: the bookmark on the cursor line.
+    fn delete_bookmark(&mut self) {
+        let file_name = self.document.file_name.as_deref().map(config::full_path);
+        let removed = file_name
+            .is_some_and(|file_name| self.bookmarks.remove(&file_name, self.cursor_position.y));
+        if !removed {
+            self.set_status(StatusMessage::error("No bookmark on this line"));
+        }
+    }
+    // `:Bookmarks`: the project's bookmarks by file and line, to pick one
+    // from by its note or place and jump to, opening its file if need be.
+    fn pick_bookmark(&mut self) {
+        if self.bookmarks.list().is_empty() {
+            self.set_status(StatusMessage::error("No bookmarks"));
+            return;
+        }
+        let cwd = env::current_dir().unwrap_or_default();
+        let items = self
+            .bookmarks
+            .list()
+            .iter()
+            .map(|bookmark| {
+                let path = Path::new(&bookmark.file_name);
+                let path = path.strip_prefix(&cwd).unwrap_or(path).display();
+                let line = bookmark.line.saturating_add(1);
+                Item {
+                    text: format!("{path}:{line} {}", bookmark.note),
+                    detail: String::new(),
+                }
+            })
+            .collect();
+        let Some(index) = self.pick("Bookmarks: ", items, 0).unwrap_or(None) else {
+            return;
+        };
+        let Some(bookmark) = self.bookmarks.list().get(index) else {
+            return;
+        };
+        let (file_name, line) = (bookmark.file_name.clone(), bookmark.line);
+        let current = self.document.file_name.as_deref().map(config::full_path);
+        if current.as_deref() != Some(file_name.as_str()) && !self.open_full_path(&file_name) {
+            return;
+        }
+        self.cursor_position = Position { x: 0, y: line };
+        self.clamp_cursor();
+        self.scroll();
+    }
+    // The lines of the file with a bookmark or a breakpoint, and the one
+    // the program being debugged stopped on.
+    fn marked_lines(&self) -> Marked {
+        let Some(file_name) = self.document.file_name.as_deref().map(config::full_path) else {
+            return Marked {
+                bookmarks: HashSet::new(),
+                breakpoints: BTreeSet::new(),
+                stopped: None,
+            };
+        };
+        Marked {
+            bookmarks: self
+                .bookmarks
+                .list()
+                .iter()
+                .filter(|bookmark| bookmark.file_name == file_name)
+                .map(|bookmark| bookmark.line)
+                .collect(),
+            breakpoints: self.debugger.breakpoints_in(&file_name),
+            stopped: self.debugger.stopped_in(&file_name),
+        }
+    }
+    // The characters of line `y` in the visual selection, running on past
+    // its end when the selection takes whole lines.
+    fn selected(&self, y: usize) -> Option<Range<usize>> {
+        if self.mode != Mode::Visual {
+            return None;
+        }
+        let (start, end) = (&self.visual_start, &self.cursor_position);
+        let (from, to) = if (start.y, start.x) <= (end.y, end.x) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        if y < from.y || y > to.y {
+            return None;
+        }
+        match self.visual_kind {
+            Kind::Lines => return Some(0..usize::MAX),
+            Kind::Block => return Some(self.block_range(y).0),
+            Kind::Characters => (),
+        }
+        let first = if y == from.y { from.x } else { 0 };
+        let last = if y == to.y {
+            to.x.saturating_add(1)
+        } else {
+            self.document.row(y).map_or(0, Row::len)
+        };
+        Some(first..last)
+    }
+    // The first and last screen columns of a block selection, which takes
+    // a wide character at either corner whole.
+    fn block_columns(&self) -> (usize, usize) {
+        let edges = |position: &Position| {
+            self.document
+                .row(position.y)
+                .map_or((position.x, position.x), |row| {
+                    let start = row.column(position.x);
+                    let end = row.column(position.x.saturating_add(1));
+                    (start, end.saturating_sub(1).max(start))
+                })
+        };
+        let (start, end) = (edges(&self.visual_start), edges(&self.cursor_position));
+        (start.0.min(end.0), start.1.max(end.1))
+    }
+    // The characters of line `y` in the block selection's columns, and how
+    // many cells of a tab cut by the block's left and right edges are
+    // outside them.
+    fn block_range(&self, y: usize) -> (Range<usize>, usize, usize) {
+        let (left, right) = self.block_columns();
+        let Some(row) = self.document.row(y) else {
+            return (0..0, 0, 0);
+        };
+        let start = row.index_at_column(left).min(row.len());
+        let end = row.index_at_column(right).saturating_add(1).min(row.len());
+        if end <= start {
+            return (start..start, 0, 0);
+        }
+        let before = left.saturating_sub(row.column(start));
+        let after = row.column(end).saturating_sub(right.saturating_add(1));
+        (start..end, before, after)
+    }
+    // The sign for line `y`: where the program stopped, then a breakpoint,
+    // its diagnostic and its bookmark.
+    fn sign(&self, y: usize, marked: &Marked) -> Sign<'_> {
+        if marked.stopped == Some(y) {
+            return Sign::Stopped;
+        }
+        if marked.breakpoints.contains(&y) {
+            return Sign::Breakpoint;
+        }
+        match self.diagnostics.on_line(y) {
+            Some(diagnostic) => Sign::Diagnostic(diagnostic),
+            None if marked.bookmarks.contains(&y) => Sign::Bookmark,
+            None => Sign::None,
+        }
+    }
+    fn open_recent_file(&mut self, index: usize) {
+        if let Some(file) = self.oldfiles.files().get(index).cloned() {
+            self.open_full_path(&file);
+        }
+    }
+    // Opens the file at the full path `file`, named from the working
+    // directory when it is under it.
+    fn open_full_path(&mut self, file: &str) -> bool {
+        let path = Path::new(file);
+        let path = env::current_dir()
+            .ok()
+            .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.to_path_buf());
+        self.open_file(&path.display().to_string())
+    }
+    // `<leader>s`: every symbol in the nearest tags file, to pick one from
+    // and jump to, opening its file if need be.
+    fn workspace_symbols(&mut self) {
+        let dir = match &self.document.file_name {
+            Some(file_name) => Path::new(file_name)
+                .canonicalize()
+                .ok()
+                .and_then(|path| path.parent().map(Path::to_path_buf)),
+            None => None,
+        };
+        let Some(dir) = dir.or_else(|| env::current_dir().ok()) else {
+            return;
+        };
+        let symbols = match tags::workspace(&dir) {
+            Ok(symbols) if !symbols.is_empty() => symbols,
+            Ok(_) => {
+                self.set_status(StatusMessage::error("No symbols"));
+                return;
+            }
+            Err(error) => {
+                self.set_status(StatusMessage::error(error));
+                return;
+            }
+        };
+        let cwd = env::current_dir().unwrap_or_default();
+        let items = symbols
+            .iter()
+            .map(|tag| {
+                let path = Path::new(&tag.file);
+                let file = path.strip_prefix(&cwd).unwrap_or(path).display();
+                let detail = match tag.line {
+                    Some(line) => format!("{} {file}:{line}", tag.kind),
+                    None => format!("{} {file}", tag.kind),
+                };
+                Item {
+                    text: tag.name.clone(),
+                    detail,
+                }
+            })
+            .collect();
+        let chosen = self.pick("Symbol: ", items, 0).unwrap_or(None);
+        let Some(tag) = chosen.and_then(|index| symbols.get(index)) else {
+            return;
+        };
+        let here = self
+            .document
+            .file_name
+            .as_ref()
+            .and_then(|file_name| Path::new(file_name).canonicalize().ok());
+        if here != Path::new(&tag.file).canonicalize().ok() && !self.open_file(&tag.file) {
+            return;
+        }
+        if let Some(y) = tag.locate(&self.document) {
+            self.cursor_position = Position { x: 0, y };
+            self.clamp_cursor();
+            self.scroll();
+        }
+    }
+    // `:DebugStart {program} [args]`: runs `program` under the debug
+    // adapter `debugadapter`.
+    fn start_debugging(&mut self, command: &str) {
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            self.set_status(StatusMessage::error("Argument required: DebugStart"));
+            return;
+        };
+        if self.options.debugadapter.is_empty() {
+            self.set_status(StatusMessage::error("No debugadapter set"));
+            return;
+        }
+        let args: Vec<&str> = words.collect();
+        let cwd = env::current_dir().unwrap_or_default().display().to_string();
+        let started =
+            Adapter::spawn(&self.options.debugadapter, self.events.sender()).and_then(|adapter| {
+                let program = config::full_path(program);
+                self.debugger.start(adapter, &program, &args, &cwd)
+            });
+        match started {
+            Ok(()) => self.set_status(StatusMessage::from(format!("Debugging {program}"))),
+            Err(error) => self.set_status(StatusMessage::error(format!(
+                "Could not start {}: {error}",
+                self.options.debugadapter
+            ))),
+        }
+    }
+    // `:DebugStop`: ends the program and its adapter.
+    fn stop_debugging(&mut self) {
+        if self.debugger.is_running() {
+            self.debugger.end();
+        } else {
+            self.set_status(StatusMessage::error("Not debugging"));
+        }
+    }
+    // `:DebugBreakpoint`: sets a breakpoint on the cursor line, or takes
+    // away the one there.
+    fn toggle_breakpoint(&mut self) {
+        let Some(file_name) = self.document.file_name.as_deref().map(config::full_path) else {
+            self.set_status(StatusMessage::error("No file name"));
+            return;
+        };
+        if let Err(error) = self.debugger.toggle(&file_name, self.cursor_position.y) {
+            self.set_status(StatusMessage::error(format!("Debug adapter: {error}")));
+        }
+    }
+    // `:DebugContinue` and `:DebugStep`: runs the program on from where it
+    // stopped, with the adapter's `continue` or `next`.
+    fn resume_debugging(&mut self, command: &str) {
+        if !self.debugger.is_stopped() {
+            self.set_status(StatusMessage::error("The program is not stopped"));
+            return;
+        }
+        if let Err(error) = self.debugger.resume(command) {
+            self.set_status(StatusMessage::error(format!("Debug adapter: {error}")));
+        }
+    }
+    // `:DebugVariables`: the variables where the program stopped.
+    fn list_variables(&mut self) {
+        if !self.debugger.is_stopped() {
+            self.set_status(StatusMessage::error("The program is not stopped"));
+            return;
+        }
+        let width = self.screen_width();
+        let mut lines = vec![String::from("--- Variables ---")];
+        lines.extend(
+            self.debugger
+                .variables()
+                .iter()
+                .map(|variable| statusline::truncate(variable, width)),
+        );
+        self.set_status(StatusMessage::from(lines.join("\n")));
+    }
+    // A message from the debug adapter: opens the file the program stopped
+    // in at its line, unless the current buffer has unsaved changes.
+    fn follow_debugger(&mut self, adapter: usize, message: Option<serde_json::Value>) {
+        match self.debugger.handle(adapter, message) {
+            Update::None => (),
+            Update::Stopped(file_name, line) => {
+                let current = self.document.file_name.as_deref().map(config::full_path);
+                if current.as_deref() != Some(file_name.as_str())
+                    && !self.open_full_path(&file_name)
+                {
+                    return;
+                }
+                self.cursor_position = Position { x: 0, y: line };
+                self.clamp_cursor();
+                self.scroll();
+            }
+            Update::Ended(message) => self.set_status(StatusMessage::from(message)),
+            Update::Error(message) => self.set_status(StatusMessage::error(message)),
+        }
+    }
+    // `:Preview`: serves the markdown buffer as a web page that follows it
+    // as it changes, and opens it in the browser.
+    fn preview(&mut self) {
+        let file_name = self.document.file_name.clone().unwrap_or_default();
+        let extension = Path::new(&file_name)
+            .extension()
+            .and_then(|ext| ext.to_str());
+        if !matches!(extension, Some("md" | "markdown")) {
+            self.set_status(StatusMessage::error("Not a markdown file"));
+            return;
+        }
+        if self.preview.is_none() {
+            match Preview::start(&file_name) {
+                Ok(preview) => self.preview = Some(preview),
+                Err(error) => {
+                    self.set_status(StatusMessage::error(format!(
+                        "Could not start the preview: {error}"
+                    )));
+                    return;
+                }
+            }
+            self.update_preview();
+        }
+        let Some(url) = self.preview.as_ref().map(Preview::url) else {
+            return;
+        };
+        // tests read the page themselves
+        let opened = if cfg!(test) {
+            Ok(())
+        } else {
+            preview::open_browser(&url)
+        };
+        self.set_status(match opened {
+            Ok(()) => StatusMessage::from(format!("Previewing at {url}")),
+            Err(error) => StatusMessage::error(format!(
+                "Previewing at {url}, but could not open it: {error}"
+            )),
+        });
+    }
+    fn update_preview(&mut self) {
+        let revision = self.document.revision();
+        let Some(preview) = self
+            .preview
+            .as_mut()
+            .filter(|preview| preview.revision != Some(revision))
+        else {
+            return;
+        };
+        let text: Vec<&str> = (0..self.document.len())
+            .filter_map(|index| self.document.row(index))
+            .map(Row::as_str)
+            .collect();
+        preview.update(revision, &text.join("\n"));
+    }
+    // Lines up `delimiter` across the lines from `start` to `end`, 0-based,
+    // or else across the lines around the cursor that have it.
+    fn align(&mut self, lines: Option<(usize, usize)>, delimiter: &str) {
+        if self.document.is_read_only() {
+            self.set_status(StatusMessage::error("Cannot modify a read-only buffer."));
+            return;
+        }
+        let has_delimiter = |y: usize| {
+            self.document
+                .row(y)
+                .is_some_and(|row| row.as_str().contains(delimiter))
+        };
+        let (start, end) = match lines {
+            Some(lines) => lines,
+            None if has_delimiter(self.cursor_position.y) => {
+                let y = self.cursor_position.y;
+                let mut start = y;
+                while start > 0 && has_delimiter(start.saturating_sub(1)) {
+                    start = start.saturating_sub(1);
+                }
+                let mut end = y;
+                while has_delimiter(end.saturating_add(1)) {
+                    end = end.saturating_add(1);
+                }
+                (start, end)
+            }
+            None => {
+                self.set_status(StatusMessage::error(format!(
+                    "Pattern not found: {delimiter}"
+                )));
+                return;
+            }
+        };
+        let old: Vec<&str> = (start..=end)
+            .filter_map(|y| self.document.row(y))
+            .map(Row::as_str)
+            .collect();
+        let new = align::align(&old, delimiter);
+        if old != new {
+            self.document
+                .replace_lines(start, end.saturating_add(1), &new);
+            self.clamp_cursor();
+        }
+    }
+    // `s{char}{char}`: jumps to the next place on the screen the two
+    // characters appear, and labels the others. Typing a label next jumps
+    // there instead.
+    fn sneak(&mut self) -> Result<(), std::io::Error> {
+        let mut query = String::new();
+        while query.chars().count() < 2 {
+            self.set_status(StatusMessage::prompt(format!("s{query}")));
+            self.refresh_screen()?;
+            match self.read_input()? {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    modifiers,
+                }) if !modifiers.contains(KeyModifiers::CONTROL) => query.push(c),
+                _ => break,
+            }
+        }
+        self.set_status(StatusMessage::from(String::new()));
+        if query.chars().count() < 2 {
+            return Ok(());
+        }
+        let pattern = Pattern::literal(&query);
+        let height = usize::from(self.terminal.size().height);
+        let text_width = self.text_width();
+        let mut targets: Vec<Position> = Vec::new();
+        let mut y = self.offset.y;
+        let mut lines = 0_usize;
+        while lines < height {
+            let Some(row) = self.document.row(y) else {
+                break;
+            };
+            targets.extend(
+                row.matches(&pattern)
+                    .map(|x| Position { x, y })
+                    .filter(|position| {
+                        let at = self.screen_position(position);
+                        at.y < height
+                            && (self.options.wrap
+                                || (row.column(position.x) >= self.offset.x && at.x < text_width))
+                    }),
+            );
+            lines = lines.saturating_add(self.row_height(y));
+            y = y.saturating_add(1);
+        }
+        let here = (self.cursor_position.y, self.cursor_position.x);
+        let Some(next) = targets
+            .iter()
+            .position(|target| (target.y, target.x) > here)
+        else {
+            self.set_status(StatusMessage::error(format!("Not found: {query}")));
+            return Ok(());
+        };
+        // the rest are labelled going on down the screen, then from the top
+        targets.rotate_left(next);
+        let mut targets = targets.into_iter();
+        if let Some(target) = targets.next() {
+            self.cursor_position = target;
+        }
+        self.sneak_labels = targets.zip(SNEAK_LABELS.chars()).collect();
+        Ok(())
+    }
+    // Opens `file_name`, creating it first if it does not exist yet.
+    fn edit_file(&mut self, file_name: &str) -> bool {
+        if !Path::new(file_name).exists() {
+            let _ = File::create(file_name);
+        }
+        self.open_file(file_name)
+    }
+    fn edit_alternate(&mut self) {
+        let (file_name, position) = if let Some(alternate) = &self.alternate_file {
+            alternate.clone()
+        } else {
+            self.set_status(StatusMessage::error("No alternate file."));
+            return;
+        };
+        if self.open_file(&file_name) {
+            self.cursor_position = position;
+            self.clamp_cursor();
+        }
+    }
+    pub fn execute(&mut self, input: &str) {
+        logging::debug("command", input);
+        let (range, command) = match Command::parse(input) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                self.set_status(StatusMessage::error(error.to_string()));
+                return;
+            }
+        };
+        if range.is_some() && !command.accepts_range() {
+            self.set_status(StatusMessage::error("No range allowed"));
+            return;
+        }
+        if !matches!(command, Command::Quit { .. }) {
+            self.quit_presses = None;
+        }
+        let lines = range.map(|range| {
+            range.resolve(
+                self.cursor_position.y.saturating_add(1),
+                self.document.len(),
+            )
+        });
+        match command {
+            Command::Goto => {
+                if let Some((_, line)) = lines {
+                    let from = self.cursor_position.clone();
+                    self.cursor_position.y = line.saturating_sub(1);
+                    self.clamp_cursor();
+                    self.jumped_from(&from);
+                }
+            }
+            Command::Write(file_name) => {
+                if self.is_still_saving() {
+                    return;
+                }
+                if let Some(file_name) = file_name {
+                    let file_name = self.resolve_path(&self.expand_file_name(&file_name));
+                    self.document.file_name = Some(file_name);
+                }
+                self.save();
+            }
+            Command::Quit { force, confirm } => self.quit(force, confirm),
+            Command::WriteQuit => self.start_save(true),
+            Command::WriteAll { quit } => self.write_all(quit),
+            Command::Buffers => self.list_buffers(),
+            Command::Edit(Some(file_name)) if file_name == "#" => self.edit_alternate(),
+            Command::Edit(Some(file_name)) => {
+                let file_name = self.resolve_path(&self.expand_file_name(&file_name));
+                self.edit_file(&file_name);
+            }
+            Command::Edit(None) => {
+                if let Some(file_name) = self.document.file_name.clone() {
+                    let position = self.cursor_position.clone();
+                    let alternate = self.alternate_file.clone();
+                    if self.open_file(&file_name) {
+                        self.alternate_file = alternate;
+                        self.cursor_position = position;
+                        self.clamp_cursor();
+                    }
+                } else {
+                    self.set_status(StatusMessage::error("No file name."));
+                }
+            }
+            Command::Args => {
+                let message = if self.arglist.is_empty() {
+                    "Argument list is empty.".to_string()
+                } else {
+                    self.arglist
+                        .files()
+                        .iter()
+                        .map(|file| {
+                            if self.document.file_name.as_ref() == Some(file) {
+                                format!("[{file}]")
+                            } else {
+                                file.clone()
+                            }
+                        })
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                };
+                self.set_status(StatusMessage::from(message));
+            }
+            Command::ArgAdd(files) => {
+                if files.is_empty() {
+                    if let Some(file_name) = &self.document.file_name {
+                        self.arglist.add(file_name);
+                    }
+                }
+                for file in &files {
+                    self.arglist.add(file);
+                }
+            }
+            Command::ArgDelete(patterns) => {
+                for pattern in &patterns {
+                    if !self.arglist.delete(pattern) {
+                        self.set_status(StatusMessage::error(format!("No match: {pattern}")));
+                    }
+                }
+            }
+            Command::ArgDo(command) => self.argdo(&command),
+            Command::Set(assignments) => {
+                for assignment in &assignments {
+                    if let Some(name) = assignment.strip_suffix('?') {
+                        self.set_status(match self.options.get(name) {
+                            Some(value) => StatusMessage::from(format!("{name}={value}")),
+                            None => StatusMessage::error(format!("Unknown option: {name}")),
+                        });
+                    } else if let Some(name) = assignment
+                        .strip_prefix("filetype=")
+                        .or_else(|| assignment.strip_prefix("ft="))
+                    {
+                        // the type belongs to the buffer, not the options
+                        if !self.document.set_file_type(name) {
+                            self.set_status(StatusMessage::error(format!(
+                                "Unknown file type: {name}"
+                            )));
+                            return;
                         }
-
-                        self.move_cursor(Event::Key(KeyEvent {
-                            code: KeyCode::Right,
-                            modifiers: KeyModifiers::NONE,
-                        }));
-                        i += 1;
+                        self.options.use_file_type(&self.document.file_type());
+                    } else if let Err(error) = self.options.set(assignment) {
+                        self.set_status(StatusMessage::error(error));
+                        return;
                     }
                 }
-
-                // Save with :w in normal mode.
-                if self.previous_characters.last() == Some(&':') {
-                    self.save();
-                    self.previous_characters.clear();
+                self.open_log();
+                self.scroll();
+            }
+            Command::FileTypeSet {
+                file_type,
+                assignments,
+            } => {
+                for assignment in &assignments {
+                    if let Err(error) = self.options.set_for(&file_type, assignment) {
+                        self.set_status(StatusMessage::error(error));
+                        return;
+                    }
+                }
+                self.scroll();
+            }
+            Command::Lua(source) => self.run_lua(&source),
+            Command::Source(file_name) => {
+                let path = match file_name.as_str() {
+                    "%" => self.document.file_name.clone().map(PathBuf::from),
+                    _ => Some(config::expand_home(&file_name)),
+                };
+                let Some(path) = path else {
+                    self.set_status(StatusMessage::error("No file name."));
+                    return;
+                };
+                if let Err(error) = self.source(&path) {
+                    self.set_status(StatusMessage::error(format!(
+                        "Can't open {}: {error}",
+                        path.display()
+                    )));
+                }
+            }
+            Command::Define {
+                name,
+                definition,
+                force,
+            } => {
+                if !force && self.user_commands.contains_key(&name) {
+                    self.set_status(StatusMessage::error(
+                        "Command already exists: add ! to replace it",
+                    ));
+                    return;
+                }
+                self.user_commands.insert(name, definition);
+            }
+            Command::ListUser => {
+                let mut names: Vec<&String> = self.user_commands.keys().collect();
+                names.sort();
+                let message = if names.is_empty() {
+                    "No user-defined commands found".to_string()
+                } else {
+                    names
+                        .iter()
+                        .map(|name| format!("{name} {}", self.user_commands[*name]))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                };
+                self.set_status(StatusMessage::from(message));
+            }
+            Command::DeleteUser(name) => {
+                if self.user_commands.remove(&name).is_none() {
+                    self.set_status(StatusMessage::error(format!(
+                        "No such user-defined command: {name}"
+                    )));
+                }
+            }
+            Command::User { name, argument } => {
+                self.run_user_command(&name, argument.as_deref(), lines);
+            }
+            Command::Map {
+                modes,
+                lhs,
+                rhs,
+                description,
+            } => {
+                let lhs = keymap::expand_leader(&lhs, &self.options.mapleader);
+                for mode in modes {
+                    if let Err(error) = self.keymaps.bind(mode, &lhs, &rhs, description.clone()) {
+                        self.set_status(StatusMessage::error(error));
+                        return;
+                    }
+                }
+            }
+            Command::ListMaps { modes, prefix } => {
+                let bindings: Vec<String> = modes
+                    .iter()
+                    .flat_map(|mode| self.keymaps.list(*mode, &prefix))
+                    .collect();
+                let message = if bindings.is_empty() {
+                    "No mapping found".to_string()
+                } else {
+                    bindings.join("\n")
+                };
+                self.set_status(StatusMessage::from(message));
+            }
+            Command::CheckHealth => self.check_health(),
+            Command::Messages => self.show_messages(),
+            Command::Outline => self.outline(),
+            Command::YankHistory => self.yank_history(),
+            Command::DebugStart(command) => self.start_debugging(&command),
+            Command::DebugStop => self.stop_debugging(),
+            Command::DebugBreakpoint => self.toggle_breakpoint(),
+            Command::DebugContinue => self.resume_debugging("continue"),
+            Command::DebugStep => self.resume_debugging("next"),
+            Command::DebugVariables => self.list_variables(),
+            Command::Files(dir) => self.find_file(dir.as_deref()),
+            Command::OldFiles => {
+                let lines: Vec<String> = self
+                    .oldfiles
+                    .files()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, file)| format!("{}: {file}", index.saturating_add(1)))
+                    .collect();
+                self.set_status(StatusMessage::from(lines.join("\n")));
+            }
+            Command::Recent => self.pick_recent_file(),
+            Command::UndoList => self.list_undo_branches(),
+            Command::Registers(names) => self.list_registers(&names),
+            Command::BookmarkAdd(note) => self.add_bookmark(&note),
+            Command::BookmarkDelete => self.delete_bookmark(),
+            Command::Bookmarks => self.pick_bookmark(),
+            Command::Preview => self.preview(),
+            Command::Rename(name) => self.rename_file(&name),
+            Command::Delete { force } => {
+                if let Err(error) = self.delete_file(force) {
+                    self.set_status(StatusMessage::error(error.to_string()));
+                }
+            }
+            Command::Mkdir(dir) => self.make_directory(dir.as_deref()),
+            Command::ChangeDir { dir, local } => self.change_dir(dir.as_deref(), local),
+            Command::Pwd => {
+                let dir = self.working_dir().display().to_string();
+                self.set_status(StatusMessage::from(dir));
+            }
+            Command::Normal(keys) => {
+                let keys = match keymap::parse_keys(&keys) {
+                    Ok(keys) => keys.into_iter().map(Event::Key).collect::<Vec<_>>(),
+                    Err(error) => {
+                        self.set_status(StatusMessage::error(error));
+                        return;
+                    }
+                };
+                let lines =
+                    lines.map(|(start, end)| (start.saturating_sub(1), end.saturating_sub(1)));
+                if let Err(error) =
+                    self.on_each_line(lines, |editor| editor.type_keys(keys.clone()))
+                {
+                    self.set_status(StatusMessage::error(error.to_string()));
+                }
+            }
+            Command::Copy(address) => {
+                let current = self.cursor_position.y.saturating_add(1);
+                let (start, end) = lines.unwrap_or((current, current));
+                let after = match address {
+                    Address::Current => current,
+                    Address::Last => self.document.len(),
+                    Address::Line(line) => line,
+                };
+                self.copy_lines((start.saturating_sub(1), end.saturating_sub(1)), after);
+            }
+            Command::Move(address) => {
+                let current = self.cursor_position.y.saturating_add(1);
+                let (start, end) = lines.unwrap_or((current, current));
+                let after = match address {
+                    Address::Current => current,
+                    Address::Last => self.document.len(),
+                    Address::Line(line) => line,
+                };
+                if after >= start && after < end {
+                    self.set_status(StatusMessage::error(
+                        "Cannot move a range of lines into itself",
+                    ));
+                    return;
+                }
+                let (start, end) = (start.saturating_sub(1), end.saturating_sub(1));
+                let first = self.move_lines((start, end), after);
+                self.cursor_position.y = first.saturating_add(end.saturating_sub(start));
+                self.clamp_cursor();
+            }
+            Command::Align(delimiter) => {
+                let lines =
+                    lines.map(|(start, end)| (start.saturating_sub(1), end.saturating_sub(1)));
+                self.align(lines, &delimiter);
+            }
+            Command::Highlight(arguments) => match self.theme.highlight(&arguments) {
+                Ok(Some(message)) => self.set_status(StatusMessage::from(message)),
+                Ok(None) => (),
+                Err(error) => self.set_status(StatusMessage::error(error)),
+            },
+            Command::Unmap { modes, lhs } => {
+                let lhs = keymap::expand_leader(&lhs, &self.options.mapleader);
+                for mode in modes {
+                    if let Err(error) = self.keymaps.remove(mode, &lhs) {
+                        self.set_status(StatusMessage::error(error));
+                    }
+                }
+            }
+        }
+    }
+    fn run_user_command(
+        &mut self,
+        name: &str,
+        argument: Option<&str>,
+        lines: Option<(usize, usize)>,
+    ) {
+        let Some(definition) = self.user_commands.get(name) else {
+            self.set_status(StatusMessage::error(format!(
+                "Not an editor command: {name}"
+            )));
+            return;
+        };
+        if self.command_depth >= MAX_COMMAND_DEPTH {
+            self.set_status(StatusMessage::error(
+                "User command nested too deeply, possible recursion",
+            ));
+            return;
+        }
+        match definition.expand(argument, lines) {
+            Ok(expanded) => {
+                self.command_depth = self.command_depth.saturating_add(1);
+                self.execute(&expanded);
+                self.command_depth = self.command_depth.saturating_sub(1);
+            }
+            Err(error) => self.set_status(StatusMessage::error(error.to_string())),
+        }
+    }
+    // Runs each line of an rvimrc-style file as an ex command. Empty lines
+    // and lines starting with `"` are skipped. A failing line doesn't stop
+    // the rest; the first error is reported with its file and line number.
+    // Returns every failing line, the first of which is also shown.
+    fn source(&mut self, path: &Path) -> Result<Vec<String>, std::io::Error> {
+        let contents = fs::read_to_string(path)?;
+        let mut errors = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            self.status_message.is_error = false;
+            self.execute(line);
+            if self.status_message.is_error {
+                errors.push(format!(
+                    "{}:{}: {}",
+                    path.display(),
+                    index.saturating_add(1),
+                    self.status_message.text
+                ));
+            }
+        }
+        if let Some((first, rest)) = errors.split_first() {
+            self.set_status(StatusMessage::error(match rest.len() {
+                0 => first.clone(),
+                1 => format!("{first} (and 1 more error)"),
+                more => format!("{first} (and {more} more errors)"),
+            }));
+        }
+        Ok(errors)
+    }
+    #[cfg(feature = "lua")]
+    fn run_lua(&mut self, source: &str) {
+        self.with_scripting(|scripting, editor| scripting.exec(editor, source, ":lua"));
+    }
+    #[cfg(not(feature = "lua"))]
+    fn run_lua(&mut self, _source: &str) {
+        self.set_status(StatusMessage::error("Lua support is not compiled in."));
+    }
+    // The interpreter is moved out of the editor while a script runs, so
+    // scripts re-entering Lua (e.g. `rvim.command("lua ...")`) are refused.
+    #[cfg(feature = "lua")]
+    fn with_scripting<F>(&mut self, f: F)
+    where
+        F: FnOnce(&Scripting, &mut Self) -> mlua::Result<()>,
+    {
+        if let Some(scripting) = self.scripting.take() {
+            if let Err(error) = f(&scripting, self) {
+                let error = error.to_string();
+                let first_line = error.lines().next().unwrap_or_default();
+                self.set_status(StatusMessage::error(format!("Lua error: {first_line}")));
+            }
+            self.scripting = Some(scripting);
+        } else {
+            self.set_status(StatusMessage::error("Lua is already running."));
+        }
+    }
+    #[cfg_attr(
+        not(any(feature = "lua", unix)),
+        allow(clippy::unused_self, unused_variables)
+    )]
+    fn fire_event(&mut self, event: &str) {
+        #[cfg(feature = "lua")]
+        if self.scripting.is_some() {
+            self.with_scripting(|scripting, editor| scripting.fire(editor, event));
+        }
+        #[cfg(unix)]
+        if let Some(server) = self.rpc.as_mut() {
+            let file_name = self.document.file_name.clone().unwrap_or_default();
+            server.notify(event, vec![file_name.into()]);
+        }
+    }
+    // `:q`: quits, unless a buffer, shown or hidden, has unsaved changes.
+    // What happens then is up to the `confirm` option, or a dialog for
+    // `:confirm q`.
+    fn quit(&mut self, force: bool, confirm: bool) {
+        let hidden = self.hidden_buffers.len();
+        if force || (hidden == 0 && !self.document.is_dirty()) {
+            self.should_quit = true;
+            return;
+        }
+        let confirm = if confirm {
+            Confirm::Dialog
+        } else {
+            self.options.confirm
+        };
+        match confirm {
+            Confirm::Never if hidden == 0 => {
+                self.set_status(StatusMessage::error("WARNING! File has unsaved changes."));
+            }
+            Confirm::Never => {
+                let buffers = hidden.saturating_add(usize::from(self.document.is_dirty()));
+                self.set_status(StatusMessage::error(format!(
+                    "WARNING! {buffers} buffer{} with unsaved changes; see :ls.",
+                    if buffers == 1 { "" } else { "s" }
+                )));
+            }
+            Confirm::Presses(needed) => {
+                let revision = self.document.revision();
+                let presses = match self.quit_presses {
+                    Some((at, presses)) if at == revision => presses.saturating_add(1),
+                    _ => 1,
+                };
+                if presses >= needed {
+                    self.should_quit = true;
+                    return;
+                }
+                self.quit_presses = Some((revision, presses));
+                let left = needed.saturating_sub(presses);
+                self.set_status(StatusMessage::error(format!(
+                    "WARNING! Unsaved changes. Quit {left} more time{}.",
+                    if left == 1 { "" } else { "s" }
+                )));
+            }
+            Confirm::Dialog => {
+                if let Err(error) = self.confirm_quit() {
+                    self.set_status(StatusMessage::error(error.to_string()));
+                }
+            }
+        }
+    }
+    // Asks about each buffer with unsaved changes, the one shown first,
+    // whether to save them, throw them away or stay. Nothing is written
+    // or thrown away until every one has been answered.
+    fn confirm_quit(&mut self) -> Result<(), std::io::Error> {
+        let save_current = if self.document.is_dirty() {
+            match self.ask_to_save(&buffer_name(&self.document))? {
+                Some(save) => save,
+                None => return Ok(()),
+            }
+        } else {
+            false
+        };
+        let mut save_hidden = Vec::new();
+        for index in 0..self.hidden_buffers.len() {
+            let Some(buffer) = self.hidden_buffers.get(index) else {
+                break;
+            };
+            match self.ask_to_save(&buffer_name(&buffer.document))? {
+                Some(save) => save_hidden.push(save),
+                None => return Ok(()),
+            }
+        }
+        // from the end, so the indexes of those not written yet stay put
+        for (index, save) in save_hidden.into_iter().enumerate().rev() {
+            if !save {
+                continue;
+            }
+            match self.write_hidden(index) {
+                Ok(None) => (),
+                // a warning stays on screen rather than going with the editor
+                Ok(Some(error)) | Err(error) => {
+                    self.set_status(StatusMessage::error(error));
+                    return Ok(());
+                }
+            }
+        }
+        // those left are the ones to throw away
+        self.hidden_buffers.clear();
+        if save_current {
+            self.start_save(true);
+        } else {
+            self.should_quit = true;
+        }
+        Ok(())
+    }
+    // Some(true) to save the changes to `name`, Some(false) to throw them
+    // away, None to stay.
+    fn ask_to_save(&mut self, name: &str) -> Result<Option<bool>, std::io::Error> {
+        self.set_status(StatusMessage::prompt(format!(
+            "Save changes to \"{name}\"? [y]es, [n]o, [c]ancel: "
+        )));
+        self.refresh_screen()?;
+        let answer = self.read_input()?;
+        self.set_status(StatusMessage::from(String::new()));
+        Ok(match answer {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('y' | 'Y'),
+                ..
+            }) => Some(true),
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('n' | 'N'),
+                ..
+            }) => Some(false),
+            _ => None,
+        })
+    }
+    // Writes the buffer shown and waits for the write to land. The warning
+    // is why it was written without formatting, if it was; the error is why
+    // it was not written at all.
+    fn write_and_wait(&mut self) -> Result<Option<String>, String> {
+        self.start_save(false);
+        self.wait_for_save().map_err(|error| error.to_string())?;
+        if self.document.is_dirty() {
+            return Err(self.status_message.text.clone());
+        }
+        Ok(self
+            .status_message
+            .is_error
+            .then(|| self.status_message.text.clone()))
+    }
+    // Writes the hidden buffer at `index`, which goes from the list once
+    // it has no unsaved changes left, as `write_and_wait` does.
+    fn write_hidden(&mut self, index: usize) -> Result<Option<String>, String> {
+        if self.saving.is_some() {
+            return Err("still writing another file".to_string());
+        }
+        let buffer = self.hidden_buffers.remove(index);
+        let shown = mem::replace(&mut self.document, buffer.document);
+        let cursor = mem::replace(&mut self.cursor_position, buffer.cursor);
+        let offset = mem::take(&mut self.offset);
+        self.options.use_file_type(&self.document.file_type());
+        let result = self.write_and_wait();
+        let written = mem::replace(&mut self.document, shown);
+        let written_cursor = mem::replace(&mut self.cursor_position, cursor);
+        self.offset = offset;
+        self.options.use_file_type(&self.document.file_type());
+        self.git_diff.reload(&self.document);
+        if written.is_dirty() {
+            self.hidden_buffers.insert(
+                index,
+                HiddenBuffer {
+                    document: written,
+                    cursor: written_cursor,
+                },
+            );
+        }
+        result
+    }
+    // `:wa`: writes every buffer with unsaved changes, going on past any
+    // that fail and listing them at the end, along with any written without
+    // formatting. `:wqa` then quits if all of them were written cleanly.
+    fn write_all(&mut self, quit: bool) {
+        if self.is_still_saving() {
+            return;
+        }
+        let (mut failed, mut warnings) = (Vec::new(), Vec::new());
+        let mut index = 0;
+        while let Some(buffer) = self.hidden_buffers.get(index) {
+            let name = buffer_name(&buffer.document);
+            let hidden = self.hidden_buffers.len();
+            let result = self.write_hidden(index);
+            // a buffer written goes from the list, so the next one takes
+            // its place; one put back is stepped over
+            if self.hidden_buffers.len() == hidden {
+                index = index.saturating_add(1);
+            }
+            match result {
+                Ok(None) => (),
+                Ok(Some(warning)) => warnings.push(format!("{name}: {warning}")),
+                Err(error) => failed.push(format!("{name}: {error}")),
+            }
+        }
+        if self.document.is_dirty() {
+            let name = buffer_name(&self.document);
+            match self.write_and_wait() {
+                Ok(None) => (),
+                Ok(Some(warning)) => warnings.push(format!("{name}: {warning}")),
+                Err(error) => failed.push(format!("{name}: {error}")),
+            }
+        }
+        if !failed.is_empty() {
+            let mut lines = vec![String::from("Could not write every buffer:")];
+            lines.extend(failed);
+            lines.extend(warnings);
+            self.set_status(StatusMessage::error(lines.join("\n")));
+        } else if !warnings.is_empty() {
+            // the warnings stay on screen rather than going with the editor
+            self.set_status(StatusMessage::error(warnings.join("\n")));
+        } else if quit {
+            self.should_quit = true;
+        }
+    }
+    // `:ls`: the buffer shown, `%a`, and the hidden ones, `h`, each with a
+    // `+` if it has unsaved changes.
+    fn list_buffers(&mut self) {
+        let shown = (&self.document, &self.cursor_position, "%a");
+        let hidden = self
+            .hidden_buffers
+            .iter()
+            .map(|buffer| (&buffer.document, &buffer.cursor, " h"));
+        let lines: Vec<String> = std::iter::once(shown)
+            .chain(hidden)
+            .map(|(document, cursor, flags)| {
+                let modified = if document.is_dirty() { "+" } else { " " };
+                format!(
+                    "{flags} {modified} \"{}\" line {}",
+                    buffer_name(document),
+                    cursor.y.saturating_add(1)
+                )
+            })
+            .collect();
+        self.set_status(StatusMessage::from(lines.join("\n")));
+    }
+    // Runs `command` in every file of the argument list, writing each file
+    // back out if the command changed it.
+    fn argdo(&mut self, command: &str) {
+        if self.document.is_dirty() {
+            self.set_status(StatusMessage::error("WARNING! File has unsaved changes."));
+            return;
+        }
+        for file in self.arglist.files().to_vec() {
+            if self.document.file_name.as_ref() != Some(&file) && !self.open_file(&file) {
+                return;
+            }
+            self.execute(command);
+            if self.document.is_dirty() {
+                self.save();
+                if let Err(error) = self.wait_for_save() {
+                    self.set_status(StatusMessage::error(error.to_string()));
+                    return;
                 }
             }
+            if self.should_quit {
+                return;
+            }
+        }
+    }
+    fn clamp_cursor(&mut self) {
+        let y = self
+            .cursor_position
+            .y
+            .min(self.document.len().saturating_sub(1));
+        let x = self.cursor_position.x.min(self.last_column(y));
+        self.cursor_position = Position { x, y };
+    }
+    // `m{name}`: lowercase marks and `'` belong to the document, uppercase
+    // ones to the file.
+    fn set_mark(&mut self, name: char) {
+        if name.is_ascii_lowercase() || matches!(name, '\'' | '`') {
+            let name = if name == '`' { '\'' } else { name };
+            self.document.set_mark(name, &self.cursor_position);
+            return;
+        }
+        if !Marks::is_global(name) {
+            return;
+        }
+        if let Some(file_name) = &self.document.file_name {
+            self.marks
+                .set_global(name, &config::full_path(file_name), &self.cursor_position);
+        } else {
+            self.set_status(StatusMessage::error(
+                "Cannot set a mark in an unnamed buffer.",
+            ));
+        }
+    }
+    // `` `{name} `` goes to the mark, and `'{name}` to the first non-blank
+    // of its line; either way, `''` comes back. `'.` is where the last
+    // change was made.
+    fn jump_to_mark(&mut self, key: char, name: char) {
+        let from = self.cursor_position.clone();
+        let name = if name == '`' { '\'' } else { name };
+        if let Some(mark) = self.document.mark(name) {
+            self.cursor_position = mark.clone();
+        } else if let Some(mark) = self.marks.global(name).cloned() {
+            let current = self.document.file_name.as_deref().map(config::full_path);
+            if current.as_deref() != Some(mark.file_name.as_str())
+                && !self.open_full_path(&mark.file_name)
+            {
+                return;
+            }
+            self.cursor_position = mark.position;
+        } else {
+            self.set_status(StatusMessage::error(format!("Mark {name} not set.")));
+            return;
+        }
+        self.clamp_cursor();
+        if key == '\'' {
+            let row = self.document.row(self.cursor_position.y);
+            let blanks = row.map_or(0, |row| {
+                (0..row.len())
+                    .take_while(|&x| row.get(x).is_some_and(|c| c.trim().is_empty()))
+                    .count()
+            });
+            self.cursor_position.x = blanks.min(self.last_column(self.cursor_position.y));
+        }
+        self.jumped_from(&from);
+    }
+    // Keeps where a jump left from as the mark `'`, for `''` to go back
+    // to, unless it stayed on the same line.
+    fn jumped_from(&mut self, from: &Position) {
+        if from.y != self.cursor_position.y {
+            self.document.set_mark('\'', from);
+        }
+    }
+    fn search(&mut self) {
+        let old_position = self.cursor_position.clone();
+        self.searcher.cancel();
+        let query = self
+            .read_line(
+                Self::search_prompt,
+                Some(HistoryKind::Search),
+                |editor, key, query| {
+                    let Event::Key(KeyEvent { code, modifiers }) = key else {
+                        return;
+                    };
+                    let direction = match (code, modifiers) {
+                        (KeyCode::Char('g'), KeyModifiers::CONTROL) => SearchDirection::Forward,
+                        (KeyCode::Char('t'), KeyModifiers::CONTROL) => SearchDirection::Backward,
+                        _ => {
+                            if let (KeyCode::Char(c), KeyModifiers::ALT) = (code, modifiers) {
+                                editor.toggle_search_flag(c);
+                            }
+                            editor.start_search(query, old_position.clone());
+                            return;
+                        }
+                    };
+                    editor.search_jump = false;
+                    let Some(pattern) = editor
+                        .searcher
+                        .search()
+                        .map(|search| search.pattern.clone())
+                    else {
+                        return;
+                    };
+                    let at = match direction {
+                        SearchDirection::Forward => Position {
+                            x: editor.cursor_position.x.saturating_add(1),
+                            y: editor.cursor_position.y,
+                        },
+                        SearchDirection::Backward => editor.cursor_position.clone(),
+                    };
+                    if let Some(position) = editor.document.find(&pattern, &at, direction) {
+                        editor.cursor_position = position;
+                        editor.scroll();
+                    }
+                },
+            )
+            .unwrap_or(None)
+            .filter(|query| !query.is_empty());
+        let pattern = query.map(|query| Pattern::new(&query, self.search_flags));
+        if let Some(Ok(pattern)) = pattern {
+            // the cursor lands on the first match before anything else runs
+            while self.search_jump && self.searcher.is_running() {
+                let event = self
+                    .events
+                    .wait_for(|event| matches!(event, event::Event::SearchMatches { .. }));
+                match event {
+                    Ok(event) => self.follow_search(event),
+                    Err(_) => break,
+                }
+            }
+            self.search_jump = false;
+            self.report_matches(&pattern);
+            self.last_search = Some(pattern);
+        } else {
+            self.searcher.cancel();
+            self.search_jump = false;
+            self.cursor_position = old_position;
+            self.scroll();
+            if let Some(Err(error)) = pattern {
+                self.set_status(StatusMessage::error(format!("Invalid pattern: {error}")));
+            }
+        }
+        self.set_highlighted_word(None);
+    }
+    // The `/` prompt, with how the search matches when not as plain text.
+    fn search_prompt(&self) -> String {
+        let SearchFlags {
+            regex,
+            ignore_case,
+            whole_word,
+        } = self.search_flags;
+        let flags: Vec<&str> = [
+            (regex, "regex"),
+            (ignore_case, "ignore case"),
+            (whole_word, "whole word"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+        if flags.is_empty() {
+            "Search (ESC to cancel, Ctrl-G/Ctrl-T to navigate): ".to_string()
+        } else {
+            format!(
+                "Search [{}] (ESC to cancel, Ctrl-G/Ctrl-T to navigate): ",
+                flags.join(", ")
+            )
+        }
+    }
+    // Alt-r, Alt-c and Alt-w at the `/` prompt.
+    fn toggle_search_flag(&mut self, key: char) {
+        let flag = match key {
+            'r' => &mut self.search_flags.regex,
+            'c' => &mut self.search_flags.ignore_case,
+            'w' => &mut self.search_flags.whole_word,
+            _ => return,
+        };
+        *flag = !*flag;
+    }
+    // Searches for `query` in the background as it is typed, moving the
+    // cursor to the first match from `origin` once one turns up.
+    fn start_search(&mut self, query: &str, origin: Position) {
+        // a regular expression still being typed may not be valid yet
+        let pattern = Pattern::new(query, self.search_flags)
+            .ok()
+            .filter(|pattern| !pattern.is_empty());
+        if pattern.is_some()
+            && self
+                .searcher
+                .search()
+                .is_some_and(|search| Some(&search.pattern) == pattern.as_ref())
+        {
+            return;
+        }
+        self.cursor_position = origin.clone();
+        self.scroll();
+        let Some(pattern) = pattern else {
+            self.searcher.cancel();
+            self.search_jump = false;
+            self.set_highlighted_word(None);
+            return;
+        };
+        self.searcher
+            .start(&self.document, &pattern, origin, self.events.sender());
+        self.search_jump = true;
+        self.set_highlighted_word(Some(pattern));
+    }
+    // Matches of the search word are part of the highlighting, so every row
+    // is highlighted again when it changes.
+    fn set_highlighted_word(&mut self, word: Option<Pattern>) {
+        if word != self.highlighted_word {
+            self.highlighted_word = word;
+            self.document.reset_highlighting();
+        }
+    }
+    fn follow_search(&mut self, event: event::Event) {
+        let event::Event::SearchMatches {
+            generation,
+            matches,
+            done,
+        } = event
+        else {
+            return;
+        };
+        if !self.searcher.receive(generation, matches, done) {
+            return;
+        }
+        let Some(search) = self.searcher.search() else {
+            return;
+        };
+        if self.search_jump {
+            if let Some(position) = search.matches.first() {
+                self.cursor_position = position.clone();
+                self.search_jump = false;
+                self.scroll();
+            } else if search.done {
+                self.search_jump = false;
+            }
+        }
+        if let Some(pattern) = self.counting.take() {
+            self.report_matches(&pattern);
+        }
+    }
+    // Shows where the cursor is among the matches of `query`, counting them
+    // in the background first when the document is large.
+    fn report_matches(&mut self, pattern: &Pattern) {
+        if !self.searcher.is_current(&self.document, pattern) {
+            self.searcher.start(
+                &self.document,
+                pattern,
+                Position::default(),
+                self.events.sender(),
+            );
+        }
+        self.set_status(StatusMessage::from(self.match_count()));
+        if self.searcher.is_running() {
+            self.counting = Some(pattern.clone());
+        }
+    }
+    // `n` and `N`: the next match of the last search in `direction`,
+    // wrapping around the ends of the document.
+    fn repeat_search(&mut self, direction: SearchDirection) {
+        let Some(pattern) = self.last_search.clone() else {
+            self.set_status(StatusMessage::error("No previous search."));
+            return;
+        };
+        let Position { x, y } = self.cursor_position;
+        let found = match direction {
+            SearchDirection::Forward => self
+                .document
+                .find(
+                    &pattern,
+                    &Position {
+                        x: x.saturating_add(1),
+                        y,
+                    },
+                    direction,
+                )
+                .or_else(|| {
+                    self.document
+                        .find(&pattern, &Position::default(), direction)
+                }),
+            SearchDirection::Backward => self
+                .document
+                .find(&pattern, &self.cursor_position, direction)
+                .or_else(|| {
+                    let y = self.document.len().saturating_sub(1);
+                    let x = self.document.row(y).map_or(0, Row::len);
+                    self.document.find(&pattern, &Position { x, y }, direction)
+                }),
+        };
+        match found {
+            Some(position) => {
+                self.cursor_position = position;
+                self.scroll();
+                self.report_matches(&pattern);
+            }
+            None => {
+                self.set_status(StatusMessage::error(format!(
+                    "Pattern not found: {}",
+                    pattern.text()
+                )));
+            }
+        }
+    }
+    // `match 3 of 17`, where the current match is the last one at or before
+    // the cursor.
+    fn match_count(&self) -> String {
+        let Some(search) = self.searcher.search() else {
+            return String::new();
+        };
+        if !search.done {
+            return format!("searching... {} matches so far", search.matches.len());
+        }
+        let at = (self.cursor_position.y, self.cursor_position.x);
+        let current = search
+            .matches
+            .iter()
+            .filter(|position| (position.y, position.x) <= at)
+            .count();
+        format!("match {current} of {}", search.matches.len())
+    }
+    fn process_keypress(&mut self, event: Event) -> Result<(), std::io::Error> {
+        if self.status_message.is_error && self.status_message.expires {
+            self.status_message = StatusMessage::from(String::new());
+        }
+        if let (Event::Key(key), true) = (event, logging::enabled(Level::Debug)) {
+            logging::debug("key", &keymap::keys_name(&[key]));
+        }
+        self.last_key = Instant::now();
+        // a label left by `s{char}{char}` jumps to its match; any other key
+        // just clears them
+        let labels = mem::take(&mut self.sneak_labels);
+        if let Event::Key(KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::NONE,
+        }) = event
+        {
+            if let Some((target, _)) = labels.into_iter().find(|(_, label)| *label == c) {
+                self.cursor_position = target;
+                self.scroll();
+                return Ok(());
+            }
+        }
+        if self.showing_dashboard()
+            && self.mode == Mode::Normal
+            && self.process_dashboard_key(event)?
+        {
+            return Ok(());
+        }
+        // a count before a command is kept apart from it for `.`, to
+        // replace with another
+        let counting = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => {
+                self.mode == Mode::Normal
+                    && matches!(self.pending, Pending::Command { .. })
+                    && self.pending_keys.is_empty()
+                    && self.pending.takes_digit(c)
+            }
+            _ => false,
+        };
+        if let (Event::Key(_), false) = (&event, counting) {
+            self.dot_repeat.record(event);
+        }
+        let revision = self.document.revision();
+        let mapped = match event {
+            // the key after m, ' or ` names a mark, and after " a register,
+            // rather than starting a binding
+            Event::Key(key)
+                if !matches!(self.pending, Pending::Mark(_) | Pending::Register { .. }) =>
+            {
+                self.process_mapped_key(keymap::normalize(key))?
+            }
+            _ => false,
+        };
+        if !mapped {
+            self.process_unmapped_key(event);
+        }
+        if counting {
+            self.dot_repeat.record_count(self.pending.count());
+        }
+        if self.mode == Mode::Normal
+            && self.pending == Pending::default()
+            && self.pending_keys.is_empty()
+        {
+            self.dot_repeat.finish();
+        }
+        if self.completion.as_ref().is_some_and(|completion| {
+            completion.cursor != self.cursor_position || self.mode != Mode::Insert
+        }) {
+            self.completion = None;
+        }
+        if self.signature.as_ref().is_some_and(|signature| {
+            let Position { x, y } = self.cursor_position;
+            let in_call = self
+                .document
+                .row(y)
+                .is_some_and(|row| signature::in_call(row, x, signature.open));
+            self.mode != Mode::Insert || !in_call
+        }) {
+            self.signature = None;
+        }
+        if self.document.revision() != revision {
+            self.last_edit = Instant::now();
+        }
+        // only insert mode and `virtualedit` let the cursor past the line
+        let last = self.last_column(self.cursor_position.y);
+        if self.cursor_position.x > last {
+            self.cursor_position.x = last;
+        }
+        // everything typed in insert mode is undone together, and in normal
+        // mode each command on its own
+        if self.mode != Mode::Insert && !self.batching {
+            self.document.end_undo_step();
+        }
+        self.scroll();
+        Ok(())
+    }
+    // Where the `(` of the call the cursor is in is on its line.
+    fn call_start(&self) -> Option<usize> {
+        let Position { x, y } = self.cursor_position;
+        self.document
+            .row(y)
+            .and_then(|row| signature::call_start(row, x))
+    }
+    // After `(` or `,`: a `SignatureHelp` notification of `[file_name,
+    // line, column]`, 1-based line, for the RPC client to answer with
+    // `show_signature`.
+    fn request_signature(&mut self) {
+        if self.call_start().is_none() {
+            return;
+        }
+        #[cfg(unix)]
+        if let Some(server) = self.rpc.as_mut() {
+            let Position { x, y } = self.cursor_position;
+            let file_name = self.document.file_name.clone().unwrap_or_default();
+            server.notify(
+                "SignatureHelp",
+                vec![file_name.into(), y.saturating_add(1).into(), x.into()],
+            );
+        }
+    }
+    // Feeds `key` to the keymap of the current mode. Returns false when no
+    // binding starts with it, so it gets the built-in handling instead.
+    fn process_mapped_key(&mut self, key: KeyEvent) -> Result<bool, std::io::Error> {
+        self.pending_keys.push(key);
+        match self.keymaps.lookup(self.mode, &self.pending_keys) {
+            Lookup::Prefix => return Ok(true),
+            Lookup::Exact(action) => {
+                let action = action.clone();
+                self.pending_keys.clear();
+                self.perform(&action)?;
+                // a count is for the command right after it only
+                if self.mode != Mode::OperatorPending {
+                    self.pending = Pending::default();
+                }
+                return Ok(true);
+            }
+            Lookup::None => (),
+        }
+        self.pending_keys.pop();
+        if self.pending_keys.is_empty() {
+            return Ok(false);
+        }
+        // the pending keys were waiting for a longer binding that `key`
+        // does not continue
+        self.flush_pending_keys()?;
+        self.process_mapped_key(key)
+    }
+    // Gives up waiting for the rest of a longer binding: the keys typed so
+    // far run their own binding if they have one, or are handled one by
+    // one.
+    fn flush_pending_keys(&mut self) -> Result<(), std::io::Error> {
+        let pending = mem::take(&mut self.pending_keys);
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if let Some(action) = self.keymaps.exact(self.mode, &pending).cloned() {
+            self.perform(&action)?;
+            if self.mode != Mode::OperatorPending {
+                self.pending = Pending::default();
+            }
+        } else {
+            for key in pending {
+                self.process_unmapped_key(Event::Key(key));
+            }
+        }
+        self.scroll();
+        Ok(())
+    }
+    // Ends the undo step before `c` is typed when `undogroup` undoes what
+    // is typed in insert mode in smaller pieces: at the first blank after a
+    // word, or after a pause.
+    fn group_typing(&mut self, c: char) {
+        if self.insertion.text.is_empty() {
+            return;
+        }
+        let breaks = match self.options.undogroup {
+            UndoGroup::Insert => false,
+            UndoGroup::Word => {
+                let Position { x, y } = self.cursor_position;
+                let before = x.checked_sub(1).and_then(|x| {
+                    self.document
+                        .row(y)
+                        .and_then(|row| row.get(x).and_then(|text| text.chars().next()))
+                });
+                c.is_whitespace() && before.is_some_and(|before| !before.is_whitespace())
+            }
+            UndoGroup::Pause(seconds) => {
+                self.last_edit.elapsed() >= Duration::from_secs(seconds as u64)
+            }
+        };
+        if breaks {
+            self.document.end_undo_step();
+        }
+    }
+    fn process_unmapped_key(&mut self, event: Event) {
+        match (&self.mode, event) {
+            // go to normal mode when Esc is pressed in Insert or Visual Mode
+            (
+                _,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Esc, ..
+                }),
+            ) => {
+                self.pending = Pending::default();
+                self.leave_insert();
+            }
 
-            // move around in normal and visual mode with h | l | j | k | Up | Down | Left | Right
+            // a count, before a command or the motion of an operator
             (
-                Mode::Normal | Mode::Visual,
+                Mode::Normal | Mode::OperatorPending,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('h' | 'l' | 'j' | 'k'),
-                    ..
-                })
-                | Event::Key(KeyEvent {
-                    code: KeyCode::Up, ..
-                })
-                | Event::Key(KeyEvent {
-                    code: KeyCode::Down,
-                    ..
-                })
-                | Event::Key(KeyEvent {
-                    code: KeyCode::Left,
-                    ..
-                })
-                | Event::Key(KeyEvent {
-                    code: KeyCode::Right,
+                    code: KeyCode::Char(c),
                     ..
                 }),
-            ) => self.move_cursor(event),
-            // delete under cursor with x
+            ) if self.pending.takes_digit(c) => self.pending.push_digit(c),
+
+            // set a mark with m{name}, or jump to one with '{name} or `{name}
             (
                 Mode::Normal,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('x'),
+                    code: KeyCode::Char(c),
                     ..
                 }),
-            ) => {
-                self.document.delete(&self.cursor_position);
-                self.move_cursor(Event::Key(KeyEvent {
-                    code: KeyCode::Left,
-                    modifiers: KeyModifiers::NONE,
-                }))
+            ) if matches!(self.pending, Pending::Mark(_)) => {
+                if let Pending::Mark(key) = mem::take(&mut self.pending) {
+                    if key == 'm' {
+                        self.set_mark(c);
+                    } else {
+                        self.jump_to_mark(key, c);
+                    }
+                }
             }
 
-            // delete line with 'D'
+            // pick the register for the command with "{a-z}
             (
                 Mode::Normal,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('D'),
+                    code: KeyCode::Char(c),
                     ..
                 }),
-            ) => self.document.delete_line(self.cursor_position.y),
+            ) if matches!(self.pending, Pending::Register { .. }) => {
+                let count = mem::take(&mut self.pending).count();
+                if register::is_name(c) {
+                    self.pending = Pending::Command {
+                        count,
+                        register: Some(c),
+                    };
+                }
+            }
 
-            // delete line with 'dd'
+            // the operator's key again applies it to whole lines, as `dd`;
+            // anything else gives it up
             (
-                Mode::Normal,
+                Mode::OperatorPending,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('d'),
-                    modifiers: KeyModifiers::NONE,
+                    code: KeyCode::Char(c),
+                    ..
                 }),
             ) => {
-                if self.previous_characters.last() == Some(&'d') {
-                    self.document.delete_line(self.cursor_position.y);
-                    self.previous_characters.clear();
-                } else {
-                    self.previous_characters.push('d');
+                let pending = mem::take(&mut self.pending);
+                self.mode = Mode::Normal;
+                if let Pending::Operator { operator, .. } = pending {
+                    if c == operator.key() {
+                        let y = self.cursor_position.y;
+                        let count = pending.count().unwrap_or(1);
+                        let span = Span::lines(y, y.saturating_add(count).saturating_sub(1));
+                        self.apply(operator, pending.register(), &span);
+                    }
                 }
             }
 
-            // Quit with ':q'
+            // Enter Backspace in Insert mode to delete a char.
             (
-                Mode::Normal,
+                Mode::Insert,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Backspace,
+                    ..
+                }),
+            ) if (self.cursor_position.x > 0 || self.cursor_position.y > 0) => {
+                self.move_cursor(Event::Key(KeyEvent {
+                    code: KeyCode::Left,
+                    modifiers: KeyModifiers::NONE,
+                }));
+                self.document.delete(&self.cursor_position);
+                self.insertion.text.pop();
+            }
+
+            // Insert if a char is pressed in Insert mode.
+            (
+                Mode::Insert,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('q'),
+                    code: KeyCode::Char(c),
                     ..
                 }),
             ) => {
-                if self.previous_characters.last() == Some(&':') {
-                    if self.document.is_dirty() {
-                        self.status_message =
-                            StatusMessage::from("WARNING! File has unsaved changes.");
-                        return Ok(());
-                    }
-                    self.should_quit = true;
+                self.group_typing(c);
+                self.insertion.text.push(c);
+                self.insert_text(c.encode_utf8(&mut [0; 4]));
+                // like Vim, a line is only broken once the next word starts
+                if !c.is_whitespace() {
+                    self.break_typed_line();
+                }
+                if matches!(c, '(' | ',') {
+                    self.request_signature();
                 }
             }
 
-            // Quit without saving with :!
+            // Insert a newline when Enter is pressed.
             (
-                Mode::Normal,
+                Mode::Insert,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('!'),
+                    code: KeyCode::Enter,
                     ..
                 }),
             ) => {
-                if self.previous_characters.last() == Some(&':') {
-                    self.should_quit = true;
-                }
+                self.group_typing('\n');
+                self.insertion.text.push('\n');
+                self.insert_text("\n");
+            }
+
+            // Tab indents with a tab, or with `expandtab` spaces to the next
+            // multiple of `shiftwidth`
+            (
+                Mode::Insert,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Tab, ..
+                }),
+            ) => {
+                let indent = if self.options.expandtab {
+                    let Position { x, y } = self.cursor_position;
+                    let column = self.document.row(y).map_or(x, |row| row.column(x));
+                    let width = self.options.shiftwidth.max(1);
+                    " ".repeat(width.saturating_sub(column % width))
+                } else {
+                    String::from("\t")
+                };
+                self.insertion.text.push_str(&indent);
+                self.insert_text(&indent);
             }
 
-            // insert newline after cursor with o
+            // the start of a mark command or of a register name; any other
+            // key without a use gives up the command typed so far
             (
                 Mode::Normal,
                 Event::Key(KeyEvent {
-                    code: KeyCode::Char('o'),
+                    code: KeyCode::Char(c),
                     ..
                 }),
             ) => {
-                let new_position = &mut self.cursor_position;
-                new_position.y = new_position.y.saturating_add(1);
-                new_position.x = 0;
-                self.document.insert_newline(new_position);
-                self.mode = Mode::Insert;
+                self.pending = match c {
+                    'm' | '\'' | '`' => Pending::Mark(c),
+                    '"' => Pending::Register {
+                        count: self.pending.count(),
+                    },
+                    _ => Pending::default(),
+                };
+            }
+            (Mode::OperatorPending, _) => {
+                self.pending = Pending::default();
+                self.mode = Mode::Normal;
+            }
+            _ => (),
+        }
+    }
+    fn perform(&mut self, action: &Action) -> Result<(), std::io::Error> {
+        if logging::enabled(Level::Debug) {
+            logging::debug("key", &format!("runs {action}"));
+        }
+        match action {
+            Action::Builtin(builtin) => self.perform_builtin(*builtin)?,
+            Action::Command(command) => {
+                self.pending = Pending::default();
+                self.execute(command);
+            }
+            #[cfg(feature = "lua")]
+            Action::Lua(function) => {
+                let function = *function;
+                self.with_scripting(|scripting, editor| scripting.call(editor, function));
+            }
+        }
+        Ok(())
+    }
+    fn perform_builtin(&mut self, builtin: Builtin) -> Result<(), std::io::Error> {
+        if builtin.is_edit() && self.document.is_read_only() {
+            self.set_status(StatusMessage::error("Cannot modify a read-only buffer."));
+            return Ok(());
+        }
+        if builtin.is_change() {
+            self.dot_repeat.mark_change();
+        }
+        if self.mode == Mode::OperatorPending && (builtin.is_motion() || builtin.is_text_object()) {
+            return self.apply_to_motion(builtin);
+        }
+        let from = builtin.is_jump().then(|| self.cursor_position.clone());
+        match builtin {
+            Builtin::Insert => {
+                self.enter_insert(false);
+                self.terminal.cursor_hide();
+            }
+            Builtin::Append => {
+                self.enter_insert(false);
+                let last = self.last_column(self.cursor_position.y);
+                self.cursor_position.x = self.cursor_position.x.saturating_add(1).min(last);
+            }
+            Builtin::AppendEnd => {
+                self.cursor_position.x = self
+                    .document
+                    .row(self.cursor_position.y)
+                    .unwrap_or(&Row::default())
+                    .len();
+                self.enter_insert(false);
+            }
+            Builtin::OpenBelow => {
+                let below = self.cursor_position.y.saturating_add(1);
+                self.open_line(below.min(self.document.len()));
+                self.enter_insert(true);
+            }
+            Builtin::OpenAbove => {
+                self.open_line(self.cursor_position.y);
+                self.enter_insert(true);
+            }
+            Builtin::Down | Builtin::Up if self.options.displaymotion && self.options.wrap => {
+                self.move_display_line(builtin == Builtin::Down);
+            }
+            Builtin::Left | Builtin::Down | Builtin::Up | Builtin::Right => {
+                let code = match builtin {
+                    Builtin::Left => KeyCode::Left,
+                    Builtin::Down => KeyCode::Down,
+                    Builtin::Up => KeyCode::Up,
+                    _ => KeyCode::Right,
+                };
+                self.move_cursor(Event::Key(KeyEvent {
+                    code,
+                    modifiers: KeyModifiers::NONE,
+                }));
+            }
+            Builtin::DisplayDown | Builtin::DisplayUp => {
+                self.move_display_line(builtin == Builtin::DisplayDown);
+            }
+            Builtin::WordForward => {
+                self.cursor_position = motion::word_forward(&self.document, &self.cursor_position);
+            }
+            Builtin::WordBackward => {
+                self.cursor_position = motion::word_backward(&self.document, &self.cursor_position);
+            }
+            Builtin::LineStart => self.cursor_position.x = 0,
+            Builtin::LineEnd => {
+                let len = self
+                    .document
+                    .row(self.cursor_position.y)
+                    .map_or(0, Row::len);
+                self.cursor_position.x = len.saturating_sub(1);
+                self.wanted_column = Some((self.cursor_position.clone(), usize::MAX));
+            }
+            Builtin::FindChar
+            | Builtin::FindCharBackward
+            | Builtin::TillChar
+            | Builtin::TillCharBackward
+            | Builtin::RepeatFind
+            | Builtin::RepeatFindReverse => {
+                self.search_char(builtin)?;
+            }
+            Builtin::InnerObject | Builtin::AroundObject => self.select_object(builtin)?,
+            Builtin::RepeatChange => self.repeat_change()?,
+            Builtin::RepeatCommand => self.repeat_command()?,
+            Builtin::RecordMacro => self.record_macro()?,
+            Builtin::RunMacro => self.run_macro()?,
+            Builtin::FirstLine | Builtin::LastLine => {
+                let last = self.document.len().saturating_sub(1);
+                self.cursor_position.y = match (self.take_count(), builtin) {
+                    (Some(count), _) => count.saturating_sub(1).min(last),
+                    (None, Builtin::FirstLine) => 0,
+                    (None, _) => last,
+                };
+                self.clamp_cursor();
+            }
+            // `x` is `dl` and `D` is `dd`
+            Builtin::DeleteChar => {
+                let register = self.pending.register();
+                let count = self.take_count().unwrap_or(1);
+                let Position { x, y } = self.cursor_position;
+                let len = self.document.row(y).map_or(0, Row::len);
+                let end = Position {
+                    x: x.saturating_add(count).min(len),
+                    y,
+                };
+                let span = Span::characters(self.cursor_position.clone(), end);
+                self.apply(Operator::Delete, register, &span);
+            }
+            Builtin::DeleteLine => {
+                let register = self.pending.register();
+                let count = self.take_count().unwrap_or(1);
+                let y = self.cursor_position.y;
+                let span = Span::lines(y, y.saturating_add(count).saturating_sub(1));
+                self.apply(Operator::Delete, register, &span);
+            }
+            Builtin::Delete
+            | Builtin::Yank
+            | Builtin::Change
+            | Builtin::Indent
+            | Builtin::Outdent
+            | Builtin::ToggleCase => {
+                if let Some(operator) = builtin.operator() {
+                    self.start_operator(operator);
+                }
+            }
+            Builtin::PutAfter
+            | Builtin::PutBefore
+            | Builtin::PutAfterIndented
+            | Builtin::PutBeforeIndented => {
+                let register = self.pending.register();
+                let count = self.take_count().unwrap_or(1);
+                let before = matches!(builtin, Builtin::PutBefore | Builtin::PutBeforeIndented);
+                let indented = matches!(
+                    builtin,
+                    Builtin::PutAfterIndented | Builtin::PutBeforeIndented
+                );
+                self.put(before, count, register, indented);
+            }
+            Builtin::CommandLine => {
+                self.pending = Pending::default();
+                let command = self.read_line(
+                    |_| ":".to_string(),
+                    Some(HistoryKind::Command),
+                    |_, _, _| {},
+                )?;
+                if let Some(command) = command.filter(|command| !command.is_empty()) {
+                    self.registers.set_command(&command);
+                    self.execute(&command);
+                }
+            }
+            Builtin::Search => self.search(),
+            Builtin::SearchNext => self.repeat_search(SearchDirection::Forward),
+            Builtin::SearchPrevious => self.repeat_search(SearchDirection::Backward),
+            Builtin::AlternateFile => self.edit_alternate(),
+            Builtin::VisualMode => {
+                self.visual_start = self.cursor_position.clone();
+                self.visual_kind = Kind::Characters;
+                self.mode = Mode::Visual;
+            }
+            // from a selection already started, they keep its start
+            Builtin::VisualLine | Builtin::VisualBlock => {
+                if self.mode != Mode::Visual {
+                    self.visual_start = self.cursor_position.clone();
+                }
+                self.visual_kind = if builtin == Builtin::VisualLine {
+                    Kind::Lines
+                } else {
+                    Kind::Block
+                };
+                self.mode = Mode::Visual;
+            }
+            Builtin::NormalMode => self.leave_insert(),
+            Builtin::NextDiagnostic | Builtin::PreviousDiagnostic => {
+                self.jump_to_diagnostic(builtin == Builtin::NextDiagnostic);
+            }
+            Builtin::CompleteNext => self.complete(true),
+            Builtin::CompletePrevious => self.complete(false),
+            Builtin::YankHistory => self.yank_history(),
+            Builtin::PutOlder => self.put_older(),
+            Builtin::BreakUndo => self.document.end_undo_step(),
+            Builtin::WorkspaceSymbols => self.workspace_symbols(),
+            Builtin::CodeActions => self.request_code_actions(),
+            Builtin::Sneak => self.sneak()?,
+            Builtin::Undo | Builtin::Redo | Builtin::Earlier | Builtin::Later => {
+                for _ in 0..self.take_count().unwrap_or(1) {
+                    let position = match builtin {
+                        Builtin::Undo => self.document.undo(),
+                        Builtin::Earlier => self.document.earlier(),
+                        Builtin::Later => self.document.later(),
+                        _ => self.document.redo(),
+                    };
+                    let Some(position) = position else {
+                        let back = matches!(builtin, Builtin::Undo | Builtin::Earlier);
+                        self.set_status(StatusMessage::from(if back {
+                            "Already at oldest change"
+                        } else {
+                            "Already at newest change"
+                        }));
+                        break;
+                    };
+                    self.cursor_position = position;
+                }
+                self.clamp_cursor();
+            }
+            Builtin::MoveDown | Builtin::MoveUp => {
+                let y = self.cursor_position.y;
+                let (start, end) = if self.mode == Mode::Visual {
+                    (self.visual_start.y.min(y), self.visual_start.y.max(y))
+                } else {
+                    (y, y)
+                };
+                let count = self.take_count().unwrap_or(1);
+                let after = if builtin == Builtin::MoveDown {
+                    end.saturating_add(1)
+                        .saturating_add(count)
+                        .min(self.document.len())
+                } else {
+                    start.saturating_sub(count)
+                };
+                let first = self.move_lines((start, end), after);
+                // the cursor and the selection go with the lines
+                let moved = |line: usize| line.saturating_sub(start).saturating_add(first);
+                self.cursor_position.y = moved(y);
+                self.visual_start.y = moved(self.visual_start.y);
+                self.clamp_cursor();
+            }
+            Builtin::PageDown | Builtin::PageUp => {
+                let count = self.take_count().unwrap_or(1);
+                self.scroll_page(builtin == Builtin::PageDown, count);
+            }
+            Builtin::Duplicate => {
+                let Position { x, y } = self.cursor_position;
+                let (start, end) = if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    (self.visual_start.y.min(y), self.visual_start.y.max(y))
+                } else {
+                    (y, y)
+                };
+                self.copy_lines((start, end), end.saturating_add(1));
+                // the cursor keeps its place, in the copy
+                self.cursor_position = Position {
+                    x,
+                    y: y.saturating_add(end.saturating_sub(start))
+                        .saturating_add(1),
+                };
+                self.clamp_cursor();
+            }
+            Builtin::Align => {
+                let lines = (self.visual_start.y, self.cursor_position.y);
+                self.mode = Mode::Normal;
+                if let Some(delimiter) = self.prompt("Align on: ", |_, _, _| {})? {
+                    self.align(
+                        Some((lines.0.min(lines.1), lines.0.max(lines.1))),
+                        &delimiter,
+                    );
+                }
+            }
+        }
+        if let Some(from) = from {
+            self.jumped_from(&from);
+        }
+        Ok(())
+    }
+    // Puts copies of the lines `start..=end` after the first `after` lines,
+    // with the cursor on the last of them.
+    fn copy_lines(&mut self, (start, end): (usize, usize), after: usize) {
+        let lines: Vec<String> = (start..=end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let at = after.min(self.document.len());
+        self.document.replace_lines(at, at, &lines);
+        self.cursor_position.y = at.saturating_add(lines.len()).saturating_sub(1);
+        self.clamp_cursor();
+    }
+    // Scrolls `count` screens forward or back, less two lines so the last
+    // ones stay in view, taking the cursor along when it goes off screen.
+    fn scroll_page(&mut self, forward: bool, count: usize) {
+        let height = self.terminal.size().height as usize;
+        let page = height.saturating_sub(2).max(1).saturating_mul(count);
+        let scrolloff = self.options.scrolloff.min(height.saturating_sub(1) / 2);
+        let last = self.document.len().saturating_sub(1);
+        let y = self.cursor_position.y;
+        if forward {
+            self.offset.y = self.offset.y.saturating_add(page).min(last);
+            self.cursor_position.y = y.max(self.offset.y.saturating_add(scrolloff)).min(last);
+        } else {
+            self.offset.y = self.offset.y.saturating_sub(page);
+            let bottom = self
+                .offset
+                .y
+                .saturating_add(height)
+                .saturating_sub(scrolloff.saturating_add(1));
+            self.cursor_position.y = y.min(bottom);
+        }
+        self.clamp_cursor();
+    }
+    // Moves the lines `start..=end` to after the first `after` lines, as
+    // numbered before the move, and returns where the first of them ends
+    // up. With `moveindent`, they are indented to suit the line above.
+    fn move_lines(&mut self, (start, end): (usize, usize), after: usize) -> usize {
+        let line = |y: usize| self.document.row(y).map(|row| row.as_str().to_string());
+        let block: Vec<String> = (start..=end).filter_map(line).collect();
+        let after = after.min(self.document.len());
+        let (first, lines) = if after > end.saturating_add(1) {
+            let mut lines: Vec<String> = (end.saturating_add(1)..after).filter_map(line).collect();
+            let first = start.saturating_add(lines.len());
+            lines.extend(block);
+            (first, (start, after, lines))
+        } else if after < start {
+            let mut lines = block;
+            lines.extend((after..start).filter_map(line));
+            (after, (after, end.saturating_add(1), lines))
+        } else {
+            return start;
+        };
+        let (from, to, mut lines) = lines;
+        if self.options.moveindent {
+            let moved = first.saturating_sub(from);
+            let (above, rest) = lines.split_at_mut(moved);
+            let block = rest
+                .get_mut(..=end.saturating_sub(start))
+                .unwrap_or_default();
+            // the closest line above that is not blank, once they are moved
+            let indent = above
+                .iter()
+                .rev()
+                .cloned()
+                .chain((0..from).rev().filter_map(line))
+                .find(|text| !text.trim().is_empty())
+                .map(|text| leading_whitespace(&text).to_string())
+                .unwrap_or_default();
+            reindent(block, &indent);
+        }
+        self.document.replace_lines(from, to, &lines);
+        first
+    }
+    // Starts insert mode, to be repeated as often as the count says.
+    fn enter_insert(&mut self, new_lines: bool) {
+        // past the end of the line with `virtualedit`, spaces fill the gap
+        let Position { x, y } = self.cursor_position;
+        if y < self.document.len() {
+            let len = self.document.row(y).map_or(0, Row::len);
+            for column in len..x {
+                self.document.insert(&Position { x: column, y }, ' ');
+            }
+        }
+        self.insertion = Insertion {
+            count: self.take_count().unwrap_or(1),
+            new_lines,
+            text: String::new(),
+            block: None,
+        };
+        self.mode = Mode::Insert;
+    }
+    // `o` and `O`: a new line at `y`, indented like the cursor line, with
+    // the cursor at its end.
+    fn open_line(&mut self, y: usize) {
+        let indent: String = self
+            .document
+            .row(self.cursor_position.y)
+            .map(|row| {
+                row.as_str()
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.document
+            .replace_lines(y, y, std::slice::from_ref(&indent));
+        self.cursor_position = Position {
+            x: indent.chars().count(),
+            y,
+        };
+    }
+    // Goes back to normal mode, first typing what was inserted again for
+    // `3i`, `5o` and the like.
+    fn leave_insert(&mut self) {
+        let Insertion {
+            count,
+            new_lines,
+            text,
+            block,
+        } = mem::take(&mut self.insertion);
+        // Esc in normal and visual mode comes here too
+        if self.mode == Mode::Insert {
+            self.registers.set_inserted(&text);
+        }
+        for _ in 1..count {
+            if new_lines {
+                self.open_line(self.cursor_position.y.saturating_add(1));
+            }
+            self.insert_text(&text);
+        }
+        // lines too short to reach the block are left alone, and so is the
+        // rest of it when a line break was typed
+        if let Some((lines, column)) = block.filter(|_| !text.contains('\n')) {
+            for y in lines {
+                let Some(row) = self.document.row(y).filter(|row| row.width() >= column) else {
+                    continue;
+                };
+                let x = row.index_at_column(column);
+                for (offset, c) in text.chars().enumerate() {
+                    let at = Position {
+                        x: x.saturating_add(offset),
+                        y,
+                    };
+                    self.document.insert(&at, c);
+                }
+            }
+        }
+        self.mode = Mode::Normal;
+        // the cursor goes back onto the last character typed
+        self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+        self.clamp_cursor();
+    }
+    // `]d` and `[d`: to the next or previous diagnostic, showing its
+    // message.
+    fn jump_to_diagnostic(&mut self, forward: bool) {
+        let Some(diagnostic) = self.diagnostics.next(&self.cursor_position, forward) else {
+            self.set_status(StatusMessage::error("No diagnostics"));
+            return;
+        };
+        let message = diagnostic.message.lines().next().unwrap_or_default();
+        let message = StatusMessage::from(message.to_string());
+        self.cursor_position = diagnostic.position.clone();
+        self.set_status(message);
+        self.clamp_cursor();
+    }
+    // Replaces the word before the cursor with the next or previous word
+    // from the buffer that starts the same, showing them all in a popup.
+    fn complete(&mut self, forward: bool) {
+        if self.completion.is_none() {
+            let Position { x, y } = self.cursor_position;
+            let Some(row) = self.document.row(y) else {
+                return;
+            };
+            let before = row.as_str().get(..row.byte_index(x)).unwrap_or_default();
+            let typed: String = before
+                .chars()
+                .rev()
+                .take_while(|&c| is_word_char(c))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .collect();
+            let items = self.completions(&typed);
+            if items.is_empty() {
+                self.set_status(StatusMessage::error("Pattern not found"));
+                return;
+            }
+            self.completion = Some(Completion {
+                popup: Popup::new(items),
+                start: Position {
+                    x: x.saturating_sub(typed.chars().count()),
+                    y,
+                },
+                current: typed.clone(),
+                typed,
+                cursor: self.cursor_position.clone(),
+            });
+        }
+        let Some(mut completion) = self.completion.take() else {
+            return;
+        };
+        if forward {
+            completion.popup.select_next();
+        } else {
+            completion.popup.select_previous();
+        }
+        let choice = completion
+            .popup
+            .selected()
+            .map_or_else(|| completion.typed.clone(), |item| item.text.clone());
+        self.cursor_position = completion.start.clone();
+        for _ in completion.current.chars() {
+            self.document.delete(&self.cursor_position);
+            self.insertion.text.pop();
+        }
+        self.insertion.text.push_str(&choice);
+        self.insert_text(&choice);
+        completion.current = choice;
+        completion.cursor = self.cursor_position.clone();
+        self.completion = Some(completion);
+    }
+    // The words in the buffer that start with `typed`, from the cursor on
+    // and around from the top, each once.
+    fn completions(&self, typed: &str) -> Vec<Item> {
+        let y = self.cursor_position.y;
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+        for index in (y..self.document.len()).chain(0..y) {
+            let Some(row) = self.document.row(index) else {
+                continue;
+            };
+            for word in row.as_str().split(|c| !is_word_char(c)) {
+                if word.len() > typed.len()
+                    && word.starts_with(typed)
+                    && seen.insert(word.to_string())
+                {
+                    items.push(Item {
+                        text: word.to_string(),
+                        detail: String::new(),
+                    });
+                    if items.len() >= MAX_COMPLETIONS {
+                        return items;
+                    }
+                }
+            }
+        }
+        items
+    }
+    // `gra`: a `CodeAction` notification of `[file_name, line, column,
+    // end_line, end_column]` for the cursor or the selection, 1-based lines,
+    // for the RPC client to answer with `code_actions`.
+    fn request_code_actions(&mut self) {
+        let (mut start, mut end) = (self.cursor_position.clone(), self.cursor_position.clone());
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+            if (self.visual_start.y, self.visual_start.x) < (start.y, start.x) {
+                start = self.visual_start.clone();
+            } else {
+                end = self.visual_start.clone();
+            }
+            end.x = end.x.saturating_add(1);
+        }
+        #[cfg(unix)]
+        if let Some(server) = self.rpc.as_mut() {
+            let file_name = self.document.file_name.clone().unwrap_or_default();
+            server.notify(
+                "CodeAction",
+                vec![
+                    file_name.into(),
+                    start.y.saturating_add(1).into(),
+                    start.x.into(),
+                    end.y.saturating_add(1).into(),
+                    end.x.into(),
+                ],
+            );
+            return;
+        }
+        self.set_status(StatusMessage::error("No RPC client for code actions"));
+    }
+    // Applies `edits`: to the buffer in one undo step, and to other files
+    // on disk.
+    fn apply_edits(&mut self, edits: &[TextEdit]) -> Result<(), String> {
+        let current = self.document.file_name.as_deref().map(config::full_path);
+        let mut files: BTreeMap<String, Vec<&TextEdit>> = BTreeMap::new();
+        for edit in edits {
+            let file = edit.file.as_deref().map(config::full_path);
+            let file = file.filter(|file| Some(file) != current.as_ref());
+            files
+                .entry(file.unwrap_or_default())
+                .or_default()
+                .push(edit);
+        }
+        for (file, edits) in files {
+            if file.is_empty() {
+                let first = edits.iter().map(|edit| edit.start.y).min().unwrap_or(0);
+                let last = edits.iter().map(|edit| edit.end.y).max().unwrap_or(0);
+                let end = last.saturating_add(1).min(self.document.len());
+                let mut lines: Vec<String> = (first..end)
+                    .filter_map(|y| self.document.row(y))
+                    .map(|row| row.as_str().to_string())
+                    .collect();
+                code_action::apply(&mut lines, first, &edits);
+                self.document.replace_lines(first, end, &lines);
+                self.document.end_undo_step();
+                self.clamp_cursor();
+                continue;
+            }
+            let text = fs::read_to_string(&file).map_err(|error| format!("{file}: {error}"))?;
+            let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+            code_action::apply(&mut lines, 0, &edits);
+            let mut text = lines.join("\n");
+            text.push('\n');
+            fs::write(&file, text).map_err(|error| format!("{file}: {error}"))?;
+        }
+        Ok(())
+    }
+    // Types `text` at the cursor, leaving the cursor after it.
+    fn insert_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.document.insert(&self.cursor_position, c);
+            self.move_cursor(Event::Key(KeyEvent {
+                code: KeyCode::Right,
+                modifiers: KeyModifiers::NONE,
+            }));
+        }
+    }
+    // `.`: types the keys of the last change again, with the count typed
+    // before it in place of the one typed before the change, if any.
+    fn repeat_change(&mut self) -> Result<(), std::io::Error> {
+        let count = self.take_count();
+        let Some(keys) = self.dot_repeat.replay(count) else {
+            return Ok(());
+        };
+        let typed = self.type_keys(keys);
+        self.dot_repeat.end_replay();
+        typed
+    }
+    // Handles `keys` as if they were typed, ahead of any others still
+    // waiting to be, and finishes the binding they end in.
+    fn type_keys(&mut self, keys: Vec<Event>) -> Result<(), std::io::Error> {
+        // the keys that typed these are no change for `.` to repeat
+        self.dot_repeat.forget_typed();
+        let waiting = self.typeahead.len();
+        for key in keys.into_iter().rev() {
+            self.typeahead.push_front(key);
+        }
+        while self.typeahead.len() > waiting {
+            let Some(key) = self.typeahead.pop_front() else {
+                break;
+            };
+            self.process_keypress(key)?;
+        }
+        self.flush_pending_keys()
+    }
+    // `@:`: the last command line again, as many times as the count says,
+    // or in visual mode on each selected line.
+    fn repeat_command(&mut self) -> Result<(), std::io::Error> {
+        let count = self.take_count().unwrap_or(1);
+        let command = self
+            .registers
+            .get(Some(':'))
+            .map(|register| register.text.clone())
+            .filter(|command| !command.is_empty());
+        let Some(command) = command else {
+            self.set_status(StatusMessage::error("No previous command line"));
+            return Ok(());
+        };
+        let lines = (self.mode == Mode::Visual).then(|| {
+            let (start, end) = (self.visual_start.y, self.cursor_position.y);
+            (start.min(end), start.max(end))
+        });
+        self.mode = Mode::Normal;
+        self.on_each_line(lines, |editor| {
+            for _ in 0..count {
+                editor.execute(&command);
+            }
+            Ok(())
+        })
+    }
+    // `q{register}`: starts recording the keys the user types into the
+    // register, or `q` while recording stops, leaving out the `q`. An
+    // uppercase name adds to what the register has.
+    fn record_macro(&mut self) -> Result<(), std::io::Error> {
+        self.pending = Pending::default();
+        if let Some((name, mut keys)) = self.recording.take() {
+            keys.pop();
+            let keys = keymap::keys_name(&keys);
+            self.registers.yank(Some(name), Register::characters(&keys));
+            return Ok(());
+        }
+        if let Some(name) = self.read_char()? {
+            if name.is_ascii_alphanumeric() || name == '"' {
+                self.recording = Some((name, Vec::new()));
+            }
+        }
+        Ok(())
+    }
+    fn record_macro_key(&mut self, input: Event) {
+        if let (Some((_, keys)), Event::Key(key)) = (&mut self.recording, input) {
+            keys.push(key);
+        }
+    }
+    // `@{register}`: types the keys in the register, in key notation, as
+    // many times as the count says, or in visual mode on each selected
+    // line. `@@` runs the register run last and `@:` the last command
+    // line. Lines in the register end in Enter.
+    fn run_macro(&mut self) -> Result<(), std::io::Error> {
+        let Some(mut name) = self.read_char()? else {
+            self.pending = Pending::default();
+            return Ok(());
+        };
+        if name == '@' {
+            let Some(last) = self.last_macro else {
+                self.pending = Pending::default();
+                self.set_status(StatusMessage::error("No previous register run"));
+                return Ok(());
+            };
+            name = last;
+        }
+        self.last_macro = Some(name);
+        if name == ':' {
+            return self.repeat_command();
+        }
+        let count = self.take_count().unwrap_or(1);
+        let Some(register) = self.register_contents(Some(name)) else {
+            return Ok(());
+        };
+        if self.running_macros.contains(&name) {
+            self.set_status(StatusMessage::error(format!("@{name} runs itself")));
+            return Ok(());
+        }
+        let mut text = register.text.replace('\n', "<CR>");
+        if register.kind == Kind::Lines {
+            text.push_str("<CR>");
+        }
+        let keys = match keymap::parse_keys(&text) {
+            Ok(keys) => keys.into_iter().map(Event::Key).collect::<Vec<_>>(),
+            Err(error) => {
+                self.set_status(StatusMessage::error(error));
+                return Ok(());
+            }
+        };
+        let lines = (self.mode == Mode::Visual).then(|| {
+            let (start, end) = (self.visual_start.y, self.cursor_position.y);
+            (start.min(end), start.max(end))
+        });
+        self.mode = Mode::Normal;
+        self.running_macros.push(name);
+        let result = self.on_each_line(lines, |editor| {
+            for _ in 0..count {
+                editor.type_keys(keys.clone())?;
+            }
+            Ok(())
+        });
+        self.running_macros.pop();
+        result
+    }
+    // Runs `run` on each of the lines `first..=last` in turn, with the
+    // cursor at the start of the line, or once where the cursor is without
+    // them. A command it leaves half typed, or insert mode, ends as Esc
+    // would end it, and its changes are undone together. Lines it adds or
+    // deletes move the ones still to go, so `:%norm dd` empties the buffer.
+    fn on_each_line<F>(
+        &mut self,
+        lines: Option<(usize, usize)>,
+        mut run: F,
+    ) -> Result<(), std::io::Error>
+    where
+        F: FnMut(&mut Self) -> Result<(), std::io::Error>,
+    {
+        let batching = mem::replace(&mut self.batching, true);
+        let (mut y, mut remaining) = match lines {
+            Some((first, last)) => (Some(first), last.saturating_sub(first).saturating_add(1)),
+            None => (None, 1),
+        };
+        let mut result = Ok(());
+        while remaining > 0 && result.is_ok() {
+            let before = self.document.len();
+            if let Some(y) = y {
+                if y >= before {
+                    break;
+                }
+                self.cursor_position = Position { x: 0, y };
+            }
+            result = run(self).and_then(|()| self.end_command());
+            remaining = remaining.saturating_sub(1);
+            y = y.map(|y| {
+                y.saturating_add(1)
+                    .saturating_add(self.document.len())
+                    .saturating_sub(before)
+            });
+        }
+        self.batching = batching;
+        result
+    }
+    // Ends a command left half typed, or insert or visual mode, as Esc does.
+    fn end_command(&mut self) -> Result<(), std::io::Error> {
+        if self.mode == Mode::Normal && self.pending == Pending::default() {
+            return Ok(());
+        }
+        self.process_keypress(Event::Key(KeyEvent::from(KeyCode::Esc)))
+    }
+    fn take_count(&mut self) -> Option<usize> {
+        mem::take(&mut self.pending).count()
+    }
+    // Waits for what `operator` acts on, keeping the count and register
+    // typed before it. In visual mode it acts on the selection straight
+    // away.
+    fn start_operator(&mut self, operator: Operator) {
+        let register = self.pending.register();
+        let count = self.take_count();
+        if self.mode == Mode::Visual {
+            self.apply_to_selection(operator, count.unwrap_or(1), register);
+            return;
+        }
+        self.pending = Pending::Operator {
+            operator,
+            count,
+            motion_count: None,
+            register,
+        };
+        self.mode = Mode::OperatorPending;
+    }
+    // `operator` on `span`, keeping what it takes in `register` as well as
+    // the unnamed one.
+    fn apply(&mut self, operator: Operator, register: Option<char>, span: &Span) {
+        if span.linewise {
+            self.apply_to_lines(operator, register, span.start.y, span.end.y);
+        } else {
+            self.apply_to_range(operator, register, &span.start, &span.end);
+        }
+    }
+    // `operator` on the lines `first..=last`, leaving the cursor on the
+    // first.
+    fn apply_to_lines(
+        &mut self,
+        operator: Operator,
+        register: Option<char>,
+        first: usize,
+        last: usize,
+    ) {
+        let last = last.min(self.document.len().saturating_sub(1));
+        let lines: Vec<String> = (first..=last)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        self.cursor_position.y = first;
+        match operator {
+            Operator::Delete => {
+                self.store(operator, register, Register::lines(&lines));
+                for _ in first..=last {
+                    self.document.delete_line(first);
+                }
+            }
+            Operator::Yank => {
+                self.store(operator, register, Register::lines(&lines));
+                self.flash = Some(Flash::lines(first, last));
+            }
+            // one empty line is left in their place, indented like the first
+            Operator::Change => {
+                self.store(operator, register, Register::lines(&lines));
+                let indent = lines
+                    .first()
+                    .map(|line| leading_whitespace(line).to_string())
+                    .unwrap_or_default();
+                self.cursor_position.x = indent.chars().count();
+                self.document
+                    .replace_lines(first, last.saturating_add(1), &[indent]);
+                self.enter_insert(false);
+                return;
             }
-
-            // insert newline before with O
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('O'),
-                    ..
-                }),
-            ) => {
-                let new_position = &mut self.cursor_position;
-                new_position.y = new_position.y.saturating_sub(1);
-                new_position.x = 0;
-                self.document.insert_newline(new_position);
-                self.mode = Mode::Insert;
+            Operator::Indent | Operator::Outdent => {
+                self.shift_lines((first, last), operator == Operator::Indent, 1);
             }
-
-            // Enter / to search in normal mode.
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('/'),
-                    modifiers: KeyModifiers::NONE,
-                }),
-            ) => self.search(),
-
-            // Enter Backspace in Insert mode to delete a char.
-            (
-                Mode::Insert,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Backspace,
-                    ..
-                }),
-            ) => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Event::Key(KeyEvent {
-                        code: KeyCode::Left,
-                        modifiers: KeyModifiers::NONE,
-                    }));
-                    self.document.delete(&self.cursor_position);
+            Operator::ToggleCase => {
+                let toggled: Vec<String> = lines.iter().map(|line| toggle_case(line)).collect();
+                self.document
+                    .replace_lines(first, last.saturating_add(1), &toggled);
+            }
+        }
+        self.clamp_cursor();
+    }
+    // The operator waiting in `pending` on what `motion` moves over, as `dw`
+    // or `y3j`. Like `ce`, `cw` on a word leaves the blanks after it.
+    fn apply_to_motion(&mut self, motion: Builtin) -> Result<(), std::io::Error> {
+        let Pending::Operator {
+            operator, register, ..
+        } = self.pending
+        else {
+            return Ok(());
+        };
+        self.mode = Mode::Normal;
+        let span = if motion.is_text_object() {
+            self.object_span(motion)?
+        } else {
+            self.motion_span(motion)?
+        };
+        let Some(mut span) = span else {
+            self.pending = Pending::default();
+            return Ok(());
+        };
+        self.pending = Pending::default();
+        let char_at = |position: &Position| {
+            self.document
+                .row(position.y)
+                .and_then(|row| row.as_str().chars().nth(position.x))
+        };
+        if operator == Operator::Change
+            && motion == Builtin::WordForward
+            && char_at(&span.start).is_some_and(|c| !c.is_whitespace())
+        {
+            while span.end.y > span.start.y || span.end.x > span.start.x {
+                let Some(x) = span.end.x.checked_sub(1) else {
+                    break;
+                };
+                let before = Position { x, y: span.end.y };
+                if !char_at(&before).is_some_and(char::is_whitespace) {
+                    break;
                 }
+                span.end = before;
             }
-
-            // Go to the end of the line with $
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('$'),
-                    ..
-                }),
-            ) => {
-                self.cursor_position.x = self
-                    .document
-                    .row(self.cursor_position.y)
-                    .unwrap_or(&Row::default())
-                    .len();
+        }
+        self.apply(operator, register, &span);
+        Ok(())
+    }
+    // What `motion` moves the cursor over, as many times as the count in
+    // `pending` says, for an operator to act on; the cursor stays where it
+    // is. Motions up and down the lines take them whole; `$`, and `f` and
+    // `t` forward, take the last character too, and the others stop short
+    // of where they land. None when a character search finds nothing, which
+    // leaves the text alone.
+    fn motion_span(&mut self, motion: Builtin) -> Result<Option<Span>, std::io::Error> {
+        let start = self.cursor_position.clone();
+        if matches!(motion, Builtin::FirstLine | Builtin::LastLine) {
+            // the count is the line to go to
+            self.perform_builtin(motion)?;
+        } else if motion.is_char_search() {
+            // the character is read once, and the count finds that many
+            if !self.search_char(motion)? {
+                return Ok(None);
             }
-
-            // Go to the beginning of the line with ^
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('^'),
-                    ..
-                }),
-            ) => {
-                self.cursor_position.x = 0;
+        } else {
+            for _ in 0..self.take_count().unwrap_or(1) {
+                self.perform_builtin(motion)?;
             }
-
-            // Insert if a char is pressed in Insert mode.
-            (
-                Mode::Insert,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    ..
-                }),
-            ) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    modifiers: KeyModifiers::NONE,
-                }));
+        }
+        let mut end = mem::replace(&mut self.cursor_position, start.clone());
+        self.wanted_column = None;
+        // `$` from past the end of the line with `virtualedit` stays there
+        if motion == Builtin::LineEnd && end.y == start.y && end.x < start.x {
+            end = start.clone();
+        }
+        let (mut from, mut to) = if (start.y, start.x) <= (end.y, end.x) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        if motion.is_linewise() {
+            return Ok(Some(Span::lines(from.y, to.y)));
+        }
+        let ends_text = || {
+            let row = self.document.row(to.y);
+            let before = row
+                .and_then(|row| row.as_str().chars().nth(to.x.checked_sub(1)?))
+                .is_some_and(|c| !c.is_whitespace());
+            before && motion::word_forward(&self.document, &to) == to
+        };
+        if motion == Builtin::LineEnd
+            || motion.is_char_search() && self.searches_forward(motion)
+            || motion == Builtin::WordForward && ends_text()
+        {
+            // `w` in the last word of the text stays on its last character
+            to.x = to.x.saturating_add(1);
+        } else if motion == Builtin::WordForward && to.y > from.y {
+            // the last word of a line ends what `w` takes
+            to.y = to.y.saturating_sub(1);
+            to.x = self.document.row(to.y).map_or(0, Row::len);
+        } else if to.y > from.y && to.x == 0 {
+            // stopping at the start of a line leaves the line break
+            to.y = to.y.saturating_sub(1);
+            to.x = self.document.row(to.y).map_or(0, Row::len);
+        }
+        // the columns past the end of a line hold no text to act on
+        for position in [&mut from, &mut to] {
+            position.x = position
+                .x
+                .min(self.document.row(position.y).map_or(0, Row::len));
+        }
+        Ok(Some(Span::characters(from, to)))
+    }
+    // The text object `builtin`, `i` or `a`, picks with the key typed after
+    // it, for an operator to act on. None when there is no such object at
+    // the cursor.
+    fn object_span(&mut self, builtin: Builtin) -> Result<Option<Span>, std::io::Error> {
+        let count = self.take_count().unwrap_or(1);
+        let Some(object) = self.read_char()?.and_then(TextObject::from_key) else {
+            return Ok(None);
+        };
+        let around = builtin == Builtin::AroundObject;
+        let extent = object.find(&self.document, &self.cursor_position, around, count);
+        Ok(extent.map(Span::from))
+    }
+    // `i` and `a` in visual mode: selects the text object named by the key
+    // typed next, whole lines for a paragraph.
+    fn select_object(&mut self, builtin: Builtin) -> Result<(), std::io::Error> {
+        let Some(span) = self.object_span(builtin)? else {
+            return Ok(());
+        };
+        if span.start == span.end && !span.linewise {
+            return Ok(());
+        }
+        self.visual_start = span.start;
+        self.visual_kind = if span.linewise {
+            Kind::Lines
+        } else {
+            Kind::Characters
+        };
+        // the selection takes in the character under the cursor
+        self.cursor_position = match span.end {
+            Position { x: 0, y } if !span.linewise => {
+                let y = y.saturating_sub(1);
+                let x = self.document.row(y).map_or(0, Row::len);
+                Position { x, y }
             }
-
-            // Insert a newline when Enter is pressed.
-            (
-                Mode::Insert,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
-                    ..
-                }),
-            ) => {
-                self.document.insert(&self.cursor_position, '\n');
-                self.move_cursor(Event::Key(KeyEvent {
-                    code: KeyCode::Right,
-                    modifiers: KeyModifiers::NONE,
-                }));
+            Position { x, y } => Position {
+                x: x.saturating_sub(1),
+                y,
+            },
+        };
+        self.mode = Mode::Visual;
+        Ok(())
+    }
+    // The next key typed, when it is a character.
+    fn read_char(&mut self) -> Result<Option<char>, std::io::Error> {
+        Ok(match self.read_input()? {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+            }) if !modifiers.contains(KeyModifiers::CONTROL) => Some(c),
+            _ => None,
+        })
+    }
+    // Whether the character search `motion` goes forward, as `f`, `t`, and
+    // `;` after one of them or `,` after `F` or `T` do.
+    fn searches_forward(&self, motion: Builtin) -> bool {
+        self.char_search
+            .is_some_and(|search| search.forward != (motion == Builtin::RepeatFindReverse))
+    }
+    // `f`, `t`, `F` and `T`, reading the character to search for, or `;`
+    // and `,` repeating the last of them, as many times as the count says.
+    // Returns whether it found the character, leaving the cursor there.
+    fn search_char(&mut self, builtin: Builtin) -> Result<bool, std::io::Error> {
+        let count = self.take_count().unwrap_or(1);
+        let search = match builtin {
+            Builtin::RepeatFind => self.char_search,
+            Builtin::RepeatFindReverse => self.char_search.map(CharSearch::reversed),
+            _ => {
+                let Some(target) = self.read_char()? else {
+                    return Ok(false);
+                };
+                let search = CharSearch {
+                    target,
+                    forward: matches!(builtin, Builtin::FindChar | Builtin::TillChar),
+                    till: matches!(builtin, Builtin::TillChar | Builtin::TillCharBackward),
+                };
+                self.char_search = Some(search);
+                Some(search)
             }
-
-            // Go to top of document with 'gg'
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('g'),
-                    ..
-                }),
-            ) => {
-                if self.previous_characters.last() == Some(&'g') {
-                    self.cursor_position.y = 0;
-                    self.previous_characters.clear();
-                } else {
-                    let mut position = 0;
-                    let mut digit = 0;
-                    while let Some(c) = self.previous_characters.pop() {
-                        if c.is_numeric() {
-                            position += (10_usize.pow(digit)) * (c.to_digit(10).unwrap() as usize);
-                            digit += 1;
-                        } else {
-                            break;
+        };
+        let repeat = matches!(builtin, Builtin::RepeatFind | Builtin::RepeatFindReverse);
+        let Position { x, y } = self.cursor_position;
+        let found = search.and_then(|search| {
+            let row = self.document.row(y)?;
+            search.find(row, x, count, repeat)
+        });
+        if let Some(x) = found {
+            self.cursor_position.x = x;
+        }
+        Ok(found.is_some())
+    }
+    // `operator` on the visual selection, `count` times over for `>` and
+    // `<`, and back to normal mode. The selection takes in the characters
+    // at both ends, and the line break after the last when it is on an
+    // empty line, or after `V` the lines it touches.
+    fn apply_to_selection(&mut self, operator: Operator, count: usize, register: Option<char>) {
+        self.mode = Mode::Normal;
+        let (start, end) = (self.visual_start.clone(), self.cursor_position.clone());
+        let (mut from, mut to) = if (start.y, start.x) <= (end.y, end.x) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        if matches!(operator, Operator::Indent | Operator::Outdent) {
+            self.shift_lines((from.y, to.y), operator == Operator::Indent, count);
+            self.cursor_position = from;
+            self.clamp_cursor();
+            return;
+        }
+        match self.visual_kind {
+            Kind::Lines => {
+                self.apply(operator, register, &Span::lines(from.y, to.y));
+                return;
+            }
+            Kind::Block => {
+                self.apply_to_block(operator, register, from.y, to.y);
+                return;
+            }
+            Kind::Characters => (),
+        }
+        // past the end of a line with `virtualedit` there is nothing more,
+        // not even the line break
+        from.x = from.x.min(self.document.row(from.y).map_or(0, Row::len));
+        let len = self.document.row(to.y).map_or(0, Row::len);
+        if to.x >= len.max(1) {
+            to.x = len;
+        } else if to.x >= len && to.y.saturating_add(1) < self.document.len() {
+            to = Position {
+                x: 0,
+                y: to.y.saturating_add(1),
+            };
+        } else {
+            to.x = to.x.saturating_add(1).min(len);
+        }
+        self.apply(operator, register, &Span::characters(from, to));
+    }
+    // `operator` on the block selected over the lines `first..=last`,
+    // leaving the cursor at its top left corner. A change types what is
+    // inserted on the first line on the others too.
+    fn apply_to_block(
+        &mut self,
+        operator: Operator,
+        register: Option<char>,
+        first: usize,
+        last: usize,
+    ) {
+        if matches!(operator, Operator::Indent | Operator::Outdent) {
+            self.shift_lines((first, last), operator == Operator::Indent, 1);
+            return;
+        }
+        let (left, _) = self.block_columns();
+        // each line in three: before, in and after the block, with a tab
+        // the block cuts through split into spaces on either side
+        let parts: Vec<(String, String, String)> = (first..=last)
+            .filter_map(|y| {
+                let (range, before, after) = self.block_range(y);
+                let row = self.document.row(y)?;
+                let line = row.as_str();
+                let (head, tail) = (row.byte_index(range.start), row.byte_index(range.end));
+                let piece: String = range
+                    .clone()
+                    .map(|index| {
+                        let text = row.get(index).unwrap_or_default();
+                        let mut cut = 0_usize;
+                        if index == range.start {
+                            cut = cut.saturating_add(before);
                         }
+                        if index.saturating_add(1) == range.end {
+                            cut = cut.saturating_add(after);
+                        }
+                        if cut == 0 {
+                            return text.to_string();
+                        }
+                        let width = row
+                            .column(index.saturating_add(1))
+                            .saturating_sub(row.column(index));
+                        " ".repeat(width.saturating_sub(cut))
+                    })
+                    .collect();
+                Some((
+                    format!(
+                        "{}{}",
+                        line.get(..head).unwrap_or_default(),
+                        " ".repeat(before)
+                    ),
+                    piece,
+                    format!(
+                        "{}{}",
+                        " ".repeat(after),
+                        line.get(tail..).unwrap_or_default()
+                    ),
+                ))
+            })
+            .collect();
+        let pieces: Vec<String> = parts.iter().map(|(_, piece, _)| piece.clone()).collect();
+        self.store(operator, register, Register::block(&pieces));
+        if operator == Operator::Yank {
+            self.flash = Some(Flash::block(first, last, self.block_columns()));
+        }
+        let lines: Vec<String> = match operator {
+            Operator::Yank | Operator::Indent | Operator::Outdent => Vec::new(),
+            Operator::Delete | Operator::Change => parts
+                .iter()
+                .map(|(head, _, tail)| format!("{head}{tail}"))
+                .collect(),
+            Operator::ToggleCase => parts
+                .iter()
+                .map(|(head, piece, tail)| format!("{head}{}{tail}", toggle_case(piece)))
+                .collect(),
+        };
+        if !lines.is_empty() {
+            self.document
+                .replace_lines(first, last.saturating_add(1), &lines);
+        }
+        let x = self
+            .document
+            .row(first)
+            .map_or(0, |row| row.index_at_column(left));
+        self.cursor_position = Position { x, y: first };
+        if operator == Operator::Change {
+            self.enter_insert(false);
+            self.insertion.block = Some((first.saturating_add(1)..last.saturating_add(1), left));
+            return;
+        }
+        self.clamp_cursor();
+    }
+    // `operator` on the text from `from` up to `to`, leaving the cursor at
+    // its start.
+    fn apply_to_range(
+        &mut self,
+        operator: Operator,
+        register: Option<char>,
+        from: &Position,
+        to: &Position,
+    ) {
+        self.cursor_position = from.clone();
+        if matches!(operator, Operator::Indent | Operator::Outdent) {
+            self.shift_lines((from.y, to.y), operator == Operator::Indent, 1);
+            self.clamp_cursor();
+            return;
+        }
+        if from == to {
+            if operator == Operator::Change {
+                self.enter_insert(false);
+            }
+            return;
+        }
+        let line = |y: usize| {
+            self.document
+                .row(y)
+                .map(|row| row.as_str().to_string())
+                .unwrap_or_default()
+        };
+        let (first, last) = (line(from.y), line(to.y));
+        let head = Row::from(first.as_str()).byte_index(from.x);
+        let tail = Row::from(last.as_str()).byte_index(to.x);
+        let text = if from.y == to.y {
+            first.get(head..tail).unwrap_or_default().to_string()
+        } else {
+            let mut text = first.get(head..).unwrap_or_default().to_string();
+            for y in from.y.saturating_add(1)..to.y {
+                text.push('\n');
+                text.push_str(&line(y));
+            }
+            text.push('\n');
+            text.push_str(last.get(..tail).unwrap_or_default());
+            text
+        };
+        let replacement = match operator {
+            Operator::Yank => {
+                self.store(operator, register, Register::characters(&text));
+                self.flash = Some(Flash::characters(from.clone(), to.clone()));
+                return;
+            }
+            Operator::ToggleCase => toggle_case(&text),
+            _ => {
+                self.store(operator, register, Register::characters(&text));
+                String::new()
+            }
+        };
+        let lines: Vec<String> = format!(
+            "{}{replacement}{}",
+            first.get(..head).unwrap_or_default(),
+            last.get(tail..).unwrap_or_default()
+        )
+        .split('\n')
+        .map(String::from)
+        .collect();
+        self.document
+            .replace_lines(from.y, to.y.saturating_add(1), &lines);
+        if operator == Operator::Change {
+            self.enter_insert(false);
+        } else {
+            self.clamp_cursor();
+        }
+    }
+    // Indents the lines `start..=end` by `shiftwidth` columns, `times` over,
+    // with spaces or with `noexpandtab` a tab, or outdents them by as much
+    // of the same. Blank lines stay as they are.
+    fn shift_lines(&mut self, (start, end): (usize, usize), indent: bool, times: usize) {
+        let width = self.options.shiftwidth.max(1);
+        let unit = if self.options.expandtab {
+            " ".repeat(width)
+        } else {
+            String::from("\t")
+        };
+        let lines: Vec<String> = (start..=end)
+            .filter_map(|y| self.document.row(y))
+            .map(|row| {
+                let mut text = row.as_str().to_string();
+                for _ in 0..times {
+                    if text.trim().is_empty() {
+                        break;
                     }
-                    if position == 0 {
-                        self.previous_characters.push('g');
+                    if indent {
+                        text.insert_str(0, &unit);
+                    } else if let Some(rest) = text.strip_prefix('\t') {
+                        text = rest.to_string();
                     } else {
-                        if position > self.document.len() - 1 {
-                            self.cursor_position.y = self.document.len() - 1;
-                        } else {
-                            self.cursor_position.y = position;
-                        }
-                        self.previous_characters.clear();
+                        let spaces = text.chars().take(width).take_while(|&c| c == ' ').count();
+                        text.replace_range(..spaces, "");
                     }
                 }
+                text
+            })
+            .collect();
+        self.document
+            .replace_lines(start, start.saturating_add(lines.len()), &lines);
+    }
+    // Keeps what `operator` yanked or deleted, in `register` as well as the
+    // unnamed one, copying it to the system clipboard for `"+` and `"*`.
+    fn store(&mut self, operator: Operator, name: Option<char>, register: Register) {
+        if let Some(selection) = Selection::of_register(name) {
+            let text = register.to_text();
+            let copied = if self.uses_clipboard_tool() {
+                self.clipboard.copy(selection, &text)
+            } else if self.options.clipboard == Provider::Tool {
+                Err(String::from("No clipboard tool found"))
+            } else {
+                self.terminal
+                    .send(&clipboard::osc52(selection, &text))
+                    .map_err(|error| error.to_string())
+            };
+            if let Err(error) = copied {
+                self.set_status(StatusMessage::error(error));
             }
-
-            // Go to bottom of document with 'G'
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('G'),
-                    ..
-                }),
-            ) => {
-                self.cursor_position.y = self.document.len() - 1;
+        }
+        match operator {
+            Operator::Delete | Operator::Change => self.registers.delete(name, register),
+            Operator::Yank => self.registers.yank(name, register),
+            Operator::Indent | Operator::Outdent | Operator::ToggleCase => (),
+        }
+    }
+    // What the register `name` picks holds, or the unnamed one; `"%` is the
+    // file name.
+    fn register_contents(&self, name: Option<char>) -> Option<Register> {
+        match name {
+            Some('%') => self.document.file_name.as_deref().map(Register::characters),
+            name => self.registers.get(name).cloned(),
+        }
+    }
+    // Whether `"+` and `"*` go through a clipboard tool rather than OSC 52.
+    fn uses_clipboard_tool(&self) -> bool {
+        self.options.clipboard != Provider::Osc52 && self.clipboard.tool().is_some()
+    }
+    // Puts `count` copies of `register`, or the unnamed one, after the
+    // cursor, or `before` it: whole lines below or above the cursor line,
+    // `indented` like it if asked, anything else into the line. The cursor
+    // goes to the first line put, or the last character.
+    fn put(&mut self, before: bool, count: usize, register: Option<char>, indented: bool) {
+        // `"+` and `"*` have what is on the system clipboard, when there is
+        // a tool to get it with; otherwise what was last copied to them
+        let pasted = match Selection::of_register(register) {
+            Some(selection) if self.uses_clipboard_tool() => {
+                match self.clipboard.paste(selection) {
+                    Ok(text) => Some(Register::from_text(&text)),
+                    Err(error) => {
+                        self.set_status(StatusMessage::error(error));
+                        return;
+                    }
+                }
             }
-
-            // push char to vector in normal mode if no use for it.
-            (
-                Mode::Normal,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    ..
-                }),
-            ) => self.previous_characters.push(c),
-            _ => (),
+            _ => None,
+        };
+        let Some(register) = pasted
+            .or_else(|| self.register_contents(register))
+            .filter(|register| !register.is_empty())
+        else {
+            self.set_status(StatusMessage::error("Nothing to put"));
+            return;
+        };
+        let cursor = self.cursor_position.clone();
+        self.put_register(before, count, &register, indented);
+        self.last_put = Some(LastPut {
+            before,
+            count,
+            indented,
+            cursor,
+            index: self
+                .registers
+                .history()
+                .iter()
+                .position(|other| *other == register),
+            revision: self.document.revision(),
+        });
+    }
+    // `<C-p>` right after a put: puts the yank or delete before it in the
+    // history in its place instead, going round to the newest after the
+    // oldest.
+    fn put_older(&mut self) {
+        let Some(last) = self.last_put.take() else {
+            return;
+        };
+        let history = self.registers.history();
+        if last.revision != self.document.revision() || history.is_empty() {
+            return;
         }
-        self.scroll();
-        Ok(())
+        let index = last
+            .index
+            .map_or(0, |index| index.saturating_add(1) % history.len());
+        let Some(register) = history.get(index).cloned() else {
+            return;
+        };
+        self.document.undo();
+        self.cursor_position = last.cursor.clone();
+        self.put_register(last.before, last.count, &register, last.indented);
+        self.last_put = Some(LastPut {
+            index: Some(index),
+            revision: self.document.revision(),
+            ..last
+        });
+    }
+    // `:YankHistory` and `<leader>p`: the last yanks and deletes, newest
+    // first, to pick one to put after the cursor.
+    fn yank_history(&mut self) {
+        let width = self.screen_width();
+        let items: Vec<Item> = self
+            .registers
+            .history()
+            .iter()
+            .map(|register| Item {
+                text: statusline::truncate(&register.text.replace('\n', "^J"), width),
+                detail: register.kind.letter().to_string(),
+            })
+            .collect();
+        if items.is_empty() {
+            self.set_status(StatusMessage::error("Nothing yanked or deleted yet"));
+            return;
+        }
+        let chosen = self.pick("Yank: ", items, 0).unwrap_or(None);
+        let Some(register) = chosen.and_then(|index| self.registers.history().get(index).cloned())
+        else {
+            return;
+        };
+        let cursor = self.cursor_position.clone();
+        self.put_register(false, 1, &register, false);
+        self.last_put = Some(LastPut {
+            before: false,
+            count: 1,
+            indented: false,
+            cursor,
+            index: chosen,
+            revision: self.document.revision(),
+        });
+    }
+    // Puts `count` copies of `register` as `put` does.
+    fn put_register(&mut self, before: bool, count: usize, register: &Register, indented: bool) {
+        let Position { x, y } = self.cursor_position;
+        if register.kind == Kind::Block {
+            self.put_block(before, count, &register.text);
+            return;
+        }
+        if register.kind == Kind::Lines {
+            let mut lines: Vec<String> = (0..count)
+                .flat_map(|_| register.text.split('\n'))
+                .map(String::from)
+                .collect();
+            if indented {
+                let indent = self
+                    .document
+                    .row(y)
+                    .map(|row| leading_whitespace(row.as_str()).to_string())
+                    .unwrap_or_default();
+                reindent(&mut lines, &indent);
+            }
+            let at = if before { y } else { y.saturating_add(1) }.min(self.document.len());
+            self.document.replace_lines(at, at, &lines);
+            let last = at.saturating_add(lines.len()).saturating_sub(1);
+            self.flash = Some(Flash::lines(at, last));
+            let indent = lines
+                .first()
+                .map_or(0, |line| leading_whitespace(line).chars().count());
+            self.cursor_position = Position { x: indent, y: at };
+            self.clamp_cursor();
+            return;
+        }
+        let line = self
+            .document
+            .row(y)
+            .map(|row| row.as_str().to_string())
+            .unwrap_or_default();
+        let row = Row::from(line.as_str());
+        let at = if before || row.is_empty() {
+            x
+        } else {
+            x.saturating_add(1)
+        }
+        .min(row.len());
+        let (head, tail) = line.split_at(row.byte_index(at).min(line.len()));
+        let text = register.text.repeat(count);
+        let lines: Vec<String> = format!("{head}{text}{tail}")
+            .split('\n')
+            .map(String::from)
+            .collect();
+        self.document.replace_lines(y, y.saturating_add(1), &lines);
+        let end = match text.rsplit_once('\n') {
+            Some((before, last)) => Position {
+                x: Row::from(last).len(),
+                y: y.saturating_add(before.matches('\n').count())
+                    .saturating_add(1),
+            },
+            None => Position {
+                x: at.saturating_add(Row::from(text.as_str()).len()),
+                y,
+            },
+        };
+        self.flash = Some(Flash::characters(Position { x: at, y }, end));
+        self.cursor_position.x = if text.contains('\n') {
+            at
+        } else {
+            at.saturating_add(Row::from(text.as_str()).len())
+                .saturating_sub(1)
+        };
+        self.clamp_cursor();
+    }
+    // `p` and `P` with a block: a piece on each line from the cursor line
+    // down, in a column after or at the cursor, `count` times side by side.
+    // Short lines are filled out with spaces to reach the column, lines are
+    // added past the end of the buffer, and pieces are filled out to the
+    // block's width when text follows them, to keep the column straight.
+    fn put_block(&mut self, before: bool, count: usize, text: &str) {
+        let Position { x, y } = self.cursor_position;
+        let column = self.document.row(y).map_or(0, |row| {
+            let at = if before || row.is_empty() {
+                x
+            } else {
+                x.saturating_add(1)
+            };
+            row.column(at.min(row.len()))
+        });
+        let pieces: Vec<&str> = text.split('\n').collect();
+        let width = pieces
+            .iter()
+            .map(|piece| statusline::width(piece))
+            .max()
+            .unwrap_or(0);
+        let lines: Vec<String> = pieces
+            .iter()
+            .zip(y..)
+            .map(|(piece, y)| {
+                let line = self
+                    .document
+                    .row(y)
+                    .map(|row| row.as_str().to_string())
+                    .unwrap_or_default();
+                let row = Row::from(line.as_str());
+                let at = row.index_at_column(column).min(row.len());
+                let (head, tail) = line.split_at(row.byte_index(at));
+                let gap = " ".repeat(column.saturating_sub(row.width()));
+                let padded = format!(
+                    "{piece}{}",
+                    " ".repeat(width.saturating_sub(statusline::width(piece)))
+                );
+                let mut text = padded.repeat(count.saturating_sub(1));
+                if tail.is_empty() {
+                    text.push_str(piece);
+                } else {
+                    text.push_str(&padded);
+                }
+                format!("{head}{gap}{text}{tail}")
+            })
+            .collect();
+        let replaced = self.document.len().saturating_sub(y).min(lines.len());
+        self.document
+            .replace_lines(y, y.saturating_add(replaced), &lines);
+        let right = column.saturating_add(width.saturating_mul(count));
+        self.flash = Some(Flash::block(
+            y,
+            y.saturating_add(lines.len()).saturating_sub(1),
+            (column, right.saturating_sub(1)),
+        ));
+        self.cursor_position = Position {
+            x: lines
+                .first()
+                .map_or(0, |line| Row::from(line.as_str()).index_at_column(column)),
+            y,
+        };
+        self.clamp_cursor();
+    }
+    // The columns of the screen; `Terminal::size` counts the line number
+    // gutter in once more.
+    fn screen_width(&self) -> usize {
+        (self.terminal.size().width as usize).saturating_sub(5)
+    }
+    // The columns left for text. `Terminal::size` counts the line number
+    // gutter in once already, and the gutter takes as many again.
+    fn text_width(&self) -> usize {
+        (self.terminal.size().width as usize).saturating_sub(10)
+    }
+    // Moves the words past `textwidth` on the cursor line to lines of their
+    // own, following the cursor.
+    fn break_typed_line(&mut self) {
+        loop {
+            let Position { x, y } = self.cursor_position;
+            let Some(found) = self
+                .document
+                .row(y)
+                .and_then(|row| format::line_break(&self.options, row, x))
+            else {
+                return;
+            };
+            for _ in found.start..found.end {
+                self.document.delete(&Position { x: found.start, y });
+            }
+            let next = y.saturating_add(1);
+            self.document
+                .insert_newline(&Position { x: found.start, y });
+            for (index, c) in found.prefix.chars().enumerate() {
+                self.document.insert(&Position { x: index, y: next }, c);
+            }
+            self.cursor_position = Position {
+                x: x.saturating_sub(found.end)
+                    .saturating_add(found.prefix.chars().count()),
+                y: next,
+            };
+        }
+    }
+    // How `row` is split over screen lines when wrapping.
+    fn layout(&self, row: &Row) -> Layout {
+        Layout::new(&self.options, self.text_width(), row)
+    }
+    // How many screen lines the row at `y` takes.
+    fn row_height(&self, y: usize) -> usize {
+        match self.document.row(y) {
+            Some(row) if self.options.wrap => self.layout(row).height(row.width()),
+            _ => 1,
+        }
+    }
+    // Where the cursor is in the text area, in screen lines and columns.
+    fn cursor_screen_position(&self) -> Position {
+        self.screen_position(&self.cursor_position)
+    }
+    fn screen_position(&self, position: &Position) -> Position {
+        let Position { x, y } = *position;
+        let column = self.document.row(y).map_or(x, |row| row.column(x));
+        if !self.options.wrap {
+            return Position {
+                x: column.saturating_sub(self.offset.x),
+                y: y.saturating_sub(self.offset.y),
+            };
+        }
+        let above: usize = (self.offset.y..y).map(|index| self.row_height(index)).sum();
+        let Some(layout) = self.document.row(y).map(|row| self.layout(row)) else {
+            return Position { x: 0, y: above };
+        };
+        Position {
+            x: layout.column(column),
+            y: above.saturating_add(layout.line_of(column)),
+        }
+    }
+    // `gj` and `gk`: {count} screen lines down or up, staying in the same
+    // screen column. Without wrapping that is just `j` and `k`.
+    fn move_display_line(&mut self, down: bool) {
+        fn layout(editor: &Editor, y: usize) -> Option<(Layout, &Row)> {
+            editor.document.row(y).map(|row| (editor.layout(row), row))
+        }
+        if !self.options.wrap {
+            self.move_cursor(Event::Key(KeyEvent {
+                code: if down { KeyCode::Down } else { KeyCode::Up },
+                modifiers: KeyModifiers::NONE,
+            }));
+            return;
+        }
+        let column = match &self.display_column {
+            Some((position, column)) if *position == self.cursor_position => *column,
+            _ => layout(self, self.cursor_position.y).map_or(0, |(layout, row)| {
+                layout.column(row.column(self.cursor_position.x))
+            }),
+        };
+        for _ in 0..self.take_count().unwrap_or(1) {
+            let Position { x, y } = self.cursor_position;
+            let line = layout(self, y).map_or(0, |(layout, row)| layout.line_of(row.column(x)));
+            let (y, line) = if down {
+                if line.saturating_add(1) < self.row_height(y) {
+                    (y, line.saturating_add(1))
+                } else if y.saturating_add(1) < self.document.len() {
+                    (y.saturating_add(1), 0)
+                } else {
+                    break;
+                }
+            } else if line > 0 {
+                (y, line.saturating_sub(1))
+            } else if y > 0 {
+                let y = y.saturating_sub(1);
+                (y, self.row_height(y).saturating_sub(1))
+            } else {
+                break;
+            };
+            let x = layout(self, y).map_or(0, |(layout, row)| {
+                row.index_at_column(layout.at(line, column)).min(row.len())
+            });
+            self.cursor_position = Position { x, y };
+        }
+        self.display_column = Some((self.cursor_position.clone(), column));
     }
     fn scroll(&mut self) {
         let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
+        let width = self.text_width();
         let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
-        if y < offset.y {
-            offset.y = y;
-        } else if y >= offset.y.saturating_add(height) {
-            offset.y = y.saturating_sub(height).saturating_add(1);
+        let scrolloff = self.options.scrolloff.min(height.saturating_sub(1) / 2);
+        let offset = &mut self.offset;
+        if y < offset.y.saturating_add(scrolloff) {
+            offset.y = y.saturating_sub(scrolloff);
+        } else if y.saturating_add(scrolloff) >= offset.y.saturating_add(height) {
+            offset.y = y
+                .saturating_add(scrolloff)
+                .saturating_add(1)
+                .saturating_sub(height);
+        }
+        // the screen columns the cursor's grapheme takes, which have to be
+        // on screen and off the columns `listchars` markers take
+        let (column, last, row_len) = self.document.row(y).map_or((x, x, 0), |row| {
+            let next = row.column(x.saturating_add(1));
+            (row.column(x), next.saturating_sub(1), row.len())
+        });
+        let left = usize::from(self.options.listchars.precedes.is_some());
+        let right =
+            usize::from(self.options.listchars.extends.is_some() && row_len > x.saturating_add(1));
+        if column < offset.x || (offset.x > 0 && column < offset.x.saturating_add(left)) {
+            offset.x = column.saturating_sub(left);
+        } else if last.saturating_add(right) >= offset.x.saturating_add(width) {
+            offset.x = last
+                .saturating_add(right)
+                .saturating_add(1)
+                .saturating_sub(width);
         }
-        if x < offset.x {
-            offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        // wrapped rows above the cursor can still push it off the bottom
+        if self.options.wrap {
+            self.offset.x = 0;
+            while self.offset.y < y && self.cursor_screen_position().y >= height {
+                self.offset.y = self.offset.y.saturating_add(1);
+            }
         }
     }
     fn move_cursor(&mut self, event: Event) {
         let Position { mut y, mut x } = self.cursor_position;
         let height = self.document.len();
-        let mut width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
+        // the column `j` and `k` keep to through shorter lines
+        let mut column = None;
         match event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('k'),
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Up, ..
-            }) => {
-                let mut position = 0;
-                let mut digit = 0;
-                while let Some(c) = self.previous_characters.pop() {
-                    if c.is_numeric() {
-                        position += (10_usize.pow(digit)) * (c.to_digit(10).unwrap() as usize);
-                        digit += 1;
-                    } else {
-                        break;
-                    }
+            Event::Key(
+                KeyEvent {
+                    code: KeyCode::Char('k'),
+                    ..
                 }
-                if position > y {
-                    y = 0;
-                } else if position == 0 {
-                    if y == 0 {
-                        y = 0;
-                    } else {
-                        y -= 1;
-                    }
-                } else {
-                    y -= position;
+                | KeyEvent {
+                    code: KeyCode::Up, ..
+                },
+            ) => {
+                column = Some(self.wanted_column());
+                y = y.saturating_sub(self.take_count().unwrap_or(1));
+            }
+            Event::Key(
+                KeyEvent {
+                    code: KeyCode::Char('j'),
+                    ..
                 }
-                self.previous_characters.clear();
+                | KeyEvent {
+                    code: KeyCode::Down,
+                    ..
+                },
+            ) => {
+                column = Some(self.wanted_column());
+                let count = self.take_count().unwrap_or(1);
+                y = y.saturating_add(count).min(height.saturating_sub(1));
             }
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('j'),
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Down,
-                ..
-            }) => {
-                let mut position = 0;
-                let mut digit = 0;
-                while let Some(c) = self.previous_characters.pop() {
-                    if c.is_numeric() {
-                        position += (10_usize.pow(digit)) * (c.to_digit(10).unwrap() as usize);
-                        digit += 1;
-                    } else {
-                        break;
-                    }
+            Event::Key(
+                KeyEvent {
+                    code: KeyCode::Char('h'),
+                    ..
                 }
-                if y + position > self.document.len() - 1 {
-                    y = self.document.len() - 1;
-                } else if position == 0 {
-                    if y + 1 > self.document.len() - 1 {
-                        y = self.document.len() - 1;
-                    } else {
-                        y += 1;
+                | KeyEvent {
+                    code: KeyCode::Left,
+                    ..
+                },
+            ) => {
+                for _ in 0..self.take_count().unwrap_or(1) {
+                    if x > 0 {
+                        x = x.saturating_sub(1);
+                    } else if y > 0 {
+                        y = y.saturating_sub(1);
+                        x = self.last_column(y);
                     }
-                } else {
-                    y += position;
                 }
-                self.previous_characters.clear();
             }
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('h'),
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Left,
-                ..
-            }) => {
-                if x > 0 {
-                    x -= 1;
-                } else if y > 0 {
-                    y -= 1;
-                    if let Some(row) = self.document.row(y) {
-                        x = row.len();
-                    } else {
+            Event::Key(
+                KeyEvent {
+                    code: KeyCode::Char('l'),
+                    ..
+                }
+                | KeyEvent {
+                    code: KeyCode::Right,
+                    ..
+                },
+            ) => {
+                for _ in 0..self.take_count().unwrap_or(1) {
+                    if x < self.last_column(y) {
+                        x = x.saturating_add(1);
+                    } else if y.saturating_add(1) < height {
+                        y = y.saturating_add(1);
                         x = 0;
                     }
                 }
             }
-            Event::Key(KeyEvent {
-                code: KeyCode::Char('l'),
-                ..
-            })
-            | Event::Key(KeyEvent {
-                code: KeyCode::Right,
-                ..
-            }) => {
-                if x < width {
-                    x += 1;
-                } else if y < height {
-                    y += 1;
-                    x = 0;
-                }
-            }
             _ => (),
         }
-        width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
-        if x > width {
-            x = width;
+        if let Some(column) = column {
+            x = self
+                .document
+                .row(y)
+                .map_or(column, |row| row.index_at_column(column));
+        }
+        x = x.min(self.last_column(y));
+        self.cursor_position = Position { x, y };
+        if let Some(column) = column {
+            self.wanted_column = Some((self.cursor_position.clone(), column));
+        }
+    }
+    // The screen column `j` and `k` aim for: the one they started from
+    // before passing through shorter lines, or the end of every line after
+    // `$`. Tabs and wide characters make it differ from the index.
+    fn wanted_column(&self) -> usize {
+        match &self.wanted_column {
+            Some((position, column)) if *position == self.cursor_position => *column,
+            _ => {
+                let Position { x, y } = self.cursor_position;
+                self.document.row(y).map_or(x, |row| row.column(x))
+            }
+        }
+    }
+    // The furthest the cursor can go on line `y`: onto the last character,
+    // or one past it in insert mode, unless `virtualedit` lets it go
+    // further.
+    fn last_column(&self, y: usize) -> usize {
+        let len = self.document.row(y).map_or(0, Row::len);
+        let virtualedit = self.options.virtualedit;
+        match self.mode {
+            Mode::Insert => len,
+            _ if virtualedit.all => usize::MAX,
+            Mode::Visual if virtualedit.block => usize::MAX,
+            _ if virtualedit.onemore => len,
+            _ => len.saturating_sub(1),
         }
-
-        self.cursor_position = Position { x, y }
     }
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("rvim -- version {}", VERSION);
+    fn welcome_message(&self) -> String {
+        let mut welcome_message = format!("rvim -- version {VERSION}");
         let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        #[allow(clippy::integer_arithmetic, clippy::integer_division)]
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{}{}", spaces, welcome_message);
+        let spaces = " ".repeat(self.welcome_padding());
+        welcome_message = format!("~{spaces}{welcome_message}");
         welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        welcome_message
     }
-    pub fn draw_row(&self, row: &Row, row_number: u16) {
+    // The spaces after the `~` that center the welcome message.
+    fn welcome_padding(&self) -> usize {
         let width = self.terminal.size().width as usize;
+        let len = format!("rvim -- version {VERSION}").len();
+        let padding = width.saturating_sub(len) / 2;
+        padding.saturating_sub(1)
+    }
+    // `set dashboard`: the editor started without a file and nothing has
+    // been typed yet, so the start screen shows in place of the buffer.
+    fn showing_dashboard(&self) -> bool {
+        self.options.dashboard
+            && self.document.file_name.is_none()
+            && self.document.is_empty()
+            && !self.document.is_dirty()
+    }
+    fn dashboard_lines(&self) -> Vec<String> {
+        if !self.showing_dashboard() {
+            return Vec::new();
+        }
+        let files = self.oldfiles.files();
+        let items = dashboard::items(files);
+        dashboard::lines(
+            VERSION,
+            &items,
+            self.dashboard_selected,
+            files.len() > dashboard::FILES,
+        )
+    }
+    // The number keys, `j`, `k` and Enter on the dashboard; false for the
+    // keys it leaves to normal mode.
+    fn process_dashboard_key(&mut self, event: Event) -> Result<bool, std::io::Error> {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }) = event
+        else {
+            return Ok(false);
+        };
+        let items = dashboard::items(self.oldfiles.files());
+        match code {
+            KeyCode::Char(c @ '1'..='9') => {
+                let key = c.to_string();
+                if let Some(item) = items.iter().find(|item| item.key == key) {
+                    self.perform(&item.action)?;
+                }
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.dashboard_selected = self
+                    .dashboard_selected
+                    .saturating_add(1)
+                    .min(items.len().saturating_sub(1));
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.dashboard_selected = self.dashboard_selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                if let Some(item) = items.get(self.dashboard_selected) {
+                    self.perform(&item.action)?;
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+    // The screen line showing `row`, line `y` of the buffer, from the screen
+    // column `start` on; a row continued from the line above has no
+    // `row_number`. Appends the row to `rendered`, which is reused from row
+    // to row.
+    fn render_row(
+        &self,
+        row: &Row,
+        y: usize,
+        row_number: Option<u16>,
+        start: usize,
+        sign: Sign,
+        rendered: &mut String,
+    ) {
+        let selected = self.selected(y);
+        let flashed = self.flash.as_ref().and_then(|flash| flash.on_line(y, row));
+        let tokens = self.semantic_tokens.on_line(y, self.document.revision());
+        let diagnostic = match sign {
+            Sign::Diagnostic(diagnostic) => Some(diagnostic),
+            Sign::Bookmark | Sign::Breakpoint | Sign::Stopped | Sign::None => None,
+        };
         let height = self.terminal.size().height as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let current_line_number = self.cursor_position.y.saturating_add(1);
-        let relative_position = height.saturating_sub(height.saturating_sub(row_number.into())) as usize;
-        let row_number = row_number as usize;
-        let fold_number = self.cursor_position.y.saturating_div(height as usize);
-        let cursor_row = self.cursor_row();
+        let wrap = self.options.wrap;
+        let layout = wrap.then(|| self.layout(row));
+        let end = start.saturating_add(layout.as_ref().map_or_else(
+            || self.text_width(),
+            |layout| layout.line_width(layout.line_of(start)),
+        ));
+        // markers in the first and last column for text cut off there
+        let listchars = self.options.listchars;
+        let precedes = listchars
+            .precedes
+            .filter(|_| !wrap && start > 0 && row.width() > start);
+        let extends = listchars.extends.filter(|_| !wrap && row.width() > end);
+        // the graphemes starting in the columns between the markers; a wide
+        // one cut by the left edge leaves a gap, and one cut by the right
+        // edge is drawn whole when wrapping and left out otherwise
+        let from = start.saturating_add(usize::from(precedes.is_some()));
+        let to = end.saturating_sub(usize::from(extends.is_some()));
+        let mut first = row.index_at_column(from);
+        if row.column(first) < from {
+            first = first.saturating_add(1);
+        }
+        let gap = row.column(first).saturating_sub(from);
+        let mut last = row.index_at_column(to);
+        if wrap && row.column(last) < to {
+            last = last.saturating_add(1);
+        }
         // current line number = where the cursor is
         // calculate the offset of the cursor
         // if it's the next line, add a line, else don't
@@ -816,125 +5377,884 @@ impl Editor {
         //  | cursor: 6 | row: 7 | cursor_row: 4 | height: 4 |
         //  | cursor: 7 | row: 8 | cursor_row: 5 | height: 4 |
         //  -------------------------------------------------
-        let line_no = relative_position.saturating_add(0);
         // relative position goes from 56 to 1.
+        let line_no = row_number.map_or_else(String::new, |row_number| {
+            height
+                .saturating_sub(height.saturating_sub(row_number.into()))
+                .to_string()
+        });
 
-        let row = row.render(start, end, line_no);
-
-        println!("{}\r", row)
-    }
-    fn cursor_row(&self) -> usize {
-        self.cursor_position.y % (self.terminal.size().height as usize)
+        let normal = self.theme.style(Group::Normal);
+        let _ = write!(
+            rendered,
+            "{}{}{line_no:^4}",
+            SetForegroundColor(normal.fg.unwrap_or(Color::Reset)),
+            SetBackgroundColor(normal.bg.unwrap_or(Color::Reset))
+        );
+        let non_text = self.theme.style(Group::NonText);
+        let mut write_styled = |style: Style, text: &str| {
+            let _ = write!(
+                rendered,
+                "{}{}{text}",
+                SetForegroundColor(style.fg.unwrap_or(Color::Reset)),
+                SetBackgroundColor(style.bg.unwrap_or(Color::Reset))
+            );
+        };
+        // a sign takes the place of the gutter bar
+        match sign {
+            Sign::Diagnostic(diagnostic)
+                if self.options.diagnostics == diagnostics::Display::Signs
+                    && row_number.is_some() =>
+            {
+                let severity = diagnostic.severity;
+                write_styled(
+                    self.theme.style(severity.group()),
+                    severity.sign().encode_utf8(&mut [0; 4]),
+                );
+                write_styled(normal, "");
+            }
+            Sign::Bookmark if row_number.is_some() => {
+                write_styled(
+                    self.theme.style(Group::BookmarkSign),
+                    bookmarks::SIGN.encode_utf8(&mut [0; 4]),
+                );
+                write_styled(normal, "");
+            }
+            Sign::Breakpoint | Sign::Stopped if row_number.is_some() => {
+                let (group, sign) = match sign {
+                    Sign::Stopped => (Group::StoppedSign, debug::STOPPED_SIGN),
+                    _ => (Group::BreakpointSign, debug::BREAKPOINT_SIGN),
+                };
+                write_styled(self.theme.style(group), sign.encode_utf8(&mut [0; 4]));
+                write_styled(normal, "");
+            }
+            _ => write_styled(normal, "|"),
+        }
+        if let Some(marker) = precedes {
+            write_styled(non_text, marker.encode_utf8(&mut [0; 4]));
+        }
+        if let (Some(layout), None) = (&layout, row_number) {
+            write_styled(normal, &" ".repeat(layout.indent()));
+            let showbreak: String = self
+                .options
+                .showbreak
+                .chars()
+                .take(layout.showbreak())
+                .collect();
+            write_styled(non_text, &showbreak);
+        }
+        write_styled(normal, &" ".repeat(gap));
+        // the selection, or else the text just yanked or put, keeps the
+        // colors of the text unless `Visual` or `Flash` sets them
+        let visual = self.theme.style(if selected.is_some() {
+            Group::Visual
+        } else {
+            Group::Flash
+        });
+        let selected = selected.or(flashed);
+        let selected_style = |style: Style| Style {
+            fg: visual.fg.or(style.fg),
+            bg: visual.bg.or(style.bg),
+        };
+        let selection = selected.as_ref().map_or(last..last, |selected| {
+            selected.start.clamp(first, last)..selected.end.clamp(first, last)
+        });
+        // drawn in pieces that are each all in or out of the selection and
+        // of any semantic token, whose color goes over the syntax's
+        let mut cuts = vec![first, selection.start, selection.end, last];
+        for token in tokens {
+            let range = token.range();
+            cuts.extend([range.start.clamp(first, last), range.end.clamp(first, last)]);
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for piece in cuts.windows(2) {
+            let &[from, to] = piece else {
+                continue;
+            };
+            let token = tokens.iter().find(|token| token.range().contains(&from));
+            row.for_each_segment(from, to, |hl_type, text| {
+                let group = token.map_or(Group::of(hl_type), |token| token.kind.group());
+                let style = self.theme.style(group);
+                if selection.contains(&from) {
+                    write_styled(selected_style(style), text);
+                } else {
+                    write_styled(style, text);
+                }
+            });
+        }
+        if let Some(marker) = extends {
+            write_styled(non_text, marker.encode_utf8(&mut [0; 4]));
+        }
+        // the message goes after the end of the line, in what room is left
+        let room = end.saturating_sub(row.width().max(start));
+        match diagnostic {
+            // whole selected lines are selected out to the edge
+            _ if selected.is_some_and(|selected| selected.end == usize::MAX) => {
+                write_styled(selected_style(normal), &" ".repeat(room));
+            }
+            Some(diagnostic)
+                if self.options.diagnostics == diagnostics::Display::Inline
+                    && row.width() <= end
+                    && room > 2 =>
+            {
+                let message = diagnostic.message.lines().next().unwrap_or_default();
+                let text: String = format!("  {message}").chars().take(room).collect();
+                write_styled(self.theme.style(diagnostic.severity.group()), &text);
+            }
+            _ => (),
+        }
+        let _ = write!(
+            rendered,
+            "{}{}",
+            SetForegroundColor(normal.fg.unwrap_or(Color::Reset)),
+            SetBackgroundColor(normal.bg.unwrap_or(Color::Reset))
+        );
     }
-    #[allow(clippy::integer_division, clippy::integer_arithmetic)]
-    fn draw_rows(&self) {
+    fn draw_rows(&mut self) {
         let height = self.terminal.size().height;
+        let mut line = String::new();
+        // the completion menu goes over the rows below or above the word, and
+        // the picker over the bottom rows
+        let picker = self.picker.as_ref().map(|picker| {
+            (
+                Position {
+                    x: 0,
+                    y: usize::from(height).saturating_sub(picker.popup.height()),
+                },
+                picker.popup.lines(
+                    self.text_width(),
+                    self.theme.style(Group::Pmenu),
+                    self.theme.style(Group::PmenuSel),
+                ),
+            )
+        });
+        let menu = picker.or_else(|| {
+            self.completion.as_ref().map(|completion| {
+                let cursor = self.cursor_screen_position();
+                let anchor = Position {
+                    x: cursor.x.saturating_sub(completion.current.chars().count()),
+                    y: cursor.y,
+                };
+                (
+                    completion
+                        .popup
+                        .place(&anchor, self.text_width(), height.into()),
+                    completion.popup.lines(
+                        self.text_width(),
+                        self.theme.style(Group::Pmenu),
+                        self.theme.style(Group::PmenuSel),
+                    ),
+                )
+            })
+        });
+        // the signature goes over the line above the call, or below it on the
+        // top line
+        let signature = self.signature.as_ref().map(|signature| {
+            let open = self.screen_position(&Position {
+                x: signature.open,
+                y: self.cursor_position.y,
+            });
+            let y = open.y.checked_sub(1).unwrap_or(open.y.saturating_add(1));
+            // moved left as far as it needs to stay on the screen
+            let width = self.text_width();
+            let x = open.x.min(width.saturating_sub(signature.width()));
+            (
+                Position { x, y },
+                signature.line(
+                    width.saturating_sub(x),
+                    self.theme.style(Group::Pmenu),
+                    self.theme.style(Group::PmenuSel),
+                ),
+            )
+        });
+        let dashboard = self.dashboard_lines();
+        // the dashboard goes in the middle of the screen, lined up on its
+        // widest line
+        let width = self.terminal.size().width as usize;
+        let dashboard_top = usize::from(height).saturating_sub(dashboard.len()) / 2;
+        let widest = dashboard.iter().map(|line| statusline::width(line)).max();
+        let padding = " ".repeat(width.saturating_sub(widest.unwrap_or(0)) / 2);
+        let marked = self.marked_lines();
+        // the row being drawn, and which of its screen lines
+        let (mut y, mut part) = (self.offset.y, 0_usize);
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
+            line.clear();
+            if let Some(row) = self.document.row(y) {
+                if self.options.wrap {
+                    let number = (part == 0).then_some(terminal_row);
+                    let layout = self.layout(row);
+                    let start = layout.line_start(part);
+                    let sign = self.sign(y, &marked);
+                    self.render_row(row, y, number, start, sign, &mut line);
+                    part = part.saturating_add(1);
+                    if part >= layout.height(row.width()) {
+                        (y, part) = (y.saturating_add(1), 0);
+                    }
+                } else {
+                    let sign = self.sign(y, &marked);
+                    let start = self.offset.x;
+                    self.render_row(row, y, Some(terminal_row), start, sign, &mut line);
+                    y = y.saturating_add(1);
+                }
+            } else if let Some(text) = usize::from(terminal_row)
+                .checked_sub(dashboard_top)
+                .and_then(|index| dashboard.get(index))
             {
-                self.draw_row(row, terminal_row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+                line = statusline::truncate(&format!("~{padding}{text}"), width);
+                line.truncate(line.trim_end().len());
+            } else if self.document.is_empty() && dashboard.is_empty() && terminal_row == height / 3
+            {
+                line = self.welcome_message();
             } else {
-                println!("~\r");
+                line.push('~');
+            }
+            for (target, label) in &self.sneak_labels {
+                let at = self.screen_position(target);
+                if at.y == usize::from(terminal_row) {
+                    let style = self.theme.style(Group::SneakLabel);
+                    let label = format!(
+                        "{}{}{label}",
+                        SetForegroundColor(style.fg.unwrap_or(Color::Reset)),
+                        SetBackgroundColor(style.bg.unwrap_or(Color::Reset))
+                    );
+                    line = popup::overlay(&line, at.x.saturating_add(5), &label);
+                }
+            }
+            if let Some(lines) = &self.long_message {
+                let first = usize::from(height).saturating_sub(lines.len());
+                if let Some(text) = usize::from(terminal_row)
+                    .checked_sub(first)
+                    .and_then(|index| lines.get(index))
+                {
+                    line.clone_from(text);
+                }
+            }
+            if let Some((at, text)) = &signature {
+                if usize::from(terminal_row) == at.y {
+                    line = popup::overlay(&line, at.x.saturating_add(5), text);
+                }
+            }
+            if let Some((at, lines)) = &menu {
+                if let Some(text) = usize::from(terminal_row)
+                    .checked_sub(at.y)
+                    .and_then(|index| lines.get(index))
+                {
+                    // past the line number gutter
+                    line = popup::overlay(&line, at.x.saturating_add(5), text);
+                }
             }
+            self.terminal.write_line(&line);
         }
     }
-    fn draw_status_bar(&self) {
-        let mut status;
-        let width = self.terminal.size().width as usize;
+    fn draw_status_bar(&mut self) {
         let modified_indicator = if self.document.is_dirty() {
             " (modified)"
         } else {
             ""
         };
-
-        let mut file_name = "[No Name]".to_string();
-        if let Some(name) = &self.document.file_name {
-            file_name = name.clone();
-            file_name.truncate(20);
-        }
-        status = format!(
-            "{} - {} lines{}",
+        let read_only = self.document.is_read_only()
+            || self.document.file_info().is_some_and(|info| info.read_only);
+        let read_only_indicator = if read_only { " [RO]" } else { "" };
+        let file_name = self.document.file_name.as_deref().map_or_else(
+            || "[No Name]".to_string(),
+            |name| statusline::truncate(name, 20),
+        );
+        let left = format!(
+            "{} - {} lines{}{}",
             file_name,
             self.document.len(),
-            modified_indicator
+            modified_indicator,
+            read_only_indicator,
         );
 
-        let line_indicator = format!(
-            "{}: {} | {}:{}",
-            self.mode,
+        let mut center = match self.git_diff.stats() {
+            Some(diff) if !diff.is_empty() => {
+                format!("+{} ~{} -{}", diff.added, diff.modified, diff.removed)
+            }
+            _ => String::new(),
+        };
+        if let Some(saving) = &self.saving {
+            let separator = if center.is_empty() { "" } else { " " };
+            let _ = write!(center, "{separator}[writing {}%]", saving.percent);
+        }
+        if let Some((name, _)) = &self.recording {
+            let separator = if center.is_empty() { "" } else { " " };
+            let _ = write!(center, "{separator}recording @{name}");
+        }
+
+        let file_info = self.document.file_info().map_or_else(String::new, |info| {
+            format!(
+                " | {} {} {}",
+                format_size(info.size),
+                self.document.encoding(),
+                self.document.line_ending(),
+            )
+        });
+        let mode = match self.visual_kind {
+            Kind::Lines if self.mode == Mode::Visual => String::from("visual line mode"),
+            Kind::Block if self.mode == Mode::Visual => String::from("visual block mode"),
+            _ => self.mode.to_string(),
+        };
+        let right = format!(
+            "{}{}: {}{} | {}:{}",
+            mode,
+            self.selection_size(),
             self.document.file_type(),
+            file_info,
             self.cursor_position.y.saturating_add(1),
             self.cursor_position.x.saturating_add(1),
         );
-        #[allow(clippy::integer_arithmetic)]
-        let len = status.len() + line_indicator.len();
-        status.push_str(&" ".repeat(width.saturating_sub(len.saturating_add(5))));
-        status = format!("{}{}", status, line_indicator);
-        status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
-    }
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+        let status = statusline::layout(&left, &center, &right, self.screen_width());
+        let style = self.theme.style(Group::StatusLine);
+        self.terminal.set_bg_color(style.bg.unwrap_or(Color::Reset));
+        self.terminal.set_fg_color(style.fg.unwrap_or(Color::Reset));
+        self.terminal.write_line(&status);
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
+    }
+    // ` (5 chars)` within one line, ` (3 lines)` across several or after
+    // `V`, ` (3x2)` lines by columns for a block, and nothing outside
+    // visual mode.
+    fn selection_size(&self) -> String {
+        if self.mode != Mode::Visual {
+            return String::new();
+        }
+        let (start, end) = (&self.visual_start, &self.cursor_position);
+        if self.visual_kind == Kind::Block {
+            let (left, right) = self.block_columns();
+            let lines = start.y.abs_diff(end.y).saturating_add(1);
+            format!(
+                " ({lines}x{})",
+                right.saturating_sub(left).saturating_add(1)
+            )
+        } else if start.y == end.y && self.visual_kind == Kind::Characters {
+            let chars = start.x.abs_diff(end.x).saturating_add(1);
+            format!(" ({chars} char{})", if chars == 1 { "" } else { "s" })
+        } else {
+            format!(" ({} lines)", start.y.abs_diff(end.y).saturating_add(1))
+        }
+    }
+    fn draw_message_bar(&mut self) {
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+        let width = self.screen_width();
+        let mut text = String::new();
+        if message.is_visible() {
+            text = statusline::truncate(&message.text, width);
+        }
+        let command = self.showcmd();
+        if !command.is_empty() {
+            // like Vim, the last ten columns but one are the command's
+            let room = width.saturating_sub(SHOWCMD_WIDTH.saturating_add(1));
+            text = statusline::truncate(&text, room);
+            text.push_str(&" ".repeat(room.saturating_sub(statusline::width(&text))));
+            text.push_str(&command);
+        }
+        self.terminal.write(&text);
+    }
+    // The part of a normal mode command typed so far: its count, the key
+    // naming a mark, or the start of a longer binding.
+    fn showcmd(&self) -> String {
+        if !self.options.showcmd || self.mode == Mode::Insert {
+            return String::new();
         }
+        let command = self.pending.typed() + &keymap::keys_name(&self.pending_keys);
+        let skip = command.chars().count().saturating_sub(SHOWCMD_WIDTH);
+        command.chars().skip(skip).collect()
     }
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
     where
         C: FnMut(&mut Self, Event, &String),
     {
-        let mut result = String::new();
-        loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
+        let result = self.read_line(|_| prompt.to_string(), None, callback)?;
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+    // Like `prompt`, but tells Enter on an empty line, Some(""), from Esc,
+    // None. The prompt is asked for again before each key, so it can show
+    // what the callback changed. With a `history`, what is entered is added
+    // to it, and Up and Down bring back the entries that start with what
+    // was typed.
+    fn read_line<P, C>(
+        &mut self,
+        prompt: P,
+        history: Option<HistoryKind>,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        P: Fn(&Self) -> String,
+        C: FnMut(&mut Self, Event, &String),
+    {
+        let mut line = CommandLine::default();
+        // the history entry shown, and what was typed before going to it
+        let mut recalled: Option<usize> = None;
+        let mut typed = String::new();
+        let answer = loop {
+            let label = prompt(self);
+            self.show_command_line(&label, &line, false);
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
-            match key {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Backspace,
-                    ..
-                }) => result.truncate(result.len().saturating_sub(1)),
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('\n'),
-                    ..
-                }) => break,
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    ..
-                }) => {
-                    if !c.is_control() {
-                        result.push(c);
+            let key = self.read_input()?;
+            if let Event::Key(KeyEvent { code, modifiers }) = key {
+                let control = modifiers.contains(KeyModifiers::CONTROL);
+                if !matches!(code, KeyCode::Up | KeyCode::Down) {
+                    recalled = None;
+                }
+                match code {
+                    KeyCode::Enter | KeyCode::Char('\n') => {
+                        if let Some(kind) = history {
+                            self.history(kind).add(&line.text());
+                        }
+                        break Some(line.text());
+                    }
+                    KeyCode::Up | KeyCode::Down => {
+                        if let Some(kind) = history {
+                            if recalled.is_none() {
+                                typed = line.text();
+                            }
+                            let history = self.history(kind);
+                            let found = match (code, recalled) {
+                                (KeyCode::Up, None) => {
+                                    history.previous(history.entries().len(), &typed)
+                                }
+                                // the oldest match stays
+                                (KeyCode::Up, Some(index)) => {
+                                    history.previous(index, &typed).or(Some(index))
+                                }
+                                (_, Some(index)) => history.next(index, &typed),
+                                (_, None) => None,
+                            };
+                            line.set(found.and_then(|index| history.get(index)).unwrap_or(&typed));
+                            recalled = found;
+                        }
+                    }
+                    KeyCode::Esc => break None,
+                    KeyCode::Backspace => line.delete_before(),
+                    KeyCode::Delete => line.delete_under(),
+                    KeyCode::Left => line.left(),
+                    KeyCode::Right => line.right(),
+                    KeyCode::Home => line.home(),
+                    KeyCode::End => line.end(),
+                    KeyCode::Char('b') if control => line.home(),
+                    KeyCode::Char('e') if control => line.end(),
+                    KeyCode::Char('w') if control => line.delete_word_before(),
+                    KeyCode::Char('u') if control => line.delete_to_start(),
+                    KeyCode::Char('r') if control => {
+                        self.show_command_line(&label, &line, true);
+                        self.refresh_screen()?;
+                        if let Event::Key(key) = self.read_input()? {
+                            if let Some(text) = self.register(key) {
+                                line.insert(&text);
+                            }
+                        }
                     }
+                    KeyCode::Char(c)
+                        if !c.is_control()
+                            && !control
+                            && !modifiers.contains(KeyModifiers::ALT) =>
+                    {
+                        line.insert(&c.to_string());
+                    }
+                    _ => (),
                 }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc, ..
-                }) => {
-                    result.truncate(0);
-                    break;
+            }
+            callback(self, key, &line.text());
+        };
+        self.prompt_cursor = None;
+        self.set_status(StatusMessage::from(String::new()));
+        Ok(answer)
+    }
+    // Shows `prompt` and what has been typed after it, scrolled so the
+    // cursor stays on screen. Waiting for the register after Ctrl-R, a `"`
+    // stands where its text will go.
+    fn show_command_line(&mut self, prompt: &str, line: &CommandLine, register: bool) {
+        let mut text: Vec<char> = prompt.chars().chain(line.text().chars()).collect();
+        let cursor = prompt.chars().count().saturating_add(line.cursor());
+        if register {
+            text.insert(cursor, '"');
+        }
+        let width = self.screen_width().saturating_sub(1).max(1);
+        let skip = cursor.saturating_sub(width);
+        self.set_status(StatusMessage::prompt(
+            text.iter().skip(skip).collect::<String>(),
+        ));
+        self.prompt_cursor = Some(cursor.saturating_sub(skip));
+    }
+    // What Ctrl-R followed by `key` puts in a prompt: the word under the
+    // cursor for Ctrl-W, the last search for `/`, or what the register
+    // `key` names holds, its lines run together.
+    fn register(&self, key: KeyEvent) -> Option<String> {
+        match (key.code, key.modifiers) {
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.word_under_cursor(),
+            (KeyCode::Char('/'), _) => self
+                .last_search
+                .as_ref()
+                .map(|pattern| pattern.text().to_string()),
+            (KeyCode::Char(name), modifiers)
+                if register::is_name(name) && !modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                let register = self.register_contents(Some(name))?;
+                Some(register.text.replace('\n', " "))
+            }
+            _ => None,
+        }
+    }
+    // The word the cursor is on, or the next one after it on the line.
+    fn word_under_cursor(&self) -> Option<String> {
+        let Position { x, y } = self.cursor_position;
+        let chars: Vec<char> = self.document.row(y)?.as_str().chars().collect();
+        let start = x.saturating_add(chars.get(x..)?.iter().position(|&c| is_word_char(c))?);
+        let start = chars
+            .get(..start)?
+            .iter()
+            .rposition(|&c| !is_word_char(c))
+            .map_or(0, |index| index.saturating_add(1));
+        let word: String = chars
+            .get(start..)?
+            .iter()
+            .take_while(|&&c| is_word_char(c))
+            .collect();
+        Some(word)
+    }
+    fn history(&mut self, kind: HistoryKind) -> &mut History {
+        match kind {
+            HistoryKind::Command => &mut self.command_history,
+            HistoryKind::Search => &mut self.search_history,
+        }
+    }
+    // Lets the user choose one of `items`, starting from the one at
+    // `selected`, by typing to narrow them down and moving through them
+    // with the arrow keys or Ctrl-N and Ctrl-P. Returns its index, or None
+    // if they gave up.
+    fn pick(
+        &mut self,
+        prompt: &str,
+        items: Vec<Item>,
+        selected: usize,
+    ) -> Result<Option<usize>, std::io::Error> {
+        self.picker = Some(Picker::new(items, selected));
+        let answer = self.read_line(
+            |_| prompt.to_string(),
+            None,
+            |editor, key, query| {
+                let Some(picker) = &mut editor.picker else {
+                    return;
+                };
+                let Event::Key(KeyEvent { code, modifiers }) = key else {
+                    return;
+                };
+                match (code, modifiers) {
+                    (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                        picker.select_next();
+                    }
+                    (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                        picker.select_previous();
+                    }
+                    _ => picker.filter(query),
+                }
+            },
+        );
+        let picker = self.picker.take();
+        Ok(answer?.and(picker).and_then(|picker| picker.selected()))
+    }
+}
+
+// Accessors for the `rvim` Lua API.
+#[cfg(feature = "lua")]
+impl Editor {
+    pub fn show_message<S: Into<String>>(&mut self, message: S) {
+        self.set_status(StatusMessage::from(message));
+    }
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+    pub fn options_mut(&mut self) -> &mut Options {
+        &mut self.options
+    }
+    pub fn keymaps_mut(&mut self) -> &mut Keymaps {
+        &mut self.keymaps
+    }
+}
+
+// Buffer access shared by Lua scripts, RPC clients and tests.
+#[cfg(any(feature = "lua", unix, test))]
+impl Editor {
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+    pub fn edit(&mut self, file_name: &str) -> bool {
+        self.edit_file(file_name)
+    }
+    pub fn replace_lines(&mut self, start: usize, end: usize, lines: &[String]) {
+        self.document.replace_lines(start, end, lines);
+        self.clamp_cursor();
+    }
+    pub fn cursor_position(&self) -> &Position {
+        &self.cursor_position
+    }
+    pub fn set_cursor_position(&mut self, position: Position) {
+        self.cursor_position = position;
+        self.clamp_cursor();
+        self.scroll();
+    }
+    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics.set(diagnostics);
+    }
+    // Shows `label` for the call the cursor is in, with the characters
+    // `parameter` of it lit up. False outside insert mode or a call.
+    pub fn show_signature(&mut self, label: &str, parameter: Option<Range<usize>>) -> bool {
+        let open = self.call_start().filter(|_| self.mode == Mode::Insert);
+        self.signature = open.map(|open| Signature::new(label, parameter, open));
+        self.signature.is_some()
+    }
+    pub fn hide_signature(&mut self) {
+        self.signature = None;
+    }
+    // Lists `actions` in the popup menu and applies the edits of the one
+    // picked, returning its index.
+    pub fn pick_code_action(&mut self, actions: &[CodeAction]) -> Option<usize> {
+        if actions.is_empty() {
+            self.set_status(StatusMessage::from("No code actions"));
+            return None;
+        }
+        let items = actions
+            .iter()
+            .map(|action| Item {
+                text: action.title.clone(),
+                detail: String::new(),
+            })
+            .collect();
+        let index = self.pick("Code action: ", items, 0).unwrap_or(None)?;
+        let action = actions.get(index)?;
+        if let Err(error) = self.apply_edits(&action.edits) {
+            self.set_status(StatusMessage::error(error));
+        }
+        Some(index)
+    }
+    // For the text as it is now.
+    pub fn set_semantic_tokens(&mut self, tokens: Vec<SemanticToken>) {
+        self.semantic_tokens.set(tokens, self.document.revision());
+    }
+}
+
+// The headless frontend driven by the key-sequence tests in `harness`.
+#[cfg(test)]
+impl Editor {
+    pub fn headless(text: &str, width: u16, height: u16) -> Self {
+        Self::with_terminal(
+            &Arguments::default(),
+            Document::from(text),
+            Terminal::headless(width, height),
+            EventLoop::headless(),
+        )
+    }
+    // An editor whose event loop waits for its timers, with keys sent over
+    // `input`.
+    pub fn timed(text: &str, width: u16, height: u16) -> Self {
+        Self::with_terminal(
+            &Arguments::default(),
+            Document::from(text),
+            Terminal::headless(width, height),
+            EventLoop::timed(),
+        )
+    }
+    pub fn input(&self) -> event::EventSender {
+        self.events.sender()
+    }
+    pub fn is_quitting(&self) -> bool {
+        self.should_quit
+    }
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+    // Lights up `query` as the search word does while it is typed, which
+    // the scripted keys only see the end of.
+    pub fn highlight_word(&mut self, query: &str) -> Result<(), std::io::Error> {
+        let pattern = Pattern::new(query, self.search_flags).map_err(std::io::Error::other)?;
+        self.set_highlighted_word(Some(pattern));
+        self.refresh_screen()?;
+        self.wait_for_highlighting()
+    }
+    // Lets the highlighting jobs finish and redraws.
+    fn wait_for_highlighting(&mut self) -> Result<(), std::io::Error> {
+        while self.highlighter.is_busy() {
+            let event = self
+                .events
+                .wait_for(|event| matches!(event, event::Event::Highlighted { .. }))?;
+            self.handle_event(event)?;
+            self.refresh_screen()?;
+        }
+        Ok(())
+    }
+    // Queues `keys` for a prompt opened outside `run_keys`, such as the
+    // menu of `pick_code_action`.
+    pub fn post_keys(&mut self, keys: Vec<Event>) {
+        for key in keys {
+            self.events.post(event::Event::Input(key));
+        }
+    }
+    // Handles `keys` the way `run` does, redrawing before each one, and
+    // leaves the last frame on the screen.
+    pub fn run_keys(&mut self, keys: Vec<Event>) -> Result<(), std::io::Error> {
+        for key in keys {
+            self.events.post(event::Event::Input(key));
+        }
+        loop {
+            self.refresh_screen()?;
+            if self.should_quit {
+                return Ok(());
+            }
+            self.set_timers();
+            match self.events.next() {
+                Ok(event) => self.handle_event(event)?,
+                // keys ran out, but a save still has to land
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::UnexpectedEof
+                        && self.saving.is_some() =>
+                {
+                    self.wait_for_save()?;
                 }
-                _ => (),
+                // and so does a search
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::UnexpectedEof
+                        && self.searcher.is_running() =>
+                {
+                    let event = self
+                        .events
+                        .wait_for(|event| matches!(event, event::Event::SearchMatches { .. }))?;
+                    self.handle_event(event)?;
+                }
+                // and the highlighting, so the screen is drawn in color
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::UnexpectedEof
+                        && self.highlighter.is_busy() =>
+                {
+                    self.wait_for_highlighting()?;
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(error) => return Err(error),
             }
-            callback(self, key, &result);
         }
-        self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
-            return Ok(None);
+    }
+    pub fn screen(&self) -> &[String] {
+        self.terminal.screen()
+    }
+    pub fn style(&self, group: Group) -> Style {
+        self.theme.style(group)
+    }
+    // Goes off as `timer` would once its time came, which the scripted
+    // keys never wait for.
+    pub fn fire(&mut self, timer: Timer) -> Result<(), std::io::Error> {
+        self.handle_event(event::Event::Timer(timer))?;
+        self.refresh_screen()
+    }
+    // Runs the event loop of `run`, minus the terminal's raw mode, over the
+    // keys posted, until they quit the editor.
+    pub fn run_headless(&mut self, keys: Vec<Event>) -> Result<(), Error> {
+        self.post_keys(keys);
+        self.run_loop()
+    }
+    // Records the startup phases the way `--startuptime` does.
+    pub fn log_startup(&mut self, path: &str) {
+        self.startup = Some(StartupLog::new(path));
+    }
+    // Serves RPC clients on the socket at `path`, as `--listen` does.
+    #[cfg(unix)]
+    pub fn listen(&mut self, path: &str) -> Result<(), std::io::Error> {
+        self.rpc = Some(RpcServer::listen(path, self.events.sender())?);
+        Ok(())
+    }
+    // Waits for a message from an RPC client and answers what came in.
+    #[cfg(unix)]
+    pub fn serve_rpc(&mut self) -> Result<(), std::io::Error> {
+        let event = self
+            .events
+            .wait_for(|event| matches!(event, event::Event::Rpc))?;
+        self.handle_event(event)
+    }
+    // Debugs `program` through an adapter at the other end of `stream`, as
+    // `:DebugStart` does through the one it starts.
+    #[cfg(unix)]
+    pub fn debug_over(
+        &mut self,
+        program: &str,
+        stream: std::os::unix::net::UnixStream,
+    ) -> Result<(), std::io::Error> {
+        let adapter = Adapter::connect(stream.try_clone()?, stream, self.events.sender());
+        self.debugger.start(adapter, program, &[], "")
+    }
+    // Waits for a message from the debug adapter and handles it.
+    pub fn serve_debugger(&mut self) -> Result<(), std::io::Error> {
+        let event = self
+            .events
+            .wait_for(|event| matches!(event, event::Event::Debug { .. }))?;
+        self.handle_event(event)?;
+        self.refresh_screen()
+    }
+}
+
+// `512B`, `1.2K`, `3.4M`: the size the way `ls -lh` shows it.
+#[allow(clippy::cast_precision_loss)]
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut size = bytes as f64;
+    for unit in ["K", "M", "G"] {
+        size /= 1024.0;
+        if size < 1024.0 {
+            return format!("{size:.1}{unit}");
         }
-        Ok(Some(result))
+    }
+    format!("{:.1}T", size / 1024.0)
+}
+
+// How `:ls` and the quit dialog name a buffer.
+fn buffer_name(document: &Document) -> String {
+    document
+        .file_name
+        .clone()
+        .unwrap_or_else(|| "[No Name]".to_string())
+}
+
+// Letters, digits and underscores make up the words completion offers.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// `text` with its lowercase letters made uppercase and the other way
+// around.
+fn toggle_case(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_lowercase() {
+                c.to_uppercase().to_string()
+            } else {
+                c.to_lowercase().to_string()
+            }
+        })
+        .collect()
+}
+
+// Gives the first of `lines` that is not blank `indent` in place of its
+// own, and the others the same change, so they keep their indent relative
+// to it. A line indented less than the first loses all of its own.
+fn reindent(lines: &mut [String], indent: &str) {
+    let old = lines
+        .iter()
+        .find(|text| !text.trim().is_empty())
+        .map(|text| leading_whitespace(text).to_string())
+        .unwrap_or_default();
+    for text in lines.iter_mut().filter(|text| !text.trim().is_empty()) {
+        let rest = text.strip_prefix(&old).unwrap_or_else(|| text.trim_start());
+        *text = format!("{indent}{rest}");
     }
 }
 
-fn die(e: std::io::Error) {
-    std::panic::panic_any(e);
+// The spaces and tabs a line starts with.
+fn leading_whitespace(text: &str) -> &str {
+    let rest = text.trim_start_matches([' ', '\t']);
+    text.get(..text.len().saturating_sub(rest.len()))
+        .unwrap_or_default()
 }
@@ -0,0 +1,39 @@
+use std::fmt;
+use std::io;
+
+// Why the editor had to stop. Failures it can carry on from, such as a file
+// that will not save or a mistyped command, are shown in the message bar
+// instead; only losing the terminal ends up here.
+#[derive(Debug)]
+pub struct Error {
+    // What was being done, as in "could not {doing}".
+    doing: &'static str,
+    source: io::Error,
+}
+
+impl Error {
+    pub fn new(doing: &'static str, source: io::Error) -> Self {
+        Self { doing, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not {}: {}", self.doing, self.source)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Errors worth trying again rather than giving up over: a read cut short by
+// a signal, or a terminal that was briefly not ready.
+pub fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
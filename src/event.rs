@@ -0,0 +1,263 @@
+use crate::Pattern;
+use crate::Position;
+use crossterm::event::{self, KeyCode, KeyEvent, KeyModifiers};
+use rvim_core::highlighting;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Everything the editor reacts to. Input and other background work arrive
+// from their own threads through an `EventSender`; timers fire from the
+// loop itself.
+pub enum Event {
+    Input(event::Event),
+    Timer(Timer),
+    // A file passed to `watch` changed on disk.
+    FileChanged(PathBuf),
+    // RPC requests are waiting in the server's queue.
+    #[cfg(unix)]
+    Rpc,
+    // A message from the debug adapter `adapter`, or None once it has
+    // gone.
+    Debug {
+        adapter: usize,
+        message: Option<serde_json::Value>,
+    },
+    // A background save has written this many percent of the file.
+    SaveProgress(usize),
+    // A background save of the buffer as of `revision` has finished.
+    Saved {
+        revision: u64,
+        result: Result<(), String>,
+    },
+    // More matches from the background search started as `generation`;
+    // `done` once the whole document has been searched.
+    SearchMatches {
+        generation: usize,
+        matches: Vec<Position>,
+        done: bool,
+    },
+    // Highlighting for the rows from `start`, computed for the buffer as of
+    // `revision` and file type with `word` as the search word.
+    Highlighted {
+        revision: u64,
+        // The file type's name, as the type can change without the text.
+        file_type: String,
+        word: Option<Pattern>,
+        start: usize,
+        rows: Vec<Vec<highlighting::Type>>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timer {
+    // The message bar has shown its message long enough.
+    MessageExpiry,
+    // Typing has paused long enough to redo the git diff counts.
+    GitDiff,
+    // Enough time has passed since the last screen update for the next.
+    Frame,
+    // Keys that could start a longer binding waited `timeoutlen` for the
+    // rest of it.
+    KeyTimeout,
+    // The text just yanked or put has been lit up long enough.
+    Flash,
+}
+
+// A handle for other threads to post events; cheap to clone.
+#[derive(Clone)]
+pub struct EventSender(Sender<io::Result<Event>>);
+
+impl EventSender {
+    // Returns false once the editor is gone.
+    pub fn send(&self, event: Event) -> bool {
+        self.0.send(Ok(event)).is_ok()
+    }
+    // Fails the read the editor is waiting on, as losing the terminal does.
+    #[cfg(test)]
+    pub fn fail(&self, error: io::Error) -> bool {
+        self.0.send(Err(error)).is_ok()
+    }
+}
+
+pub struct EventLoop {
+    sender: Sender<io::Result<Event>>,
+    receiver: Receiver<io::Result<Event>>,
+    timers: Vec<(Instant, Timer)>,
+    // Events put aside by `wait_for`, handed out again before anything new.
+    deferred: VecDeque<Event>,
+    // Tests post their keys up front and stop when they run out instead of
+    // waiting for more.
+    headless: bool,
+    // How long an Esc waits for a key right behind it; see `join_escape`.
+    escape_timeout: Duration,
+}
+
+impl EventLoop {
+    // Starts reading terminal input on a thread of its own.
+    pub fn new() -> Self {
+        let event_loop = Self::without_input();
+        let sender = event_loop.sender.clone();
+        thread::spawn(move || loop {
+            let event = event::read().map(Event::Input);
+            let failed = event.is_err();
+            if sender.send(event).is_err() || failed {
+                return;
+            }
+        });
+        event_loop
+    }
+    #[cfg(test)]
+    pub fn headless() -> Self {
+        Self {
+            headless: true,
+            ..Self::without_input()
+        }
+    }
+    // Like `headless`, but waiting for timers and for keys sent from another
+    // thread, as the editor does between keypresses.
+    #[cfg(test)]
+    pub fn timed() -> Self {
+        Self::without_input()
+    }
+    fn without_input() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            timers: Vec::new(),
+            deferred: VecDeque::new(),
+            headless: false,
+            escape_timeout: Duration::ZERO,
+        }
+    }
+    pub fn sender(&self) -> EventSender {
+        EventSender(self.sender.clone())
+    }
+    // (Re)starts `timer`; each timer has at most one deadline.
+    pub fn set_timer(&mut self, timer: Timer, deadline: Instant) {
+        self.cancel_timer(timer);
+        self.timers.push((deadline, timer));
+    }
+    pub fn cancel_timer(&mut self, timer: Timer) {
+        self.timers.retain(|(_, other)| *other != timer);
+    }
+    pub fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.escape_timeout = timeout;
+    }
+    // Waits for whichever comes first: an event from another thread or the
+    // earliest timer.
+    pub fn next(&mut self) -> io::Result<Event> {
+        if let Some(event) = self.deferred.pop_front() {
+            return Ok(event);
+        }
+        if self.headless {
+            return match self.receiver.try_recv() {
+                Ok(event) => self.join_escape(event),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "no more scripted keys",
+                )),
+            };
+        }
+        let earliest = self
+            .timers
+            .iter()
+            .min_by_key(|(deadline, _)| *deadline)
+            .copied();
+        let Some((deadline, timer)) = earliest else {
+            let event = self.receiver.recv().unwrap_or_else(|_| Err(disconnected()));
+            return self.join_escape(event);
+        };
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => self.join_escape(event),
+            Err(RecvTimeoutError::Timeout) => {
+                self.cancel_timer(timer);
+                Ok(Event::Timer(timer))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(disconnected()),
+        }
+    }
+    // Terminals send a key with Alt held as Esc and then the key. crossterm
+    // joins the two when they come in one read, so this is only for links
+    // slow enough to split them: with a nonzero `escape_timeout`, a key that
+    // comes that soon after a lone Esc makes one key with it.
+    fn join_escape(&mut self, event: io::Result<Event>) -> io::Result<Event> {
+        let escape = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        let is_escape =
+            matches!(&event, Ok(Event::Input(event::Event::Key(key))) if *key == escape);
+        if !is_escape || self.escape_timeout.is_zero() {
+            return event;
+        }
+        match self.receiver.recv_timeout(self.escape_timeout) {
+            Ok(Ok(Event::Input(event::Event::Key(key))))
+                if matches!(key.code, KeyCode::Char(_))
+                    && !key.modifiers.contains(KeyModifiers::ALT) =>
+            {
+                let modifiers = key.modifiers | KeyModifiers::ALT;
+                Ok(Event::Input(event::Event::Key(KeyEvent::new(
+                    key.code, modifiers,
+                ))))
+            }
+            Ok(next) => {
+                self.deferred.push_back(next?);
+                event
+            }
+            Err(_) => event,
+        }
+    }
+    // Blocks until an event `wanted` picks arrives, keeping every other
+    // event for later, in order. Timers are left to fire afterwards.
+    pub fn wait_for<F: Fn(&Event) -> bool>(&mut self, wanted: F) -> io::Result<Event> {
+        if let Some(index) = self.deferred.iter().position(&wanted) {
+            if let Some(event) = self.deferred.remove(index) {
+                return Ok(event);
+            }
+        }
+        loop {
+            let event = self
+                .receiver
+                .recv()
+                .unwrap_or_else(|_| Err(disconnected()))?;
+            if wanted(&event) {
+                return Ok(event);
+            }
+            self.deferred.push_back(event);
+        }
+    }
+    #[cfg(test)]
+    pub fn post(&self, event: Event) {
+        let _ = self.sender.send(Ok(event));
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "event sources are gone")
+}
+
+// Posts `FileChanged` whenever the modification time of `path` changes,
+// including when it is created or removed.
+pub fn watch(path: PathBuf, sender: EventSender) {
+    let modified =
+        |path: &PathBuf| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+    thread::spawn(move || {
+        let mut last = modified(&path);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let current = modified(&path);
+            if current != last {
+                last = current;
+                if !sender.send(Event::FileChanged(path.clone())) {
+                    return;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,79 @@
+// The text just yanked or put, lit up for a moment so it is plain what went
+// into a register or came out of one. The event loop's `Timer::Flash`
+// takes it down again.
+
+use crate::register::Kind;
+use crate::Position;
+use rvim_core::Row;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// How long the text stays lit up.
+pub const FLASH_TIME: Duration = Duration::from_millis(150);
+
+pub struct Flash {
+    // The first character lit up, and the one after the last; or for
+    // whole lines and blocks the first line and the last.
+    start: Position,
+    end: Position,
+    kind: Kind,
+    // The first and last screen columns of a block.
+    columns: (usize, usize),
+    pub until: Instant,
+}
+
+impl Flash {
+    pub fn characters(start: Position, end: Position) -> Self {
+        Self::new(start, end, Kind::Characters, (0, 0))
+    }
+    pub fn lines(first: usize, last: usize) -> Self {
+        Self::new(
+            Position { x: 0, y: first },
+            Position { x: 0, y: last },
+            Kind::Lines,
+            (0, 0),
+        )
+    }
+    pub fn block(first: usize, last: usize, columns: (usize, usize)) -> Self {
+        Self::new(
+            Position { x: 0, y: first },
+            Position { x: 0, y: last },
+            Kind::Block,
+            columns,
+        )
+    }
+    fn new(start: Position, end: Position, kind: Kind, columns: (usize, usize)) -> Self {
+        Self {
+            start,
+            end,
+            kind,
+            columns,
+            until: Instant::now() + FLASH_TIME,
+        }
+    }
+    // The characters of `row`, line `y`, that are lit up, running on past
+    // its end for whole lines.
+    pub fn on_line(&self, y: usize, row: &Row) -> Option<Range<usize>> {
+        if y < self.start.y || y > self.end.y {
+            return None;
+        }
+        match self.kind {
+            Kind::Lines => Some(0..usize::MAX),
+            Kind::Block => {
+                let (left, right) = self.columns;
+                let start = row.index_at_column(left).min(row.len());
+                let end = row.index_at_column(right).saturating_add(1).min(row.len());
+                Some(start..end.max(start))
+            }
+            Kind::Characters => {
+                let first = if y == self.start.y { self.start.x } else { 0 };
+                let last = if y == self.end.y {
+                    self.end.x
+                } else {
+                    row.len()
+                };
+                Some(first..last.max(first))
+            }
+        }
+    }
+}
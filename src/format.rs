@@ -0,0 +1,104 @@
+use crate::Options;
+use crate::Row;
+
+// Leaders that make a line a comment, longest first: the leader, what the
+// lines it wraps onto start with instead, and whether a blank must follow.
+const COMMENT_LEADERS: [(&str, &str, bool); 8] = [
+    ("//!", "//!", false),
+    ("///", "///", false),
+    ("//", "//", false),
+    ("/*", " *", false),
+    ("--", "--", false),
+    ("*", "*", true),
+    ("#", "#", true),
+    (">", ">", true),
+];
+
+// A break that brings a line back within `textwidth`: the blanks in
+// `start..end` become a line break, and the new line starts with `prefix`.
+pub struct Break {
+    pub start: usize,
+    pub end: usize,
+    pub prefix: String,
+}
+
+// Where `row` should be broken while typing at `cursor`, if it runs past
+// `textwidth`: at the last blank that keeps the text before it within the
+// width, or after the first word when that alone is too long. Only blanks
+// before the cursor count. `formatoptions` decides which lines wrap: `t`
+// for text and `c` for comments, whose leader and indentation are repeated.
+pub fn line_break(options: &Options, row: &Row, cursor: usize) -> Option<Break> {
+    if options.textwidth == 0 {
+        return None;
+    }
+    let graphemes: Vec<&str> = (0..row.len()).filter_map(|index| row.get(index)).collect();
+    let is_blank = |grapheme: &str| grapheme == " " || grapheme == "\t";
+    // tabs are drawn two columns wide
+    let width = |grapheme: &str| if grapheme == "\t" { 2 } else { 1 };
+    if graphemes
+        .iter()
+        .map(|grapheme| width(grapheme))
+        .sum::<usize>()
+        <= options.textwidth
+    {
+        return None;
+    }
+    let indent = graphemes
+        .iter()
+        .take_while(|grapheme| is_blank(grapheme))
+        .count();
+    let rest = row
+        .as_str()
+        .get(row.byte_index(indent)..)
+        .unwrap_or_default();
+    let comment = COMMENT_LEADERS.iter().find(|(leader, _, blank)| {
+        rest.strip_prefix(leader)
+            .is_some_and(|after| !blank || after.starts_with([' ', '\t']))
+    });
+    let (kept, prefix) = match comment {
+        Some((leader, continued, _)) if options.formatoptions.contains('c') => {
+            let leader_len = leader.chars().count();
+            let blanks = graphemes
+                .iter()
+                .skip(indent.saturating_add(leader_len))
+                .take_while(|grapheme| is_blank(grapheme))
+                .count();
+            let kept = indent.saturating_add(leader_len).saturating_add(blanks);
+            let mut prefix: String = graphemes.iter().take(indent).copied().collect();
+            prefix.push_str(continued);
+            prefix.extend(
+                graphemes
+                    .iter()
+                    .skip(kept.saturating_sub(blanks))
+                    .take(blanks)
+                    .copied(),
+            );
+            (kept, prefix)
+        }
+        None if options.formatoptions.contains('t') => {
+            (indent, graphemes.iter().take(indent).copied().collect())
+        }
+        _ => return None,
+    };
+    // the runs of blanks with a word after them, from the end of the
+    // indentation and leader up to the cursor
+    let mut runs = Vec::new();
+    let (mut column, mut run) = (0_usize, None);
+    for (index, grapheme) in graphemes.iter().enumerate().take(cursor) {
+        if is_blank(grapheme) {
+            run.get_or_insert((index, column));
+        } else if let Some((start, start_column)) = run.take() {
+            if start > kept {
+                runs.push((start, index, start_column));
+            }
+        }
+        column = column.saturating_add(width(grapheme));
+    }
+    let (start, end, _) = runs
+        .iter()
+        .rev()
+        .find(|(_, _, column)| *column <= options.textwidth)
+        .or_else(|| runs.first())
+        .copied()?;
+    Some(Break { start, end, prefix })
+}
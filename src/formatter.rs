@@ -0,0 +1,92 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a running formatter is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// Pipes `text` through each of the `|`-separated `commands` in turn, giving
+// each at most `timeout`. Any formatter that fails, times out or cannot be
+// started stops the chain, and the error says which one it was.
+pub fn run_chain(commands: &str, text: &str, timeout: Duration) -> Result<String, String> {
+    let mut text = text.to_string();
+    for command in commands.split('|').map(str::trim) {
+        if command.is_empty() {
+            continue;
+        }
+        text = run(command, &text, timeout).map_err(|error| format!("{command}: {error}"))?;
+    }
+    Ok(text)
+}
+
+pub fn run(command: &str, input: &str, timeout: Duration) -> Result<String, String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| error.to_string())?;
+    // written and read on threads of their own so a formatter that fills a
+    // pipe before reading all of its input does not stall
+    let stdin = child.stdin.take().map(|mut stdin| {
+        let input = input.to_string();
+        thread::spawn(move || stdin.write_all(input.as_bytes()))
+    });
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+    let status = wait(&mut child, timeout)?;
+    if let Some(stdin) = stdin {
+        let _ = stdin.join();
+    }
+    let output = stdout
+        .and_then(|stdout| stdout.join().ok())
+        .unwrap_or_default();
+    if !status.success() {
+        let errors = stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        let errors = String::from_utf8_lossy(&errors);
+        return Err(errors
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map_or_else(|| status.to_string(), str::to_string));
+    }
+    String::from_utf8(output).map_err(|_| "output is not UTF-8".to_string())
+}
+
+// Waits for `child` to exit, killing it once `timeout` has passed.
+pub fn wait(child: &mut Child, timeout: Duration) -> Result<std::process::ExitStatus, String> {
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        if let Some(status) = child.try_wait().map_err(|error| error.to_string())? {
+            return Ok(status);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {}ms", timeout.as_millis()));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = reader.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+pub fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
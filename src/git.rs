@@ -0,0 +1,109 @@
+use crate::Document;
+use crate::Row;
+use rvim_core::diff::{self, Stats};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// The buffer's changes against the version of its file in HEAD. The HEAD
+// version is read once per `reload`; `update` only re-diffs the buffer.
+#[derive(Default)]
+pub struct GitDiff {
+    head: Option<Vec<String>>,
+    stats: Option<Stats>,
+    revision: u64,
+}
+
+impl GitDiff {
+    pub fn reload(&mut self, document: &Document) {
+        self.head = document.file_name.as_deref().and_then(head_lines);
+        self.update(document);
+    }
+    pub fn update(&mut self, document: &Document) {
+        self.revision = document.revision();
+        self.stats = self.head.as_ref().map(|head| {
+            let old: Vec<&str> = head.iter().map(String::as_str).collect();
+            let new: Vec<&str> = (0..document.len())
+                .filter_map(|index| document.row(index))
+                .map(Row::as_str)
+                .collect();
+            Stats::from(diff::hunks(&old, &new).as_slice())
+        });
+    }
+    pub fn is_stale(&self, document: &Document) -> bool {
+        self.head.is_some() && self.revision != document.revision()
+    }
+    // None when the file is not tracked.
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats
+    }
+}
+
+// The lines of `file_name` as committed in HEAD, or None outside a
+// repository or for a file HEAD does not have.
+fn head_lines(file_name: &str) -> Option<Vec<String>> {
+    let path = Path::new(file_name);
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = path.file_name()?.to_str()?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(format!("HEAD:./{name}"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+// Moves `from` to `to` with `git mv` when git tracks it, so the index
+// follows; false, leaving it alone, when it does not.
+pub fn rename(from: &str, to: &str) -> Result<bool, String> {
+    let from = fs::canonicalize(from).map_err(|error| error.to_string())?;
+    let (Some(dir), Some(name)) = (from.parent(), from.file_name()) else {
+        return Ok(false);
+    };
+    let tracked = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["ls-files", "--error-unmatch", "--"])
+        .arg(name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !tracked {
+        return Ok(false);
+    }
+    let to: PathBuf = env::current_dir()
+        .map_err(|error| error.to_string())?
+        .join(to);
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["mv", "--"])
+        .arg(&from)
+        .arg(&to)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| error.to_string())?;
+    if !output.status.success() {
+        let errors = String::from_utf8_lossy(&output.stderr);
+        return Err(errors.trim().to_string());
+    }
+    Ok(true)
+}
@@ -0,0 +1,2753 @@
+// Key-sequence tests run against a headless editor, with keys written in
+// the same notation as mappings.
+use crate::clipboard::Clipboard;
+use crate::code_action::{CodeAction, TextEdit};
+#[cfg(unix)]
+use crate::dap;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::editor::Editor;
+use crate::error::Error;
+use crate::event::{self, Timer};
+use crate::flash::FLASH_TIME;
+use crate::keymap;
+use crate::markdown;
+use crate::semantic::{SemanticToken, TokenKind};
+use crate::statusline;
+use crate::theme::{Group, Style};
+use crate::Document;
+use crate::Position;
+use crate::Row;
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
+
+const WIDTH: u16 = 60;
+const HEIGHT: u16 = 8;
+
+struct Harness {
+    editor: Editor,
+}
+
+impl Harness {
+    fn new(text: &str) -> Self {
+        Self::with_width(text, WIDTH)
+    }
+    fn with_width(text: &str, width: u16) -> Self {
+        Self::with_size(text, width, HEIGHT)
+    }
+    fn with_size(text: &str, width: u16, height: u16) -> Self {
+        Self {
+            editor: Editor::headless(text, width, height),
+        }
+    }
+    fn keys(&mut self, keys: &str) -> &mut Self {
+        self.editor
+            .run_keys(
+                keymap::parse_keys(keys)
+                    .expect("keys are valid")
+                    .into_iter()
+                    .map(Event::Key)
+                    .collect(),
+            )
+            .unwrap_or_else(|error| panic!("{keys:?}: {error}"));
+        self
+    }
+    // Runs the editor's event loop, as `run` does, over `keys`.
+    fn run(&mut self, keys: &str) -> Result<(), Error> {
+        self.editor.run_headless(
+            keymap::parse_keys(keys)
+                .expect("keys are valid")
+                .into_iter()
+                .map(Event::Key)
+                .collect(),
+        )
+    }
+    fn lines(&self) -> Vec<&str> {
+        let document = self.editor.document();
+        (0..document.len())
+            .filter_map(|index| document.row(index))
+            .map(Row::as_str)
+            .collect()
+    }
+    // The cursor as (line, column), both 0-based.
+    fn cursor(&self) -> (usize, usize) {
+        let Position { x, y } = self.editor.cursor_position();
+        (*y, *x)
+    }
+    fn screen(&self) -> &[String] {
+        self.editor.screen()
+    }
+    // Whether screen line `row` draws `text` in the colors of `group`, or
+    // with `over` the colors of an overlay such as `Visual` on top.
+    fn painted(&self, row: usize, text: &str, group: Group, over: Option<Group>) -> bool {
+        let style = self.editor.style(group);
+        let style = over.map_or(style, |over| {
+            let over = self.editor.style(over);
+            Style {
+                fg: over.fg.or(style.fg),
+                bg: over.bg.or(style.bg),
+            }
+        });
+        let drawn = format!(
+            "{}{}{text}",
+            SetForegroundColor(style.fg.unwrap_or(Color::Reset)),
+            SetBackgroundColor(style.bg.unwrap_or(Color::Reset))
+        );
+        self.editor
+            .terminal()
+            .styled_screen()
+            .get(row)
+            .is_some_and(|line| line.contains(&drawn))
+    }
+}
+
+#[test]
+fn moves_with_hjkl_and_counts() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour\nfive");
+    harness.keys("jjl");
+    assert_eq!(harness.cursor(), (2, 1));
+    harness.keys("k2j");
+    assert_eq!(harness.cursor(), (3, 1));
+    harness.keys("h");
+    assert_eq!(harness.cursor(), (3, 0));
+}
+
+#[test]
+fn pages_through_read_only_text_in_the_pager() {
+    let text: Vec<String> = (1..=20).map(|n| format!("line {n}")).collect();
+    let mut harness = Harness::new(&text.join("\n"));
+    harness.editor.start_pager();
+    harness.keys("x");
+    assert_eq!(harness.lines()[0], "line 1");
+    harness.keys("<Space>");
+    assert_eq!(harness.cursor(), (6, 0));
+    assert!(
+        harness.screen()[0].ends_with("line 7"),
+        "{:?}",
+        harness.screen()
+    );
+    harness.keys("2<Space>");
+    assert_eq!(harness.cursor(), (18, 0));
+    harness.keys("2b");
+    assert_eq!(harness.cursor(), (13, 0));
+    harness.keys("/line 3<CR>");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("<C-f><C-b>");
+    assert_eq!(harness.cursor(), (8, 0));
+    harness.keys("q");
+    assert!(harness.editor.is_quitting());
+}
+
+#[test]
+fn goes_to_first_and_last_lines() {
+    let mut harness = Harness::new("a\nb\nc\nd");
+    harness.keys("G");
+    assert_eq!(harness.cursor(), (3, 0));
+    harness.keys("gg");
+    assert_eq!(harness.cursor(), (0, 0));
+    harness.keys("3gg");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("2G");
+    assert_eq!(harness.cursor(), (1, 0));
+}
+
+#[test]
+fn word_motions_stop_at_word_starts() {
+    let mut harness = Harness::new("let x = foo.bar(1);\n\n  next");
+    harness.keys("w");
+    assert_eq!(harness.cursor(), (0, 4));
+    harness.keys("www");
+    assert_eq!(harness.cursor(), (0, 11));
+    harness.keys("wwww");
+    assert_eq!(harness.cursor(), (0, 17));
+    harness.keys("w");
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys("w");
+    assert_eq!(harness.cursor(), (2, 2));
+    harness.keys("bb");
+    assert_eq!(harness.cursor(), (0, 17));
+}
+
+#[test]
+fn inserts_and_deletes_text() {
+    let mut harness = Harness::new("world");
+    harness.keys("ihello <Esc>");
+    assert_eq!(harness.lines(), ["hello world"]);
+    harness.keys("Ax<BS>!<Esc>");
+    assert_eq!(harness.lines(), ["hello world!"]);
+    harness.keys("^x");
+    assert_eq!(harness.lines(), ["ello world!"]);
+}
+
+#[test]
+fn deletes_and_opens_lines() {
+    let mut harness = Harness::new("a\nb\nc");
+    harness.keys("jdd");
+    assert_eq!(harness.lines(), ["a", "c"]);
+    harness.keys("onew<Esc>");
+    assert_eq!(harness.lines(), ["a", "c", "new"]);
+    assert_eq!(harness.cursor(), (2, 2));
+}
+
+#[test]
+fn undoes_and_redoes_changes() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    harness.keys("jdd");
+    assert_eq!(harness.lines(), ["one", "three"]);
+    harness.keys("ggu");
+    assert_eq!(harness.lines(), ["one", "two", "three"]);
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys("<C-r>");
+    assert_eq!(harness.lines(), ["one", "three"]);
+    harness.keys("uA and more<CR>lines<Esc>");
+    assert_eq!(harness.lines(), ["one", "two and more", "lines", "three"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["one", "two", "three"]);
+    assert_eq!(harness.cursor(), (1, 2));
+    harness.keys("xxx2u");
+    assert_eq!(harness.lines(), ["one", "tw", "three"]);
+    harness.keys("uu");
+    assert_eq!(harness.lines(), ["one", "two", "three"]);
+    assert!(
+        harness
+            .screen()
+            .last()
+            .is_some_and(|line| line.starts_with("Already at oldest change")),
+        "{:?}",
+        harness.screen().last()
+    );
+    harness.keys("3<C-r>");
+    assert_eq!(harness.lines(), ["one", "", "three"]);
+}
+
+#[test]
+fn lights_up_what_was_yanked_or_put_for_a_moment() {
+    let mut harness = Harness::new("one two\nthree");
+    harness.keys("yw");
+    assert!(harness.painted(0, "one ", Group::Normal, Some(Group::Flash)));
+    assert!(harness.painted(0, "two", Group::Normal, None));
+    harness.editor.fire(Timer::Flash).expect("flash goes out");
+    assert!(harness.painted(0, "one two", Group::Normal, None));
+    harness.keys("jP");
+    assert!(harness.painted(1, "one ", Group::Normal, Some(Group::Flash)));
+    assert!(harness.painted(1, "three", Group::Normal, None));
+    harness.editor.fire(Timer::Flash).expect("flash goes out");
+    harness.keys("yyp");
+    assert!(harness.painted(2, "one three", Group::Normal, Some(Group::Flash)));
+    assert!(!harness.painted(1, "one three", Group::Normal, Some(Group::Flash)));
+    harness.editor.fire(Timer::Flash).expect("flash goes out");
+    harness.keys("gg<C-v>jly");
+    harness.editor.fire(Timer::Flash).expect("flash goes out");
+    harness.keys("G$p");
+    assert_eq!(harness.lines()[2], "one threeon");
+    assert!(harness.painted(2, "on", Group::Normal, Some(Group::Flash)));
+    assert!(harness.painted(3, "on", Group::Normal, Some(Group::Flash)));
+}
+
+#[test]
+fn puts_older_yanks_and_deletes_from_the_history() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    harness.keys("yiwjyyjdiw");
+    assert_eq!(harness.lines(), ["one", "two", ""]);
+    harness.keys("p");
+    assert_eq!(harness.lines(), ["one", "two", "three"]);
+    harness.keys("<C-p>");
+    assert_eq!(harness.lines(), ["one", "two", "", "two"]);
+    harness.keys("<C-p>");
+    assert_eq!(harness.lines(), ["one", "two", "one"]);
+    harness.keys("<C-p>");
+    assert_eq!(harness.lines(), ["one", "two", "three"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["one", "two", ""]);
+    harness.keys(":YankHistory<CR><Down><Down><CR>");
+    assert_eq!(harness.lines(), ["one", "two", "one"]);
+    harness.keys("gg<leader>p<CR>");
+    assert_eq!(harness.lines(), ["onethree", "two", "one"]);
+}
+
+#[test]
+fn undoes_typing_in_smaller_pieces() {
+    let mut harness = Harness::new("");
+    harness.keys("ione<C-g>u two<Esc>u");
+    assert_eq!(harness.lines(), ["one"]);
+    harness.keys(":set undogroup=word<CR>A two  three<CR>four<Esc>u");
+    assert_eq!(harness.lines(), ["one two  three"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["one two"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["one"]);
+    harness.keys(":set ug=2<CR>:set ug?<CR>");
+    assert_eq!(harness.screen().last().map(String::as_str), Some("ug=2"));
+    harness.keys(":set ug=0<CR>");
+    assert!(
+        harness
+            .screen()
+            .last()
+            .is_some_and(|line| line.starts_with("Invalid argument: undogroup=0")),
+        "{:?}",
+        harness.screen().last()
+    );
+}
+
+#[test]
+fn duplicates_lines_and_selections() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    // `<leader>d` starts the debugger's bindings too, so it waits.
+    harness.keys("ll<leader>d");
+    harness.editor.fire(Timer::KeyTimeout).expect("key timeout");
+    assert_eq!(harness.lines(), ["one", "one", "two", "three"]);
+    assert_eq!(harness.cursor(), (1, 2));
+    harness.keys("jvj<leader>d");
+    assert_eq!(
+        harness.lines(),
+        ["one", "one", "two", "three", "two", "three"]
+    );
+    assert_eq!(harness.cursor(), (5, 2));
+    harness.keys(":1,2t$<CR>");
+    assert_eq!(harness.lines()[6..], ["one", "one"]);
+    assert_eq!(harness.cursor(), (7, 2));
+    harness.keys(":copy 0<CR>");
+    assert_eq!(harness.lines().len(), 9);
+    assert_eq!(harness.lines()[0], "one");
+    assert_eq!(harness.cursor(), (0, 2));
+    harness.keys(":t x<CR>");
+    assert!(
+        harness
+            .screen()
+            .last()
+            .is_some_and(|line| line.starts_with("Invalid address: x")),
+        "{:?}",
+        harness.screen().last()
+    );
+}
+
+#[test]
+fn walks_every_branch_of_the_undo_tree_in_order() {
+    let mut harness = Harness::new("one");
+    harness.keys("xuA!<Esc>");
+    assert_eq!(harness.lines(), ["one!"]);
+    // the change undone is still there on its own branch
+    harness.keys("g-");
+    assert_eq!(harness.lines(), ["ne"]);
+    harness.keys("g-");
+    assert_eq!(harness.lines(), ["one"]);
+    harness.keys("g-");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Already at oldest change")
+    );
+    harness.keys("2g+");
+    assert_eq!(harness.lines(), ["one!"]);
+    harness.keys("g-<C-r>");
+    assert_eq!(harness.lines(), ["ne"]);
+    harness.keys("uA?<Esc>");
+    // the listing waits for a key, so the keys run out while it is shown
+    let keys = keymap::parse_keys(":undolist<CR>").expect("keys are valid");
+    let listed = harness
+        .editor
+        .run_keys(keys.into_iter().map(Event::Key).collect());
+    assert!(listed.is_err());
+    let screen = harness.screen().join("\n");
+    for line in [
+        "number changes  when",
+        "     1       1  0 seconds ago",
+        "     2       1  0 seconds ago",
+        "     3       1  0 seconds ago",
+    ] {
+        assert!(screen.contains(line), "{screen}");
+    }
+}
+
+#[test]
+fn moves_lines_and_selections() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour");
+    harness.keys("l<A-j>");
+    assert_eq!(harness.lines(), ["two", "one", "three", "four"]);
+    assert_eq!(harness.cursor(), (1, 1));
+    harness.keys("vj<A-j>");
+    assert_eq!(harness.lines(), ["two", "four", "one", "three"]);
+    harness.keys("<A-j><A-k><A-k><A-k>");
+    assert_eq!(harness.lines(), ["one", "three", "two", "four"]);
+    // the selection comes along
+    harness.keys("<leader>d");
+    assert_eq!(
+        harness.lines(),
+        ["one", "three", "one", "three", "two", "four"]
+    );
+    // one step for each keypress
+    harness.keys("uu");
+    assert_eq!(harness.lines(), ["two", "one", "three", "four"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["two", "four", "one", "three"]);
+    harness.keys(":1m$<CR>");
+    assert_eq!(harness.lines(), ["four", "one", "three", "two"]);
+    assert_eq!(harness.cursor().0, 3);
+    harness.keys(":2,3move 0<CR>");
+    assert_eq!(harness.lines(), ["one", "three", "four", "two"]);
+    assert_eq!(harness.cursor().0, 1);
+    harness.keys(":1,3m2<CR>");
+    assert!(
+        harness
+            .screen()
+            .last()
+            .is_some_and(|line| line.starts_with("Cannot move a range of lines into itself")),
+        "{:?}",
+        harness.screen().last()
+    );
+}
+
+#[test]
+fn reindents_moved_lines_with_moveindent() {
+    let mut harness = Harness::new("fn main() {\n    a();\n}\nb();");
+    harness.keys(":set moveindent<CR>G<A-k>");
+    assert_eq!(
+        harness.lines(),
+        ["fn main() {", "    a();", "    b();", "}"]
+    );
+    harness.keys("gg<A-j>");
+    assert_eq!(
+        harness.lines(),
+        ["    a();", "    fn main() {", "    b();", "}"]
+    );
+}
+
+#[test]
+fn opens_indented_lines_above_and_below() {
+    let mut harness = Harness::new("fn main() {\n    let a = 1;\n}");
+    harness.keys("jOb<Esc>");
+    assert_eq!(
+        harness.lines(),
+        ["fn main() {", "    b", "    let a = 1;", "}"]
+    );
+    assert_eq!(harness.cursor(), (1, 4));
+    harness.keys("jo2<Esc>");
+    assert_eq!(harness.lines()[3], "    2");
+    harness.keys("ggOtop<Esc>");
+    assert_eq!(harness.lines()[0], "top");
+    harness.keys("G2olast<Esc>");
+    assert_eq!(harness.lines()[6..], ["last", "last"]);
+    assert_eq!(harness.cursor(), (7, 3));
+
+    let mut harness = Harness::new("");
+    harness.keys("Ofirst<Esc>");
+    assert_eq!(harness.lines(), ["first"]);
+}
+
+#[test]
+fn runs_ex_commands_from_the_prompt() {
+    let mut harness = Harness::new("1\n2\n3\n4");
+    harness.keys(":3<CR>");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys(":set so=2<CR>:set so?<CR>");
+    assert_eq!(harness.screen().last().map(String::as_str), Some("so=2"));
+}
+
+#[test]
+fn keeps_a_quick_escape_apart_from_the_next_key() {
+    let mut harness = Harness::new("a\nb\nc");
+    harness.keys("ix<Esc>j");
+    assert_eq!(harness.lines(), vec!["xa", "b", "c"]);
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys(":set ttm=50<CR>gg<Esc>j");
+    assert_eq!(harness.lines(), vec!["b", "xa", "c"]);
+}
+
+#[test]
+fn sets_how_long_keys_wait_for_the_rest_of_a_binding() {
+    let mut harness = Harness::new("");
+    harness.keys(":set notimeout ttimeoutlen=10<CR>:set ttm?<CR>");
+    assert_eq!(harness.screen().last().map(String::as_str), Some("ttm=10"));
+    harness.keys(":set to?<CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("to=false")
+    );
+}
+
+#[test]
+fn renders_rows_and_status_bar() {
+    let mut harness = Harness::new("fn main() {}\n\tx");
+    harness.keys("j");
+    let screen = harness.screen();
+    assert_eq!(screen.len(), usize::from(HEIGHT) + 2);
+    assert_eq!(screen[0], " 0  |fn main() {}");
+    assert_eq!(screen[1], " 1  |  x");
+    assert_eq!(screen[2], "~");
+    let status = &screen[usize::from(HEIGHT)];
+    assert!(status.starts_with("[No Name] - 2 lines"), "{status}");
+    assert!(
+        status.ends_with("normal mode: No filetype | 2:1"),
+        "{status}"
+    );
+}
+
+#[test]
+fn maps_key_sequences_to_commands_and_actions() {
+    let mut harness = Harness::new("1\n2\n3\n4");
+    harness.keys(":nmap -desc=\"Third line\" ,t :3<CR><CR>");
+    harness.keys(",t");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys(":nmap ,, <first-line><CR>,,");
+    assert_eq!(harness.cursor(), (0, 0));
+    harness.keys(":nmap ,<CR><CR>:messages<CR>");
+    assert_eq!(harness.lines().last().copied(), Some(",t :3  Third line"));
+}
+
+#[test]
+fn replays_keys_that_do_not_complete_a_mapping() {
+    let mut harness = Harness::new("");
+    harness.keys(":imap jk <normal-mode><CR>");
+    harness.keys("ijam<Esc>");
+    assert_eq!(harness.lines(), ["jam"]);
+    harness.keys("^ijkx");
+    assert_eq!(harness.lines(), ["am"]);
+}
+
+#[test]
+fn unmaps_default_bindings() {
+    let mut harness = Harness::new("a\nb");
+    harness.keys(":nunmap d<CR>dd");
+    assert_eq!(harness.lines(), ["a", "b"]);
+    harness.keys("D");
+    assert_eq!(harness.lines(), ["b"]);
+}
+
+#[test]
+fn changes_highlight_groups() {
+    let mut harness = Harness::new("");
+    harness.keys(":hi Comment fg=#ff0000 bg=darkblue<CR>:hi comment<CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Comment fg=#ff0000 bg=darkblue")
+    );
+    harness.keys(":hi Comment fg=chartreuse<CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Invalid color: chartreuse")
+    );
+}
+
+#[test]
+fn sources_files_and_reports_the_failing_line() {
+    let path = std::env::temp_dir().join(format!("rvim-source-{}", std::process::id()));
+    std::fs::write(&path, "\" comment\nset so=1\nset bogus=1\nset so=4\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":source {}<CR>", path.display()));
+    let message = harness.screen().last().cloned().unwrap_or_default();
+    harness.keys(":set so?<CR>");
+    let _ = std::fs::remove_file(&path);
+    assert!(message.ends_with(":3: Unknown option: bogus"), "{message}");
+    assert_eq!(harness.screen().last().map(String::as_str), Some("so=4"));
+}
+
+#[test]
+fn counts_search_matches_while_navigating() {
+    let mut harness = Harness::new("foo bar foo\nfoo");
+    harness.keys("/foo<CR>");
+    assert_eq!(harness.cursor(), (0, 0));
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("match 1 of 3")
+    );
+    harness.keys("nn");
+    assert_eq!(harness.cursor(), (1, 0));
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("match 3 of 3")
+    );
+    harness.keys("n");
+    assert_eq!(harness.cursor(), (0, 0));
+    harness.keys("N");
+    assert_eq!(harness.cursor(), (1, 0));
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("match 3 of 3")
+    );
+}
+
+#[test]
+fn searches_from_the_cursor_and_wraps_around() {
+    let mut harness = Harness::new("abc\nfoo\nxfoo bar");
+    harness.keys("jjw/foo<CR>");
+    assert_eq!(harness.cursor(), (1, 0));
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("match 1 of 2")
+    );
+    harness.keys("/xf<C-t><Esc>");
+    assert_eq!(harness.cursor(), (1, 0));
+}
+
+#[test]
+fn searches_the_text_of_the_buffer_shown() {
+    let path = std::env::temp_dir().join(format!("rvim-search-{}", std::process::id()));
+    std::fs::write(&path, "one\ntwo beta\n").expect("temp file");
+    let mut harness = Harness::new("alpha\nbeta");
+    harness.keys("/beta<CR>");
+    let first = harness.cursor();
+    // the new buffer is at the same revision as the last, but not the same
+    harness.keys(&format!(":e {}<CR>/beta<CR>", path.display()));
+    let second = harness.cursor();
+
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(first, (1, 0));
+    assert_eq!(second, (1, 4));
+}
+
+#[test]
+fn toggles_regex_case_and_whole_word_search() {
+    let mut harness = Harness::new("Foo foo food\nfoo");
+    let message = |harness: &Harness| harness.screen().last().cloned().unwrap_or_default();
+    harness.keys("/foo<CR>");
+    assert_eq!(message(&harness), "match 1 of 3");
+    harness.keys("gg/foo<A-w><CR>");
+    assert_eq!(message(&harness), "match 1 of 2");
+    // the flags stay for the next search
+    harness.keys("gg/foo<A-c><CR>");
+    assert_eq!(message(&harness), "match 2 of 3");
+    harness.keys("gg/<A-w><A-c><A-r>fo+d<CR>");
+    assert_eq!(harness.cursor(), (0, 8));
+    assert_eq!(message(&harness), "match 1 of 1");
+    harness.keys("/(<CR><CR>");
+    assert_eq!(harness.cursor(), (0, 8));
+    harness.keys(":messages<CR>");
+    assert!(harness
+        .lines()
+        .contains(&"Invalid pattern: regex parse error:"));
+}
+
+#[test]
+fn edits_anywhere_in_a_prompt() {
+    let mut harness = Harness::new("alpha beta\nbeta\nthree\nfour\nfive");
+    harness.keys(":4<Left>2<Right><BS><CR>");
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys(":51<Home><Del><Del>3<CR>");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys(":abc<C-u>4 foo<C-w><BS><End><CR>");
+    assert_eq!(harness.cursor(), (3, 0));
+    harness.keys("ggw/<C-r><C-w><CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("match 1 of 2")
+    );
+    harness.keys(":set so=1<C-r>/<CR>");
+    assert!(harness.screen()[usize::from(HEIGHT) + 1].contains("so=1beta"));
+}
+
+#[test]
+fn fits_the_status_line_to_the_screen_by_columns() {
+    let dir = std::env::temp_dir().join(format!("rvim-状態-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let mut harness = Harness::with_width("", 100);
+    harness.keys(&format!(
+        ":e {}<CR>",
+        dir.join("一二三四五六七八九十").display()
+    ));
+    let wide = harness.screen()[usize::from(HEIGHT)].clone();
+    let mut harness = Harness::with_width("", 30);
+    harness.keys(&format!(":e {}<CR>", dir.join("a").display()));
+    let narrow = harness.screen()[usize::from(HEIGHT)].clone();
+    let _ = std::fs::remove_dir_all(&dir);
+    // the file name is cut to 20 columns, not split inside a character
+    let name = wide.split(" - ").next().unwrap_or_default();
+    assert_eq!(statusline::width(name), 20, "{wide}");
+    assert!(name.ends_with('…'), "{wide}");
+    assert!(
+        wide.ends_with("normal mode: No filetype | 0B utf-8 unix | 1:1"),
+        "{wide}"
+    );
+    assert_eq!(statusline::width(&wide), 95);
+    // with no room for both sides, the left one goes first
+    assert_eq!(narrow, "normal mode: No filetype…");
+}
+
+#[test]
+fn shows_the_selection_size_in_visual_mode() {
+    let mut harness = Harness::new("hello world\nsecond\nthird");
+    let status = |harness: &Harness| harness.screen()[usize::from(HEIGHT)].clone();
+    harness.keys("lvlll");
+    assert!(status(&harness).contains("visual mode (4 chars): "));
+    harness.keys("jj");
+    assert!(status(&harness).contains("visual mode (3 lines): "));
+    harness.keys("<Esc>");
+    assert!(status(&harness).contains("normal mode: "));
+}
+
+#[test]
+fn shows_file_metadata_and_keeps_line_endings() {
+    let path = std::env::temp_dir().join(format!("rvim-dos-{}", std::process::id()));
+    std::fs::write(&path, "a\r\nb\r\n").expect("temp file");
+    let mut harness = Harness::with_width("", 100);
+    harness.keys(&format!(":e {}<CR>", path.display()));
+    let status = harness.screen()[usize::from(HEIGHT)].clone();
+    harness.keys("x:w<CR>");
+    let written = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    assert!(
+        status.ends_with("No filetype | 6B utf-8 dos | 1:1"),
+        "{status}"
+    );
+    assert_eq!(written, "\r\nb\r\n");
+}
+
+#[test]
+fn checks_health_in_a_read_only_buffer() {
+    let mut harness = Harness::new("text");
+    harness.keys(":checkhealth<CR>");
+    assert_eq!(harness.lines().first(), Some(&"rvim health report"));
+    assert!(harness.lines().contains(&"Terminal"));
+    let lines = harness.lines().len();
+    harness.keys("ddx");
+    assert_eq!(harness.lines().len(), lines);
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Cannot modify a read-only buffer.")
+    );
+}
+
+#[test]
+fn writes_every_modified_buffer() {
+    let path = std::env::temp_dir().join(format!("rvim-wall-{}", std::process::id()));
+    std::fs::write(&path, "abc\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>x:wa<CR>", path.display()));
+    let written = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(!harness.editor.is_quitting());
+    harness.keys(":xa<CR>");
+    assert!(harness.editor.is_quitting());
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>x:wqa<CR>", path.display()));
+    let quit_written = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(written, "bc\n");
+    assert_eq!(quit_written, "c\n");
+    assert!(harness.editor.is_quitting());
+}
+
+#[test]
+fn keeps_modified_buffers_hidden_until_written() {
+    let dir = std::env::temp_dir().join(format!("rvim-hidden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let (a, b) = (dir.join("a"), dir.join("b"));
+    std::fs::write(&a, "abc\n").expect("temp file");
+    std::fs::write(&b, "xyz\n").expect("temp file");
+    let (a, b) = (a.display().to_string(), b.display().to_string());
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {a}<CR>x:e {b}<CR>"));
+    assert_eq!(harness.lines(), ["bc"]);
+    harness.keys(&format!(":set hidden<CR>:e {b}<CR>:ls<CR><CR>"));
+    assert_eq!(harness.lines(), ["xyz"]);
+    harness.keys(":q<CR>");
+    assert!(!harness.editor.is_quitting());
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("WARNING! 1 buffer with unsaved changes; see :ls.")
+    );
+    harness.keys(":messages<CR>");
+    let listed = harness.lines().join("\n");
+    harness.keys(&format!(":e {a}<CR>"));
+    assert_eq!(harness.lines(), ["bc"]);
+    harness.keys(&format!(":e {b}<CR>:wa<CR>"));
+    let written = std::fs::read_to_string(&a).unwrap_or_default();
+    harness.keys(":q<CR>");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(listed.contains(&format!("%a   \"{b}\" line 1")), "{listed}");
+    assert!(listed.contains(&format!(" h + \"{a}\" line 1")), "{listed}");
+    assert_eq!(written, "bc\n");
+    assert!(harness.editor.is_quitting());
+}
+
+#[cfg(unix)]
+#[test]
+fn writes_every_hidden_buffer_past_one_written_without_formatting() {
+    let dir = std::env::temp_dir().join(format!("rvim-hidden-format-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let (a, b, c) = (dir.join("a.rs"), dir.join("b.txt"), dir.join("c"));
+    for file in [&a, &b, &c] {
+        std::fs::write(file, "abc\n").expect("temp file");
+    }
+    let (a, b, c) = (
+        a.display().to_string(),
+        b.display().to_string(),
+        c.display().to_string(),
+    );
+    let mut harness = Harness::new("");
+    harness.keys(":set hidden<CR>:ftset Rust formatters=false<CR>");
+    // the message runs past the screen's width, so it waits for Enter
+    harness.keys(&format!(":e {a}<CR>x:e {b}<CR>x:e {c}<CR>:wa<CR><CR>"));
+    harness.keys(":messages<CR>");
+    let status = harness.lines().join("\n");
+    let written = [&a, &b].map(|file| std::fs::read_to_string(file).unwrap_or_default());
+    harness.keys(":q<CR>");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(written, ["bc\n", "bc\n"]);
+    assert!(
+        status.contains(&format!("{a}: File saved without formatting: false")),
+        "{status}"
+    );
+    assert!(!status.contains("Could not write"), "{status}");
+    assert!(harness.editor.is_quitting());
+}
+
+#[test]
+fn renames_deletes_and_makes_directories() {
+    let dir = std::env::temp_dir().join(format!("rvim-files-ops-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.txt"), "abc\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(
+        ":e {}<CR>:Rename b.rs<CR>",
+        dir.join("a.txt").display()
+    ));
+    let renamed = (dir.join("a.txt").exists(), dir.join("b.rs").exists());
+    let status = harness.screen()[usize::from(HEIGHT)].clone();
+    harness.keys(&format!(":Mkdir {}<CR>", dir.join("sub/deep").display()));
+    let made = dir.join("sub/deep").is_dir();
+    harness.keys(":Delete<CR>n");
+    let kept = dir.join("b.rs").exists();
+    harness.keys(":Delete<CR>y");
+    let deleted = !dir.join("b.rs").exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(renamed, (false, true));
+    assert!(status.contains("Rust"), "{status}");
+    assert!(made);
+    assert!(kept);
+    assert!(deleted);
+    assert!(harness.lines().is_empty());
+}
+
+#[test]
+fn writes_to_the_same_file_after_changing_directory() {
+    let dir = std::env::temp_dir().join(format!("rvim-cd-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("a")).expect("temp dir");
+    std::fs::create_dir_all(dir.join("b")).expect("temp dir");
+    std::fs::write(dir.join("a/x.txt"), "original\n").expect("temp file");
+    let dir = dir.canonicalize().expect("temp dir");
+    let cwd = std::env::current_dir().expect("current dir");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":cd {}<CR>:e x.txt<CR>", dir.join("a").display()));
+    harness.keys(&format!(
+        ":cd {}<CR>ccchanged<Esc>:w<CR>",
+        dir.join("b").display()
+    ));
+    let name = harness.editor.document().file_name.clone();
+    harness.keys(&format!(":cd {}<CR>", cwd.display()));
+    let written = std::fs::read_to_string(dir.join("a/x.txt"));
+    let stray = dir.join("b/x.txt").exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    // not under the new directory, the file is named from the root
+    assert_eq!(name, Some(dir.join("a/x.txt").display().to_string()));
+    assert_eq!(written.ok().as_deref(), Some("changed\n"));
+    assert!(!stray);
+}
+
+#[test]
+fn changes_the_window_directory() {
+    let dir = std::env::temp_dir().join(format!("rvim-lcd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("b.txt"), "bee\n").expect("temp file");
+    let dir = dir.canonicalize().expect("temp dir");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(
+        ":e {}<CR>:lcd %:h<CR>:pwd<CR>",
+        dir.join("a.txt").display()
+    ));
+    let pwd = harness.screen().last().cloned();
+    harness.keys(":e b.txt<CR>");
+    let opened = harness.lines().join("\n");
+    harness.keys(":lcd nowhere<CR>");
+    let missing = harness.screen().last().cloned();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(pwd, Some(dir.display().to_string()));
+    assert_eq!(opened, "bee");
+    assert!(missing.is_some_and(|message| message.starts_with("No such directory")));
+}
+
+#[test]
+fn jumps_to_bookmarks_picked_by_their_note() {
+    let dir = std::env::temp_dir().join(format!("rvim-bookmarks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").expect("temp file");
+    std::fs::write(dir.join("b.txt"), "bbb\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>", dir.join("a.txt").display()));
+    harness.keys("jj:BookmarkAdd the end<CR>k:BookmarkAdd<CR>");
+    let screen = harness.screen().to_vec();
+    harness.keys(&format!(":e {}<CR>", dir.join("b.txt").display()));
+    harness.keys(":Bookmarks<CR>end<CR>");
+    let (lines, cursor) = (harness.lines().join("\n"), harness.cursor());
+    harness.keys(":BookmarkDelete<CR>:BookmarkDelete<CR>");
+    let message = harness.screen().last().cloned();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(screen[0].chars().nth(4), Some('|'));
+    assert_eq!(screen[1].chars().nth(4), Some('*'));
+    assert_eq!(screen[2].chars().nth(4), Some('*'));
+    assert_eq!(lines, "one\ntwo\nthree");
+    assert_eq!(cursor, (2, 0));
+    assert!(
+        message
+            .as_deref()
+            .is_some_and(|line| line.starts_with("No bookmark on this line")),
+        "{message:?}"
+    );
+}
+
+#[test]
+fn detects_scripts_by_shebang_and_sets_the_file_type() {
+    let dir = std::env::temp_dir().join(format!("rvim-filetype-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("script"), "#!/usr/bin/env python3\nprint(1)\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>", dir.join("script").display()));
+    let detected = harness.screen()[HEIGHT as usize].clone();
+    harness.keys(":set ft=rs<CR>");
+    let set = harness.screen()[HEIGHT as usize].clone();
+    harness.keys(":set filetype?<CR>");
+    let shown = harness.screen().last().cloned();
+    harness.keys(":set ft=cobol<CR>");
+    let unknown = harness.screen().last().cloned();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(detected.contains("Python |"), "{detected}");
+    assert!(set.contains("Rust |"), "{set}");
+    assert!(
+        shown
+            .as_deref()
+            .is_some_and(|line| line.starts_with("filetype=Rust")),
+        "{shown:?}"
+    );
+    assert!(
+        unknown
+            .as_deref()
+            .is_some_and(|line| line.starts_with("Unknown file type: cobol")),
+        "{unknown:?}"
+    );
+}
+
+#[test]
+fn detects_file_types_by_name_doctype_and_modeline() {
+    let dir = std::env::temp_dir().join(format!("rvim-detect-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let files = [
+        ("Makefile", "all:\n"),
+        ("page", "<!DOCTYPE html>\n<p>hi</p>\n"),
+        ("notes.txt", "func main() {}\n// vim: set ft=go:\n"),
+    ];
+    let mut harness = Harness::new("");
+    let mut detected = Vec::new();
+    for (name, text) in files {
+        std::fs::write(dir.join(name), text).expect("temp file");
+        harness.keys(&format!(":e {}<CR>:set ft?<CR>", dir.join(name).display()));
+        detected.push(harness.screen().last().cloned().unwrap_or_default());
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let types: Vec<&str> = detected.iter().map(|line| line.trim_end()).collect();
+    assert_eq!(types, ["ft=Make", "ft=HTML", "ft=Golang"]);
+}
+
+#[test]
+fn indents_makefiles_with_tabs() {
+    let dir = std::env::temp_dir().join(format!("rvim-indent-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let mut harness = Harness::new("");
+    let mut typed = Vec::new();
+    for name in ["Makefile", "main.py", ".env.local"] {
+        std::fs::write(dir.join(name), "ab\n").expect("temp file");
+        harness.keys(&format!(
+            ":e {}<CR>a<Tab><Esc>:set ft?<CR>",
+            dir.join(name).display()
+        ));
+        typed.push((
+            harness.lines().join("\n"),
+            harness.screen().last().cloned().unwrap_or_default(),
+        ));
+        harness.keys(":w<CR>");
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let typed: Vec<(&str, &str)> = typed
+        .iter()
+        .map(|(line, file_type)| (line.as_str(), file_type.trim_end()))
+        .collect();
+    assert_eq!(
+        typed,
+        [
+            ("a\tb", "ft=Make"),
+            ("a   b", "ft=Python"),
+            ("a   b", "ft=Env")
+        ]
+    );
+}
+
+#[test]
+fn remembers_recent_files() {
+    let dir = std::env::temp_dir().join(format!("rvim-oldfiles-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.txt"), "aaa\n").expect("temp file");
+    std::fs::write(dir.join("b.txt"), "bbb\n").expect("temp file");
+    let dir = dir.canonicalize().expect("temp dir");
+    let mut harness = Harness::with_size("", WIDTH, 24);
+    harness.keys(&format!(
+        ":e {}<CR>:e {}<CR>:e {}<CR>",
+        dir.join("a.txt").display(),
+        dir.join("b.txt").display(),
+        dir.join("a.txt").display()
+    ));
+    harness.keys(":Recent<CR><C-n><CR>");
+    let picked = harness.lines().join("\n");
+    harness.keys(":e #<2<CR>");
+    let expanded = harness.lines().join("\n");
+    harness.keys(":Delete<CR>y");
+    let dashboard = harness.screen().join("\n");
+    harness.keys("2");
+    let opened = harness.lines().join("\n");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(picked, "bbb");
+    assert_eq!(expanded, "aaa");
+    assert!(dashboard.contains("Recent files"), "{dashboard}");
+    assert!(
+        dashboard.contains("  1        ") && dashboard.contains("a.txt"),
+        "{dashboard}"
+    );
+    assert_eq!(opened, "bbb");
+}
+
+#[test]
+fn runs_the_line_picked_on_the_start_screen() {
+    let mut harness = Harness::with_size("", WIDTH, 16);
+    harness.keys("jjjjjk");
+    let screen = harness.screen().join("\n");
+    assert!(screen.contains("rvim -- version"), "{screen}");
+    assert!(
+        screen.contains("> :nmap    List the key bindings"),
+        "{screen}"
+    );
+    assert!(screen.contains("See man rvim for help"), "{screen}");
+    harness.keys("kkkkk<CR>typed<Esc>");
+    assert_eq!(harness.lines(), ["typed"]);
+    assert!(!harness.screen().join("\n").contains("Start typing"));
+}
+
+#[test]
+fn recalls_prompt_history_and_file_positions() {
+    let mut harness = Harness::new("one\ntwo\nthree\nten");
+    harness.keys("/two<CR>/three<CR>/ten<CR>gg/t<Up><Up><CR>");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("gg/<Up><Up><Up><Down><CR>");
+    assert_eq!(harness.cursor(), (3, 0));
+    let long = "x".repeat(80);
+    let mut harness = Harness::new(&long);
+    harness.keys(":set wrap<CR>:set nowrap<CR>:set w<Up><CR>");
+    assert!(
+        harness.screen()[1].contains("xxx"),
+        "{:?}",
+        harness.screen()
+    );
+
+    let dir = std::env::temp_dir().join(format!("rvim-positions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.txt"), "a\nb\nc\n").expect("temp file");
+    std::fs::write(dir.join("b.txt"), "d\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(
+        ":e {}<CR>jj:e {}<CR>:e {}<CR>",
+        dir.join("a.txt").display(),
+        dir.join("b.txt").display(),
+        dir.join("a.txt").display()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(harness.cursor(), (2, 0));
+}
+
+#[test]
+fn quits_with_unsaved_changes_only_as_confirm_allows() {
+    let mut harness = Harness::new("abc");
+    harness.keys("x:q<CR>");
+    assert!(!harness.editor.is_quitting());
+    harness.keys(":set confirm=3<CR>:q<CR>:qa<CR>");
+    assert!(!harness.editor.is_quitting());
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("WARNING! Unsaved changes. Quit 1 more time.")
+    );
+    harness.keys(":q<CR>");
+    assert!(harness.editor.is_quitting());
+
+    let mut harness = Harness::new("abc");
+    harness.keys("x:confirm qa<CR>c");
+    assert!(!harness.editor.is_quitting());
+    harness.keys(":confirm q<CR>n");
+    assert!(harness.editor.is_quitting());
+}
+
+#[test]
+fn writes_in_the_background_and_quits_once_written() {
+    let path = std::env::temp_dir().join(format!("rvim-save-{}", std::process::id()));
+    std::fs::write(&path, "abc\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>x:w<CR>", path.display()));
+    let written = std::fs::read_to_string(&path).unwrap_or_default();
+    let status = harness.screen()[usize::from(HEIGHT)].clone();
+    harness.keys("x:wq<CR>");
+    let quit_written = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(written, "bc\n");
+    assert!(!status.contains("(modified)"), "{status}");
+    assert_eq!(quit_written, "c\n");
+}
+
+#[test]
+fn trims_only_changed_lines_and_keeps_missing_final_newlines() {
+    let path = std::env::temp_dir().join(format!("rvim-trim-{}", std::process::id()));
+    let markdown = path.with_extension("md");
+    std::fs::write(&path, "keep  \nedit\nlast").expect("temp file");
+    std::fs::write(&markdown, "line  \nbreak").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(":set trim_trailing_whitespace noensure_final_newline<CR>");
+    harness.keys(":ftset Markdown notrim_trailing_whitespace<CR>");
+    harness.keys(&format!(":e {}<CR>jA x  <Esc>:w<CR>", path.display()));
+    let kept = std::fs::read_to_string(&path).unwrap_or_default();
+    harness.keys(":set ensure_final_newline<CR>:w<CR>");
+    let ended = std::fs::read_to_string(&path).unwrap_or_default();
+    harness.keys(&format!(":e {}<CR>jA  <Esc>:w<CR>", markdown.display()));
+    let untrimmed = std::fs::read_to_string(&markdown).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&markdown);
+    assert_eq!(kept, "keep  \nedit x\nlast");
+    assert_eq!(ended, "keep  \nedit x\nlast\n");
+    assert_eq!(untrimmed, "line  \nbreak  \n");
+}
+
+#[cfg(unix)]
+#[test]
+fn formats_on_write_and_writes_unformatted_when_a_formatter_fails() {
+    let path = std::env::temp_dir().join(format!("rvim-formatters-{}", std::process::id()));
+    std::fs::write(&path, "b\na\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>", path.display()));
+    harness.keys(r":set formatters=sort\ -r|tr\ a-z\ A-Z<CR>:w<CR>");
+    let formatted = std::fs::read_to_string(&path).unwrap_or_default();
+    let lines = harness.lines().join("\n");
+    harness.keys(":set formatters=false<CR>ic<Esc>:w<CR>");
+    let failed = std::fs::read_to_string(&path).unwrap_or_default();
+    let status = harness.screen()[usize::from(HEIGHT) + 1].clone();
+    harness.keys(r":set formatters=sleep\ 5 formattimeout=50<CR>id<Esc>:wq<CR>");
+    let timed_out = std::fs::read_to_string(&path).unwrap_or_default();
+    let timeout_status = harness.screen()[usize::from(HEIGHT) + 1].clone();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(formatted, "B\nA\n");
+    assert_eq!(lines, "B\nA");
+    assert_eq!(failed, "cB\nA\n");
+    assert!(status.contains("without formatting: false"), "{status}");
+    assert_eq!(timed_out, "dcB\nA\n");
+    assert!(
+        timeout_status.contains("timed out afte…"),
+        "{timeout_status}"
+    );
+}
+
+#[test]
+fn finds_files_the_gitignore_leaves_in() {
+    let dir = std::env::temp_dir().join(format!("rvim-files-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("target")).expect("temp dir");
+    std::fs::create_dir_all(dir.join("src")).expect("temp dir");
+    for (name, contents) in [
+        (".gitignore", "target/\n*.log\n!keep.log\n"),
+        ("src/.gitignore", "/generated.rs\n"),
+        ("src/main.rs", "main\n"),
+        ("src/generated.rs", "generated\n"),
+        ("target/out.rs", "out\n"),
+        ("debug.log", "debug\n"),
+        ("keep.log", "keep\n"),
+        (".hidden", "hidden\n"),
+    ] {
+        std::fs::write(dir.join(name), contents).expect("temp file");
+    }
+    std::fs::write(dir.join("big"), "big".repeat(1000)).expect("temp file");
+    // the first line of the file each query picks, if any
+    let files = |set: &str| -> Vec<String> {
+        ["keep", "main", "generated", "out", "debug", "hidden", "big"]
+            .iter()
+            .map(|query| {
+                let mut harness = Harness::new("");
+                harness.keys(&format!("{set}:Files {}<CR>{query}<CR>", dir.display()));
+                harness.lines().concat()
+            })
+            .collect()
+    };
+    let listed = files(":set walkmaxsize=1<CR>");
+    std::fs::write(dir.join("src/.gitignore"), "").expect("temp file");
+    let relisted = files(":set walkhidden walkmaxsize=0<CR>");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(listed, ["keep", "main", "", "", "", "", ""]);
+    assert_eq!(
+        relisted[..6],
+        ["keep", "main", "generated", "", "", "hidden"]
+    );
+    assert_eq!(relisted[6].len(), 3000);
+}
+
+#[test]
+fn shows_the_command_being_typed() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour\nfive");
+    // the last columns of the message line, where the command goes
+    let shown = |harness: &mut Harness, keys: &str| {
+        harness.keys(keys);
+        let line = &harness.screen()[usize::from(HEIGHT) + 1];
+        let command: String = line.chars().skip(usize::from(WIDTH) - 16).collect();
+        command.trim().to_string()
+    };
+    assert_eq!(shown(&mut harness, "12"), "12");
+    assert_eq!(shown(&mut harness, "g"), "12g");
+    assert_eq!(shown(&mut harness, "g"), "");
+    assert_eq!(shown(&mut harness, "3x"), "");
+    assert_eq!(shown(&mut harness, "m"), "m");
+    assert_eq!(shown(&mut harness, "A"), "");
+    assert_eq!(shown(&mut harness, "j4<leader>"), "4\\");
+    assert_eq!(shown(&mut harness, "<Esc>2d3"), "2d3");
+    assert_eq!(shown(&mut harness, "<Esc>:set noshowcmd<CR>4d"), "");
+}
+
+#[test]
+fn waits_for_what_an_operator_acts_on() {
+    let text = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+    let mut harness = Harness::new(text);
+    harness.keys("dd");
+    assert_eq!(harness.lines(), ["2", "3", "4", "5", "6", "7", "8", "9"]);
+    harness.keys("2dd");
+    assert_eq!(harness.lines(), ["4", "5", "6", "7", "8", "9"]);
+    harness.keys("2d2d");
+    assert_eq!(harness.lines(), ["8", "9"]);
+    // Esc or a key with no use gives up the operator and its count
+    let mut harness = Harness::new(text);
+    harness.keys("3d<Esc>dd");
+    assert_eq!(harness.lines().len(), 8);
+    harness.keys("3dzdd");
+    assert_eq!(harness.lines().len(), 7);
+    harness.keys("5d<Esc>j");
+    assert_eq!(harness.cursor(), (1, 0));
+}
+
+#[test]
+fn yanks_and_puts_lines_and_words() {
+    let mut harness = Harness::new("one two three\nfour\nfive");
+    // whole lines go below or above the cursor line
+    harness.keys("yyjp");
+    assert_eq!(
+        harness.lines(),
+        ["one two three", "four", "one two three", "five"]
+    );
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("y2jggP");
+    assert_eq!(harness.lines().len(), 6);
+    assert_eq!(harness.lines().first(), Some(&"one two three"));
+    assert_eq!(harness.lines().get(1), Some(&"five"));
+    // part of a line goes into the line, after or before the cursor
+    let mut harness = Harness::new("one two three");
+    harness.keys("ywwP");
+    assert_eq!(harness.lines(), ["one one two three"]);
+    assert_eq!(harness.cursor(), (0, 7));
+    harness.keys("y$^p");
+    assert_eq!(harness.lines(), ["o two threene one two three"]);
+    // deleting over a motion fills the register too
+    let mut harness = Harness::new("one two three");
+    harness.keys("dw$p");
+    assert_eq!(harness.lines(), ["two threeone "]);
+    harness.keys("^d$");
+    assert_eq!(harness.lines(), [""]);
+}
+
+#[test]
+fn composes_operators_with_motions() {
+    let mut harness = Harness::new("one two three\nfour");
+    // `cw` leaves the space after the word
+    harness.keys("cwONE<Esc>");
+    assert_eq!(harness.lines(), ["ONE two three", "four"]);
+    harness.keys("w3x");
+    assert_eq!(harness.lines(), ["ONE  three", "four"]);
+    assert_eq!(harness.cursor(), (0, 4));
+    harness.keys("c$end<Esc>");
+    assert_eq!(harness.lines(), ["ONE end", "four"]);
+    harness.keys(">>j>k");
+    assert_eq!(harness.lines(), ["        ONE end", "    four"]);
+    harness.keys("<<");
+    assert_eq!(harness.lines(), ["    ONE end", "    four"]);
+    harness.keys("ccnew<Esc>");
+    assert_eq!(harness.lines(), ["    new", "    four"]);
+}
+
+#[test]
+fn finds_characters_in_the_line() {
+    let mut harness = Harness::new("a,b,c,d;e\ncall(a, b);");
+    harness.keys("f,");
+    assert_eq!(harness.cursor(), (0, 1));
+    harness.keys(";");
+    assert_eq!(harness.cursor(), (0, 3));
+    harness.keys(",");
+    assert_eq!(harness.cursor(), (0, 1));
+    harness.keys("2;t;");
+    assert_eq!(harness.cursor(), (0, 6));
+    // a repeated `T` goes past the match next to the cursor
+    harness.keys("T,");
+    assert_eq!(harness.cursor(), (0, 6));
+    harness.keys(";");
+    assert_eq!(harness.cursor(), (0, 4));
+    // forward they take the character they land on, backward not the one
+    // they start on
+    harness.keys("j^f(lct)x<Esc>");
+    assert_eq!(harness.lines()[1], "call(x);");
+    harness.keys("$dFa");
+    assert_eq!(harness.lines()[1], "c;");
+    harness.keys("^df;");
+    assert_eq!(harness.lines()[1], "");
+    // nothing is found, so nothing changes
+    harness.keys("k^dfzcfz");
+    assert_eq!(harness.lines()[0], "a,b,c,d;e");
+    harness.keys("y2f,P");
+    assert_eq!(harness.lines()[0], "a,b,a,b,c,d;e");
+}
+
+#[test]
+fn operates_on_the_visual_selection() {
+    let mut harness = Harness::new("one two three\nfour");
+    harness.keys("wvlld");
+    assert_eq!(harness.lines(), ["one  three", "four"]);
+    assert_eq!(harness.cursor(), (0, 4));
+    harness.keys("P");
+    assert_eq!(harness.lines(), ["one two three", "four"]);
+    // the selection runs across lines, and takes the character under the
+    // cursor at either end
+    harness.keys("^vj~");
+    assert_eq!(harness.lines(), ["ONE TWO THREE", "Four"]);
+    harness.keys("vj>");
+    assert_eq!(harness.lines(), ["    ONE TWO THREE", "    Four"]);
+    harness.keys("vj<");
+    assert_eq!(harness.lines(), ["ONE TWO THREE", "Four"]);
+    harness.keys("wvllcxyz<Esc>");
+    assert_eq!(harness.lines(), ["ONE xyz THREE", "Four"]);
+    harness.keys("^vllyP");
+    assert_eq!(harness.lines(), ["ONEONE xyz THREE", "Four"]);
+}
+
+#[test]
+fn selects_whole_lines_in_visual_line_mode() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour");
+    harness.keys("lVj");
+    assert!(harness
+        .screen()
+        .iter()
+        .any(|line| line.contains("visual line mode (2 lines)")));
+    harness.keys("d");
+    assert_eq!(harness.lines(), ["three", "four"]);
+    harness.keys("p");
+    assert_eq!(harness.lines(), ["three", "one", "two", "four"]);
+    harness.keys("Vj>");
+    assert_eq!(harness.lines(), ["three", "    one", "    two", "four"]);
+    // `V` from a selection keeps where it started
+    harness.keys("vkVyGp");
+    assert_eq!(harness.lines().get(4..), Some(&["three", "    one"][..]));
+}
+
+#[test]
+fn yanks_and_puts_blocks() {
+    let mut harness = Harness::new("abcd\nefgh\nij\nklmn");
+    harness.keys("l<C-v>jl");
+    assert!(harness
+        .screen()
+        .iter()
+        .any(|line| line.contains("visual block mode (2x2)")));
+    // a block goes back as a column, adding lines past the end
+    harness.keys("yG^p");
+    assert_eq!(harness.lines().get(3..), Some(&["kbclmn", " fg"][..]));
+    assert_eq!(harness.cursor(), (3, 1));
+    harness.keys("u");
+    // a short line gives what it has of the block
+    harness.keys("ggjjl<C-v>kkld");
+    assert_eq!(harness.lines(), ["ad", "eh", "i", "klmn"]);
+    harness.keys("P");
+    assert_eq!(harness.lines(), ["abcd", "efgh", "ij", "klmn"]);
+    // what is typed after `c` goes on every line of the block
+    harness.keys("gg<C-v>jcXY<Esc>");
+    assert_eq!(harness.lines().get(..2), Some(&["aXYcd", "eXYgh"][..]));
+    harness.keys("G^<C-v>kkl~");
+    assert_eq!(harness.lines(), ["aXYcd", "ExYgh", "IJ", "KLmn"]);
+    // pieces are filled out to the block's width when text follows
+    harness.keys("jl<C-v>klly");
+    harness.keys("gg^P");
+    assert_eq!(
+        harness.lines().get(..2),
+        Some(&["xYgaXYcd", "J  ExYgh"][..])
+    );
+}
+
+#[test]
+fn puts_lines_indented_like_the_cursor_line() {
+    let mut harness = Harness::new("fn a() {\n    if x {\n        y();\n    }\n}\nfn b() {");
+    harness.keys("j3yyG]p");
+    assert_eq!(
+        harness.lines().get(5..),
+        Some(&["fn b() {", "if x {", "    y();", "}"][..])
+    );
+    assert_eq!(harness.cursor(), (6, 0));
+    harness.keys("u3G[p");
+    assert_eq!(
+        harness.lines().get(2..5),
+        Some(&["        if x {", "            y();", "        }"][..])
+    );
+    // anything less than whole lines goes in as `p` puts it
+    harness.keys("ywgg^]p");
+    assert_eq!(harness.lines()[0], "fif n a() {");
+}
+
+#[test]
+fn keeps_yanks_in_named_registers() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    // uppercase appends to the register
+    harness.keys("\"ayyj\"Ayyj\"byw");
+    harness.keys("\"apG\"bP");
+    assert_eq!(harness.lines(), ["one", "two", "three", "one", "threetwo"]);
+    // the unnamed register has what was last yanked
+    harness.keys("p");
+    assert_eq!(
+        harness.lines(),
+        ["one", "two", "three", "one", "threethreetwo"]
+    );
+    let keys = keymap::parse_keys(":registers<CR>").expect("keys are valid");
+    let listed = harness
+        .editor
+        .run_keys(keys.into_iter().map(Event::Key).collect());
+    assert!(listed.is_err());
+    let screen = harness.screen().join("\n");
+    for line in [
+        "Type Name Content",
+        "  c  \"\"   three",
+        "  l  \"a   one^Jtwo",
+        "  c  \"b   three",
+    ] {
+        assert!(screen.contains(line), "{line:?} in {screen}");
+    }
+}
+
+#[test]
+fn keeps_deletes_in_numbered_registers() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour");
+    // each line deleted moves the ones before down a register
+    harness.keys("dddd");
+    harness.keys("\"2p");
+    assert_eq!(harness.lines(), ["three", "one", "four"]);
+    // less than a line goes in "-, leaving "1 alone
+    harness.keys("lx\"1p\"-P");
+    assert_eq!(harness.lines(), ["three", "oe", "ntwo", "four"]);
+    // a yank goes in "0, and stays there through deletes
+    harness.keys("yyjx\"0P");
+    assert_eq!(harness.lines(), ["three", "oe", "ntwo", "ntwo", "our"]);
+}
+
+#[test]
+fn repeats_the_last_change_with_dot() {
+    let mut harness =
+        Harness::new("one two three four five six seven eight nine ten eleven twelve\nx\ny");
+    harness.keys("dw.");
+    assert_eq!(
+        harness.lines()[0],
+        "three four five six seven eight nine ten eleven twelve"
+    );
+    // the count of the change is repeated with it
+    harness.keys("2dw.");
+    assert_eq!(harness.lines()[0], "seven eight nine ten eleven twelve");
+    // a count before . replaces the one before the change
+    harness.keys("2dw3.");
+    assert_eq!(harness.lines()[0], "twelve");
+    harness.keys("jciwab<Esc>j.");
+    assert_eq!(harness.lines()[1..], ["ab", "ab"]);
+    // undoing is not a change, so . types the one it undid again
+    harness.keys("ggAz<Esc>u.");
+    assert_eq!(harness.lines(), ["twelvez", "ab", "ab"]);
+}
+
+#[test]
+fn types_normal_mode_keys_on_each_line_of_a_range() {
+    let mut harness = Harness::new("a\nb\nc\nd");
+    harness.keys(":2,3norm A;<CR>");
+    assert_eq!(harness.lines(), ["a", "b;", "c;", "d"]);
+    // the lines go away under it, and come back with one undo
+    harness.keys(":%normal dd<CR>");
+    assert!(harness.lines().is_empty());
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["a", "b;", "c;", "d"]);
+    // insert mode left open ends with the line
+    harness.keys(":1normal ix<CR>");
+    assert_eq!(harness.lines()[0], "xa");
+}
+
+#[test]
+fn repeats_the_last_command_line() {
+    let mut harness = Harness::new("one\ntwo\nthree\nfour");
+    harness.keys(":m$<CR>");
+    assert_eq!(harness.lines(), ["two", "three", "four", "one"]);
+    harness.keys("gg@:");
+    assert_eq!(harness.lines(), ["three", "four", "one", "two"]);
+    harness.keys(":norm Ax<CR>ggVj@:");
+    assert_eq!(harness.lines(), ["threex", "fourx", "one", "twox"]);
+}
+
+#[test]
+fn records_and_runs_macros() {
+    let mut harness = Harness::new("a\nb\nc\nd\ne\nf");
+    harness.keys("qaA;<Esc>jq");
+    assert_eq!(harness.lines(), ["a;", "b", "c", "d", "e", "f"]);
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys("\"ap");
+    assert_eq!(harness.lines()[1], "bA;<Esc>j");
+    harness.keys("u@a");
+    assert_eq!(harness.lines()[..3], ["a;", "b;", "c"]);
+    // a count, `@@`, and one undo for the lot
+    harness.keys("2@a@@");
+    assert_eq!(harness.lines(), ["a;", "b;", "c;", "d;", "e;", "f"]);
+    harness.keys("u");
+    assert_eq!(harness.lines(), ["a;", "b;", "c;", "d;", "e", "f"]);
+    // `.` repeats the change the macro made last
+    harness.keys(".");
+    assert_eq!(harness.lines()[4], "e;");
+    // in visual mode, on each selected line
+    harness.keys("qbi-<Esc>q");
+    harness.keys("ggVj@b");
+    assert_eq!(harness.lines()[..3], ["-a;", "-b;", "c;"]);
+}
+
+#[test]
+fn keeps_marks_on_their_lines() {
+    let mut harness = Harness::new("a\n  b\nc\nd\ne");
+    harness.keys("jlmagg`a");
+    assert_eq!(harness.cursor(), (1, 1));
+    harness.keys("gg'a");
+    assert_eq!(harness.cursor(), (1, 2));
+    // lines added and deleted above move it, and so does undoing that
+    harness.keys("ggOnew<Esc>`a");
+    assert_eq!(harness.cursor(), (2, 1));
+    harness.keys("ggddgg`a");
+    assert_eq!(harness.cursor(), (1, 1));
+    harness.keys("u`a");
+    assert_eq!(harness.cursor(), (2, 1));
+    // `'.` is the last change, and `''` where the last jump left from
+    harness.keys("Gxgg`.");
+    assert_eq!(harness.cursor(), (5, 0));
+    harness.keys("''");
+    assert_eq!(harness.cursor(), (0, 0));
+    harness.keys("''");
+    assert_eq!(harness.cursor(), (5, 0));
+    // the mark goes with its line
+    harness.keys("`add`a");
+    assert_eq!(harness.cursor(), (2, 0));
+}
+
+#[test]
+fn acts_on_text_objects() {
+    let mut harness = Harness::new("call(one, \"two three\")\n\nfn f() {\n    body;\n}");
+    harness.keys("wwdiw");
+    assert_eq!(harness.lines()[0], "call(, \"two three\")");
+    harness.keys("fhci\"x<Esc>");
+    assert_eq!(harness.lines()[0], "call(, \"x\")");
+    harness.keys("ya($\"0p");
+    assert_eq!(harness.lines()[0], "call(, \"x\")(, \"x\")");
+    // inside brackets on lines of their own, the lines between go whole
+    harness.keys("jjjdi{");
+    assert_eq!(harness.lines()[2..], ["fn f() {", "}"]);
+    harness.keys("ggvapd");
+    assert_eq!(harness.lines(), ["fn f() {", "}"]);
+}
+
+#[test]
+fn keeps_the_last_insert_and_command_in_registers() {
+    let mut harness = Harness::new("one\ntwo");
+    harness.keys("Aab<Esc>\".p");
+    assert_eq!(harness.lines(), ["oneabab", "two"]);
+    harness.keys(":set so=1<CR>j$\":p");
+    assert_eq!(harness.lines(), ["oneabab", "twoset so=1"]);
+    // they can be read but not yanked into
+    harness.keys("\".yyk$\".p");
+    assert_eq!(harness.lines(), ["oneababab", "twoset so=1"]);
+    harness.keys("/<C-r>:<CR>");
+    assert_eq!(harness.cursor(), (1, 3));
+}
+
+#[test]
+fn copies_and_pastes_through_the_system_clipboard() {
+    let path = std::env::temp_dir().join(format!("rvim-clipboard-{}", std::process::id()));
+    let mut harness = Harness::new("one two\nthree");
+    harness.editor.set_clipboard(Clipboard::with_commands(
+        &format!("cat > {}", path.display()),
+        &format!("cat {}", path.display()),
+    ));
+    harness.keys("\"+yy");
+    let copied = std::fs::read_to_string(&path).unwrap_or_default();
+    std::fs::write(&path, "pasted").expect("temp file");
+    harness.keys("j\"+p");
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(copied, "one two\n");
+    assert_eq!(harness.lines(), ["one two", "tpastedhree"]);
+}
+
+#[test]
+fn sends_yanks_to_the_terminal_clipboard_with_osc52() {
+    let mut harness = Harness::new("hi\nthere");
+    harness.editor.set_clipboard(Clipboard::default());
+    harness.keys("\"+yyj\"*yw");
+    assert_eq!(
+        harness.editor.terminal().sent(),
+        ["\u{1b}]52;c;aGkK\u{7}", "\u{1b}]52;p;dGhlcmU=\u{7}"]
+    );
+    // puts get what was yanked last
+    harness.keys("\"+P");
+    assert_eq!(harness.lines(), ["hi", "hi", "there"]);
+    harness.keys(":set clipboard=tool<CR>\"+yy");
+    assert_eq!(harness.editor.terminal().sent().len(), 2);
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("No clipboard tool found")
+    );
+}
+
+#[test]
+fn writes_unsaved_changes_to_a_recovery_file() {
+    let path = std::env::temp_dir().join(format!("rvim-crash-{}", std::process::id()));
+    std::fs::write(&path, "abc\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>", path.display()));
+    let clean = harness.editor.write_recovery_file().is_none();
+    harness.keys("x");
+    let recovery = harness.editor.write_recovery_file().map(Result::ok);
+    let recovered = std::fs::read_to_string(format!("{}.rvim-crash", path.display()));
+    let original = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(format!("{}.rvim-crash", path.display()));
+    let _ = std::fs::remove_file(&path);
+    assert!(clean);
+    assert_eq!(
+        recovery,
+        Some(Some(format!("{}.rvim-crash", path.display())))
+    );
+    assert_eq!(recovered.unwrap_or_default(), "bc\n");
+    assert_eq!(original, "abc\n");
+}
+
+#[test]
+fn keeps_messages_and_logs_to_a_file() {
+    let path = std::env::temp_dir().join(format!("rvim-log-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut harness = Harness::new("abc");
+    harness.keys(&format!(
+        ":set logfile={} loglevel=debug<CR>",
+        path.display()
+    ));
+    harness.keys(":nosuch<CR>:set so?<CR>l:messages<CR>");
+    // the log is shared with every other test running at the same time
+    harness.keys(":set logfile=<CR>");
+    let log = std::fs::read_to_string(&path).unwrap_or_default();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(harness.lines(), ["Not an editor command: nosuch", "so=0"]);
+    assert!(
+        log.contains(" ERROR message: Not an editor command: nosuch\n"),
+        "{log}"
+    );
+    assert!(log.contains(" DEBUG key: l\n"), "{log}");
+    assert!(log.contains(" DEBUG command: messages\n"), "{log}");
+}
+
+#[test]
+fn keeps_errors_until_a_key_and_pages_long_messages() {
+    let mut harness = Harness::new("a\nb\nc");
+    harness.keys(":nosuch<CR>");
+    let error = harness.screen()[usize::from(HEIGHT) + 1].clone();
+    harness.keys("l");
+    let cleared = harness.screen()[usize::from(HEIGHT) + 1].clone();
+    assert!(
+        error.starts_with("Not an editor command: nosuch"),
+        "{error}"
+    );
+    assert!(!cleared.contains("nosuch"), "{cleared}");
+    // a key other than Enter leaves the listing and is handled as usual
+    harness.keys(":nmap ,a :2<CR>:nmap ,b :3<CR>:nmap ,<CR>j");
+    assert_eq!(harness.cursor(), (2, 0));
+    assert!(!harness.screen().iter().any(|row| row.contains(",a :2")));
+}
+
+#[test]
+fn marks_lines_cut_off_at_either_edge() {
+    let line: String = ('a'..='z').chain('A'..='Z').collect();
+    let mut harness = Harness::with_width(&line, 30);
+    harness.keys("^");
+    assert_eq!(harness.screen()[0], " 0  |abcdefghijklmnopqrs>");
+    harness.keys("$");
+    assert_eq!(harness.screen()[0], " 0  |<HIJKLMNOPQRSTUVWXYZ");
+    harness.keys(":set listchars=extends:»<CR>^");
+    assert_eq!(harness.screen()[0], " 0  |abcdefghijklmnopqrs»");
+}
+
+#[test]
+fn keeps_the_cursor_on_the_line_and_to_its_column() {
+    let mut harness = Harness::new("long line\nab\nlonger line here");
+    harness.keys("A<Esc>");
+    assert_eq!(harness.cursor(), (0, 8));
+    harness.keys("^llllllj");
+    assert_eq!(harness.cursor(), (1, 1));
+    harness.keys("j");
+    assert_eq!(harness.cursor(), (2, 6));
+    harness.keys("$kk");
+    assert_eq!(harness.cursor(), (0, 8));
+    harness.keys("jj");
+    assert_eq!(harness.cursor(), (2, 15));
+    harness.keys("kA!<Esc>");
+    assert_eq!(harness.cursor(), (1, 2));
+    harness.keys(":set ve=all<CR>^lllli-<Esc>");
+    assert_eq!(harness.lines()[1], "ab! -");
+    harness.keys(":set ve=block<CR>$l");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("k$vll");
+    assert_eq!(harness.cursor(), (1, 6));
+    harness.keys("<Esc>");
+    assert_eq!(harness.cursor(), (1, 4));
+    harness.keys(":set ve=sideways<CR>");
+    assert!(
+        harness
+            .screen()
+            .last()
+            .is_some_and(|line| line.contains("Invalid argument: virtualedit=sideways")),
+        "{:?}",
+        harness.screen().last()
+    );
+}
+
+#[test]
+fn keeps_to_the_screen_column_through_tabs_and_wide_characters() {
+    let mut harness = Harness::new("日本\nabcd\n日本");
+    harness.keys("lj");
+    assert_eq!(harness.cursor(), (1, 2));
+    harness.keys("hj");
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys("lkk");
+    assert_eq!(harness.cursor(), (0, 1));
+    let mut harness = Harness::new("a\tb\nabcdefghij");
+    harness.keys("llj");
+    assert_eq!(harness.cursor(), (1, 3));
+    harness.keys("hhk");
+    assert_eq!(harness.cursor(), (0, 1));
+    let mut harness = Harness::new("abcdef\nxy");
+    harness.keys("3l");
+    assert_eq!(harness.cursor(), (0, 3));
+    harness.keys("2h");
+    assert_eq!(harness.cursor(), (0, 1));
+    harness.keys("d3l");
+    assert_eq!(harness.lines(), ["aef", "xy"]);
+}
+
+#[test]
+fn acts_on_no_text_past_the_end_of_the_line() {
+    for keys in ["$lvd", "$ld$", "$ly$P"] {
+        let mut harness = Harness::new("abc\nd");
+        harness.keys(":set ve=onemore<CR>");
+        harness.keys(keys);
+        assert_eq!(harness.lines(), ["abc", "d"], "{keys}");
+    }
+    let mut harness = Harness::new("abc\nd");
+    harness.keys(":set ve=all<CR>$lllvjd");
+    assert_eq!(harness.lines(), ["abc"]);
+    harness.keys("u$llldh");
+    assert_eq!(harness.lines(), ["abc", "d"]);
+    harness.keys("$dh");
+    assert_eq!(harness.lines(), ["ac", "d"]);
+}
+
+#[test]
+fn scrolls_by_screen_columns_through_tabs_and_wide_characters() {
+    let mut harness = Harness::with_width("一二三四五六七八九十百千万億兆\n\tx", 30);
+    harness.keys("$");
+    assert_eq!(harness.screen()[0], " 0  |< 七八九十百千万億兆");
+    harness.keys("^");
+    assert_eq!(harness.screen()[0], " 0  |一二三四五六七八九>");
+    harness.keys("j$");
+    assert_eq!(harness.cursor(), (1, 1));
+    assert_eq!(harness.screen()[1], " 1  |  x");
+}
+
+#[test]
+fn moves_by_screen_line_through_wrapped_rows() {
+    let long: String = ('a'..='z').chain('A'..='X').collect();
+    let mut harness = Harness::with_width(&format!("{long}\nshort"), 30);
+    harness.keys(":set wrap<CR>lll");
+    assert_eq!(harness.screen()[0], " 0  |abcdefghijklmnopqrst");
+    assert_eq!(harness.screen()[1], "    |uvwxyzABCDEFGHIJKLMN");
+    assert_eq!(harness.screen()[3], " 3  |short");
+    harness.keys("gj");
+    assert_eq!(harness.cursor(), (0, 23));
+    harness.keys("2gj");
+    assert_eq!(harness.cursor(), (1, 3));
+    harness.keys("gk");
+    assert_eq!(harness.cursor(), (0, 43));
+    harness.keys(":set displaymotion<CR>kk");
+    assert_eq!(harness.cursor(), (0, 3));
+}
+
+#[test]
+fn indents_continuation_lines_after_showbreak() {
+    let long: String = ('a'..='z').chain('A'..='D').collect();
+    let mut harness = Harness::with_width(&format!("  {long}"), 30);
+    harness.keys(":set wrap<CR>:set breakindent<CR>:set showbreak=+<CR>$");
+    assert_eq!(harness.screen()[0], " 0  |  abcdefghijklmnopqr");
+    assert_eq!(harness.screen()[1], "    |  +stuvwxyzABCD");
+    harness.keys("gk");
+    assert_eq!(harness.cursor(), (0, 14));
+    harness.keys("gj");
+    assert_eq!(harness.cursor(), (0, 31));
+}
+
+#[test]
+fn breaks_lines_typed_past_textwidth() {
+    let mut harness = Harness::new("");
+    harness.keys(":set tw=20<CR>ihello world this is text<Esc>");
+    assert_eq!(harness.lines(), ["hello world this is", "text"]);
+    harness.keys(":set tw=14<CR>o  // one two three four");
+    assert_eq!(
+        harness.lines(),
+        [
+            "hello world this is",
+            "text",
+            "  // one two",
+            "  // three",
+            "  // four"
+        ]
+    );
+    assert_eq!(harness.cursor(), (4, 9));
+    harness.keys("<Esc>:set fo=t<CR>o# a long comment line<Esc>");
+    assert_eq!(harness.lines().last(), Some(&"  # a long comment line"));
+}
+
+#[test]
+fn repeats_counted_inserts() {
+    let mut harness = Harness::new("ab");
+    harness.keys("3i-<Esc>");
+    assert_eq!(harness.lines(), ["---ab"]);
+    harness.keys("2Ax<BS>yz<Esc>");
+    assert_eq!(harness.lines(), ["---abyzyz"]);
+    harness.keys("3onew<CR>line<Esc>");
+    assert_eq!(
+        harness.lines(),
+        ["---abyzyz", "new", "line", "new", "line", "new", "line"]
+    );
+    assert_eq!(harness.cursor(), (6, 3));
+}
+
+#[test]
+fn completes_words_from_the_buffer_in_a_popup() {
+    let mut harness = Harness::new("foo food fool\nbar");
+    harness.keys("jofo<C-n>");
+    assert_eq!(harness.lines()[2], "foo");
+    assert_eq!(harness.screen()[2], " 2  |foo");
+    assert_eq!(harness.screen()[3], "~     foo  ");
+    assert_eq!(harness.screen()[4], "~     food ");
+    harness.keys("<C-n><C-n><C-n>");
+    assert_eq!(harness.lines()[2], "fo");
+    harness.keys("<C-p>");
+    assert_eq!(harness.lines()[2], "fool");
+    harness.keys("s<Esc>");
+    assert_eq!(harness.lines()[2], "fools");
+    assert_eq!(harness.screen()[3], "~");
+}
+
+#[test]
+fn shows_and_jumps_between_diagnostics() {
+    let mut harness = Harness::new("let x = 1;\nfoo\nbar baz");
+    let diagnostic = |y, x, severity, message: &str| Diagnostic {
+        position: Position { x, y },
+        severity,
+        message: message.to_string(),
+    };
+    harness.editor.set_diagnostics(vec![
+        diagnostic(2, 4, Severity::Error, "cannot find value `baz`"),
+        diagnostic(0, 4, Severity::Warning, "unused variable: `x`"),
+    ]);
+    harness.keys("]d");
+    assert_eq!(harness.cursor(), (0, 4));
+    assert_eq!(harness.screen()[0], " 0  |let x = 1;  unused variable: `x`");
+    assert_eq!(harness.screen()[1], " 1  |foo");
+    harness.keys("]d");
+    assert_eq!(harness.cursor(), (2, 4));
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("cannot find value `baz`")
+    );
+    harness.keys("]d[d");
+    assert_eq!(harness.cursor(), (2, 4));
+    harness.keys(":set diagnostics=signs<CR>");
+    assert_eq!(harness.screen()[0], " 0  Wlet x = 1;");
+    assert_eq!(harness.screen()[2], " 2  Ebar baz");
+}
+
+#[test]
+fn shows_the_signature_of_the_call_until_it_is_closed() {
+    let mut harness = Harness::new("one\ntwo");
+    harness.keys("jccfoo(");
+    assert!(harness
+        .editor
+        .show_signature("foo(a: i32, b: i32)", Some(4..10)));
+    harness.keys("1");
+    assert!(harness.screen()[0].contains(" foo(a: i32, b: i32) "));
+    assert!(harness.painted(0, "a: i32", Group::PmenuSel, None));
+    assert!(harness.painted(0, ", b: i32)", Group::Pmenu, None));
+    // a call inside it leaves it up once closed
+    harness.keys(", bar(x)");
+    assert!(harness.screen()[0].contains("foo(a: i32, b: i32)"));
+    harness.keys(")");
+    assert!(!harness.screen()[0].contains("foo("));
+    assert!(!harness.editor.show_signature("foo(a: i32, b: i32)", None));
+    // on the top line it goes below, and leaving insert mode takes it away
+    harness.keys("<Esc>ggibar(");
+    assert!(harness.editor.show_signature("bar(x: u8)", None));
+    harness.keys("y");
+    assert!(harness.screen()[1].contains(" bar(x: u8) "));
+    harness.keys("<Esc>");
+    assert!(!harness.screen()[1].contains("bar(x: u8)"));
+}
+
+#[test]
+fn applies_the_edits_of_the_code_action_picked() {
+    let dir = std::env::temp_dir().join(format!("rvim-code-action-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let other = dir.join("lib.rs");
+    std::fs::write(&other, "pub fn foo() {}\n").expect("temp file");
+    let mut harness = Harness::new("fn main() {\n    foo();\n}");
+    harness.keys("jgra");
+    let unanswered = harness.screen().last().cloned();
+    let edit =
+        |file: Option<&std::path::Path>, start: (usize, usize), end: (usize, usize), text: &str| {
+            TextEdit {
+                file: file.map(|file| file.display().to_string()),
+                start: Position {
+                    x: start.1,
+                    y: start.0,
+                },
+                end: Position { x: end.1, y: end.0 },
+                text: text.to_string(),
+            }
+        };
+    let actions = vec![
+        CodeAction {
+            title: String::from("Remove the call"),
+            edits: vec![edit(None, (1, 4), (1, 10), "")],
+        },
+        CodeAction {
+            title: String::from("Import foo"),
+            edits: vec![
+                edit(None, (0, 0), (0, 0), "use lib::foo;\n\n"),
+                edit(None, (1, 4), (1, 7), "lib::foo"),
+                edit(Some(&other), (0, 0), (0, 0), "pub fn bar() {}\n"),
+            ],
+        },
+    ];
+    harness.editor.post_keys(vec![
+        Event::Key(KeyEvent::from(KeyCode::Down)),
+        Event::Key(KeyEvent::from(KeyCode::Enter)),
+    ]);
+    let picked = harness.editor.pick_code_action(&actions);
+    let (lines, written) = (
+        harness.lines().join("\n"),
+        std::fs::read_to_string(&other).unwrap_or_default(),
+    );
+    harness.keys("u");
+    let undone = harness.lines().join("\n");
+    let none = harness.editor.pick_code_action(&[]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(
+        unanswered
+            .as_deref()
+            .is_some_and(|line| line.starts_with("No RPC client for code actions")),
+        "{unanswered:?}"
+    );
+    assert_eq!(picked, Some(1));
+    assert_eq!(lines, "use lib::foo;\n\nfn main() {\n    lib::foo();\n}");
+    assert_eq!(written, "pub fn bar() {}\npub fn foo() {}\n");
+    assert_eq!(undone, "fn main() {\n    foo();\n}");
+    assert_eq!(none, None);
+}
+
+#[test]
+fn draws_semantic_tokens_over_the_text() {
+    let mut harness = Harness::new("fn area(width: u32) -> u32 {\n    width * HEIGHT\n}");
+    let token = |line, column, length, name| SemanticToken {
+        line,
+        column,
+        length,
+        kind: TokenKind::parse(name).expect("a known token type"),
+    };
+    assert_eq!(TokenKind::parse("keyword"), None);
+    harness.editor.set_semantic_tokens(vec![
+        token(1, 12, 6, "macro"),
+        token(0, 8, 5, "parameter"),
+        token(1, 4, 5, "parameter"),
+    ]);
+    // splitting the line at the tokens and the selection keeps its text
+    harness.keys("jwlvee");
+    assert!(harness.screen()[0].ends_with("|fn area(width: u32) -> u32 {"));
+    assert!(harness.screen()[1].ends_with("|    width * HEIGHT"));
+    harness.keys("<Esc>$x");
+    assert!(harness.screen()[1].ends_with("|    width * HEIGH"));
+}
+
+#[test]
+fn picks_symbols_from_the_outline() {
+    let dir = std::env::temp_dir().join(format!("rvim-outline-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let path = dir.join("a.rs");
+    std::fs::write(
+        &path,
+        "struct Point {\n    x: i32,\n}\nimpl Point {\n    fn new() -> Self {\n    }\n}\nfn main() {\n}\n",
+    )
+    .expect("temp file");
+    std::fs::write(
+        dir.join("tags"),
+        "!_TAG_FILE_FORMAT\t2\n\
+         Point\ta.rs\t/^struct Point {$/;\"\tstruct\tline:1\n\
+         x\ta.rs\t/^    x: i32,$/;\"\tfield\tline:2\tstruct:Point\n\
+         new\ta.rs\t/^    fn new() -> Self {$/;\"\tmethod\tline:5\timplementation:Point\n\
+         main\ta.rs\t/^fn main() {$/;\"\tfunction\tline:8\n\
+         Point\ta.rs\t/^impl Point {$/;\"\timplementation\tline:4\n\
+         other\tb.rs\t/^fn other() {$/;\"\tfunction\tline:1\n",
+    )
+    .expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>5j", path.display()));
+    harness.keys(":Outline<CR><CR>");
+    assert_eq!(harness.cursor(), (4, 0));
+    harness.keys(":Outline<CR><Up><CR>");
+    assert_eq!(harness.cursor(), (3, 0));
+    harness.keys(":Outline<CR>mai<CR>");
+    assert_eq!(harness.cursor(), (7, 0));
+    harness.keys(":Outline<CR><C-p><C-p><Esc>");
+    assert_eq!(harness.cursor(), (7, 0));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn searches_symbols_across_the_workspace() {
+    let dir = std::env::temp_dir().join(format!("rvim-symbols-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.rs"), "fn main() {\n    other();\n}\n").expect("temp file");
+    std::fs::write(dir.join("b.rs"), "\n\nfn other() {\n}\n").expect("temp file");
+    std::fs::write(
+        dir.join("tags"),
+        "main\ta.rs\t/^fn main() {$/;\"\tfunction\tline:1\n\
+         other\tb.rs\t/^fn other() {$/;\"\tfunction\tline:3\n",
+    )
+    .expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>", dir.join("a.rs").display()));
+    harness.keys("\\soth<CR>");
+    assert_eq!(harness.lines().get(2), Some(&"fn other() {"));
+    assert_eq!(harness.cursor(), (2, 0));
+    harness.keys(":set mapleader=,<CR>:nmap <lt>leader>m <lt>workspace-symbols><CR>");
+    harness.keys(",m<CR>");
+    assert_eq!(harness.lines().first(), Some(&"fn main() {"));
+    assert_eq!(harness.cursor(), (0, 0));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+// Plays the debug adapter's part: the next request, after checking it is
+// `command`, and then answers it with `body`.
+#[cfg(unix)]
+fn answer_debug_request(
+    stream: &mut std::io::BufReader<std::os::unix::net::UnixStream>,
+    command: &str,
+    body: &serde_json::Value,
+) -> serde_json::Value {
+    let request = dap::read_message(stream)
+        .expect("read a request")
+        .expect("a request");
+    assert_eq!(request["command"], command, "{request}");
+    let response = serde_json::json!({
+        "seq": 0,
+        "type": "response",
+        "request_seq": request["seq"],
+        "command": command,
+        "success": true,
+        "body": body,
+    });
+    dap::write_message(stream.get_mut(), &response).expect("answer");
+    request
+}
+
+#[cfg(unix)]
+#[test]
+fn debugs_a_program_through_a_debug_adapter() {
+    use serde_json::json;
+
+    let dir = std::env::temp_dir().join(format!("rvim-debug-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let file = dir.join("main.rs");
+    std::fs::write(&file, "fn main() {\n    let x = 1;\n}\n").expect("temp file");
+    let path = file.display().to_string();
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {path}<CR>"));
+    harness.keys("j<leader>db:DebugContinue<CR>");
+    let marked = harness.screen().to_vec();
+
+    let (ours, theirs) = std::os::unix::net::UnixStream::pair().expect("socket pair");
+    let mut adapter = std::io::BufReader::new(theirs);
+    harness.editor.debug_over("main", ours).expect("start");
+    answer_debug_request(&mut adapter, "initialize", &json!({}));
+    harness.editor.serve_debugger().expect("initialized");
+    let launch = answer_debug_request(&mut adapter, "launch", &json!({}));
+    harness.editor.serve_debugger().expect("launched");
+    let initialized = json!({ "seq": 0, "type": "event", "event": "initialized" });
+    dap::write_message(adapter.get_mut(), &initialized).expect("initialized event");
+    harness.editor.serve_debugger().expect("configure");
+    let breakpoints = answer_debug_request(&mut adapter, "setBreakpoints", &json!({}));
+    harness.editor.serve_debugger().expect("breakpoints set");
+    answer_debug_request(&mut adapter, "configurationDone", &json!({}));
+    harness.editor.serve_debugger().expect("configured");
+
+    let stopped =
+        json!({ "seq": 0, "type": "event", "event": "stopped", "body": { "threadId": 7 } });
+    dap::write_message(adapter.get_mut(), &stopped).expect("stopped event");
+    harness.editor.serve_debugger().expect("stopped");
+    let frames = json!({ "stackFrames": [{ "id": 3, "line": 2, "source": { "path": path } }] });
+    let stack = answer_debug_request(&mut adapter, "stackTrace", &frames);
+    harness.editor.serve_debugger().expect("stack trace");
+    let at_stop = harness.screen().to_vec();
+    let scopes = json!({ "scopes": [{ "variablesReference": 9, "expensive": false }] });
+    answer_debug_request(&mut adapter, "scopes", &scopes);
+    harness.editor.serve_debugger().expect("scopes");
+    let variables = json!({ "variables": [{ "name": "x", "value": "1" }] });
+    let asked = answer_debug_request(&mut adapter, "variables", &variables);
+    harness.editor.serve_debugger().expect("variables");
+    let keys = keymap::parse_keys(":DebugVariables<CR>").expect("keys are valid");
+    let listed = harness
+        .editor
+        .run_keys(keys.into_iter().map(Event::Key).collect());
+    let listing = harness.screen().join("\n");
+    harness.keys("<CR>:DebugStep<CR>");
+    let step = dap::read_message(&mut adapter)
+        .expect("read")
+        .expect("next");
+    drop(adapter);
+    harness.editor.serve_debugger().expect("adapter gone");
+    let ended = harness.screen().to_vec();
+    harness.keys("<leader>db");
+    let cleared = harness.screen().to_vec();
+
+    // an adapter started from `debugadapter`: `cat` sends back the
+    // `initialize` request, which is turned down, and then that answer
+    let mut echo = Harness::with_width("", 120);
+    echo.keys(":set debugadapter=cat<CR>:DebugStart main<CR>");
+    while !echo.screen()[HEIGHT as usize + 1].starts_with("initialize: not supported") {
+        echo.editor.serve_debugger().expect("echo");
+    }
+    echo.keys(":DebugStop<CR>:DebugStop<CR>");
+    let not_debugging = echo.screen()[HEIGHT as usize + 1].clone();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(
+        marked
+            .last()
+            .is_some_and(|line| line.starts_with("The program is not stopped")),
+        "{marked:?}"
+    );
+    assert_eq!(marked[1].chars().nth(4), Some('B'));
+    assert!(launch["arguments"]["program"]
+        .as_str()
+        .is_some_and(|program| program.ends_with("main")));
+    assert_eq!(breakpoints["arguments"]["source"]["path"], path.as_str());
+    assert_eq!(
+        breakpoints["arguments"]["breakpoints"],
+        json!([{ "line": 2 }])
+    );
+    assert_eq!(stack["arguments"]["threadId"], 7);
+    assert_eq!(at_stop[1].chars().nth(4), Some('>'));
+    assert_eq!(asked["arguments"]["variablesReference"], 9);
+    assert!(listed.is_err());
+    assert!(listing.contains("--- Variables ---\nx = 1"), "{listing}");
+    assert_eq!(step["command"], "next");
+    assert_eq!(step["arguments"]["threadId"], 7);
+    assert!(
+        ended
+            .last()
+            .is_some_and(|line| line.starts_with("The debug adapter exited")),
+        "{ended:?}"
+    );
+    assert_eq!(ended[1].chars().nth(4), Some('B'));
+    assert_eq!(cleared[1].chars().nth(4), Some('|'));
+    assert!(
+        not_debugging.starts_with("Not debugging"),
+        "{not_debugging}"
+    );
+}
+
+#[test]
+fn previews_markdown_as_a_page_that_follows_the_buffer() {
+    use std::io::{Read, Write};
+    let address = |url: &str| {
+        url.trim_start_matches("http://")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let fetch_as = |url: &str, host: &str, path: &str| {
+        let mut stream = std::net::TcpStream::connect(address(url)).expect("preview server");
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: {host}\r\n\r\n").expect("request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("response");
+        response
+    };
+    let fetch = |url: &str, path: &str| fetch_as(url, &address(url), path);
+    let path = std::env::temp_dir().join(format!("rvim-preview-{}.md", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Notes\n\nSome *light* and `code`:\n\n- one\n- two\n",
+    )
+    .expect("temp");
+    let mut harness = Harness::with_width("", 100);
+    harness.keys(&format!(":e {}<CR>:Preview<CR>", path.display()));
+    let message = harness.screen().last().cloned().unwrap_or_default();
+    let url = message.strip_prefix("Previewing at ").expect(&message);
+    let page = fetch(url, "/");
+    assert!(page.contains("<h1>Notes</h1>"), "{page}");
+    assert!(page.contains("<p>Some <em>light</em> and <code>code</code>:</p>"));
+    assert!(page.contains("<ul>\n<li>one</li>\n<li>two</li>\n</ul>"));
+    harness.keys("Ax<Esc>");
+    assert!(fetch(url, "/body").starts_with("HTTP/1.1 200 OK"));
+    assert!(fetch(url, "/body").contains("<h1>Notesx</h1>"));
+    // a connection that sends nothing does not hold up the others
+    let _idle = std::net::TcpStream::connect(address(url)).expect("preview server");
+    let port = address(url)
+        .rsplit(':')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let response = fetch_as(url, &format!("localhost:{port}"), "/version");
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    // nor can a page elsewhere read it by pointing its own name here
+    let response = fetch_as(url, "example.com", "/body");
+    assert!(response.starts_with("HTTP/1.1 403 Forbidden"), "{response}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn previews_links_only_to_safe_urls() {
+    let page = markdown::to_html(
+        "[site](https://example.com) [mail](mailto:a@b.c) [doc](notes/a.md#top) \
+         [x](javascript:alert(1\\)) [y](JavaScript :alert) [z](data:text/html,hi)",
+    );
+    assert!(
+        page.contains("<a href=\"https://example.com\">site</a>"),
+        "{page}"
+    );
+    assert!(page.contains("<a href=\"mailto:a@b.c\">mail</a>"), "{page}");
+    assert!(
+        page.contains("<a href=\"notes/a.md#top\">doc</a>"),
+        "{page}"
+    );
+    assert_eq!(page.matches("<a ").count(), 3, "{page}");
+    assert!(page.contains("[z](data:text/html,hi)"), "{page}");
+}
+
+#[test]
+fn aligns_lines_on_a_delimiter() {
+    let mut harness = Harness::new("x = 1\nlong_name = 2\n\nfoo(a, bb, c)\nfoo(ccc, d, e)");
+    harness.keys(":Align =<CR>");
+    assert_eq!(harness.lines()[..2], ["x         = 1", "long_name = 2"]);
+    harness.keys("Gvkga,<CR>");
+    assert_eq!(harness.lines()[3..], ["foo(a,   bb, c)", "foo(ccc, d,  e)"]);
+    let mut harness =
+        Harness::with_width("| a | Number |\n|:-|--:|\n| longer | 1 |\n| b | 22 |", 100);
+    harness.keys(":%Align |<CR>");
+    assert_eq!(
+        harness.lines(),
+        [
+            "| a      | Number |",
+            "| ------ | -----: |",
+            "| longer |      1 |",
+            "| b      |     22 |",
+        ]
+    );
+}
+
+#[test]
+fn sneaks_to_two_characters_and_their_labels() {
+    let mut harness = Harness::new("ab x ab y ab\nab");
+    harness.keys("sab");
+    assert_eq!(harness.cursor(), (0, 5));
+    assert_eq!(harness.screen()[0], " 0  |db x ab y ab");
+    assert_eq!(harness.screen()[1], " 1  |sb");
+    harness.keys("s");
+    assert_eq!(harness.cursor(), (1, 0));
+    harness.keys("ggsabd");
+    assert_eq!(harness.cursor(), (0, 0));
+    harness.keys("sabx");
+    assert_eq!(harness.lines()[0], "ab x b y ab");
+    let cursor = harness.cursor();
+    harness.keys("szz");
+    assert_eq!(harness.cursor(), cursor);
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Not found: zz")
+    );
+}
+
+#[test]
+fn highlights_sql_lua_and_typescript() {
+    let mut harness = Harness::new(
+        "select name FROM users -- who\nlocal x = nil -- none\nconst s: string = \"a\";",
+    );
+    harness.keys(":set ft=sql<CR>");
+    // SQL keywords are the same in any case
+    assert!(harness.painted(0, "select", Group::Keyword, None));
+    assert!(harness.painted(0, "FROM", Group::Keyword, None));
+    assert!(harness.painted(0, "-- who", Group::Comment, None));
+    assert!(harness.painted(1, "local x = nil ", Group::Normal, None));
+    // the search word goes over the syntax while it is typed
+    harness
+        .editor
+        .highlight_word("users")
+        .expect("highlighting finishes");
+    assert!(harness.painted(0, "users", Group::Search, None));
+    assert!(harness.painted(0, "FROM", Group::Keyword, None));
+    harness.keys(":set ft=lua<CR>");
+    assert!(harness.painted(1, "local", Group::Keyword, None));
+    assert!(harness.painted(1, "nil", Group::Type, None));
+    assert!(harness.painted(1, "-- none", Group::Comment, None));
+    harness.keys(":set ft=ts<CR>");
+    assert!(harness.painted(2, "const", Group::Keyword, None));
+    assert!(harness.painted(2, "\"a\"", Group::String, None));
+}
+
+#[test]
+fn highlights_escapes_in_strings_and_flags_invalid_ones() {
+    let mut harness = Harness::new("let s = \"tab\\there \\q\";\nlet b = \"\\\\\";");
+    harness.keys(":set ft=rs<CR>");
+    assert!(harness.painted(0, "\"tab", Group::String, None));
+    assert!(harness.painted(0, "\\t", Group::SpecialChar, None));
+    assert!(harness.painted(0, "here ", Group::String, None));
+    assert!(harness.painted(0, "\\q", Group::Error, None));
+    assert!(harness.painted(1, "\\\\", Group::SpecialChar, None));
+    // the selection goes over them, keeping their colors where it has none
+    harness.keys("gg0f\\vlll");
+    assert!(harness.painted(0, "\\t", Group::SpecialChar, Some(Group::Visual)));
+    assert!(harness.painted(0, "he", Group::String, Some(Group::Visual)));
+    assert!(harness.painted(0, "re ", Group::String, None));
+}
+
+#[test]
+fn highlights_number_literals_in_every_base() {
+    let mut harness = Harness::new("let n = 0x1F + 0o17 + 0b101 + 1.5e3 + 10u8;\nlet name1 = x2;");
+    harness.keys(":set ft=rs<CR>");
+    for number in ["0x1F", "0o17", "0b101", "1.5e3", "10u8"] {
+        assert!(harness.painted(0, number, Group::Number, None), "{number}");
+    }
+    // digits in a name are part of the name
+    assert!(harness.painted(1, " name1 = x2;", Group::Normal, None));
+    // a line selection colors every number it takes in
+    harness.keys("ggV");
+    assert!(harness.painted(0, "0x1F", Group::Number, Some(Group::Visual)));
+    assert!(harness.painted(1, " name1 = x2;", Group::Normal, None));
+}
+
+#[test]
+fn highlights_function_names_and_type_names() {
+    let mut harness = Harness::new("fn parse(s: &str) -> Option<Token> {\n    Token::new(s)\n}");
+    harness.keys(":set ft=rs<CR>");
+    assert!(harness.painted(0, "parse", Group::Function, None));
+    assert!(harness.painted(0, "Option", Group::Type, None));
+    assert!(harness.painted(0, "Token", Group::Type, None));
+    assert!(harness.painted(1, "Token", Group::Type, None));
+    assert!(harness.painted(1, "new", Group::Function, None));
+    // a block selection colors only its columns
+    harness.keys("j0<C-v>kll");
+    assert!(harness.painted(0, "fn", Group::Keyword, Some(Group::Visual)));
+    assert!(harness.painted(0, "parse", Group::Function, None));
+    assert!(harness.painted(1, "   ", Group::Normal, Some(Group::Visual)));
+}
+
+#[test]
+fn highlights_doc_comments_attributes_and_lifetimes() {
+    let mut harness =
+        Harness::new("/// Splits it.\n#[derive(Debug)]\nstruct Words<'a>(&'a str);\n// plain");
+    harness.keys(":set ft=rs<CR>");
+    assert!(harness.painted(0, "/// Splits it.", Group::DocComment, None));
+    assert!(harness.painted(1, "#[derive(Debug)]", Group::Attribute, None));
+    assert!(harness.painted(2, "'a", Group::Lifetime, None));
+    assert!(harness.painted(3, "// plain", Group::Comment, None));
+    // the search word goes over them
+    harness
+        .editor
+        .highlight_word("Split")
+        .expect("highlighting finishes");
+    assert!(harness.painted(0, "Split", Group::Search, None));
+    assert!(harness.painted(0, "s it.", Group::DocComment, None));
+}
+
+#[test]
+fn highlights_preprocessor_directives_and_shell_variables() {
+    let mut harness = Harness::new("#include <stdio.h>\necho \"$HOME\" $(pwd) ${USER}");
+    harness.keys(":set ft=c<CR>");
+    assert!(harness.painted(0, "#include", Group::PreProc, None));
+    harness.keys(":set ft=sh<CR>");
+    assert!(harness.painted(1, "$HOME", Group::Variable, None));
+    assert!(harness.painted(1, "$(pwd)", Group::Variable, None));
+    assert!(harness.painted(1, "${USER}", Group::Variable, None));
+    // a selection over a variable keeps its color where it has none
+    harness.keys("j0f$vl");
+    assert!(harness.painted(1, "$H", Group::Variable, Some(Group::Visual)));
+    assert!(harness.painted(1, "OME", Group::Variable, None));
+}
+
+#[test]
+fn switches_to_the_alternate_file_where_it_was_left() {
+    let dir = std::env::temp_dir().join(format!("rvim-alternate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").expect("temp file");
+    std::fs::write(dir.join("b.txt"), "bbb\n").expect("temp file");
+    let mut harness = Harness::new("");
+    harness.keys(&format!(":e {}<CR>jl", dir.join("a.txt").display()));
+    harness.keys(&format!(":e {}<CR>", dir.join("b.txt").display()));
+    harness.keys("<C-^>");
+    let (back, cursor) = (harness.lines().join("\n"), harness.cursor());
+    harness.keys(":e #<CR>");
+    let again = harness.lines().join("\n");
+    harness.keys("x<C-^>");
+    let (kept, message) = (harness.lines().join("\n"), harness.screen().last().cloned());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(back, "one\ntwo\nthree");
+    assert_eq!(cursor, (1, 1));
+    assert_eq!(again, "bbb");
+    // An unsaved change keeps the buffer where it is.
+    assert_eq!(kept, "bb");
+    assert_eq!(
+        message.as_deref(),
+        Some("WARNING! File has unsaved changes.")
+    );
+}
+
+#[test]
+fn runs_a_command_in_every_file_of_the_argument_list() {
+    let dir = std::env::temp_dir().join(format!("rvim-argdo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+    std::fs::write(&a, "old a\n").expect("temp file");
+    std::fs::write(&b, "old b\n").expect("temp file");
+    let (a, b) = (a.display().to_string(), b.display().to_string());
+    let mut harness = Harness::with_width("", 200);
+    harness.keys(&format!(":e {a}<CR>:argadd<CR>:argadd {b}<CR>:args<CR>"));
+    let listed = harness.screen().last().cloned();
+    harness.keys(":argdo normal cwnew<CR>");
+    let done = (std::fs::read_to_string(&a), std::fs::read_to_string(&b));
+    harness.keys(&format!(":argdelete {a}<CR>:args<CR>"));
+    let left = harness.screen().last().cloned();
+    harness.keys(":argdelete *<CR>:args<CR>");
+    let empty = harness.screen().last().cloned();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(
+        listed.map(|line| line.trim_end().to_string()),
+        Some(format!("[{a}] {b}"))
+    );
+    assert_eq!(done.0.ok().as_deref(), Some("new a\n"));
+    assert_eq!(done.1.ok().as_deref(), Some("new b\n"));
+    assert_eq!(
+        left.map(|line| line.trim_end().to_string()),
+        Some(format!("[{b}]"))
+    );
+    assert_eq!(
+        empty.map(|line| line.trim_end().to_string()).as_deref(),
+        Some("Argument list is empty.")
+    );
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn runs_lua_against_the_rvim_api() {
+    let mut harness = Harness::with_width("one\ntwo", 120);
+    harness.keys(":lua rvim.set_option('shiftwidth', 2)<CR>");
+    harness.keys(":lua rvim.set_lines(1, 2, {'TWO', 'three'})<CR>");
+    harness.keys(":lua rvim.keymap('n', 'Q', function() rvim.set_cursor(3, 2) end)<CR>Q");
+    assert_eq!(harness.lines(), ["one", "TWO", "three"]);
+    assert_eq!(harness.cursor(), (2, 2));
+    harness.keys(":lua rvim.echo('sw=' .. rvim.get_option('shiftwidth'))<CR>");
+    assert_eq!(harness.screen().last().map(String::as_str), Some("sw=2"));
+    harness.keys(":lua rvim.nothing()<CR>");
+    assert!(harness
+        .screen()
+        .last()
+        .is_some_and(|line| line.starts_with("Lua error: ")));
+}
+
+#[test]
+fn runs_user_commands_with_their_arguments_and_range() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    harness.keys(":command -nargs=1 Put normal ggi<args>-<CR>:Put new<CR>");
+    assert_eq!(harness.lines(), ["new-one", "two", "three"]);
+    harness.keys(":command -range Dup <line1>,<line2>copy <line2><CR>:2,3Dup<CR>");
+    assert_eq!(harness.lines(), ["new-one", "two", "three", "two", "three"]);
+    harness.keys(":Put<CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Argument required")
+    );
+    harness.keys(":delcommand Put<CR>:Put x<CR>");
+    assert_eq!(harness.lines().len(), 5);
+    harness.keys(":command<CR>");
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("Dup -nargs=0 -range <line1>,<line2>copy <line2>")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn answers_rpc_requests_and_notifies_subscribers() {
+    use rmpv::Value;
+    let dir = std::env::temp_dir().join(format!("rvim-rpc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let (socket, file) = (dir.join("socket"), dir.join("a.txt"));
+    let mut harness = Harness::with_width("one\ntwo", 200);
+    harness
+        .editor
+        .listen(&socket.display().to_string())
+        .expect("listen");
+    let mut client = std::os::unix::net::UnixStream::connect(&socket).expect("connect");
+    client
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .expect("timeout");
+    let requests: [(&str, Vec<Value>); 4] = [
+        ("subscribe", vec!["BufWritePost".into()]),
+        (
+            "set_lines",
+            vec![0.into(), 1.into(), vec![Value::from("ONE")].into()],
+        ),
+        ("get_lines", vec![0.into(), 2.into()]),
+        ("nothing", vec![]),
+    ];
+    for (id, (method, params)) in requests.into_iter().enumerate() {
+        let request = Value::Array(vec![0.into(), id.into(), method.into(), params.into()]);
+        rmpv::encode::write_value(&mut client, &request).expect("request");
+        harness.editor.serve_rpc().expect("served");
+    }
+    let responses: Vec<Value> = (0..4)
+        .map(|_| rmpv::decode::read_value(&mut client).expect("response"))
+        .collect();
+    harness.keys(&format!(":w {}<CR>", file.display()));
+    let notification = rmpv::decode::read_value(&mut client).expect("notification");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let response = |id: u64, error: Value, result: Value| {
+        Value::Array(vec![1.into(), id.into(), error, result])
+    };
+    assert_eq!(responses[0], response(0, Value::Nil, Value::Nil));
+    assert_eq!(responses[1], response(1, Value::Nil, Value::Nil));
+    let lines = Value::Array(vec!["ONE".into(), "two".into()]);
+    assert_eq!(responses[2], response(2, Value::Nil, lines));
+    let error = Value::from("unknown method: nothing");
+    assert_eq!(responses[3], response(3, error, Value::Nil));
+    let params = Value::Array(vec![file.display().to_string().into()]);
+    assert_eq!(
+        notification,
+        Value::Array(vec![2.into(), "BufWritePost".into(), params])
+    );
+}
+
+#[test]
+fn edits_the_same_way_as_the_core_document() {
+    let mut harness = Harness::new("let x = 1;");
+    harness.keys("wiy<Esc>");
+    let mut document = Document::from("let x = 1;");
+    let at = rvim_core::motion::word_forward(&document, &Position::default());
+    document.insert(&at, 'y');
+    document.end_undo_step();
+    assert_eq!(harness.lines(), ["let yx = 1;"]);
+    assert_eq!(document.row(0).map(Row::as_str), Some("let yx = 1;"));
+    assert_eq!(harness.editor.document().file_type(), document.file_type());
+
+    harness.keys("u");
+    document.undo();
+    assert_eq!(harness.lines(), ["let x = 1;"]);
+    assert_eq!(document.row(0).map(Row::as_str), Some("let x = 1;"));
+}
+
+#[test]
+fn writes_the_startup_times_to_the_file_given() {
+    let dir = std::env::temp_dir().join(format!("rvim-startuptime-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    let log = dir.join("startup.log");
+    for _ in 0..2 {
+        let mut harness = Harness::new("one");
+        harness.editor.log_startup(&log.display().to_string());
+        harness.run(":q<CR>").expect("quits");
+    }
+    let text = std::fs::read_to_string(&log).unwrap_or_default();
+
+    let _ = std::fs::remove_dir_all(&dir);
+    // a second run adds its times after the first
+    assert_eq!(text.matches("times in msec").count(), 2);
+    let phases: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.split_once(": ").map(|(_, phase)| phase))
+        .collect();
+    // the header names the column of phases
+    let run = [
+        "phase",
+        "--- RVIM STARTING ---",
+        "highlighting",
+        "first screen drawn",
+    ];
+    assert_eq!(phases, [run, run].concat());
+}
+
+#[test]
+fn takes_the_flash_down_on_its_timer_without_a_keypress() {
+    let mut harness = Harness {
+        editor: Editor::timed("one two", WIDTH, HEIGHT),
+    };
+    let input = harness.editor.input();
+    // every key drawn, so the frame left shows what came after the last
+    let keys = keymap::parse_keys(":set maxfps=0<CR>yy").expect("keys are valid");
+    let typing = std::thread::spawn(move || {
+        for key in keys {
+            input.send(event::Event::Input(Event::Key(key)));
+        }
+        std::thread::sleep(FLASH_TIME * 3);
+        input.fail(std::io::Error::other("terminal gone"));
+    });
+    let result = harness.editor.run_headless(Vec::new());
+    typing.join().expect("keys sent");
+    // a failed read stops the loop, leaving the last frame drawn
+    assert_eq!(
+        result.map_err(|error| error.to_string()).err().as_deref(),
+        Some("could not read input: terminal gone")
+    );
+    assert!(harness.painted(0, "one two", Group::Normal, None));
+}
+
+#[test]
+fn highlights_the_rows_around_the_screen_as_they_come_into_view() {
+    let text = vec!["let x = 1;"; 500].join("\n");
+    let mut harness = Harness::new(&text);
+    harness.keys(":set ft=rust<CR>");
+    let highlighted = |harness: &Harness, index: usize| {
+        harness
+            .editor
+            .document()
+            .row(index)
+            .is_some_and(|row| row.is_highlighted)
+    };
+    assert!(harness.painted(0, "let", Group::Keyword, None));
+    // the screen and a margin under it, not the whole file
+    assert!(highlighted(&harness, 100));
+    assert!(!highlighted(&harness, 300));
+    harness.keys("G");
+    assert!(highlighted(&harness, 400) && highlighted(&harness, 499));
+    assert!(!highlighted(&harness, 300));
+    assert!(harness.painted(0, "let", Group::Keyword, None));
+    // an edited row is highlighted again
+    harness.keys("ccfn x() {}<Esc>");
+    assert!(harness.painted(usize::from(HEIGHT) - 1, "fn", Group::Keyword, None));
+}
+
+#[test]
+fn draws_a_burst_of_keys_at_most_maxfps_times_a_second() {
+    let typed = "ione two three four five<Esc>";
+    let keys = keymap::parse_keys(typed).expect("keys are valid").len();
+    let mut harness = Harness::new("");
+    harness.keys(":set maxfps=1<CR>");
+    let before = harness.editor.terminal().frames();
+    // the keys run out before the next frame is due
+    assert!(harness.run(typed).is_err());
+    let paced = harness.editor.terminal().frames() - before;
+    harness.keys(":set maxfps=0<CR>");
+    let before = harness.editor.terminal().frames();
+    assert!(harness.run(typed).is_err());
+    let unpaced = harness.editor.terminal().frames() - before;
+    assert_eq!(paced, 1);
+    // one for each key after the one the loop starts with, and maybe one
+    // for highlighting that came back in between
+    assert!(unpaced > keys, "{unpaced} frames for {keys} keys");
+}
+
+#[test]
+fn prints_only_the_lines_that_changed() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    harness.keys("");
+    let printed = |harness: &mut Harness, keys: &str| {
+        let before = harness.editor.terminal().printed().len();
+        harness.keys(keys);
+        harness.editor.terminal().printed()[before..].to_vec()
+    };
+    let status = usize::from(HEIGHT);
+    assert_eq!(printed(&mut harness, "j"), [status]);
+    assert_eq!(printed(&mut harness, "x"), [1, status]);
+    // nothing changed, so nothing is printed
+    assert!(printed(&mut harness, "<Esc>").is_empty());
+    harness.keys("kd");
+    // the rows under the deleted line move up, and the pending `d` goes
+    assert_eq!(printed(&mut harness, "d"), [0, 1, 2, status, status + 1]);
+}
+
+#[test]
+fn moves_through_a_huge_line_and_highlights_only_its_start() {
+    let line = format!("{}end", "let x = 1; ".repeat(400));
+    let mut harness = Harness::new(&line);
+    harness.keys(":set ft=rust nowrap<CR>");
+    assert!(harness.painted(0, "let", Group::Keyword, None));
+    harness.keys("$");
+    assert_eq!(harness.cursor(), (0, line.len() - 1));
+    // past the first 3000 characters the line is shown plain
+    assert!(!harness.painted(0, "let", Group::Keyword, None));
+    harness.keys("0/end<CR>");
+    assert_eq!(harness.cursor(), (0, line.len() - 3));
+    harness.keys("bbx");
+    assert!(harness.lines()[0].ends_with("let x = 1; let x = ; end"));
+}
+
+#[test]
+fn shows_errors_it_can_carry_on_from_and_stops_on_the_rest() {
+    let mut harness = Harness::new("one");
+    harness.keys(":set maxfps=0<CR>:w /nonexistent/rvim/file<CR>");
+    // a file that will not save is only reported
+    assert!(harness
+        .screen()
+        .last()
+        .is_some_and(|line| line.contains("No such file or directory")));
+    let input = harness.editor.input();
+    let keys = keymap::parse_keys("x").expect("keys are valid");
+    harness
+        .editor
+        .post_keys(keys.into_iter().map(Event::Key).collect());
+    input.fail(std::io::Error::from(std::io::ErrorKind::Interrupted));
+    input.fail(std::io::Error::other("terminal gone"));
+    let result = harness.editor.run_headless(Vec::new());
+    assert_eq!(
+        result.map_err(|error| error.to_string()).err().as_deref(),
+        Some("could not read input: terminal gone")
+    );
+    // a read cut short by a signal is only shown, and reading goes on
+    assert_eq!(harness.lines(), ["ne"]);
+    assert_eq!(
+        harness.screen().last().map(String::as_str),
+        Some("operation interrupted")
+    );
+}
+#[test]
+fn splits_a_tab_the_block_cuts_through() {
+    // the tab takes two cells, and the block only the first of them
+    let mut harness = Harness::new("a\tb\nxyz\n-\n-");
+    harness.keys("<C-v>jly");
+    harness.keys("jj0P");
+    assert_eq!(harness.lines()[2..], ["a -", "xy-"]);
+    harness.keys("gg<C-v>jld");
+    assert_eq!(harness.lines()[..2], [" b", "z"]);
+    harness.keys("u<C-v>jlcQ<Esc>");
+    assert_eq!(harness.lines()[..2], ["Q b", "Qz"]);
+    // a tab the block takes whole goes
+    harness.keys("u<C-v>jlld");
+    assert_eq!(harness.lines()[..2], ["b", ""]);
+}
@@ -0,0 +1,147 @@
+use crate::clipboard::Clipboard;
+use crate::config;
+use std::env;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+// Language servers and formatters looked for on `PATH`, by language.
+const LANGUAGE_SERVERS: &[(&str, &str)] = &[
+    ("Rust", "rust-analyzer"),
+    ("C/C++", "clangd"),
+    ("Go", "gopls"),
+    ("Python", "pyright-langserver"),
+    ("JavaScript/TypeScript", "typescript-language-server"),
+];
+const FORMATTERS: &[(&str, &str)] = &[
+    ("Rust", "rustfmt"),
+    ("C/C++", "clang-format"),
+    ("Go", "gofmt"),
+    ("Python", "black"),
+    ("JavaScript/TypeScript", "prettier"),
+];
+
+#[derive(Default)]
+struct Report {
+    text: String,
+}
+
+impl Report {
+    fn section(&mut self, title: &str) {
+        let _ = write!(self.text, "\n{title}\n");
+    }
+    fn ok(&mut self, message: &str) {
+        let _ = writeln!(self.text, "  OK    {message}");
+    }
+    // `advice` says what to do about it.
+    fn warn(&mut self, message: &str, advice: &str) {
+        let _ = writeln!(self.text, "  WARN  {message}\n        - {advice}");
+    }
+    fn error(&mut self, message: &str, advice: &str) {
+        let _ = writeln!(self.text, "  ERROR {message}\n        - {advice}");
+    }
+}
+
+// `:checkhealth`: the terminal, the config files and the external tools rvim
+// can use, each with what to do about it when something is wrong.
+pub fn report(config_errors: &[String], theme: Option<&Path>, theme_error: Option<&str>) -> String {
+    let mut report = Report::default();
+    report.text.push_str("rvim health report\n");
+    check_terminal(&mut report);
+    check_config(&mut report, config_errors, theme, theme_error);
+    check_tools(&mut report);
+    report.text
+}
+
+fn check_terminal(report: &mut Report) {
+    report.section("Terminal");
+    match env::var("COLORTERM") {
+        Ok(value) if matches!(value.as_str(), "truecolor" | "24bit") => {
+            report.ok(&format!("truecolor: COLORTERM={value}"));
+        }
+        _ => report.warn(
+            "truecolor: COLORTERM is not truecolor or 24bit",
+            "Set COLORTERM=truecolor if the terminal has it, or use color names in the theme file.",
+        ),
+    }
+    // the tool the "+ and "* registers would use, or OSC 52 without one
+    match Clipboard::detect().tool() {
+        Some(tool) => report.ok(&format!("clipboard: {tool}")),
+        None => report.warn(
+            "clipboard: no tool found, so yanks to \"+ and \"* are sent to the terminal as OSC 52",
+            "Install wl-clipboard, xclip or xsel to paste from the clipboard too.",
+        ),
+    }
+    match env::var("TERM") {
+        Ok(term) if !term.is_empty() && term != "dumb" => report.ok(&format!("TERM={term}")),
+        _ => report.error(
+            "TERM is not set or is dumb",
+            "Run rvim in a terminal emulator with TERM set, e.g. xterm-256color.",
+        ),
+    }
+}
+
+fn check_config(
+    report: &mut Report,
+    config_errors: &[String],
+    theme: Option<&Path>,
+    theme_error: Option<&str>,
+) {
+    report.section("Configuration");
+    let Some(dir) = config::config_dir() else {
+        report.warn(
+            "config directory: neither XDG_CONFIG_HOME nor HOME is set",
+            "Set HOME so rvim can find ~/.config/rvim.",
+        );
+        return;
+    };
+    let rvimrc = dir.join("rvimrc");
+    if !rvimrc.exists() {
+        report.ok(&format!("rvimrc: none at {}", rvimrc.display()));
+    } else if config_errors.is_empty() {
+        report.ok(&format!("rvimrc: {}", rvimrc.display()));
+    }
+    for error in config_errors {
+        report.error(error, "Fix the line, then reload it with :source.");
+    }
+    match (theme, theme_error) {
+        (_, Some(error)) => report.error(error, "Fix the line; the theme reloads when saved."),
+        (Some(path), None) if path.exists() => report.ok(&format!("theme: {}", path.display())),
+        (Some(path), None) => report.ok(&format!("theme: none at {}", path.display())),
+        (None, None) => (),
+    }
+}
+
+fn check_tools(report: &mut Report) {
+    report.section("External tools");
+    match find_executable("git") {
+        Some(path) => report.ok(&format!("git: {}", path.display())),
+        None => report.warn(
+            "git: not found",
+            "Install git to see diff counts in the status line.",
+        ),
+    }
+    match find_executable("rg") {
+        Some(path) => report.ok(&format!("rg: {}", path.display())),
+        None => report.warn("rg: not found", "Install ripgrep for fast project search."),
+    }
+    for (kind, tools) in [
+        ("language server", LANGUAGE_SERVERS),
+        ("formatter", FORMATTERS),
+    ] {
+        for (language, tool) in tools {
+            match find_executable(tool) {
+                Some(path) => report.ok(&format!("{language} {kind}: {}", path.display())),
+                None => report.warn(
+                    &format!("{language} {kind}: {tool} not found"),
+                    &format!("Install {tool} and put it on PATH."),
+                ),
+            }
+        }
+    }
+}
+
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
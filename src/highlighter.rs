@@ -0,0 +1,84 @@
+use crate::event::{Event, EventSender};
+use crate::Document;
+use crate::Pattern;
+use crate::Row;
+use std::thread;
+
+// Rows highlighted above and below the screen, so scrolling a little finds
+// them ready.
+const MARGIN: usize = 100;
+
+// Highlights the rows around the screen on a worker thread, one job at a
+// time, so typing never waits for the highlighter. Rows show up plain until
+// their `Event::Highlighted` arrives.
+#[derive(Default)]
+pub struct Highlighter {
+    busy: bool,
+}
+
+impl Highlighter {
+    // Starts a job for the rows in `top..top + height`, plus the margin,
+    // that are not highlighted yet; does nothing while one is running.
+    pub fn request(
+        &mut self,
+        document: &Document,
+        word: Option<&Pattern>,
+        top: usize,
+        height: usize,
+        sender: EventSender,
+    ) {
+        if self.busy {
+            return;
+        }
+        let end = top
+            .saturating_add(height)
+            .saturating_add(MARGIN)
+            .min(document.len());
+        let Some(start) = (top.saturating_sub(MARGIN)..end)
+            .find(|&index| document.row(index).is_some_and(|row| !row.is_highlighted))
+        else {
+            return;
+        };
+        // a row above the margin that is not highlighted yet counts as
+        // outside any comment
+        let in_comment = start
+            .checked_sub(1)
+            .and_then(|index| document.row(index))
+            .is_some_and(|row| row.is_highlighted && row.ends_in_comment());
+        let lines: Vec<String> = (start..end)
+            .filter_map(|index| document.row(index))
+            .map(|row| row.as_str().to_string())
+            .collect();
+        let options = document.highlighting_options().clone();
+        let revision = document.revision();
+        let file_type = document.file_type();
+        let word = word.cloned();
+        self.busy = true;
+        thread::spawn(move || {
+            let mut in_comment = in_comment;
+            let rows = lines
+                .iter()
+                .map(|line| {
+                    let mut row = Row::from(line.as_str());
+                    in_comment = row.highlight(&options, &word, in_comment);
+                    row.highlighting().to_vec()
+                })
+                .collect();
+            sender.send(Event::Highlighted {
+                revision,
+                file_type,
+                word,
+                start,
+                rows,
+            });
+        });
+    }
+    // The running job has sent its rows; the next `request` starts another.
+    pub fn finish(&mut self) {
+        self.busy = false;
+    }
+    #[cfg(test)]
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+}
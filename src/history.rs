@@ -0,0 +1,60 @@
+// What was entered at the `:` and `/` prompts before, oldest first, to bring
+// back with Up and Down.
+
+// How many entries each history keeps.
+const LIMIT: usize = 100;
+
+#[derive(Clone, Copy)]
+pub enum HistoryKind {
+    Command,
+    Search,
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn new(entries: Vec<String>) -> Self {
+        let mut history = Self::default();
+        for entry in entries {
+            history.add(&entry);
+        }
+        history
+    }
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+    // Entering the same line again moves it to the end rather than keeping
+    // it twice.
+    pub fn add(&mut self, entry: &str) {
+        if entry.is_empty() {
+            return;
+        }
+        self.entries.retain(|old| old != entry);
+        self.entries.push(entry.to_string());
+        let extra = self.entries.len().saturating_sub(LIMIT);
+        self.entries.drain(..extra);
+    }
+    // The index of the latest entry before `before` that starts with
+    // `prefix`.
+    pub fn previous(&self, before: usize, prefix: &str) -> Option<usize> {
+        self.entries
+            .get(..before)?
+            .iter()
+            .rposition(|entry| entry.starts_with(prefix))
+    }
+    // The index of the first entry after `after` that starts with `prefix`.
+    pub fn next(&self, after: usize, prefix: &str) -> Option<usize> {
+        let start = after.saturating_add(1);
+        self.entries
+            .get(start..)?
+            .iter()
+            .position(|entry| entry.starts_with(prefix))
+            .map(|index| index.saturating_add(start))
+    }
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+}
@@ -0,0 +1,778 @@
+use crate::editor::Mode;
+use crate::pending::Operator;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fmt;
+
+// Built-in editor actions, bound with `<name>` on the right-hand side of
+// `:nmap` and friends.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Insert,
+    Append,
+    AppendEnd,
+    OpenBelow,
+    OpenAbove,
+    Left,
+    Down,
+    Up,
+    Right,
+    // `gj` and `gk`: down and up by screen line when lines wrap.
+    DisplayDown,
+    DisplayUp,
+    WordForward,
+    WordBackward,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    // `f`, `F`, `t` and `T`: to the next or previous place in the line of
+    // the character typed after them, or just short of it.
+    FindChar,
+    FindCharBackward,
+    TillChar,
+    TillCharBackward,
+    // `;` and `,`: the last of those again, the same way or the other.
+    RepeatFind,
+    RepeatFindReverse,
+    // `i` and `a` after an operator or in visual mode: the text object
+    // named by the key typed next, such as a word or what is in brackets,
+    // without or with what surrounds it.
+    InnerObject,
+    AroundObject,
+    DeleteChar,
+    DeleteLine,
+    // `d`: the delete operator, waiting for what to delete.
+    Delete,
+    // `y`: the yank operator, and `p` and `P` to put what it copied.
+    Yank,
+    PutAfter,
+    PutBefore,
+    // `]p` and `[p`: put whole lines indented like the cursor line.
+    PutAfterIndented,
+    PutBeforeIndented,
+    // The `c`, `>` and `<` operators, and `~` in visual mode: change,
+    // indent, outdent or toggle the case of the selection.
+    Change,
+    Indent,
+    Outdent,
+    ToggleCase,
+    CommandLine,
+    Search,
+    SearchNext,
+    SearchPrevious,
+    AlternateFile,
+    VisualMode,
+    // `V`: visual mode selecting whole lines.
+    VisualLine,
+    // `Ctrl-V`: visual mode selecting a block, the same columns of each
+    // line.
+    VisualBlock,
+    NormalMode,
+    // `Ctrl-N` and `Ctrl-P` in insert mode: complete the word before the
+    // cursor from the words in the buffer.
+    CompleteNext,
+    CompletePrevious,
+    // `Ctrl-G u` in insert mode: what is typed after it is undone apart
+    // from what came before.
+    BreakUndo,
+    NextDiagnostic,
+    PreviousDiagnostic,
+    // Fuzzy search through the symbols in the nearest tags file.
+    WorkspaceSymbols,
+    // Pick one of the last yanks and deletes to put after the cursor.
+    YankHistory,
+    // `Ctrl-P` right after a put: put the yank or delete before it in the
+    // history instead.
+    PutOlder,
+    // Ask the RPC client for code actions for the line or selection.
+    CodeActions,
+    // `ga` in visual mode: line up the selected lines on a delimiter.
+    Align,
+    // `s{char}{char}`: the next place on the screen the two characters
+    // appear, with labels on the others to jump to them instead.
+    Sneak,
+    // `<leader>d`: a copy of the line, or the lines selected, below them.
+    Duplicate,
+    Undo,
+    Redo,
+    // `<C-f>` and `<C-b>`: a screen forward or back.
+    PageDown,
+    PageUp,
+    // `g-` and `g+`: the text as it was before or after the current state,
+    // in the order the changes were made, whichever branch they are on.
+    Earlier,
+    Later,
+    // `<A-j>` and `<A-k>`: the line, or the lines selected, down or up past
+    // the next one.
+    MoveDown,
+    MoveUp,
+    // `.`: the last change again.
+    RepeatChange,
+    // `@:`: the last command line again, or on each selected line.
+    RepeatCommand,
+    // `q{register}`: the keys typed from now on go in the register, until
+    // `q` again.
+    RecordMacro,
+    // `@{register}`: the keys in the register typed again, or on each
+    // selected line; `@@` runs the last one run.
+    RunMacro,
+}
+
+impl Builtin {
+    // Whether the action changes the text, or enters insert mode to.
+    pub fn is_edit(self) -> bool {
+        matches!(
+            self,
+            Self::Insert
+                | Self::Append
+                | Self::AppendEnd
+                | Self::OpenBelow
+                | Self::OpenAbove
+                | Self::DeleteChar
+                | Self::DeleteLine
+                | Self::Delete
+                | Self::PutAfter
+                | Self::PutBefore
+                | Self::PutAfterIndented
+                | Self::PutBeforeIndented
+                | Self::Change
+                | Self::Indent
+                | Self::Outdent
+                | Self::ToggleCase
+                | Self::Align
+                | Self::Duplicate
+                | Self::Undo
+                | Self::Redo
+                | Self::Earlier
+                | Self::Later
+                | Self::MoveDown
+                | Self::MoveUp
+                | Self::RepeatChange
+        )
+    }
+    // Whether the action is a change `.` repeats: an edit other than going
+    // through the undo history.
+    pub fn is_change(self) -> bool {
+        self.is_edit()
+            && !matches!(
+                self,
+                Self::Undo | Self::Redo | Self::Earlier | Self::Later | Self::RepeatChange
+            )
+    }
+    // Whether the action moves the cursor, so an operator can act on the
+    // text it moves over.
+    pub fn is_motion(self) -> bool {
+        matches!(
+            self,
+            Self::Left
+                | Self::Down
+                | Self::Up
+                | Self::Right
+                | Self::DisplayDown
+                | Self::DisplayUp
+                | Self::WordForward
+                | Self::WordBackward
+                | Self::LineStart
+                | Self::LineEnd
+                | Self::FirstLine
+                | Self::LastLine
+                | Self::FindChar
+                | Self::FindCharBackward
+                | Self::TillChar
+                | Self::TillCharBackward
+                | Self::RepeatFind
+                | Self::RepeatFindReverse
+        )
+    }
+    // Whether the action is a jump, which `''` comes back from.
+    pub fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::FirstLine
+                | Self::LastLine
+                | Self::Search
+                | Self::SearchNext
+                | Self::SearchPrevious
+        )
+    }
+    // Whether the action picks a text object.
+    pub fn is_text_object(self) -> bool {
+        matches!(self, Self::InnerObject | Self::AroundObject)
+    }
+    // Whether the action searches the line for a character.
+    pub fn is_char_search(self) -> bool {
+        matches!(
+            self,
+            Self::FindChar
+                | Self::FindCharBackward
+                | Self::TillChar
+                | Self::TillCharBackward
+                | Self::RepeatFind
+                | Self::RepeatFindReverse
+        )
+    }
+    // The operator the action starts, or applies to the selection in
+    // visual mode.
+    pub fn operator(self) -> Option<Operator> {
+        match self {
+            Self::Delete => Some(Operator::Delete),
+            Self::Yank => Some(Operator::Yank),
+            Self::Change => Some(Operator::Change),
+            Self::Indent => Some(Operator::Indent),
+            Self::Outdent => Some(Operator::Outdent),
+            Self::ToggleCase => Some(Operator::ToggleCase),
+            _ => None,
+        }
+    }
+    // Whether an operator acts on whole lines when the action moves the
+    // cursor for it.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Down | Self::Up | Self::FirstLine | Self::LastLine
+        )
+    }
+}
+
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("insert", Builtin::Insert),
+    ("append", Builtin::Append),
+    ("append-end", Builtin::AppendEnd),
+    ("open-below", Builtin::OpenBelow),
+    ("open-above", Builtin::OpenAbove),
+    ("left", Builtin::Left),
+    ("down", Builtin::Down),
+    ("up", Builtin::Up),
+    ("right", Builtin::Right),
+    ("display-down", Builtin::DisplayDown),
+    ("display-up", Builtin::DisplayUp),
+    ("word-forward", Builtin::WordForward),
+    ("word-backward", Builtin::WordBackward),
+    ("line-start", Builtin::LineStart),
+    ("line-end", Builtin::LineEnd),
+    ("first-line", Builtin::FirstLine),
+    ("last-line", Builtin::LastLine),
+    ("find-char", Builtin::FindChar),
+    ("find-char-backward", Builtin::FindCharBackward),
+    ("till-char", Builtin::TillChar),
+    ("till-char-backward", Builtin::TillCharBackward),
+    ("repeat-find", Builtin::RepeatFind),
+    ("repeat-find-reverse", Builtin::RepeatFindReverse),
+    ("inner-object", Builtin::InnerObject),
+    ("around-object", Builtin::AroundObject),
+    ("delete-char", Builtin::DeleteChar),
+    ("delete-line", Builtin::DeleteLine),
+    ("delete", Builtin::Delete),
+    ("yank", Builtin::Yank),
+    ("put-after", Builtin::PutAfter),
+    ("put-before", Builtin::PutBefore),
+    ("put-after-indented", Builtin::PutAfterIndented),
+    ("put-before-indented", Builtin::PutBeforeIndented),
+    ("change", Builtin::Change),
+    ("indent", Builtin::Indent),
+    ("outdent", Builtin::Outdent),
+    ("toggle-case", Builtin::ToggleCase),
+    ("command-line", Builtin::CommandLine),
+    ("search", Builtin::Search),
+    ("search-next", Builtin::SearchNext),
+    ("search-previous", Builtin::SearchPrevious),
+    ("alternate-file", Builtin::AlternateFile),
+    ("visual-mode", Builtin::VisualMode),
+    ("visual-line", Builtin::VisualLine),
+    ("visual-block", Builtin::VisualBlock),
+    ("normal-mode", Builtin::NormalMode),
+    ("complete-next", Builtin::CompleteNext),
+    ("complete-previous", Builtin::CompletePrevious),
+    ("break-undo", Builtin::BreakUndo),
+    ("next-diagnostic", Builtin::NextDiagnostic),
+    ("previous-diagnostic", Builtin::PreviousDiagnostic),
+    ("workspace-symbols", Builtin::WorkspaceSymbols),
+    ("yank-history", Builtin::YankHistory),
+    ("put-older", Builtin::PutOlder),
+    ("code-actions", Builtin::CodeActions),
+    ("align", Builtin::Align),
+    ("sneak", Builtin::Sneak),
+    ("duplicate", Builtin::Duplicate),
+    ("undo", Builtin::Undo),
+    ("redo", Builtin::Redo),
+    ("page-down", Builtin::PageDown),
+    ("page-up", Builtin::PageUp),
+    ("earlier", Builtin::Earlier),
+    ("later", Builtin::Later),
+    ("move-down", Builtin::MoveDown),
+    ("move-up", Builtin::MoveUp),
+    ("repeat-change", Builtin::RepeatChange),
+    ("repeat-command", Builtin::RepeatCommand),
+    ("record-macro", Builtin::RecordMacro),
+    ("run-macro", Builtin::RunMacro),
+];
+
+// The bindings every editor starts with, in the same form as `:{mode}map`.
+const DEFAULTS: &[(&str, &str, &str, &str)] = &[
+    ("n", "i", "<insert>", "Insert before the cursor"),
+    ("n", "a", "<append>", "Insert after the cursor"),
+    ("n", "A", "<append-end>", "Insert at the end of the line"),
+    ("n", "o", "<open-below>", "Open a line below"),
+    ("n", "O", "<open-above>", "Open a line above"),
+    ("nvo", "h", "<left>", "Left"),
+    ("nvo", "j", "<down>", "Down"),
+    ("nvo", "k", "<up>", "Up"),
+    ("nvo", "l", "<right>", "Right"),
+    ("nvo", "<Left>", "<left>", "Left"),
+    ("nvo", "<Down>", "<down>", "Down"),
+    ("nvo", "<Up>", "<up>", "Up"),
+    ("nvo", "<Right>", "<right>", "Right"),
+    ("nv", "<C-f>", "<page-down>", "Forward a screen"),
+    ("nv", "<C-b>", "<page-up>", "Back a screen"),
+    ("nv", "<PageDown>", "<page-down>", "Forward a screen"),
+    ("nv", "<PageUp>", "<page-up>", "Back a screen"),
+    ("nvo", "gj", "<display-down>", "Down a screen line"),
+    ("nvo", "gk", "<display-up>", "Up a screen line"),
+    ("nvo", "w", "<word-forward>", "Next word"),
+    ("nvo", "b", "<word-backward>", "Previous word"),
+    ("nvo", "^", "<line-start>", "Start of the line"),
+    ("nvo", "$", "<line-end>", "End of the line"),
+    ("nvo", "gg", "<first-line>", "First line, or line {count}"),
+    ("nvo", "G", "<last-line>", "Last line, or line {count}"),
+    ("nvo", "f", "<find-char>", "To the next {char} in the line"),
+    (
+        "nvo",
+        "F",
+        "<find-char-backward>",
+        "To the previous {char} in the line",
+    ),
+    (
+        "nvo",
+        "t",
+        "<till-char>",
+        "Just before the next {char} in the line",
+    ),
+    (
+        "nvo",
+        "T",
+        "<till-char-backward>",
+        "Just after the previous {char} in the line",
+    ),
+    ("nvo", ";", "<repeat-find>", "Repeat the last f, t, F or T"),
+    (
+        "nvo",
+        ",",
+        "<repeat-find-reverse>",
+        "Repeat the last f, t, F or T the other way",
+    ),
+    ("vo", "i", "<inner-object>", "Inside the text object {char}"),
+    (
+        "vo",
+        "a",
+        "<around-object>",
+        "The text object {char} and around it",
+    ),
+    ("nv", "s", "<sneak>", "Jump to two characters on the screen"),
+    ("n", "x", "<delete-char>", "Delete a character"),
+    ("n", "d", "<delete>", "Delete; dd for the line"),
+    ("v", "d", "<delete>", "Delete the selection"),
+    ("v", "x", "<delete>", "Delete the selection"),
+    ("n", "D", "<delete-line>", "Delete the line"),
+    ("n", "y", "<yank>", "Yank; yy for the line"),
+    ("v", "y", "<yank>", "Yank the selection"),
+    ("n", "c", "<change>", "Change; cc for the line"),
+    ("v", "c", "<change>", "Change the selection"),
+    ("n", ">", "<indent>", "Indent; >> for the line"),
+    ("v", ">", "<indent>", "Indent the selected lines"),
+    ("n", "<", "<outdent>", "Outdent; << for the line"),
+    ("v", "<", "<outdent>", "Outdent the selected lines"),
+    (
+        "v",
+        "~",
+        "<toggle-case>",
+        "Toggle the case of the selection",
+    ),
+    ("n", "p", "<put-after>", "Put after the cursor"),
+    ("n", "P", "<put-before>", "Put before the cursor"),
+    (
+        "n",
+        "]p",
+        "<put-after-indented>",
+        "Put after the cursor, indented like its line",
+    ),
+    (
+        "n",
+        "[p",
+        "<put-before-indented>",
+        "Put before the cursor, indented like its line",
+    ),
+    (
+        "n",
+        "]P",
+        "<put-before-indented>",
+        "Put before the cursor, indented like its line",
+    ),
+    (
+        "n",
+        "[P",
+        "<put-before-indented>",
+        "Put before the cursor, indented like its line",
+    ),
+    ("n", "u", "<undo>", "Undo"),
+    ("n", "<C-r>", "<redo>", "Redo"),
+    ("n", "g-", "<earlier>", "Go back to the previous text state"),
+    ("n", "g+", "<later>", "Go on to the next text state"),
+    ("n", ".", "<repeat-change>", "Repeat the last change"),
+    ("n", "q", "<record-macro>", "Record keys into a register"),
+    ("n", "@", "<run-macro>", "Type the keys in a register"),
+    (
+        "v",
+        "@",
+        "<run-macro>",
+        "Type the keys in a register on each selected line",
+    ),
+    ("n", ":", "<command-line>", "Enter an ex command"),
+    ("n", "/", "<search>", "Search"),
+    ("n", "n", "<search-next>", "Next match"),
+    ("n", "N", "<search-previous>", "Previous match"),
+    ("n", "<C-^>", "<alternate-file>", "Edit the alternate file"),
+    ("n", "<C-6>", "<alternate-file>", "Edit the alternate file"),
+    ("n", "v", "<visual-mode>", "Visual mode"),
+    ("nv", "V", "<visual-line>", "Visual line mode"),
+    ("nv", "<C-v>", "<visual-block>", "Visual block mode"),
+    ("v", "ga", "<align>", "Align the lines on a delimiter"),
+    (
+        "nv",
+        "<A-j>",
+        "<move-down>",
+        "Move the line or selection down",
+    ),
+    ("nv", "<A-k>", "<move-up>", "Move the line or selection up"),
+    (
+        "nv",
+        "<leader>d",
+        "<duplicate>",
+        "Duplicate the line or selection",
+    ),
+    ("n", "]d", "<next-diagnostic>", "Next diagnostic"),
+    ("n", "[d", "<previous-diagnostic>", "Previous diagnostic"),
+    (
+        "n",
+        "<leader>s",
+        "<workspace-symbols>",
+        "Search workspace symbols",
+    ),
+    (
+        "n",
+        "<leader>db",
+        ":DebugBreakpoint",
+        "Toggle a breakpoint on the line",
+    ),
+    ("n", "<leader>dc", ":DebugContinue", "Continue the program"),
+    ("n", "<leader>ds", ":DebugStep", "Step the program"),
+    (
+        "nv",
+        "gra",
+        "<code-actions>",
+        "Code actions for the line or selection",
+    ),
+    (
+        "n",
+        "<leader>p",
+        "<yank-history>",
+        "Put one of the last yanks or deletes",
+    ),
+    (
+        "n",
+        "<C-p>",
+        "<put-older>",
+        "Swap what was just put for the yank before it",
+    ),
+    ("i", "<C-n>", "<complete-next>", "Next completion"),
+    ("i", "<C-p>", "<complete-previous>", "Previous completion"),
+    ("i", "<C-g>u", "<break-undo>", "Start a new undo step"),
+];
+
+#[derive(Clone)]
+pub enum Action {
+    Builtin(Builtin),
+    Command(String),
+    // A function registered from Lua, by its index in `Scripting`.
+    #[cfg(feature = "lua")]
+    Lua(usize),
+}
+
+impl Action {
+    // Accepts `<builtin-name>` or `:{ex command}`, optionally ending in `<CR>`.
+    pub fn parse(rhs: &str) -> Result<Self, String> {
+        if let Some(command) = rhs.strip_prefix(':') {
+            let command = command.strip_suffix("<CR>").unwrap_or(command);
+            return Ok(Self::Command(command.to_string()));
+        }
+        let name = rhs
+            .strip_prefix('<')
+            .and_then(|name| name.strip_suffix('>'))
+            .ok_or_else(|| format!("Expected :{{command}} or <{{action}}>: {rhs}"))?;
+        BUILTINS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, builtin)| Self::Builtin(*builtin))
+            .ok_or_else(|| format!("Unknown action: <{name}>"))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Builtin(builtin) => {
+                let name = BUILTINS
+                    .iter()
+                    .find(|(_, other)| other == builtin)
+                    .map_or("?", |(name, _)| name);
+                write!(f, "<{name}>")
+            }
+            Self::Command(command) => write!(f, ":{command}"),
+            #[cfg(feature = "lua")]
+            Self::Lua(_) => write!(f, "<lua function>"),
+        }
+    }
+}
+
+struct Binding {
+    keys: Vec<KeyEvent>,
+    action: Action,
+    description: Option<String>,
+}
+
+pub enum Lookup<'a> {
+    Exact(&'a Action),
+    // The keys start a longer binding, so wait for more.
+    Prefix,
+    None,
+}
+
+pub struct Keymaps {
+    bindings: HashMap<Mode, Vec<Binding>>,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        let mut keymaps = Self {
+            bindings: HashMap::new(),
+        };
+        for (modes, lhs, rhs, description) in DEFAULTS {
+            for mode in parse_modes(modes) {
+                keymaps
+                    .bind(mode, lhs, rhs, Some((*description).to_string()))
+                    .expect("default keymaps are valid");
+            }
+        }
+        keymaps
+    }
+}
+
+impl Keymaps {
+    pub fn bind(
+        &mut self,
+        mode: Mode,
+        lhs: &str,
+        rhs: &str,
+        description: Option<String>,
+    ) -> Result<(), String> {
+        self.insert(mode, lhs, Action::parse(rhs)?, description)
+    }
+    pub fn insert(
+        &mut self,
+        mode: Mode,
+        lhs: &str,
+        action: Action,
+        description: Option<String>,
+    ) -> Result<(), String> {
+        let keys = parse_keys(lhs)?;
+        if keys.is_empty() {
+            return Err("Argument required".to_string());
+        }
+        let bindings = self.bindings.entry(mode).or_default();
+        bindings.retain(|binding| binding.keys != keys);
+        bindings.push(Binding {
+            keys,
+            action,
+            description,
+        });
+        Ok(())
+    }
+    pub fn remove(&mut self, mode: Mode, lhs: &str) -> Result<(), String> {
+        let keys = parse_keys(lhs)?;
+        let bindings = self.bindings.entry(mode).or_default();
+        let count = bindings.len();
+        bindings.retain(|binding| binding.keys != keys);
+        if bindings.len() == count {
+            return Err(format!("No such mapping: {lhs}"));
+        }
+        Ok(())
+    }
+    pub fn lookup(&self, mode: Mode, keys: &[KeyEvent]) -> Lookup<'_> {
+        let bindings = self.bindings.get(&mode).map_or(&[][..], Vec::as_slice);
+        if bindings
+            .iter()
+            .any(|binding| binding.keys.len() > keys.len() && binding.keys.starts_with(keys))
+        {
+            return Lookup::Prefix;
+        }
+        self.exact(mode, keys).map_or(Lookup::None, Lookup::Exact)
+    }
+    pub fn exact(&self, mode: Mode, keys: &[KeyEvent]) -> Option<&Action> {
+        self.bindings
+            .get(&mode)?
+            .iter()
+            .find(|binding| binding.keys == keys)
+            .map(|binding| &binding.action)
+    }
+    // `lhs rhs  description` for each binding in `mode` whose keys start
+    // with `prefix`, sorted by keys.
+    pub fn list(&self, mode: Mode, prefix: &str) -> Vec<String> {
+        let prefix = parse_keys(prefix).unwrap_or_default();
+        let mut lines: Vec<String> = self
+            .bindings
+            .get(&mode)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter(|binding| binding.keys.starts_with(&prefix))
+            .map(|binding| {
+                let keys = keys_name(&binding.keys);
+                match &binding.description {
+                    Some(description) => format!("{keys} {}  {description}", binding.action),
+                    None => format!("{keys} {}", binding.action),
+                }
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+// `n`, `i`, `v` and `o` in any combination, as in the `:nmap`/`:imap`/
+// `:vmap` prefixes, with `o` for the motion after an operator. `:map`
+// covers normal and visual mode.
+pub fn parse_modes(modes: &str) -> Vec<Mode> {
+    modes
+        .chars()
+        .filter_map(|mode| match mode {
+            'n' => Some(Mode::Normal),
+            'i' => Some(Mode::Insert),
+            'v' => Some(Mode::Visual),
+            'o' => Some(Mode::OperatorPending),
+            _ => None,
+        })
+        .collect()
+}
+
+// Parses keys in vim notation: `<Esc>`, `<CR>`, `<BS>`, `<Tab>`, `<Space>`,
+// `<lt>`, the arrow keys and `<C-x>`; everything else stands for itself.
+// `<leader>` is `\` here; `expand_leader` puts in another `mapleader` first.
+pub fn parse_keys(keys: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        if let Some((name, after)) = rest.strip_prefix('<').and_then(|name| name.split_once('>')) {
+            if let Some(key) = special_key(name) {
+                events.push(key);
+                rest = after;
+                continue;
+            }
+        }
+        events.push(KeyEvent {
+            code: KeyCode::Char(c),
+            modifiers: KeyModifiers::NONE,
+        });
+        rest = &rest[c.len_utf8()..];
+    }
+    if events.is_empty() && !keys.is_empty() {
+        return Err(format!("Invalid keys: {keys}"));
+    }
+    Ok(events)
+}
+
+// Replaces `<leader>` in `keys` with `leader`, itself in key notation, the
+// way a mapping picks up `mapleader` when it is defined.
+pub fn expand_leader(keys: &str, leader: &str) -> String {
+    keys.replace("<leader>", leader).replace("<Leader>", leader)
+}
+
+// Terminals report Shift along with uppercase letters and some symbols;
+// bindings are stored without it so `A` matches however it was typed.
+pub fn normalize(key: KeyEvent) -> KeyEvent {
+    match key.code {
+        KeyCode::Char(_) => KeyEvent {
+            code: key.code,
+            modifiers: key.modifiers - KeyModifiers::SHIFT,
+        },
+        _ => key,
+    }
+}
+
+fn special_key(name: &str) -> Option<KeyEvent> {
+    let code = match name {
+        "Esc" => KeyCode::Esc,
+        "CR" | "Enter" => KeyCode::Enter,
+        "BS" => KeyCode::Backspace,
+        "Tab" => KeyCode::Tab,
+        "Space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "leader" | "Leader" => KeyCode::Char('\\'),
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        "Del" => KeyCode::Delete,
+        _ => {
+            let (modifiers, rest) = if let Some(rest) = name.strip_prefix("C-") {
+                (KeyModifiers::CONTROL, rest)
+            } else {
+                let rest = name
+                    .strip_prefix("A-")
+                    .or_else(|| name.strip_prefix("M-"))?;
+                (KeyModifiers::ALT, rest)
+            };
+            let mut chars = rest.chars();
+            let c = chars.next().filter(|_| chars.next().is_none())?;
+            return Some(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+            });
+        }
+    };
+    Some(KeyEvent {
+        code,
+        modifiers: KeyModifiers::NONE,
+    })
+}
+
+// `keys` in key notation, as `:map` lists them.
+pub fn keys_name(keys: &[KeyEvent]) -> String {
+    keys.iter().map(key_name).collect()
+}
+
+fn key_name(key: &KeyEvent) -> String {
+    match (key.code, key.modifiers) {
+        (KeyCode::Char(c), KeyModifiers::CONTROL) => format!("<C-{c}>"),
+        (KeyCode::Char(c), KeyModifiers::ALT) => format!("<A-{c}>"),
+        (KeyCode::Char(' '), _) => "<Space>".to_string(),
+        (KeyCode::Char('<'), _) => "<lt>".to_string(),
+        (KeyCode::Char(c), _) => c.to_string(),
+        (KeyCode::Esc, _) => "<Esc>".to_string(),
+        (KeyCode::Enter, _) => "<CR>".to_string(),
+        (KeyCode::Backspace, _) => "<BS>".to_string(),
+        (KeyCode::Tab, _) => "<Tab>".to_string(),
+        (KeyCode::Left, _) => "<Left>".to_string(),
+        (KeyCode::Right, _) => "<Right>".to_string(),
+        (KeyCode::Up, _) => "<Up>".to_string(),
+        (KeyCode::Down, _) => "<Down>".to_string(),
+        (KeyCode::Home, _) => "<Home>".to_string(),
+        (KeyCode::End, _) => "<End>".to_string(),
+        (KeyCode::PageUp, _) => "<PageUp>".to_string(),
+        (KeyCode::PageDown, _) => "<PageDown>".to_string(),
+        (KeyCode::Delete, _) => "<Del>".to_string(),
+        (code, _) => format!("{code:?}"),
+    }
+}
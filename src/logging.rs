@@ -0,0 +1,113 @@
+// `--log {file}` or `set logfile={file}`: a record of what the editor does,
+// appended to the file one line at a time as
+// `{milliseconds since start} {LEVEL} {area}: {message}`.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    // Every key, command and screen update.
+    Debug,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        })
+    }
+}
+
+struct Logger {
+    path: String,
+    file: File,
+    level: Level,
+    start: Instant,
+}
+
+// Shared with the threads that write files in the background.
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+// Starts logging to `path`, or stops with an empty one. Nothing changes if
+// it is the file being logged to already.
+pub fn open(path: &str, level: Level) -> io::Result<()> {
+    let Ok(mut logger) = LOGGER.lock() else {
+        return Ok(());
+    };
+    if let Some(current) = logger.as_mut().filter(|current| current.path == path) {
+        current.level = level;
+        return Ok(());
+    }
+    *logger = None;
+    if path.is_empty() {
+        return Ok(());
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *logger = Some(Logger {
+        path: path.to_string(),
+        file,
+        level,
+        start: Instant::now(),
+    });
+    Ok(())
+}
+
+// Whether anything at `level` is written, to skip building messages that
+// would not be.
+pub fn enabled(level: Level) -> bool {
+    LOGGER
+        .lock()
+        .is_ok_and(|logger| logger.as_ref().is_some_and(|logger| level <= logger.level))
+}
+
+pub fn log(level: Level, area: &str, message: &str) {
+    let Ok(mut logger) = LOGGER.lock() else {
+        return;
+    };
+    let Some(logger) = logger.as_mut().filter(|logger| level <= logger.level) else {
+        return;
+    };
+    let elapsed = logger.start.elapsed().as_millis();
+    let level = level.to_string().to_uppercase();
+    // a message over several lines stays on one
+    let message = message.replace('\n', "\\n");
+    let _ = writeln!(logger.file, "{elapsed} {level} {area}: {message}");
+}
+
+pub fn error(area: &str, message: &str) {
+    log(Level::Error, area, message);
+}
+
+pub fn warn(area: &str, message: &str) {
+    log(Level::Warn, area, message);
+}
+
+pub fn info(area: &str, message: &str) {
+    log(Level::Info, area, message);
+}
+
+pub fn debug(area: &str, message: &str) {
+    log(Level::Debug, area, message);
+}
@@ -0,0 +1,246 @@
+use crate::config;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::editor::Editor;
+use crate::keymap::{self, Action};
+use crate::semantic::{SemanticToken, TokenKind};
+use crate::Position;
+use mlua::{Function, Lua, RegistryKey, Result, Table, Value};
+use std::cell::RefCell;
+use std::fs;
+
+// Owns the interpreter together with everything scripts have registered.
+// The `rvim` table is rebuilt around every call into Lua so its functions
+// can borrow the editor for exactly as long as the script runs.
+pub struct Scripting {
+    lua: Lua,
+    // Functions bound with `rvim.keymap`, referred to by index from
+    // `Action::Lua`.
+    functions: RefCell<Vec<RegistryKey>>,
+    autocmds: RefCell<Vec<(String, RegistryKey)>>,
+}
+
+impl Scripting {
+    pub fn new() -> Self {
+        Self {
+            lua: Lua::new(),
+            functions: RefCell::new(Vec::new()),
+            autocmds: RefCell::new(Vec::new()),
+        }
+    }
+    pub fn load_init(&self, editor: &mut Editor) -> Result<()> {
+        if let Some(path) = config::config_dir().map(|dir| dir.join("init.lua")) {
+            if let Ok(source) = fs::read_to_string(&path) {
+                return self.exec(editor, &source, &path.to_string_lossy());
+            }
+        }
+        Ok(())
+    }
+    pub fn exec(&self, editor: &mut Editor, source: &str, name: &str) -> Result<()> {
+        self.with_api(editor, || self.lua.load(source).set_name(name).exec())
+    }
+    pub fn call(&self, editor: &mut Editor, function: usize) -> Result<()> {
+        let function: Function = match self.functions.borrow().get(function) {
+            Some(key) => self.lua.registry_value(key)?,
+            None => return Ok(()),
+        };
+        self.with_api(editor, || function.call(()))
+    }
+    pub fn fire(&self, editor: &mut Editor, event: &str) -> Result<()> {
+        let callbacks = self
+            .autocmds
+            .borrow()
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(event))
+            .map(|(_, key)| self.lua.registry_value(key))
+            .collect::<Result<Vec<Function>>>()?;
+        for callback in callbacks {
+            self.with_api(editor, || callback.call(event))?;
+        }
+        Ok(())
+    }
+
+    fn with_api<F>(&self, editor: &mut Editor, f: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let editor = RefCell::new(editor);
+        self.lua.scope(|scope| {
+            let rvim = self.lua.create_table()?;
+            rvim.set(
+                "command",
+                scope.create_function(|_, command: String| {
+                    editor.borrow_mut().execute(&command);
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "echo",
+                scope.create_function(|_, message: String| {
+                    editor.borrow_mut().show_message(message);
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "set_option",
+                scope.create_function(|_, (name, value): (String, Value)| {
+                    let value = match value {
+                        Value::String(value) => value.to_str()?.to_string(),
+                        Value::Integer(value) => value.to_string(),
+                        Value::Boolean(value) => value.to_string(),
+                        _ => return Err(mlua::Error::runtime("invalid option value")),
+                    };
+                    editor
+                        .borrow_mut()
+                        .options_mut()
+                        .set(&format!("{name}={value}"))
+                        .map_err(mlua::Error::runtime)
+                })?,
+            )?;
+            rvim.set(
+                "get_option",
+                scope
+                    .create_function(|_, name: String| Ok(editor.borrow().options().get(&name)))?,
+            )?;
+            rvim.set(
+                "keymap",
+                scope.create_function(
+                    |lua, (modes, lhs, rhs, description): (String, String, Value, Option<String>)| {
+                        let action = match rhs {
+                            // a bare ex command, as well as `:cmd` or `<action>`
+                            Value::String(rhs) => {
+                                let rhs = rhs.to_str()?;
+                                Action::parse(rhs).unwrap_or_else(|_| Action::Command(rhs.to_string()))
+                            }
+                            Value::Function(function) => {
+                                let mut functions = self.functions.borrow_mut();
+                                functions.push(lua.create_registry_value(function)?);
+                                Action::Lua(functions.len().saturating_sub(1))
+                            }
+                            _ => {
+                                return Err(mlua::Error::runtime(
+                                    "keymap target must be a string or function",
+                                ))
+                            }
+                        };
+                        let modes = keymap::parse_modes(&modes);
+                        if modes.is_empty() {
+                            return Err(mlua::Error::runtime("modes must be some of 'n', 'i', 'v' and 'o'"));
+                        }
+                        let mut editor = editor.borrow_mut();
+                        let lhs = keymap::expand_leader(&lhs, &editor.options().mapleader);
+                        for mode in modes {
+                            editor
+                                .keymaps_mut()
+                                .insert(mode, &lhs, action.clone(), description.clone())
+                                .map_err(mlua::Error::runtime)?;
+                        }
+                        Ok(())
+                    },
+                )?,
+            )?;
+            rvim.set(
+                "autocmd",
+                scope.create_function(|lua, (event, callback): (String, Function)| {
+                    let key = lua.create_registry_value(callback)?;
+                    self.autocmds.borrow_mut().push((event, key));
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "line_count",
+                scope.create_function(|_, ()| Ok(editor.borrow().document().len()))?,
+            )?;
+            rvim.set(
+                "get_lines",
+                scope.create_function(|lua, (start, end): (Option<usize>, Option<usize>)| {
+                    let editor = editor.borrow();
+                    let document = editor.document();
+                    let end = end.unwrap_or(document.len()).min(document.len());
+                    let lines = lua.create_table()?;
+                    for index in start.unwrap_or(0)..end {
+                        if let Some(row) = document.row(index) {
+                            lines.push(row.as_str())?;
+                        }
+                    }
+                    Ok(lines)
+                })?,
+            )?;
+            rvim.set(
+                "set_lines",
+                scope.create_function(|_, (start, end, lines): (usize, usize, Table)| {
+                    let lines = lines.sequence_values().collect::<Result<Vec<String>>>()?;
+                    editor.borrow_mut().replace_lines(start, end, &lines);
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "get_cursor",
+                scope.create_function(|_, ()| {
+                    let Position { x, y } = editor.borrow().cursor_position().clone();
+                    Ok((y.saturating_add(1), x))
+                })?,
+            )?;
+            rvim.set(
+                "set_cursor",
+                scope.create_function(|_, (line, column): (usize, usize)| {
+                    editor.borrow_mut().set_cursor_position(Position {
+                        x: column,
+                        y: line.saturating_sub(1),
+                    });
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "set_diagnostics",
+                scope.create_function(|_, diagnostics: Table| {
+                    let diagnostics = diagnostics
+                        .sequence_values::<Table>()
+                        .map(|diagnostic| {
+                            let diagnostic = diagnostic?;
+                            let line: usize = diagnostic.get("line")?;
+                            let severity = diagnostic
+                                .get::<_, Option<String>>("severity")?
+                                .map_or(Ok(Severity::Error), |name| Severity::parse(&name))
+                                .map_err(mlua::Error::runtime)?;
+                            Ok(Diagnostic {
+                                position: Position {
+                                    x: diagnostic.get::<_, Option<usize>>("column")?.unwrap_or(0),
+                                    y: line.saturating_sub(1),
+                                },
+                                severity,
+                                message: diagnostic
+                                    .get::<_, Option<String>>("message")?
+                                    .unwrap_or_default(),
+                            })
+                        })
+                        .collect::<Result<Vec<Diagnostic>>>()?;
+                    editor.borrow_mut().set_diagnostics(diagnostics);
+                    Ok(())
+                })?,
+            )?;
+            rvim.set(
+                "set_semantic_tokens",
+                scope.create_function(|_, tokens: Table| {
+                    let mut parsed = Vec::new();
+                    for token in tokens.sequence_values::<Table>() {
+                        let token = token?;
+                        let Some(kind) = TokenKind::parse(&token.get::<_, String>("type")?) else {
+                            continue;
+                        };
+                        let line: usize = token.get("line")?;
+                        parsed.push(SemanticToken {
+                            line: line.saturating_sub(1),
+                            column: token.get("column")?,
+                            length: token.get("length")?,
+                            kind,
+                        });
+                    }
+                    editor.borrow_mut().set_semantic_tokens(parsed);
+                    Ok(())
+                })?,
+            )?;
+            self.lua.globals().set("rvim", rvim)?;
+            f()
+        })
+    }
+}
@@ -1,27 +1,118 @@
-#![warn(clippy::all, clippy::pedantic, clippy::restriction)]
+#![warn(clippy::all, clippy::pedantic)]
 #![allow(
-    clippy::missing_docs_in_private_items,
-    clippy::implicit_return,
-    clippy::shadow_reuse,
-    clippy::print_stdout,
-    clippy::wildcard_enum_match_arm,
-    clippy::else_if_without_else
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::case_sensitive_file_extension_comparisons,
+    clippy::too_many_lines,
+    clippy::struct_excessive_bools
 )]
-mod document;
+mod align;
+mod arglist;
+mod bookmarks;
+mod cli;
+mod clipboard;
+mod cmdline;
+mod code_action;
+mod command;
+mod config;
+mod crash;
+mod dap;
+mod dashboard;
+mod debug;
+mod diagnostics;
 mod editor;
-mod filetype;
-mod highlighting;
-mod row;
+mod error;
+mod event;
+mod flash;
+mod format;
+mod formatter;
+mod git;
+#[cfg(test)]
+mod harness;
+mod health;
+mod highlighter;
+mod history;
+mod keymap;
+mod logging;
+#[cfg(feature = "lua")]
+mod lua;
+mod markdown;
+mod marks;
+mod oldfiles;
+mod options;
+mod pending;
+mod picker;
+mod popup;
+mod preview;
+mod register;
+mod repeat;
+#[cfg(unix)]
+mod rpc;
+mod search;
+mod semantic;
+mod shada;
+mod signature;
+mod startup;
+mod statusline;
+mod tags;
 mod terminal;
-pub use document::Document;
+mod theme;
+mod walk;
+mod wrap;
+pub use arglist::ArgList;
+use cli::Arguments;
+pub use command::Command;
 use editor::Editor;
-pub use editor::Position;
-pub use editor::SearchDirection;
-pub use filetype::FileType;
-pub use filetype::HighlightingOptions;
-pub use row::Row;
+pub use marks::Marks;
+pub use options::Options;
+pub use rvim_core::{Document, FileType, Pattern, Position, Row, SearchDirection, SearchFlags};
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
 pub use terminal::Terminal;
 
 fn main() {
-    Editor::default().run();
+    let arguments = Arguments::parse(env::args().skip(1));
+    #[cfg(unix)]
+    if let Some(wait) = arguments.remote {
+        match rpc::remote(arguments.server.as_deref(), &arguments.files, wait) {
+            Ok(()) => return,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                eprintln!("rvim: {error}, editing locally");
+            }
+            // the server was reached but could not edit the files
+            Err(error) => {
+                eprintln!("rvim: {error}");
+                process::exit(1);
+            }
+        }
+    }
+    let mut editor = match Editor::new(&arguments) {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("rvim: {error}");
+            process::exit(1);
+        }
+    };
+    crash::install_hook();
+    match panic::catch_unwind(AssertUnwindSafe(|| editor.run())) {
+        Ok(Ok(())) => (),
+        Ok(Err(error)) => {
+            eprintln!("rvim: {error}");
+            process::exit(1);
+        }
+        Err(_) => {
+            match editor.write_recovery_file() {
+                Some(Ok(path)) => eprintln!("rvim: unsaved changes were written to {path}"),
+                Some(Err(error)) => eprintln!("rvim: could not save unsaved changes: {error}"),
+                None => (),
+            }
+            process::exit(101);
+        }
+    }
 }
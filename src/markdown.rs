@@ -0,0 +1,229 @@
+// Markdown to HTML for `:Preview`: headings, paragraphs, block quotes,
+// lists, fenced code blocks, rules, and inline code, emphasis and links.
+// Anything else comes through as text.
+
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Block {
+    None,
+    Paragraph,
+    Quote,
+    List { ordered: bool },
+    Code,
+}
+
+impl Block {
+    fn close(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Paragraph => "</p>\n",
+            Self::Quote => "</p></blockquote>\n",
+            Self::List { ordered: false } => "</ul>\n",
+            Self::List { ordered: true } => "</ol>\n",
+            Self::Code => "</code></pre>\n",
+        }
+    }
+}
+
+pub fn to_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut block = Block::None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if block == Block::Code {
+            if trimmed.starts_with("```") {
+                html.push_str(block.close());
+                block = Block::None;
+            } else {
+                html.push_str(&escape(line));
+                html.push('\n');
+            }
+            continue;
+        }
+        let mut start = |html: &mut String, next: Block, open: &str| {
+            if block != next {
+                html.push_str(block.close());
+                html.push_str(open);
+                block = next;
+            } else if matches!(next, Block::Paragraph | Block::Quote) {
+                html.push('\n');
+            }
+        };
+        if let Some(language) = trimmed.strip_prefix("```") {
+            let language = language.trim();
+            if language.is_empty() {
+                start(&mut html, Block::Code, "<pre><code>");
+            } else {
+                let open = format!("<pre><code class=\"language-{}\">", escape(language));
+                start(&mut html, Block::Code, &open);
+            }
+        } else if trimmed.is_empty() {
+            start(&mut html, Block::None, "");
+        } else if let Some((level, title)) = heading(trimmed) {
+            start(&mut html, Block::None, "");
+            let _ = writeln!(html, "<h{level}>{}</h{level}>", inline(title));
+        } else if is_rule(trimmed) {
+            start(&mut html, Block::None, "");
+            html.push_str("<hr>\n");
+        } else if let Some((ordered, item)) = list_item(trimmed) {
+            let open = if ordered { "<ol>\n" } else { "<ul>\n" };
+            start(&mut html, Block::List { ordered }, open);
+            let _ = writeln!(html, "<li>{}</li>", inline(item));
+        } else if let Some(quoted) = trimmed.strip_prefix('>') {
+            start(&mut html, Block::Quote, "<blockquote><p>");
+            html.push_str(&inline(quoted.trim_start()));
+        } else {
+            start(&mut html, Block::Paragraph, "<p>");
+            html.push_str(&inline(trimmed));
+        }
+    }
+    html.push_str(block.close());
+    html
+}
+
+// `## Title` to (2, "Title").
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let title = line.get(level..)?;
+    if !(1..=6).contains(&level) || !(title.is_empty() || title.starts_with(' ')) {
+        return None;
+    }
+    Some((level, title.trim().trim_end_matches('#').trim_end()))
+}
+
+// `---`, `***` or `___`, possibly with spaces between.
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|mark| marks.chars().all(|c| c.to_string() == *mark))
+}
+
+// `- item`, `* item`, `+ item`, `1. item` or `1) item` to whether it is
+// numbered and the item.
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((false, item));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = line.get(digits..)?;
+    let item = rest
+        .strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))?;
+    (digits > 0).then_some((true, item))
+}
+
+// Inline markup: `code`, **strong**, *emphasis*, [links](url) and
+// backslash escapes. Underscores only count at the edges of words, so
+// snake_case stays as it is.
+fn inline(text: &str) -> String {
+    let mut html = String::new();
+    // the emphasis delimiters open so far, innermost last
+    let mut open: Vec<&str> = Vec::new();
+    let mut previous = ' ';
+    let mut rest = text;
+    'outer: while let Some(c) = rest.chars().next() {
+        let after = rest.get(c.len_utf8()..).unwrap_or_default();
+        if c == '\\' {
+            if let Some(escaped) = after.chars().next().filter(char::is_ascii_punctuation) {
+                html.push_str(&escape(&escaped.to_string()));
+                rest = after.get(1..).unwrap_or_default();
+                previous = escaped;
+                continue;
+            }
+        }
+        if c == '`' {
+            if let Some((code, after)) = after.split_once('`') {
+                let _ = write!(html, "<code>{}</code>", escape(code));
+                rest = after;
+                previous = '`';
+                continue;
+            }
+        }
+        if c == '[' {
+            if let Some((label, after)) = after.split_once("](") {
+                if let Some((url, after)) = after.split_once(')').filter(|(url, _)| is_safe(url)) {
+                    let _ = write!(html, "<a href=\"{}\">{}</a>", escape(url), inline(label));
+                    rest = after;
+                    previous = ')';
+                    continue;
+                }
+            }
+        }
+        for delimiter in ["**", "__", "*", "_"] {
+            let Some(after) = rest.strip_prefix(delimiter) else {
+                continue;
+            };
+            let tag = if delimiter.len() == 2 { "strong" } else { "em" };
+            let next = after.chars().next().unwrap_or(' ');
+            let underscore = delimiter.starts_with('_');
+            // an opening delimiter comes before a word and has a closing one
+            // after it
+            let blocked = open.contains(&delimiter)
+                || next.is_whitespace()
+                || (underscore && previous.is_alphanumeric());
+            if open.last() == Some(&delimiter) {
+                if !(underscore && next.is_alphanumeric()) {
+                    open.pop();
+                    let _ = write!(html, "</{tag}>");
+                    rest = after;
+                    previous = '*';
+                    continue 'outer;
+                }
+            } else if !blocked && after.contains(delimiter) {
+                open.push(delimiter);
+                let _ = write!(html, "<{tag}>");
+                rest = after;
+                previous = '*';
+                continue 'outer;
+            }
+            break;
+        }
+        html.push_str(&escape(&c.to_string()));
+        rest = after;
+        previous = c;
+    }
+    // an emphasis never closed ends with the line
+    for delimiter in open.iter().rev() {
+        html.push_str(if delimiter.len() == 2 {
+            "</strong>"
+        } else {
+            "</em>"
+        });
+    }
+    html
+}
+
+// Whether a link may go to `url`: one relative to the page, or an http,
+// https or mailto one. Others, such as `javascript:`, stay as text.
+fn is_safe(url: &str) -> bool {
+    // browsers skip blanks and control characters in the scheme
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    let scheme = url
+        .split(['/', '?', '#'])
+        .next()
+        .and_then(|start| start.split_once(':'))
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    scheme.is_none_or(|scheme| ["http", "https", "mailto"].contains(&scheme.as_str()))
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,66 @@
+use crate::Position;
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct GlobalMark {
+    pub file_name: String,
+    pub position: Position,
+}
+
+// How many files the cursor position is remembered for.
+const LAST_POSITIONS: usize = 100;
+
+// Uppercase marks remember the file they were set in, so they survive
+// switching to another file and can bring the editor back to it.
+#[derive(Default)]
+pub struct Marks {
+    global: HashMap<char, GlobalMark>,
+    // Where the cursor was when each file was last left, by full path, the
+    // latest last.
+    last_positions: Vec<(String, Position)>,
+}
+
+impl Marks {
+    pub fn is_global(name: char) -> bool {
+        name.is_ascii_uppercase()
+    }
+    pub fn set_global(&mut self, name: char, file_name: &str, position: &Position) {
+        self.global.insert(
+            name,
+            GlobalMark {
+                file_name: file_name.to_string(),
+                position: position.clone(),
+            },
+        );
+    }
+    pub fn global(&self, name: char) -> Option<&GlobalMark> {
+        self.global.get(&name)
+    }
+    // Every global mark, by name.
+    pub fn globals(&self) -> Vec<(char, &GlobalMark)> {
+        let mut globals: Vec<_> = self
+            .global
+            .iter()
+            .map(|(&name, mark)| (name, mark))
+            .collect();
+        globals.sort_unstable_by_key(|(name, _)| *name);
+        globals
+    }
+    pub fn set_last_position(&mut self, file_name: &str, position: &Position) {
+        self.last_positions.retain(|(file, _)| file != file_name);
+        self.last_positions
+            .push((file_name.to_string(), position.clone()));
+        let extra = self.last_positions.len().saturating_sub(LAST_POSITIONS);
+        self.last_positions.drain(..extra);
+    }
+    pub fn last_position(&self, file_name: &str) -> Option<&Position> {
+        self.last_positions
+            .iter()
+            .rev()
+            .find(|(file, _)| file == file_name)
+            .map(|(_, position)| position)
+    }
+    pub fn last_positions(&self) -> &[(String, Position)] {
+        &self.last_positions
+    }
+}
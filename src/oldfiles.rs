@@ -0,0 +1,63 @@
+// The files edited most recently, newest first, kept between runs in
+// `oldfiles` in the state directory, one full path per line.
+
+use crate::config;
+use std::fs;
+use std::path::PathBuf;
+
+// How many files are remembered.
+const LIMIT: usize = 100;
+
+#[derive(Default)]
+pub struct OldFiles {
+    files: Vec<String>,
+    // Where the list is written as it changes; None keeps it in memory, as
+    // in tests.
+    path: Option<PathBuf>,
+}
+
+impl OldFiles {
+    // A missing or unreadable file starts an empty list.
+    pub fn load(path: PathBuf) -> Self {
+        let files = fs::read_to_string(&path)
+            .map(|text| {
+                text.lines()
+                    .filter(|line| !line.is_empty())
+                    .take(LIMIT)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            files,
+            path: Some(path),
+        }
+    }
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+    // Moves `file_name` to the front, by its full path.
+    pub fn add(&mut self, file_name: &str) {
+        let path = config::full_path(file_name);
+        if self.files.first() == Some(&path) {
+            return;
+        }
+        self.files.retain(|file| *file != path);
+        self.files.insert(0, path);
+        self.files.truncate(LIMIT);
+        self.save();
+    }
+    // Failing to write only loses the list for the next run, so it is not
+    // worth interrupting the edit for.
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let mut text = self.files.join("\n");
+        text.push('\n');
+        let _ = fs::write(path, text);
+    }
+}
@@ -0,0 +1,560 @@
+use crate::clipboard;
+use crate::diagnostics;
+use crate::logging::Level;
+use std::fmt;
+use std::mem;
+
+// What file types need set differently to start with, applied over `:set`
+// and under `:ftset`: recipes in a Makefile have to start with a tab, and
+// gofmt indents with them.
+const FILE_TYPE_DEFAULTS: [(&str, &str); 2] = [("Make", "noexpandtab"), ("Golang", "noexpandtab")];
+
+pub struct Options {
+    pub scrolloff: usize,
+    // The most screen updates per second; 0 for no limit.
+    pub maxfps: usize,
+    // The command being typed in normal mode is shown at the right of the
+    // message line.
+    pub showcmd: bool,
+    pub listchars: ListChars,
+    // Long lines continue on the next screen line instead of running off
+    // the right edge.
+    pub wrap: bool,
+    // `j` and `k` move by screen line, like `gj` and `gk`, when wrapping.
+    pub displaymotion: bool,
+    // Drawn at the start of each continuation of a wrapped line.
+    pub showbreak: String,
+    // Continuations of a wrapped line are indented as far as the line is.
+    pub breakindent: bool,
+    // Lines typed past this many columns are broken at a blank; 0 for off.
+    pub textwidth: usize,
+    // Tab in insert mode indents with spaces, to the next multiple of
+    // `shiftwidth`, rather than a tab.
+    pub expandtab: bool,
+    pub shiftwidth: usize,
+    // Which lines `textwidth` breaks: `t` for text, `c` for comments.
+    pub formatoptions: String,
+    pub diagnostics: diagnostics::Display,
+    // The command `:DebugStart` runs as the debug adapter; empty for none.
+    pub debugadapter: String,
+    // What `<leader>` stands for in mappings defined after it is set, in
+    // key notation.
+    pub mapleader: String,
+    // Strip trailing whitespace from the lines changed since the file was
+    // last written, on `:w`.
+    pub trim_trailing_whitespace: bool,
+    // End the file with a newline on `:w` even if it did not have one.
+    pub ensure_final_newline: bool,
+    // Commands the buffer is piped through on `:w`, one after another,
+    // separated by `|`; empty for none.
+    pub formatters: String,
+    // How long each formatter may take, in milliseconds.
+    pub formattimeout: usize,
+    // How many milliseconds keys that start a longer binding wait for the
+    // rest of it before they count on their own; with `notimeout` they wait
+    // for the next key however long it takes.
+    pub timeout: bool,
+    pub timeoutlen: usize,
+    // How many milliseconds a lone Esc waits for a key right behind it,
+    // which makes the two one key with Alt held; 0, the default, for never,
+    // as crossterm already joins the two when they arrive together.
+    pub ttimeoutlen: usize,
+    // Where to log what the editor does, and how much; see `logging`.
+    pub logfile: String,
+    pub loglevel: Level,
+    // `:Files` lists hidden files too.
+    pub walkhidden: bool,
+    // `:Files` leaves out files larger than this many KiB; 0 for no limit.
+    pub walkmaxsize: usize,
+    pub confirm: Confirm,
+    // Opening another file keeps a buffer with unsaved changes loaded
+    // rather than refusing to leave it.
+    pub hidden: bool,
+    // Starting without a file shows the recently edited files to open.
+    pub dashboard: bool,
+    pub virtualedit: VirtualEdit,
+    // Lines moved with `:m`, `<A-j>` and `<A-k>` take the indentation of
+    // the line they end up under.
+    pub moveindent: bool,
+    pub clipboard: clipboard::Provider,
+    pub undogroup: UndoGroup,
+    // Every `:set` so far, and the `:ftset` overrides by file type, replayed
+    // in that order when the file type changes.
+    assignments: Vec<String>,
+    overrides: Vec<(String, String)>,
+    file_type: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scrolloff: 0,
+            maxfps: 60,
+            showcmd: true,
+            listchars: ListChars::default(),
+            wrap: false,
+            displaymotion: false,
+            showbreak: String::new(),
+            breakindent: false,
+            textwidth: 0,
+            expandtab: true,
+            shiftwidth: 4,
+            formatoptions: "tc".to_string(),
+            diagnostics: diagnostics::Display::Inline,
+            debugadapter: String::new(),
+            mapleader: "\\".to_string(),
+            trim_trailing_whitespace: false,
+            ensure_final_newline: true,
+            formatters: String::new(),
+            formattimeout: 2000,
+            timeout: true,
+            timeoutlen: 1000,
+            ttimeoutlen: 0,
+            logfile: String::new(),
+            loglevel: Level::Info,
+            walkhidden: false,
+            walkmaxsize: 1024,
+            confirm: Confirm::Never,
+            hidden: false,
+            dashboard: true,
+            virtualedit: VirtualEdit::default(),
+            moveindent: false,
+            clipboard: clipboard::Provider::Auto,
+            undogroup: UndoGroup::Insert,
+            assignments: Vec::new(),
+            overrides: Vec::new(),
+            file_type: String::new(),
+        }
+    }
+}
+
+// `confirm`: what quitting does when there are unsaved changes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Confirm {
+    // Refuses, with a warning.
+    Never,
+    // Asks whether to save them, throw them away or not quit after all.
+    Dialog,
+    // Quits on this many quit commands in a row, with no change between.
+    Presses(usize),
+}
+
+impl Confirm {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "never" => Ok(Self::Never),
+            "dialog" => Ok(Self::Dialog),
+            _ => match value.parse() {
+                Ok(presses) if presses > 0 => Ok(Self::Presses(presses)),
+                _ => Err(format!("Invalid argument: confirm={value}")),
+            },
+        }
+    }
+}
+
+// `undogroup`: how much of what is typed in insert mode `u` undoes at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UndoGroup {
+    // Everything typed in one visit to insert mode.
+    Insert,
+    // Each word, with the blanks before it.
+    Word,
+    // What is typed without stopping for this many seconds.
+    Pause(usize),
+}
+
+impl UndoGroup {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "insert" => Ok(Self::Insert),
+            "word" => Ok(Self::Word),
+            _ => match value.parse() {
+                Ok(seconds) if seconds > 0 => Ok(Self::Pause(seconds)),
+                _ => Err(format!("Invalid argument: undogroup={value}")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for UndoGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Insert => write!(f, "insert"),
+            Self::Word => write!(f, "word"),
+            Self::Pause(seconds) => write!(f, "{seconds}"),
+        }
+    }
+}
+
+// `virtualedit`: where the cursor may go past the end of a line, from a
+// comma-separated list, or none when empty.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct VirtualEdit {
+    // Anywhere in visual mode.
+    pub block: bool,
+    // One past the last character in normal mode, as in insert mode.
+    pub onemore: bool,
+    // Anywhere in every mode.
+    pub all: bool,
+}
+
+impl VirtualEdit {
+    fn parse(value: &str) -> Result<Self, String> {
+        let mut virtualedit = Self::default();
+        for part in value.split(',').filter(|part| !part.is_empty()) {
+            match part {
+                "block" => virtualedit.block = true,
+                "onemore" => virtualedit.onemore = true,
+                "all" => virtualedit.all = true,
+                "none" => (),
+                _ => return Err(format!("Invalid argument: virtualedit={value}")),
+            }
+        }
+        Ok(virtualedit)
+    }
+}
+
+impl fmt::Display for VirtualEdit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<&str> = [
+            (self.block, "block"),
+            (self.onemore, "onemore"),
+            (self.all, "all"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+impl fmt::Display for Confirm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::Dialog => write!(f, "dialog"),
+            Self::Presses(presses) => write!(f, "{presses}"),
+        }
+    }
+}
+
+// `listchars`: what marks a line that goes on past the left or right edge
+// of the screen, as in `extends:>,precedes:<`.
+#[derive(Clone, Copy)]
+pub struct ListChars {
+    pub extends: Option<char>,
+    pub precedes: Option<char>,
+}
+
+impl Default for ListChars {
+    fn default() -> Self {
+        Self {
+            extends: Some('>'),
+            precedes: Some('<'),
+        }
+    }
+}
+
+impl ListChars {
+    fn parse(value: &str) -> Result<Self, String> {
+        let mut listchars = Self {
+            extends: None,
+            precedes: None,
+        };
+        for item in value.split(',').filter(|item| !item.is_empty()) {
+            let (name, marker) = item
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid argument: listchars={value}"))?;
+            let mut chars = marker.chars();
+            let (Some(marker), None) = (chars.next(), chars.next()) else {
+                return Err(format!("Invalid argument: listchars={value}"));
+            };
+            match name {
+                "extends" => listchars.extends = Some(marker),
+                "precedes" => listchars.precedes = Some(marker),
+                _ => return Err(format!("Invalid argument: listchars={value}")),
+            }
+        }
+        Ok(listchars)
+    }
+}
+
+impl fmt::Display for ListChars {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let items: Vec<String> = [("extends", self.extends), ("precedes", self.precedes)]
+            .iter()
+            .filter_map(|(name, marker)| marker.map(|marker| format!("{name}:{marker}")))
+            .collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+impl Options {
+    // Accepts the `name=value` form used by `:set`, and `name` or `noname`
+    // for on/off options. Overrides for the current file type still win.
+    pub fn set(&mut self, assignment: &str) -> Result<(), String> {
+        self.apply(assignment)?;
+        self.assignments.push(assignment.to_string());
+        for (file_type, assignment) in &self.overrides.clone() {
+            if file_type.eq_ignore_ascii_case(&self.file_type) {
+                let _ = self.apply(assignment);
+            }
+        }
+        Ok(())
+    }
+    // `:ftset {filetype} {assignment}`: `assignment` applies only while
+    // editing files of `file_type`, such as `Markdown`.
+    pub fn set_for(&mut self, file_type: &str, assignment: &str) -> Result<(), String> {
+        Self::default().apply(assignment)?;
+        self.overrides
+            .push((file_type.to_string(), assignment.to_string()));
+        if file_type.eq_ignore_ascii_case(&self.file_type) {
+            self.apply(assignment)?;
+        }
+        Ok(())
+    }
+    // Switches to the options for editing a file of `file_type`: the global
+    // ones with its overrides on top.
+    pub fn use_file_type(&mut self, file_type: &str) {
+        let assignments = mem::take(&mut self.assignments);
+        let overrides = mem::take(&mut self.overrides);
+        *self = Self::default();
+        for assignment in &assignments {
+            let _ = self.apply(assignment);
+        }
+        for (other, assignment) in FILE_TYPE_DEFAULTS {
+            if other.eq_ignore_ascii_case(file_type) {
+                let _ = self.apply(assignment);
+            }
+        }
+        for (other, assignment) in &overrides {
+            if other.eq_ignore_ascii_case(file_type) {
+                let _ = self.apply(assignment);
+            }
+        }
+        self.assignments = assignments;
+        self.overrides = overrides;
+        file_type.clone_into(&mut self.file_type);
+    }
+    fn apply(&mut self, assignment: &str) -> Result<(), String> {
+        let (name, value) = assignment
+            .split_once('=')
+            .map_or((assignment, None), |(name, value)| (name, Some(value)));
+        match (name, value) {
+            ("scrolloff" | "so", Some(value)) => {
+                self.scrolloff = parse_number(name, value)?;
+                Ok(())
+            }
+            ("maxfps", Some(value)) => {
+                self.maxfps = parse_number(name, value)?;
+                Ok(())
+            }
+            ("listchars" | "lcs", Some(value)) => {
+                self.listchars = ListChars::parse(value)?;
+                Ok(())
+            }
+            ("formatters", Some(value)) => {
+                value.clone_into(&mut self.formatters);
+                Ok(())
+            }
+            ("timeoutlen" | "tm", Some(value)) => {
+                self.timeoutlen = parse_number(name, value)?;
+                Ok(())
+            }
+            ("ttimeoutlen" | "ttm", Some(value)) => {
+                self.ttimeoutlen = parse_number(name, value)?;
+                Ok(())
+            }
+            ("timeout" | "to" | "notimeout" | "noto", None) => {
+                self.timeout = !name.starts_with("no");
+                Ok(())
+            }
+            ("formattimeout", Some(value)) => {
+                self.formattimeout = parse_number(name, value)?;
+                Ok(())
+            }
+            ("logfile", Some(value)) => {
+                value.clone_into(&mut self.logfile);
+                Ok(())
+            }
+            ("loglevel", Some(value)) => {
+                self.loglevel =
+                    Level::parse(value).ok_or_else(|| format!("Invalid argument: {assignment}"))?;
+                Ok(())
+            }
+            ("virtualedit" | "ve", Some(value)) => {
+                self.virtualedit = VirtualEdit::parse(value)?;
+                Ok(())
+            }
+            ("undogroup" | "ug", Some(value)) => {
+                self.undogroup = UndoGroup::parse(value)?;
+                Ok(())
+            }
+            ("confirm", Some(value)) => {
+                self.confirm = Confirm::parse(value)?;
+                Ok(())
+            }
+            ("hidden" | "hid" | "nohidden" | "nohid", None) => {
+                self.hidden = !name.starts_with("no");
+                Ok(())
+            }
+            ("dashboard" | "nodashboard", None) => {
+                self.dashboard = name == "dashboard";
+                Ok(())
+            }
+            ("moveindent" | "nomoveindent", None) => {
+                self.moveindent = name == "moveindent";
+                Ok(())
+            }
+            ("walkhidden" | "nowalkhidden", None) => {
+                self.walkhidden = !name.starts_with("no");
+                Ok(())
+            }
+            ("walkmaxsize", Some(value)) => {
+                self.walkmaxsize = parse_number(name, value)?;
+                Ok(())
+            }
+            ("showcmd" | "sc" | "noshowcmd" | "nosc", None) => {
+                self.showcmd = !name.starts_with("no");
+                Ok(())
+            }
+            ("wrap" | "nowrap", None) => {
+                self.wrap = name == "wrap";
+                Ok(())
+            }
+            ("displaymotion" | "nodisplaymotion", None) => {
+                self.displaymotion = name == "displaymotion";
+                Ok(())
+            }
+            ("textwidth" | "tw", Some(value)) => {
+                self.textwidth = parse_number(name, value)?;
+                Ok(())
+            }
+            ("expandtab" | "et" | "noexpandtab" | "noet", None) => {
+                self.expandtab = !name.starts_with("no");
+                Ok(())
+            }
+            ("shiftwidth" | "sw", Some(value)) => {
+                match parse_number(name, value)? {
+                    0 => return Err(format!("Invalid argument: {assignment}")),
+                    width => self.shiftwidth = width,
+                }
+                Ok(())
+            }
+            ("formatoptions" | "fo", Some(value)) => {
+                self.formatoptions = value.to_string();
+                Ok(())
+            }
+            ("clipboard" | "cb", Some(value)) => {
+                self.clipboard = clipboard::Provider::parse(value)?;
+                Ok(())
+            }
+            ("diagnostics", Some(value)) => {
+                self.diagnostics = diagnostics::Display::parse(value)?;
+                Ok(())
+            }
+            ("debugadapter", Some(value)) => {
+                self.debugadapter = value.to_string();
+                Ok(())
+            }
+            ("mapleader", Some(value)) => {
+                if value.is_empty() {
+                    return Err(format!("Invalid argument: {assignment}"));
+                }
+                self.mapleader = value.to_string();
+                Ok(())
+            }
+            ("showbreak" | "sbr", Some(value)) => {
+                self.showbreak = value.to_string();
+                Ok(())
+            }
+            ("breakindent" | "bri" | "nobreakindent" | "nobri", None) => {
+                self.breakindent = !name.starts_with("no");
+                Ok(())
+            }
+            ("trim_trailing_whitespace" | "notrim_trailing_whitespace", None) => {
+                self.trim_trailing_whitespace = !name.starts_with("no");
+                Ok(())
+            }
+            ("ensure_final_newline" | "noensure_final_newline", None) => {
+                self.ensure_final_newline = !name.starts_with("no");
+                Ok(())
+            }
+            (
+                "wrap"
+                | "displaymotion"
+                | "breakindent"
+                | "bri"
+                | "showcmd"
+                | "sc"
+                | "trim_trailing_whitespace"
+                | "ensure_final_newline"
+                | "walkhidden"
+                | "timeout"
+                | "to"
+                | "hidden"
+                | "hid"
+                | "dashboard"
+                | "moveindent"
+                | "expandtab"
+                | "et",
+                Some(_),
+            ) => Err(format!("Invalid argument: {assignment}")),
+            (
+                "scrolloff" | "so" | "maxfps" | "listchars" | "lcs" | "showbreak" | "sbr"
+                | "textwidth" | "tw" | "formatoptions" | "fo" | "diagnostics" | "debugadapter"
+                | "mapleader" | "formatters" | "formattimeout" | "timeoutlen" | "tm"
+                | "ttimeoutlen" | "ttm" | "virtualedit" | "ve" | "walkmaxsize" | "logfile"
+                | "loglevel" | "confirm" | "filetype" | "ft" | "shiftwidth" | "sw" | "clipboard"
+                | "cb" | "undogroup" | "ug",
+                None,
+            ) => Err(format!("Argument required: {name}")),
+            _ => Err(format!("Unknown option: {name}")),
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "filetype" | "ft" => Some(self.file_type.clone()),
+            "scrolloff" | "so" => Some(self.scrolloff.to_string()),
+            "maxfps" => Some(self.maxfps.to_string()),
+            "showcmd" | "sc" => Some(self.showcmd.to_string()),
+            "listchars" | "lcs" => Some(self.listchars.to_string()),
+            "wrap" => Some(self.wrap.to_string()),
+            "displaymotion" => Some(self.displaymotion.to_string()),
+            "showbreak" | "sbr" => Some(self.showbreak.clone()),
+            "breakindent" | "bri" => Some(self.breakindent.to_string()),
+            "textwidth" | "tw" => Some(self.textwidth.to_string()),
+            "expandtab" | "et" => Some(self.expandtab.to_string()),
+            "shiftwidth" | "sw" => Some(self.shiftwidth.to_string()),
+            "formatoptions" | "fo" => Some(self.formatoptions.clone()),
+            "diagnostics" => Some(self.diagnostics.to_string()),
+            "debugadapter" => Some(self.debugadapter.clone()),
+            "mapleader" => Some(self.mapleader.clone()),
+            "trim_trailing_whitespace" => Some(self.trim_trailing_whitespace.to_string()),
+            "ensure_final_newline" => Some(self.ensure_final_newline.to_string()),
+            "formatters" => Some(self.formatters.clone()),
+            "formattimeout" => Some(self.formattimeout.to_string()),
+            "timeout" | "to" => Some(self.timeout.to_string()),
+            "timeoutlen" | "tm" => Some(self.timeoutlen.to_string()),
+            "ttimeoutlen" | "ttm" => Some(self.ttimeoutlen.to_string()),
+            "logfile" => Some(self.logfile.clone()),
+            "loglevel" => Some(self.loglevel.to_string()),
+            "walkhidden" => Some(self.walkhidden.to_string()),
+            "walkmaxsize" => Some(self.walkmaxsize.to_string()),
+            "confirm" => Some(self.confirm.to_string()),
+            "hidden" | "hid" => Some(self.hidden.to_string()),
+            "dashboard" => Some(self.dashboard.to_string()),
+            "moveindent" => Some(self.moveindent.to_string()),
+            "virtualedit" | "ve" => Some(self.virtualedit.to_string()),
+            "clipboard" | "cb" => Some(self.clipboard.to_string()),
+            "undogroup" | "ug" => Some(self.undogroup.to_string()),
+            _ => None,
+        }
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Number required after =: {name}={value}"))
+}
@@ -0,0 +1,183 @@
+// A normal mode command part way through being typed, a key at a time: a
+// count waits for the command it repeats, `m`, `'` and `` ` `` for the name
+// of a mark, `"` for the name of a register and an operator such as `d` for
+// what it acts on.
+
+use crate::Position;
+use rvim_core::textobject::Extent;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operator {
+    Delete,
+    Yank,
+    // Deletes, then starts insert mode in its place.
+    Change,
+    // Shift whole lines by `shiftwidth`.
+    Indent,
+    Outdent,
+    ToggleCase,
+}
+
+impl Operator {
+    // The key that starts it, and typed again, applies it to whole lines.
+    pub fn key(self) -> char {
+        match self {
+            Self::Delete => 'd',
+            Self::Yank => 'y',
+            Self::Change => 'c',
+            Self::Indent => '>',
+            Self::Outdent => '<',
+            Self::ToggleCase => '~',
+        }
+    }
+}
+
+// The text an operator acts on: from `start` up to but not including `end`,
+// or when `linewise` the lines from `start`'s to `end`'s whole.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+    pub linewise: bool,
+}
+
+impl Span {
+    pub fn characters(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+    pub fn lines(first: usize, last: usize) -> Self {
+        Self {
+            start: Position { x: 0, y: first },
+            end: Position { x: 0, y: last },
+            linewise: true,
+        }
+    }
+}
+
+impl From<Extent> for Span {
+    fn from(extent: Extent) -> Self {
+        Self {
+            start: extent.start,
+            end: extent.end,
+            linewise: extent.linewise,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pending {
+    // Waiting for a command, after the count and register typed so far,
+    // if any.
+    Command {
+        count: Option<usize>,
+        register: Option<char>,
+    },
+    // The key typed, `m`, `'` or `` ` ``; the next one names the mark.
+    Mark(char),
+    // After `"`, with the count typed before it; the next key names the
+    // register.
+    Register {
+        count: Option<usize>,
+    },
+    // Waiting for what `operator` acts on, with the count typed before the
+    // operator and the one typed after it.
+    Operator {
+        operator: Operator,
+        count: Option<usize>,
+        motion_count: Option<usize>,
+        register: Option<char>,
+    },
+}
+
+impl Default for Pending {
+    fn default() -> Self {
+        Self::Command {
+            count: None,
+            register: None,
+        }
+    }
+}
+
+impl Pending {
+    // Whether `c` goes on the count being typed: not when it is not a
+    // digit, or a `0` before any other digit, or the next key names a mark.
+    pub fn takes_digit(&self, c: char) -> bool {
+        match self {
+            Self::Command { count, .. }
+            | Self::Operator {
+                motion_count: count,
+                ..
+            } => c.is_ascii_digit() && (count.is_some() || c != '0'),
+            Self::Mark(_) | Self::Register { .. } => false,
+        }
+    }
+    pub fn push_digit(&mut self, c: char) {
+        let (Self::Command { count, .. }
+        | Self::Operator {
+            motion_count: count,
+            ..
+        }) = self
+        else {
+            return;
+        };
+        let digit = c.to_digit(10).map_or(0, |digit| digit as usize);
+        *count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+    }
+    // The count for the command; for an operator, the counts before and
+    // after it multiplied, as `2d3d` deletes six lines.
+    pub fn count(&self) -> Option<usize> {
+        match *self {
+            Self::Command { count, .. } | Self::Register { count } => count,
+            Self::Mark(_) => None,
+            Self::Operator {
+                count,
+                motion_count,
+                ..
+            } => match (count, motion_count) {
+                (Some(count), Some(motion_count)) => Some(count.saturating_mul(motion_count)),
+                (count, motion_count) => count.or(motion_count),
+            },
+        }
+    }
+    // The register named for the command, if any.
+    pub fn register(&self) -> Option<char> {
+        match *self {
+            Self::Command { register, .. } | Self::Operator { register, .. } => register,
+            Self::Mark(_) | Self::Register { .. } => None,
+        }
+    }
+    // The keys typed so far, for `showcmd`. The count before a register
+    // shows after it, as it counts the same.
+    pub fn typed(&self) -> String {
+        let count = |count: Option<usize>| count.map(|count| count.to_string()).unwrap_or_default();
+        let register = |register: Option<char>| {
+            register
+                .map(|register| format!("\"{register}"))
+                .unwrap_or_default()
+        };
+        match *self {
+            Self::Command {
+                count: typed,
+                register: name,
+            } => format!("{}{}", register(name), count(typed)),
+            Self::Mark(key) => key.to_string(),
+            Self::Register { count: typed } => format!("{}\"", count(typed)),
+            Self::Operator {
+                operator,
+                count: before,
+                motion_count,
+                register: name,
+            } => format!(
+                "{}{}{}{}",
+                register(name),
+                count(before),
+                operator.key(),
+                count(motion_count)
+            ),
+        }
+    }
+}
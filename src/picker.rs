@@ -0,0 +1,90 @@
+use crate::popup::{Item, Popup};
+
+// A list of choices narrowed down by typing: the items whose text has the
+// typed characters in order, best matches first. The editor reads the query
+// on the message line and draws the popup above it.
+pub struct Picker {
+    items: Vec<Item>,
+    // Indexes into `items` of those the query matches, in the order shown.
+    matches: Vec<usize>,
+    pub popup: Popup,
+}
+
+impl Picker {
+    // Shows every item, in order, with the one at `selected` selected.
+    pub fn new(items: Vec<Item>, selected: usize) -> Self {
+        let mut picker = Self {
+            matches: (0..items.len()).collect(),
+            popup: Popup::new(items.clone()),
+            items,
+        };
+        let last = picker.matches.len().checked_sub(1);
+        picker.popup.select(last.map(|last| selected.min(last)));
+        picker
+    }
+    pub fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(usize, usize, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let text = item.text.trim_start();
+                score(query, text).map(|score| (score, text.chars().count(), index))
+            })
+            .collect();
+        // with nothing typed they stay in their own order
+        if !query.is_empty() {
+            scored.sort_unstable();
+        }
+        self.matches = scored.into_iter().map(|(_, _, index)| index).collect();
+        let items = self
+            .matches
+            .iter()
+            .filter_map(|&index| self.items.get(index).cloned())
+            .collect();
+        self.popup = Popup::new(items);
+        self.popup.select(self.first());
+    }
+    fn first(&self) -> Option<usize> {
+        (!self.matches.is_empty()).then_some(0)
+    }
+    // Moves down the matches, wrapping around to the first.
+    pub fn select_next(&mut self) {
+        let next = self
+            .popup
+            .selected_index()
+            .map(|index| index.saturating_add(1))
+            .filter(|&next| next < self.matches.len());
+        self.popup.select(next.or_else(|| self.first()));
+    }
+    // Moves up the matches, wrapping around to the last.
+    pub fn select_previous(&mut self) {
+        let previous = self
+            .popup
+            .selected_index()
+            .and_then(|index| index.checked_sub(1))
+            .or_else(|| self.matches.len().checked_sub(1));
+        self.popup.select(previous);
+    }
+    // The index of the chosen item in those it was made with.
+    pub fn selected(&self) -> Option<usize> {
+        self.popup
+            .selected_index()
+            .and_then(|index| self.matches.get(index).copied())
+    }
+}
+
+// How well `query` matches `text`: None unless its characters all appear in
+// order, ignoring case, and otherwise lower for matches that start earlier
+// and have fewer characters between them.
+pub fn score(query: &str, text: &str) -> Option<usize> {
+    let mut chars = text.chars().enumerate();
+    let mut score = 0_usize;
+    let mut next = 0_usize;
+    for wanted in query.chars() {
+        let (index, _) = chars.find(|(_, c)| c.to_lowercase().eq(wanted.to_lowercase()))?;
+        score = score.saturating_add(index.saturating_sub(next));
+        next = index.saturating_add(1);
+    }
+    Some(score)
+}
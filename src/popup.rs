@@ -0,0 +1,197 @@
+use crate::theme::Style;
+use crate::Position;
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
+use std::fmt::Write;
+
+// The most items shown at once; the rest scroll into view.
+const MAX_HEIGHT: usize = 10;
+
+#[derive(Clone)]
+pub struct Item {
+    pub text: String,
+    // Shown in a column of its own after the text, such as where it came from.
+    pub detail: String,
+}
+
+// A menu drawn over the text next to the cursor, such as the choices for
+// completing a word. It keeps the items and which one is selected; the
+// editor places it with `place` and draws its `lines` with `overlay`.
+pub struct Popup {
+    items: Vec<Item>,
+    // None while what was there before the menu opened is kept.
+    selected: Option<usize>,
+    // The first item shown when they do not all fit.
+    top: usize,
+}
+
+impl Popup {
+    pub fn new(items: Vec<Item>) -> Self {
+        Self {
+            items,
+            selected: None,
+            top: 0,
+        }
+    }
+    pub fn selected(&self) -> Option<&Item> {
+        self.selected.and_then(|index| self.items.get(index))
+    }
+    pub fn selected_index(&self) -> Option<usize> {
+        self.selected
+    }
+    // Moves down the items, then past the last back to none selected.
+    pub fn select_next(&mut self) {
+        self.select(match self.selected {
+            None => (!self.items.is_empty()).then_some(0),
+            Some(index) => Some(index.saturating_add(1)).filter(|&next| next < self.items.len()),
+        });
+    }
+    // Moves up the items, then past the first back to none selected.
+    pub fn select_previous(&mut self) {
+        self.select(match self.selected {
+            None => self.items.len().checked_sub(1),
+            Some(index) => index.checked_sub(1),
+        });
+    }
+    // Selects an item, scrolling it into view.
+    pub fn select(&mut self, selected: Option<usize>) {
+        self.selected = selected;
+        let index = selected.unwrap_or(0);
+        if index < self.top {
+            self.top = index;
+        } else if index >= self.top.saturating_add(MAX_HEIGHT) {
+            self.top = index.saturating_add(1).saturating_sub(MAX_HEIGHT);
+        }
+    }
+    pub fn height(&self) -> usize {
+        self.items.len().min(MAX_HEIGHT)
+    }
+    fn text_width(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| item.text.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+    fn detail_width(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| item.detail.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+    // The columns it takes: a space either side, and one between the text
+    // and detail columns when there are details.
+    pub fn width(&self) -> usize {
+        let detail = self.detail_width();
+        let detail = if detail > 0 {
+            detail.saturating_add(1)
+        } else {
+            0
+        };
+        self.text_width().saturating_add(detail).saturating_add(2)
+    }
+    // Where its top left corner goes on a `width` by `height` screen, for
+    // text starting at `anchor`: on the lines below if it fits there, above
+    // otherwise, and moved left as far as it needs to stay on the screen.
+    pub fn place(&self, anchor: &Position, width: usize, height: usize) -> Position {
+        let below = anchor.y.saturating_add(1);
+        let y = if below.saturating_add(self.height()) <= height || anchor.y < self.height() {
+            below
+        } else {
+            anchor.y.saturating_sub(self.height())
+        };
+        let x = anchor.x.min(width.saturating_sub(self.width()));
+        Position { x, y }
+    }
+    // The items in view, one line each, cut to `max_width` columns.
+    pub fn lines(&self, max_width: usize, normal: Style, selected: Style) -> Vec<String> {
+        let (text_width, detail_width) = (self.text_width(), self.detail_width());
+        let width = self.width().min(max_width);
+        self.items
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(MAX_HEIGHT)
+            .map(|(index, item)| {
+                let mut text = format!(" {:text_width$}", item.text);
+                if detail_width > 0 {
+                    let _ = write!(text, " {:detail_width$}", item.detail);
+                }
+                text.push(' ');
+                let text: String = text.chars().take(width).collect();
+                let style = if self.selected == Some(index) {
+                    selected
+                } else {
+                    normal
+                };
+                format!(
+                    "{}{}{text}",
+                    SetForegroundColor(style.fg.unwrap_or(Color::Reset)),
+                    SetBackgroundColor(style.bg.unwrap_or(Color::Reset))
+                )
+            })
+            .collect()
+    }
+}
+
+// Draws `text` over a screen line from `column` on, keeping what `line`
+// shows on either side of it in its own colors. Columns count characters,
+// leaving out escape codes.
+pub fn overlay(line: &str, column: usize, text: &str) -> String {
+    let width = visible(text).count();
+    let mut result = String::with_capacity(line.len().saturating_add(text.len()));
+    // every escape code so far, replayed after `text` so the rest of the
+    // line is drawn in the colors it was going to be
+    let mut escapes = String::new();
+    let mut shown = 0_usize;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            let start = result.len();
+            result.push(c);
+            for c in chars.by_ref() {
+                result.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            escapes.push_str(result.get(start..).unwrap_or_default());
+            if shown >= column && shown < column.saturating_add(width) {
+                // inside the covered part only the colors matter
+                result.truncate(start);
+            }
+            continue;
+        }
+        if shown == column {
+            result.push_str(text);
+        }
+        if shown < column || shown >= column.saturating_add(width) {
+            if shown == column.saturating_add(width) {
+                result.push_str(&escapes);
+            }
+            result.push(c);
+        }
+        shown = shown.saturating_add(1);
+    }
+    // a line too short to reach `column` is padded out to it
+    if shown <= column {
+        result.push_str(&" ".repeat(column.saturating_sub(shown)));
+        result.push_str(text);
+    }
+    result
+}
+
+// The characters of `text` that take up columns.
+fn visible(text: &str) -> impl Iterator<Item = char> + '_ {
+    let mut in_escape = false;
+    text.chars().filter(move |&c| {
+        if c == '\u{1b}' {
+            in_escape = true;
+        } else if in_escape {
+            in_escape = !c.is_ascii_alphabetic();
+        } else {
+            return true;
+        }
+        false
+    })
+}
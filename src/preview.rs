@@ -0,0 +1,186 @@
+use crate::markdown;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the page asks whether the buffer changed, in milliseconds.
+const POLL_INTERVAL: u32 = 300;
+// How long a connection may take to send its request before it is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The rendered buffer, and a count of how many times it was rendered that
+// the page polls to see whether to fetch it again.
+#[derive(Default)]
+struct Page {
+    version: u64,
+    body: String,
+}
+
+// `:Preview`: the buffer rendered from markdown, served on a local port to
+// be viewed in a browser. The page follows the buffer as it changes.
+pub struct Preview {
+    address: SocketAddr,
+    page: Arc<Mutex<Page>>,
+    stopped: Arc<AtomicBool>,
+    // The buffer revision last rendered.
+    pub revision: Option<u64>,
+}
+
+impl Preview {
+    pub fn start(title: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let page = Arc::new(Mutex::new(Page::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let shell: Arc<str> = Arc::from(shell(title));
+        {
+            let (page, stopped) = (Arc::clone(&page), Arc::clone(&stopped));
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    // each connection on its own thread, so a slow one
+                    // does not hold up the page's polling
+                    let (shell, page) = (Arc::clone(&shell), Arc::clone(&page));
+                    thread::spawn(move || {
+                        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                        let _ = serve(&stream, &shell, &page);
+                    });
+                }
+            });
+        }
+        Ok(Self {
+            address,
+            page,
+            stopped,
+            revision: None,
+        })
+    }
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+    pub fn update(&mut self, revision: u64, text: &str) {
+        let body = markdown::to_html(text);
+        if let Ok(mut page) = self.page.lock() {
+            page.version = page.version.wrapping_add(1);
+            page.body = body;
+        }
+        self.revision = Some(revision);
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        // wakes the server up to see it should stop
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+// Answers one request: `/` for the page, `/version` and `/body` for the
+// script that keeps it up to date. Requests naming another host are
+// refused, so a web page cannot read the buffer by pointing its own name
+// at 127.0.0.1.
+fn serve(stream: &TcpStream, shell: &str, page: &Mutex<Page>) -> io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream);
+    reader.read_line(&mut request_line)?;
+    // only the host matters of the rest of the request, but all of it has
+    // to be read
+    let mut header = String::new();
+    let mut local = false;
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("host") {
+                local = is_local(value.trim());
+            }
+        }
+        header.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = match page.lock() {
+        Ok(_) if !local => ("403 Forbidden", "text/plain", "Forbidden".to_string()),
+        Ok(page) => match path {
+            "/" => ("200 OK", "text/html", shell.replace("{body}", &page.body)),
+            "/version" => ("200 OK", "text/plain", page.version.to_string()),
+            "/body" => ("200 OK", "text/html", page.body.clone()),
+            _ => ("404 Not Found", "text/plain", "Not found".to_string()),
+        },
+        Err(_) => ("500 Internal Server Error", "text/plain", String::new()),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}; charset=utf-8\r\n\
+         Content-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+// Whether the `Host` header `host` names this machine, with or without a
+// port.
+fn is_local(host: &str) -> bool {
+    let name = host
+        .rsplit_once(':')
+        .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+        .map_or(host, |(name, _)| name);
+    name == "127.0.0.1" || name.eq_ignore_ascii_case("localhost")
+}
+
+// The page around the rendered buffer, with `{body}` where it goes.
+fn shell(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n\
+         body {{ max-width: 46em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; \
+         line-height: 1.5; }}\n\
+         pre, code {{ background: #f4f4f4; font-family: monospace; }}\n\
+         pre {{ padding: 0.75em; overflow-x: auto; }}\n\
+         blockquote {{ margin-left: 0; padding-left: 1em; border-left: 3px solid #ccc; \
+         color: #555; }}\n\
+         </style>\n</head>\n<body>\n<main id=\"body\">{{body}}</main>\n<script>\n\
+         let version = null;\n\
+         setInterval(async () => {{\n\
+         \x20 const latest = await (await fetch('/version')).text();\n\
+         \x20 if (latest !== version) {{\n\
+         \x20   document.getElementById('body').innerHTML = await (await fetch('/body')).text();\n\
+         \x20   version = latest;\n\
+         \x20 }}\n\
+         }}, {POLL_INTERVAL});\n\
+         </script>\n</body>\n</html>\n",
+        markdown::escape(title)
+    )
+}
+
+// Opens `url` in the browser: `$BROWSER` if set, or else the system's
+// handler for web pages.
+pub fn open_browser(url: &str) -> io::Result<()> {
+    let mut command = match std::env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => Command::new(browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let mut child = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // reaped once it exits so it does not linger as a zombie
+    thread::spawn(move || child.wait());
+    Ok(())
+}
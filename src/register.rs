@@ -0,0 +1,245 @@
+// Text yanked or deleted, to put back with `p` and `P`. Whole lines go back
+// as lines of their own, a block from `Ctrl-V` goes back as a column over
+// the lines below, and anything else goes back into the line. `"{a-z}`
+// before a command picks a named register to use as well as the unnamed
+// one, and `"{A-Z}` adds to the named one rather than replacing it.
+//
+// `"+` and `"*` copy to and paste from the system clipboard; see
+// `clipboard`.
+//
+// Without a named register, the last yank also goes in `"0`, and deletes
+// are kept as Vim keeps them: whole lines, or text over more than one,
+// in `"1`, moving the ones before down to `"9`, and less in `"-`.
+//
+// `".` holds the text last typed in insert mode and `":` the last command
+// line run, both to read but not to yank into; the editor answers `"%`,
+// the file name, itself.
+//
+// Whatever register they go to, the last yanks and deletes are also kept
+// in a history of their own, for `:YankHistory` and `<C-p>` after a put.
+
+use std::collections::{BTreeMap, VecDeque};
+
+// How many yanks and deletes the history keeps.
+const HISTORY_SIZE: usize = 20;
+
+// What a register holds, which decides how it is put back.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Kind {
+    #[default]
+    Characters,
+    Lines,
+    // A piece of each of a run of lines, in `text` a line each.
+    Block,
+}
+
+impl Kind {
+    // The letter `:registers` shows for it.
+    pub fn letter(self) -> char {
+        match self {
+            Self::Characters => 'c',
+            Self::Lines => 'l',
+            Self::Block => 'b',
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    pub kind: Kind,
+}
+
+impl Register {
+    pub fn lines(lines: &[String]) -> Self {
+        Self {
+            text: lines.join("\n"),
+            kind: Kind::Lines,
+        }
+    }
+    pub fn characters(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            kind: Kind::Characters,
+        }
+    }
+    pub fn block(pieces: &[String]) -> Self {
+        Self {
+            text: pieces.join("\n"),
+            kind: Kind::Block,
+        }
+    }
+    // Whether there is nothing to put; an empty line is still a line.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.kind != Kind::Lines
+    }
+    // Text from outside the editor, such as the clipboard: whole lines when
+    // it ends in a line break.
+    pub fn from_text(text: &str) -> Self {
+        let text = text.replace("\r\n", "\n");
+        match text.strip_suffix('\n') {
+            Some(lines) => Self {
+                text: lines.to_string(),
+                kind: Kind::Lines,
+            },
+            None => Self::characters(&text),
+        }
+    }
+    // The text as it goes to the clipboard, whole lines ending in a line
+    // break.
+    pub fn to_text(&self) -> String {
+        if self.kind == Kind::Lines {
+            format!("{}\n", self.text)
+        } else {
+            self.text.clone()
+        }
+    }
+    // Adds `other` to the end, on a line of its own when either is whole
+    // lines or a block; whole lines win over a block.
+    fn append(&mut self, other: Self) {
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+        self.kind = match (self.kind, other.kind) {
+            (Kind::Lines, _) | (_, Kind::Lines) => Kind::Lines,
+            (Kind::Block, _) | (_, Kind::Block) => Kind::Block,
+            _ => Kind::Characters,
+        };
+        if self.kind != Kind::Characters {
+            self.text.push('\n');
+        }
+        self.text.push_str(&other.text);
+    }
+}
+
+#[derive(Default)]
+pub struct Registers {
+    unnamed: Register,
+    // `"0` to `"9`.
+    numbered: [Register; 10],
+    // `"-`.
+    small: Register,
+    // `".` and `":`.
+    inserted: Register,
+    command: Register,
+    named: BTreeMap<char, Register>,
+    // Newest first.
+    history: VecDeque<Register>,
+}
+
+// Whether `"{name}` picks a register.
+pub fn is_name(name: char) -> bool {
+    name.is_ascii_alphanumeric() || matches!(name, '"' | '-' | '+' | '*') || is_read_only(name)
+}
+
+// Whether `"{name}` picks a register that only the editor fills.
+pub fn is_read_only(name: char) -> bool {
+    matches!(name, '.' | ':' | '%')
+}
+
+impl Registers {
+    // The register `name` picks, or the unnamed one.
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        match name {
+            None | Some('"') => Some(&self.unnamed),
+            Some('-') => Some(&self.small),
+            Some('.') => Some(&self.inserted),
+            Some(':') => Some(&self.command),
+            Some('%') => None,
+            Some(name) => match name.to_digit(10) {
+                Some(digit) => self.numbered.get(digit as usize),
+                None => self.named.get(&name.to_ascii_lowercase()),
+            },
+        }
+    }
+    // Stores yanked text.
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        if is_unnamed(name) {
+            self.numbered[0] = register.clone();
+        }
+        self.remember(&register);
+        self.set(name, register);
+    }
+    // Stores deleted text.
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        if is_unnamed(name) {
+            if register.kind == Kind::Lines || register.text.contains('\n') {
+                self.numbered[1..].rotate_right(1);
+                self.numbered[1] = register.clone();
+            } else {
+                self.small = register.clone();
+            }
+        }
+        self.remember(&register);
+        self.set(name, register);
+    }
+    // Adds `register` to the front of the history, unless it is already
+    // there.
+    fn remember(&mut self, register: &Register) {
+        if register.is_empty() || self.history.front() == Some(register) {
+            return;
+        }
+        self.history.push_front(register.clone());
+        self.history.truncate(HISTORY_SIZE);
+    }
+    // The last yanks and deletes, newest first.
+    pub fn history(&self) -> &VecDeque<Register> {
+        &self.history
+    }
+    // Keeps the text typed in insert mode, for `".`.
+    pub fn set_inserted(&mut self, text: &str) {
+        self.inserted = Register::characters(text);
+    }
+    // Keeps the command line run, for `":`.
+    pub fn set_command(&mut self, command: &str) {
+        self.command = Register::characters(command);
+    }
+    // Stores `register` in the unnamed register and the one `name` picks;
+    // an uppercase name appends to it, and the unnamed register gets the
+    // whole of it. A read-only register is left as it is.
+    fn set(&mut self, name: Option<char>, register: Register) {
+        let stored = match name {
+            None | Some('"') => {
+                self.unnamed = register;
+                return;
+            }
+            Some('-') => &mut self.small,
+            Some(name) if is_read_only(name) => {
+                self.unnamed = register;
+                return;
+            }
+            Some(name) => match name.to_digit(10) {
+                Some(digit) => &mut self.numbered[digit as usize],
+                None => self.named.entry(name.to_ascii_lowercase()).or_default(),
+            },
+        };
+        if name.is_some_and(|name| name.is_ascii_uppercase()) {
+            stored.append(register);
+        } else {
+            *stored = register;
+        }
+        self.unnamed = stored.clone();
+    }
+    // Every register holding something, by name: `"` for the unnamed one,
+    // the numbered ones, the named ones, `-`, `.` and `:`.
+    pub fn list(&self) -> Vec<(char, &Register)> {
+        let numbered = ('0'..='9').zip(&self.numbered);
+        let named = self.named.iter().map(|(&name, register)| (name, register));
+        Some(('"', &self.unnamed))
+            .into_iter()
+            .chain(numbered)
+            .chain(named)
+            .chain([
+                ('-', &self.small),
+                ('.', &self.inserted),
+                (':', &self.command),
+            ])
+            .filter(|(_, register)| !register.is_empty())
+            .collect()
+    }
+}
+
+fn is_unnamed(name: Option<char>) -> bool {
+    matches!(name, None | Some('"'))
+}
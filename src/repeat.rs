@@ -0,0 +1,84 @@
+// `.`: the keys of the last change, typed again. A change is a normal mode
+// command that edits the text, such as `dw`, `x` or `p`, or a visual mode
+// one along with the keys that made the selection, through to the end of
+// any insert mode it starts, so `ciwfoo<Esc>` comes back whole. The count
+// typed before it is kept apart, for a count before `.` to replace.
+
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use std::mem;
+
+#[derive(Default)]
+struct Change {
+    count: Option<usize>,
+    keys: Vec<Event>,
+}
+
+#[derive(Default)]
+pub struct DotRepeat {
+    last: Change,
+    // The command being typed, and whether it edits the text.
+    typing: Change,
+    changing: bool,
+    // Whether `.` is typing the last change, which records nothing.
+    replaying: bool,
+}
+
+impl DotRepeat {
+    // Keeps `event`, typed as part of the current command.
+    pub fn record(&mut self, event: Event) {
+        if !self.replaying {
+            self.typing.keys.push(event);
+        }
+    }
+    // The count typed before the current command.
+    pub fn record_count(&mut self, count: Option<usize>) {
+        if !self.replaying {
+            self.typing.count = count;
+        }
+    }
+    // Forgets the keys typed so far, as a command that types others, such
+    // as `:normal`, is no change itself.
+    pub fn forget_typed(&mut self) {
+        if !self.replaying {
+            self.typing = Change::default();
+        }
+    }
+    // The current command edits the text.
+    pub fn mark_change(&mut self) {
+        self.changing |= !self.replaying;
+    }
+    // The current command is done: it becomes the last change when it
+    // edited the text, and is forgotten otherwise.
+    pub fn finish(&mut self) {
+        if self.replaying {
+            return;
+        }
+        let typed = mem::take(&mut self.typing);
+        if mem::take(&mut self.changing) {
+            self.last = typed;
+        }
+    }
+    // The keys to type the last change again, with `count` in place of its
+    // own if given, or None when there is none.
+    pub fn replay(&mut self, count: Option<usize>) -> Option<Vec<Event>> {
+        if self.last.keys.is_empty() {
+            return None;
+        }
+        if count.is_some() {
+            self.last.count = count;
+        }
+        let digits = self.last.count.map(|count| count.to_string());
+        let digits = digits.unwrap_or_default();
+        let digits = digits
+            .chars()
+            .map(|c| Event::Key(KeyEvent::from(KeyCode::Char(c))));
+        self.typing = Change::default();
+        self.changing = false;
+        self.replaying = true;
+        Some(digits.chain(self.last.keys.iter().copied()).collect())
+    }
+    // `.` is done typing; what comes next is recorded again.
+    pub fn end_replay(&mut self) {
+        self.replaying = false;
+    }
+}
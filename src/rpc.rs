@@ -0,0 +1,526 @@
+use crate::code_action::{CodeAction, TextEdit};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::editor::Editor;
+use crate::event::{Event, EventSender};
+use crate::semantic::{SemanticToken, TokenKind};
+use crate::Position;
+use rmpv::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+// Set while a server is running so rvim processes started from inside it
+// can find it with --remote.
+pub const SERVER_ENV: &str = "RVIM_LISTEN_ADDRESS";
+
+const REQUEST: u64 = 0;
+const RESPONSE: u64 = 1;
+const NOTIFICATION: u64 = 2;
+
+pub struct Request {
+    pub method: String,
+    pub params: Vec<Value>,
+    client: usize,
+    id: Option<Value>,
+}
+
+enum Incoming {
+    Connected(usize, UnixStream),
+    Message(Request),
+    Disconnected(usize),
+}
+
+struct Client {
+    stream: UnixStream,
+    subscriptions: HashSet<String>,
+}
+
+// A msgpack-RPC server on a unix socket. Connections are read on their own
+// threads and handed to the editor through a channel, posting `Event::Rpc`
+// so every request is handled on the main thread between keypresses.
+pub struct RpcServer {
+    path: PathBuf,
+    receiver: Receiver<Incoming>,
+    clients: HashMap<usize, Client>,
+}
+
+impl RpcServer {
+    pub fn listen(path: &str, waker: EventSender) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is already in use", path.display()),
+                ));
+            }
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || accept(&listener, &sender, &waker));
+        Ok(Self {
+            path,
+            receiver,
+            clients: HashMap::new(),
+        })
+    }
+    // Returns the next request the editor has to answer. Connection
+    // bookkeeping and `subscribe`/`unsubscribe` are handled here.
+    pub fn next_request(&mut self) -> Option<Request> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Incoming::Connected(client, stream)) => {
+                    self.clients.insert(
+                        client,
+                        Client {
+                            stream,
+                            subscriptions: HashSet::new(),
+                        },
+                    );
+                }
+                Ok(Incoming::Disconnected(client)) => {
+                    self.clients.remove(&client);
+                }
+                Ok(Incoming::Message(request)) => {
+                    if !matches!(request.method.as_str(), "subscribe" | "unsubscribe") {
+                        return Some(request);
+                    }
+                    let result = self.update_subscription(&request);
+                    self.respond(&request, result);
+                }
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+    pub fn respond(&mut self, request: &Request, result: Result<Value, String>) {
+        let Some(id) = request.id.clone() else {
+            return;
+        };
+        let (error, result) = match result {
+            Ok(result) => (Value::Nil, result),
+            Err(error) => (Value::from(error), Value::Nil),
+        };
+        let response = Value::Array(vec![Value::from(RESPONSE), id, error, result]);
+        self.send(request.client, &response);
+    }
+    pub fn notify(&mut self, event: &str, params: Vec<Value>) {
+        let notification = Value::Array(vec![
+            Value::from(NOTIFICATION),
+            Value::from(event),
+            Value::Array(params),
+        ]);
+        let subscribers: Vec<usize> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.subscriptions.contains(event))
+            .map(|(id, _)| *id)
+            .collect();
+        for client in subscribers {
+            self.send(client, &notification);
+        }
+    }
+
+    fn update_subscription(&mut self, request: &Request) -> Result<Value, String> {
+        let event = request
+            .params
+            .first()
+            .and_then(Value::as_str)
+            .ok_or("expected an event name")?
+            .to_string();
+        let client = self
+            .clients
+            .get_mut(&request.client)
+            .ok_or("unknown client")?;
+        if request.method == "subscribe" {
+            client.subscriptions.insert(event);
+        } else {
+            client.subscriptions.remove(&event);
+        }
+        Ok(Value::Nil)
+    }
+    fn send(&mut self, client: usize, message: &Value) {
+        let mut buffer = Vec::new();
+        if rmpv::encode::write_value(&mut buffer, message).is_err() {
+            return;
+        }
+        let failed = self
+            .clients
+            .get_mut(&client)
+            .is_some_and(|client| client.stream.write_all(&buffer).is_err());
+        if failed {
+            self.clients.remove(&client);
+        }
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn accept(listener: &UnixListener, sender: &Sender<Incoming>, waker: &EventSender) {
+    for (client, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(writer) = stream.try_clone() else {
+            continue;
+        };
+        if sender.send(Incoming::Connected(client, writer)).is_err() {
+            return;
+        }
+        let (sender, waker) = (sender.clone(), waker.clone());
+        thread::spawn(move || read_messages(client, stream, &sender, &waker));
+    }
+}
+
+fn read_messages(
+    client: usize,
+    stream: UnixStream,
+    sender: &Sender<Incoming>,
+    waker: &EventSender,
+) {
+    let mut reader = BufReader::new(stream);
+    while let Ok(message) = rmpv::decode::read_value(&mut reader) {
+        if let Some(request) = parse_message(client, message) {
+            if sender.send(Incoming::Message(request)).is_err() || !waker.send(Event::Rpc) {
+                return;
+            }
+        }
+    }
+    let _ = sender.send(Incoming::Disconnected(client));
+}
+
+// Requests are `[0, msgid, method, params]`, notifications `[2, method, params]`.
+fn parse_message(client: usize, message: Value) -> Option<Request> {
+    let Value::Array(fields) = message else {
+        return None;
+    };
+    let (id, method, params) = match fields.as_slice() {
+        [kind, id, method, params] if kind.as_u64() == Some(REQUEST) => {
+            (Some(id.clone()), method, params)
+        }
+        [kind, method, params] if kind.as_u64() == Some(NOTIFICATION) => (None, method, params),
+        _ => return None,
+    };
+    Some(Request {
+        method: method.as_str()?.to_string(),
+        params: params.as_array().cloned().unwrap_or_default(),
+        client,
+        id,
+    })
+}
+
+// Buffer-level methods:
+//   get_buffer_info() -> {name, line_count, modified, filetype}
+//   get_lines(start, end) -> [line]
+//   set_lines(start, end, [line])
+//   get_cursor() -> [line, column]
+//   set_cursor(line, column)
+//   command(ex_command)
+//   edit(file_name)
+//   set_diagnostics([{line, column, severity, message}])
+//   show_signature(label, start, end)
+//   hide_signature()
+//   code_actions([{title, edits: [{file, line, column, end_line, end_column, text}]}])
+//     -> index picked
+//   set_semantic_tokens([{line, column, length, type}])
+// Lines are 0-based and end-exclusive for get/set_lines; the cursor uses a
+// 1-based line like the status bar.
+pub fn handle(editor: &mut Editor, request: &Request) -> Result<Value, String> {
+    let param = |index: usize| request.params.get(index);
+    let number = |index: usize| {
+        param(index)
+            .and_then(Value::as_u64)
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or_else(|| format!("{}: expected a number at position {index}", request.method))
+    };
+    match request.method.as_str() {
+        "get_buffer_info" => {
+            let document = editor.document();
+            Ok(Value::Map(vec![
+                (
+                    "name".into(),
+                    document.file_name.clone().unwrap_or_default().into(),
+                ),
+                ("line_count".into(), document.len().into()),
+                ("modified".into(), document.is_dirty().into()),
+                ("filetype".into(), document.file_type().into()),
+            ]))
+        }
+        "get_lines" => {
+            let document = editor.document();
+            let end = number(1).unwrap_or(document.len()).min(document.len());
+            let start = number(0).unwrap_or(0).min(end);
+            Ok(Value::Array(
+                (start..end)
+                    .filter_map(|index| document.row(index))
+                    .map(|row| row.as_str().into())
+                    .collect(),
+            ))
+        }
+        "set_lines" => {
+            let lines = param(2)
+                .and_then(Value::as_array)
+                .ok_or("set_lines: expected an array of lines")?
+                .iter()
+                .map(|line| line.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()
+                .ok_or("set_lines: lines must be strings")?;
+            editor.replace_lines(number(0)?, number(1)?, &lines);
+            Ok(Value::Nil)
+        }
+        "get_cursor" => {
+            let Position { x, y } = editor.cursor_position().clone();
+            Ok(Value::Array(vec![y.saturating_add(1).into(), x.into()]))
+        }
+        "set_cursor" => {
+            editor.set_cursor_position(Position {
+                x: number(1)?,
+                y: number(0)?.saturating_sub(1),
+            });
+            Ok(Value::Nil)
+        }
+        "command" => {
+            let command = param(0)
+                .and_then(Value::as_str)
+                .ok_or("command: expected a string")?;
+            editor.execute(command);
+            Ok(Value::Nil)
+        }
+        "edit" => {
+            let file_name = param(0)
+                .and_then(Value::as_str)
+                .ok_or("edit: expected a file name")?;
+            if editor.edit(file_name) {
+                Ok(Value::Nil)
+            } else {
+                Err(format!(
+                    "could not edit {file_name}: the current buffer has unsaved changes"
+                ))
+            }
+        }
+        "set_diagnostics" => {
+            let diagnostics = param(0)
+                .and_then(Value::as_array)
+                .ok_or("set_diagnostics: expected an array of diagnostics")?
+                .iter()
+                .map(parse_diagnostic)
+                .collect::<Result<Vec<Diagnostic>, String>>()?;
+            editor.set_diagnostics(diagnostics);
+            Ok(Value::Nil)
+        }
+        "show_signature" => {
+            let label = param(0)
+                .and_then(Value::as_str)
+                .ok_or("show_signature: expected a label")?;
+            let parameter = number(1).and_then(|start| Ok(start..number(2)?)).ok();
+            if editor.show_signature(label, parameter) {
+                Ok(Value::Nil)
+            } else {
+                Err("show_signature: the cursor is not in a call".to_string())
+            }
+        }
+        "hide_signature" => {
+            editor.hide_signature();
+            Ok(Value::Nil)
+        }
+        "code_actions" => {
+            let actions = param(0)
+                .and_then(Value::as_array)
+                .ok_or("code_actions: expected an array of actions")?
+                .iter()
+                .map(parse_code_action)
+                .collect::<Result<Vec<CodeAction>, String>>()?;
+            Ok(editor
+                .pick_code_action(&actions)
+                .map_or(Value::Nil, Value::from))
+        }
+        "set_semantic_tokens" => {
+            let tokens = param(0)
+                .and_then(Value::as_array)
+                .ok_or("set_semantic_tokens: expected an array of tokens")?
+                .iter()
+                .map(parse_semantic_token)
+                .collect::<Result<Vec<Option<SemanticToken>>, String>>()?;
+            editor.set_semantic_tokens(tokens.into_iter().flatten().collect());
+            Ok(Value::Nil)
+        }
+        method => Err(format!("unknown method: {method}")),
+    }
+}
+
+// `{line, column, severity, message}`, with a 1-based line and a 0-based
+// column; the severity defaults to "error".
+fn parse_diagnostic(value: &Value) -> Result<Diagnostic, String> {
+    let field = |name: &str| {
+        value.as_map().and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .map(|(_, value)| value)
+        })
+    };
+    let number = |name: &str| {
+        field(name)
+            .and_then(Value::as_u64)
+            .and_then(|n| usize::try_from(n).ok())
+    };
+    let line = number("line").ok_or("set_diagnostics: each needs a line")?;
+    let severity = field("severity")
+        .and_then(Value::as_str)
+        .map_or(Ok(Severity::Error), Severity::parse)?;
+    Ok(Diagnostic {
+        position: Position {
+            x: number("column").unwrap_or(0),
+            y: line.saturating_sub(1),
+        },
+        severity,
+        message: field("message")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+// `{title, edits}`, each edit `{file, line, column, end_line, end_column,
+// text}` with 1-based lines and 0-based columns; without a file it is for
+// the current buffer.
+fn parse_code_action(value: &Value) -> Result<CodeAction, String> {
+    let field = |value: &'_ Value, name: &str| {
+        value.as_map().and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .map(|(_, value)| value.clone())
+        })
+    };
+    let title = field(value, "title")
+        .and_then(|title| title.as_str().map(str::to_string))
+        .ok_or("code_actions: each needs a title")?;
+    let edits = field(value, "edits")
+        .and_then(|edits| edits.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .map(|edit| {
+            let number = |name: &str| {
+                field(edit, name)
+                    .and_then(|n| n.as_u64())
+                    .and_then(|n| usize::try_from(n).ok())
+                    .ok_or_else(|| format!("code_actions: each edit needs a {name}"))
+            };
+            Ok(TextEdit {
+                file: field(edit, "file").and_then(|file| file.as_str().map(str::to_string)),
+                start: Position {
+                    x: number("column")?,
+                    y: number("line")?.saturating_sub(1),
+                },
+                end: Position {
+                    x: number("end_column")?,
+                    y: number("end_line")?.saturating_sub(1),
+                },
+                text: field(edit, "text")
+                    .and_then(|text| text.as_str().map(str::to_string))
+                    .unwrap_or_default(),
+            })
+        })
+        .collect::<Result<Vec<TextEdit>, String>>()?;
+    Ok(CodeAction { title, edits })
+}
+
+// `{line, column, length, type}`, with a 1-based line, a 0-based column
+// and the token type's name in the protocol, such as "enumMember"; None
+// for a type without a color of its own.
+fn parse_semantic_token(value: &Value) -> Result<Option<SemanticToken>, String> {
+    let field = |name: &str| {
+        value.as_map().and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .map(|(_, value)| value)
+        })
+    };
+    let number = |name: &str| {
+        field(name)
+            .and_then(Value::as_u64)
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or_else(|| format!("set_semantic_tokens: each needs a {name}"))
+    };
+    let kind = field("type")
+        .and_then(Value::as_str)
+        .ok_or("set_semantic_tokens: each needs a type")?;
+    let Some(kind) = TokenKind::parse(kind) else {
+        return Ok(None);
+    };
+    Ok(Some(SemanticToken {
+        line: number("line")?.saturating_sub(1),
+        column: number("column")?,
+        length: number("length")?,
+        kind,
+    }))
+}
+
+// Asks the server at `server` (or $RVIM_LISTEN_ADDRESS) to edit `files`.
+// With `wait`, blocks until the server has moved away from every file,
+// which makes `rvim --remote-wait` usable as $EDITOR. Fails with NotFound
+// or ConnectionRefused when there is no server to reach.
+pub fn remote(server: Option<&str>, files: &[String], wait: bool) -> io::Result<()> {
+    let server = server
+        .map(str::to_string)
+        .or_else(|| env::var(SERVER_ENV).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no server to connect to"))?;
+    let mut stream = UnixStream::connect(&server)?;
+    let current_dir = env::current_dir()?;
+    let files: Vec<String> = files
+        .iter()
+        .map(|file| current_dir.join(file).to_string_lossy().into_owned())
+        .collect();
+    let mut requests = Vec::new();
+    if wait {
+        requests.push(("subscribe", Value::from("BufUnload")));
+    }
+    requests.extend(
+        files
+            .iter()
+            .map(|file| ("edit", Value::from(file.as_str()))),
+    );
+    for (id, (method, param)) in requests.into_iter().enumerate() {
+        let request = Value::Array(vec![
+            Value::from(REQUEST),
+            Value::from(id),
+            Value::from(method),
+            Value::Array(vec![param]),
+        ]);
+        rmpv::encode::write_value(&mut stream, &request).map_err(io::Error::other)?;
+    }
+    let mut pending: HashSet<String> = files.into_iter().collect();
+    let mut reader = BufReader::new(stream);
+    let mut responses = 0;
+    let expected = pending.len().saturating_add(usize::from(wait));
+    while responses < expected || (wait && !pending.is_empty()) {
+        let Value::Array(message) =
+            rmpv::decode::read_value(&mut reader).map_err(io::Error::other)?
+        else {
+            continue;
+        };
+        match message.as_slice() {
+            [kind, _, error, _] if kind.as_u64() == Some(RESPONSE) => {
+                responses = responses.saturating_add(1);
+                if let Some(error) = error.as_str() {
+                    return Err(io::Error::other(error.to_string()));
+                }
+            }
+            [kind, _, params] if kind.as_u64() == Some(NOTIFICATION) => {
+                if let Some(file) = params.as_array().and_then(|params| params.first()) {
+                    pending.remove(file.as_str().unwrap_or_default());
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use crate::event::{Event, EventSender};
+use crate::Document;
+use crate::Pattern;
+use crate::Position;
+use crate::Row;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+// Rows searched between two batches of matches sent to the editor.
+const BATCH_ROWS: usize = 1 << 16;
+
+// The matches of one pattern, as far as the worker has got.
+pub struct Search {
+    pub pattern: Pattern,
+    pub matches: Vec<Position>,
+    pub done: bool,
+    revision: u64,
+}
+
+// Finds every match of a pattern on a worker thread, so typing in the search
+// prompt never waits for a long document to be scanned. Starting a search
+// cancels the one before it.
+#[derive(Default)]
+pub struct Searcher {
+    generation: Arc<AtomicUsize>,
+    search: Option<Search>,
+    // The lines workers search, copied once per revision of the document
+    // rather than on every key typed at the prompt.
+    lines: Option<(u64, Arc<Vec<String>>)>,
+}
+
+impl Searcher {
+    // Matches arrive as `Event::SearchMatches`, starting at `origin` and
+    // wrapping around the end of the document.
+    pub fn start(
+        &mut self,
+        document: &Document,
+        pattern: &Pattern,
+        origin: Position,
+        sender: EventSender,
+    ) {
+        let generation = self.cancel();
+        let revision = document.revision();
+        let lines = match &self.lines {
+            Some((cached, lines)) if *cached == revision => Arc::clone(lines),
+            _ => {
+                let lines: Arc<Vec<String>> = Arc::new(
+                    (0..document.len())
+                        .filter_map(|index| document.row(index))
+                        .map(|row| row.as_str().to_string())
+                        .collect(),
+                );
+                self.lines = Some((revision, Arc::clone(&lines)));
+                lines
+            }
+        };
+        self.search = Some(Search {
+            pattern: pattern.clone(),
+            matches: Vec::new(),
+            done: false,
+            revision,
+        });
+        let current = Arc::clone(&self.generation);
+        let pattern = pattern.clone();
+        thread::spawn(move || {
+            scan(
+                &lines,
+                &pattern,
+                &origin,
+                || current.load(Ordering::Relaxed) == generation,
+                |matches, done| {
+                    sender.send(Event::SearchMatches {
+                        generation,
+                        matches,
+                        done,
+                    })
+                },
+            );
+        });
+    }
+    // Stops the running search, if any; returns the generation of the next.
+    pub fn cancel(&mut self) -> usize {
+        self.search = None;
+        self.generation
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1)
+    }
+    // Lets go of the copied lines, as another buffer's revisions count from
+    // zero again.
+    pub fn forget_lines(&mut self) {
+        self.lines = None;
+    }
+    // Adds a batch from a worker; false when it belongs to a cancelled search.
+    pub fn receive(&mut self, generation: usize, matches: Vec<Position>, done: bool) -> bool {
+        if generation != self.generation.load(Ordering::Relaxed) {
+            return false;
+        }
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        search.matches.extend(matches);
+        search.done = done;
+        true
+    }
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+    pub fn is_running(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| !search.done)
+    }
+    // Whether the last search was for `pattern` in the document as it is now.
+    pub fn is_current(&self, document: &Document, pattern: &Pattern) -> bool {
+        self.search.as_ref().is_some_and(|search| {
+            search.pattern == *pattern && search.revision == document.revision()
+        })
+    }
+}
+
+// Sends the matches from `origin` to the end, then from the start back to
+// `origin`, in batches, until done or no longer `current`.
+fn scan<C, S>(lines: &[String], pattern: &Pattern, origin: &Position, current: C, mut send: S)
+where
+    C: Fn() -> bool,
+    S: FnMut(Vec<Position>, bool) -> bool,
+{
+    let start = origin.y.min(lines.len());
+    let rows = (start..lines.len())
+        .map(|y| (y, false))
+        .chain((0..=start).map(|y| (y, true)));
+    let (mut batch, mut first, mut since_sent) = (Vec::new(), true, 0_usize);
+    for (y, wrapped) in rows {
+        if !current() {
+            return;
+        }
+        since_sent = since_sent.saturating_add(1);
+        let Some(line) = lines.get(y) else {
+            continue;
+        };
+        // counting graphemes is only worth it for lines that match
+        if pattern.is_match(line) {
+            let row = Row::from(line.as_str());
+            batch.extend(
+                row.matches(pattern)
+                    .filter(|&x| y != origin.y || (x < origin.x) == wrapped)
+                    .map(|x| Position { x, y }),
+            );
+        }
+        // the first match goes out at once, so the cursor can jump to it
+        if !batch.is_empty() && (first || since_sent >= BATCH_ROWS) {
+            if !send(std::mem::take(&mut batch), false) {
+                return;
+            }
+            first = false;
+            since_sent = 0;
+        }
+    }
+    send(batch, true);
+}
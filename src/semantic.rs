@@ -0,0 +1,79 @@
+// Semantic tokens a language server reports through a script or RPC:
+// names it knows the meaning of, drawn over the syntax highlighting in
+// colors of their own.
+
+use crate::theme::Group;
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Parameter,
+    Property,
+    EnumMember,
+    Macro,
+}
+
+impl TokenKind {
+    // The token types of the language server protocol that have a color
+    // of their own; the rest keep the syntax highlighting.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "parameter" => Some(Self::Parameter),
+            "property" => Some(Self::Property),
+            "enumMember" => Some(Self::EnumMember),
+            "macro" => Some(Self::Macro),
+            _ => None,
+        }
+    }
+    pub fn group(self) -> Group {
+        match self {
+            Self::Parameter => Group::Parameter,
+            Self::Property => Group::Property,
+            Self::EnumMember => Group::EnumMember,
+            Self::Macro => Group::Macro,
+        }
+    }
+}
+
+// `length` graphemes of line `line` from `column`.
+pub struct SemanticToken {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub kind: TokenKind,
+}
+
+impl SemanticToken {
+    pub fn range(&self) -> Range<usize> {
+        self.column..self.column.saturating_add(self.length)
+    }
+}
+
+// The tokens for the current buffer, in buffer order, along with the
+// revision of the text they were made for. Once the text changes they are
+// out of date and hidden until whoever reports them sends the next set.
+#[derive(Default)]
+pub struct SemanticTokens {
+    items: Vec<SemanticToken>,
+    revision: u64,
+}
+
+impl SemanticTokens {
+    pub fn set(&mut self, mut items: Vec<SemanticToken>, revision: u64) {
+        items.sort_by_key(|item| (item.line, item.column));
+        self.items = items;
+        self.revision = revision;
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+    // The ones on line `y`, if they were made for `revision` of the text.
+    pub fn on_line(&self, y: usize, revision: u64) -> &[SemanticToken] {
+        if revision != self.revision {
+            return &[];
+        }
+        let start = self.items.partition_point(|item| item.line < y);
+        let end = self.items.partition_point(|item| item.line <= y);
+        self.items.get(start..end).unwrap_or_default()
+    }
+}
@@ -0,0 +1,99 @@
+// What is kept of a session for the next one, as in Vim's shada file: the
+// `:` and `/` histories, the global marks and where the cursor was in each
+// file. It is written on exit to `shada` in the state directory, one entry
+// per line with its fields separated by tabs:
+//
+//     :	{command}
+//     /	{search}
+//     '	{mark}	{line}	{column}	{file}
+//     "	{line}	{column}	{file}
+//
+// Lines and columns count from 0, and files are full paths.
+
+use crate::marks::GlobalMark;
+use crate::Position;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Shada {
+    pub command_history: Vec<String>,
+    pub search_history: Vec<String>,
+    pub global_marks: Vec<(char, GlobalMark)>,
+    pub last_positions: Vec<(String, Position)>,
+}
+
+impl Shada {
+    // A missing file is an empty state, and lines that do not make sense
+    // are skipped rather than losing the rest.
+    pub fn read(path: &Path) -> Self {
+        let mut shada = Self::default();
+        let Ok(text) = fs::read_to_string(path) else {
+            return shada;
+        };
+        for line in text.lines() {
+            let Some((kind, rest)) = line.split_once('\t') else {
+                continue;
+            };
+            match kind {
+                ":" => shada.command_history.push(rest.to_string()),
+                "/" => shada.search_history.push(rest.to_string()),
+                "'" => {
+                    let mut fields = rest.splitn(4, '\t');
+                    let mark = fields.next().and_then(|name| name.chars().next());
+                    let position = parse_position(fields.next(), fields.next());
+                    if let (Some(name), Some(position), Some(file_name)) =
+                        (mark, position, fields.next())
+                    {
+                        let file_name = file_name.to_string();
+                        shada.global_marks.push((
+                            name,
+                            GlobalMark {
+                                file_name,
+                                position,
+                            },
+                        ));
+                    }
+                }
+                "\"" => {
+                    let mut fields = rest.splitn(3, '\t');
+                    let position = parse_position(fields.next(), fields.next());
+                    if let (Some(position), Some(file_name)) = (position, fields.next()) {
+                        shada.last_positions.push((file_name.to_string(), position));
+                    }
+                }
+                _ => (),
+            }
+        }
+        shada
+    }
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        for command in &self.command_history {
+            let _ = writeln!(text, ":\t{command}");
+        }
+        for search in &self.search_history {
+            let _ = writeln!(text, "/\t{search}");
+        }
+        for (name, mark) in &self.global_marks {
+            let Position { x, y } = mark.position;
+            let _ = writeln!(text, "'\t{name}\t{y}\t{x}\t{}", mark.file_name);
+        }
+        for (file_name, Position { x, y }) in &self.last_positions {
+            let _ = writeln!(text, "\"\t{y}\t{x}\t{file_name}");
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+}
+
+fn parse_position(line: Option<&str>, column: Option<&str>) -> Option<Position> {
+    Some(Position {
+        y: line?.parse().ok()?,
+        x: column?.parse().ok()?,
+    })
+}
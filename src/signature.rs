@@ -0,0 +1,91 @@
+// The signature of the function whose arguments are being typed, shown in a
+// window over the line above the cursor with the argument at the cursor lit
+// up. Typing `(` or `,` sends a `SignatureHelp` notification to the clients
+// of the RPC server; one that asks the language server hands the answer
+// back with `show_signature`. The window goes once the call is closed.
+
+use crate::theme::Style;
+use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
+use rvim_core::Row;
+use std::ops::Range;
+
+pub struct Signature {
+    label: String,
+    // The characters of the label naming the argument at the cursor.
+    parameter: Option<Range<usize>>,
+    // Where the `(` of the call is on the cursor line.
+    pub open: usize,
+}
+
+impl Signature {
+    pub fn new(label: &str, parameter: Option<Range<usize>>, open: usize) -> Self {
+        Self {
+            label: label.lines().next().unwrap_or_default().to_string(),
+            parameter,
+            open,
+        }
+    }
+    // The columns it takes, with a space either side.
+    pub fn width(&self) -> usize {
+        self.label.chars().count().saturating_add(2)
+    }
+    // The window's line, cut to `max_width` columns.
+    pub fn line(&self, max_width: usize, normal: Style, active: Style) -> String {
+        let color = |style: Style| {
+            format!(
+                "{}{}",
+                SetForegroundColor(style.fg.unwrap_or(Color::Reset)),
+                SetBackgroundColor(style.bg.unwrap_or(Color::Reset))
+            )
+        };
+        let parameter = self.parameter.clone().unwrap_or_default();
+        let mut line = color(normal);
+        line.push(' ');
+        let width = max_width.saturating_sub(2);
+        for (index, c) in self.label.chars().take(width).enumerate() {
+            if index == parameter.start && !parameter.is_empty() {
+                line.push_str(&color(active));
+            } else if index == parameter.end {
+                line.push_str(&color(normal));
+            }
+            line.push(c);
+        }
+        line.push_str(&color(normal));
+        line.push(' ');
+        line
+    }
+}
+
+// Whether index `x` of `row` is inside the call whose `(` is at `open`,
+// calls inside it and all.
+pub fn in_call(row: &Row, x: usize, open: usize) -> bool {
+    if open >= x || row.get(open) != Some("(") {
+        return false;
+    }
+    let mut depth = 0_usize;
+    for index in open.saturating_add(1)..x.min(row.len()) {
+        match row.get(index) {
+            Some("(") => depth = depth.saturating_add(1),
+            Some(")") if depth == 0 => return false,
+            Some(")") => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    true
+}
+
+// Where the `(` of the innermost call around index `x` of `row` is: the
+// last one before it not closed again. Brackets in strings are not told
+// apart.
+pub fn call_start(row: &Row, x: usize) -> Option<usize> {
+    let mut depth = 0_usize;
+    for index in (0..x.min(row.len())).rev() {
+        match row.get(index) {
+            Some(")") => depth = depth.saturating_add(1),
+            Some("(") if depth == 0 => return Some(index),
+            Some("(") => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    None
+}
@@ -0,0 +1,54 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// `--startuptime {file}`: how long each startup phase took, written out
+// once the first screen has been drawn.
+pub struct StartupLog {
+    path: PathBuf,
+    start: Instant,
+    last: Instant,
+    phases: Vec<(Duration, Duration, String)>,
+}
+
+impl StartupLog {
+    pub fn new(path: &str) -> Self {
+        let now = Instant::now();
+        let mut log = Self {
+            path: PathBuf::from(path),
+            start: now,
+            last: now,
+            phases: Vec::new(),
+        };
+        log.mark("--- RVIM STARTING ---");
+        log
+    }
+    // Records that `phase` just finished.
+    pub fn mark(&mut self, phase: &str) {
+        let now = Instant::now();
+        self.phases.push((
+            now.duration_since(self.start),
+            now.duration_since(self.last),
+            phase.to_string(),
+        ));
+        self.last = now;
+    }
+    // Appends to the file, so several runs can be compared.
+    pub fn write(&self) -> Result<(), io::Error> {
+        let mut text = String::from("\ntimes in msec\n clock   self: phase\n\n");
+        for (clock, elapsed, phase) in &self.phases {
+            let _ = writeln!(text, "{}  {}: {phase}", millis(*clock), millis(*elapsed));
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(text.as_bytes())
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:07.3}", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,67 @@
+// Lines laid out by the columns their text takes on screen rather than its
+// bytes, so wide and multibyte characters are neither split nor miscounted.
+
+use crate::Row;
+
+// Ends text cut short to fit.
+const ELLIPSIS: char = '…';
+
+pub fn width(text: &str) -> usize {
+    Row::from(text).width()
+}
+
+// `text` cut down to at most `columns` columns, ending in `…` when any of
+// it was left out.
+pub fn truncate(text: &str, columns: usize) -> String {
+    let row = Row::from(text);
+    if row.width() <= columns {
+        return text.to_string();
+    }
+    let Some(room) = columns.checked_sub(1) else {
+        return String::new();
+    };
+    let end = row.index_at_column(room);
+    let mut truncated = row
+        .as_str()
+        .get(..row.byte_index(end))
+        .unwrap_or_default()
+        .to_string();
+    // a wide character that did not fit leaves a column over
+    truncated.push_str(&" ".repeat(room.saturating_sub(row.column(end))));
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+// One line `columns` wide, with `left` at the start, `right` at the end and
+// `center` in the middle. `right` is cut only when it fills the line by
+// itself and `left` gets what room is left; `center` moves aside for them,
+// and is left out when it does not fit between.
+pub fn layout(left: &str, center: &str, right: &str, columns: usize) -> String {
+    let right = truncate(right, columns);
+    let right_width = width(&right);
+    let gap = usize::from(right_width > 0);
+    let left = truncate(
+        left,
+        columns.saturating_sub(right_width.saturating_add(gap)),
+    );
+    let left_width = width(&left);
+    let mut line = left;
+    let center_width = width(center);
+    // the columns the center can start in, one clear of either side
+    let earliest = left_width.saturating_add(1);
+    let latest = columns
+        .saturating_sub(right_width.saturating_add(gap))
+        .checked_sub(center_width);
+    match latest {
+        Some(latest) if center_width > 0 && earliest <= latest => {
+            let start = (columns.saturating_sub(center_width) / 2).clamp(earliest, latest);
+            line.push_str(&" ".repeat(start.saturating_sub(left_width)));
+            line.push_str(center);
+        }
+        _ => (),
+    }
+    let used = width(&line);
+    line.push_str(&" ".repeat(columns.saturating_sub(used.saturating_add(right_width))));
+    line.push_str(&right);
+    line
+}
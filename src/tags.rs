@@ -0,0 +1,167 @@
+use crate::Document;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Extension fields that say something other than what a tag is nested in.
+const NOT_SCOPES: &[&str] = &[
+    "access",
+    "end",
+    "extras",
+    "file",
+    "inherits",
+    "language",
+    "nth",
+    "properties",
+    "roles",
+    "signature",
+    "template",
+    "typeref",
+];
+
+// One line of a tags file, as written by ctags.
+pub struct Tag {
+    pub name: String,
+    // Relative to the directory of the tags file it came from.
+    pub file: String,
+    // 1-based.
+    pub line: Option<usize>,
+    // The text of the line it is on, when the address is a search pattern.
+    pub pattern: Option<String>,
+    pub kind: String,
+    // What it is nested in, such as `Editor` or `outer::Inner`.
+    pub scope: Option<String>,
+}
+
+impl Tag {
+    // How deep it is nested, 0 at the top level.
+    pub fn depth(&self) -> usize {
+        self.scope.as_ref().map_or(0, |scope| {
+            scope.split("::").flat_map(|part| part.split('.')).count()
+        })
+    }
+    // The 0-based line it is on in `document`: where the pattern is, if
+    // anywhere, or else the line number it was written with.
+    pub fn locate(&self, document: &Document) -> Option<usize> {
+        let found = self.pattern.as_ref().and_then(|pattern| {
+            (0..document.len()).find(|&index| {
+                document
+                    .row(index)
+                    .is_some_and(|row| row.as_str() == pattern)
+            })
+        });
+        found.or_else(|| self.line.map(|line| line.saturating_sub(1)))
+    }
+}
+
+// `name<Tab>file<Tab>address;"<Tab>fields`, skipping the `!_TAG_` header
+// lines.
+pub fn parse(contents: &str) -> Vec<Tag> {
+    contents.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<Tag> {
+    if line.starts_with("!_TAG_") {
+        return None;
+    }
+    let mut fields = line.splitn(3, '\t');
+    let (name, file, rest) = (fields.next()?, fields.next()?, fields.next()?);
+    let (address, extensions) = rest.split_once(";\"").unwrap_or((rest, ""));
+    let mut tag = Tag {
+        name: name.to_string(),
+        file: file.to_string(),
+        line: address.parse().ok(),
+        pattern: parse_pattern(address),
+        kind: String::new(),
+        scope: None,
+    };
+    for field in extensions.split('\t').filter(|field| !field.is_empty()) {
+        match field.split_once(':') {
+            None => field.clone_into(&mut tag.kind),
+            Some(("kind", kind)) => kind.clone_into(&mut tag.kind),
+            Some(("line", line)) => tag.line = line.parse().ok(),
+            Some((key, scope)) if !scope.is_empty() && !NOT_SCOPES.contains(&key) => {
+                tag.scope = Some(scope.to_string());
+            }
+            _ => (),
+        }
+    }
+    Some(tag)
+}
+
+// `/^fn main() {$/` or `?...?` to the line it stands for.
+fn parse_pattern(address: &str) -> Option<String> {
+    let delimiter = address.chars().next().filter(|c| matches!(c, '/' | '?'))?;
+    let pattern = address.strip_prefix(delimiter)?;
+    let pattern = pattern.strip_suffix(delimiter).unwrap_or(pattern);
+    let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+    let pattern = pattern.strip_suffix('$').unwrap_or(pattern);
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    Some(text)
+}
+
+// The nearest `tags` file in `dir` or a directory above it.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join("tags"))
+        .find(|path| path.is_file())
+}
+
+// Every tag in the nearest tags file above `dir`, with file names made
+// relative to `dir`'s tags file directory as absolute paths.
+pub fn workspace(dir: &Path) -> Result<Vec<Tag>, String> {
+    let path = find(dir).ok_or("No tags file")?;
+    let contents =
+        fs::read_to_string(&path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let base = path.parent().unwrap_or(dir);
+    let mut tags = parse(&contents);
+    for tag in &mut tags {
+        tag.file = base.join(&tag.file).to_string_lossy().into_owned();
+    }
+    Ok(tags)
+}
+
+// The symbols defined in `file`, in the order they appear: from the nearest
+// tags file if it lists any, or else from running ctags on the file.
+pub fn symbols(file: &Path) -> Result<Vec<Tag>, String> {
+    let wanted = file.canonicalize().ok();
+    let dir = wanted
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new("."));
+    if let Ok(tags) = workspace(dir) {
+        let mut same_file: HashMap<String, bool> = HashMap::new();
+        let mut tags: Vec<Tag> = tags
+            .into_iter()
+            .filter(|tag| {
+                *same_file.entry(tag.file.clone()).or_insert_with(|| {
+                    Path::new(&tag.file).canonicalize().ok() == wanted && wanted.is_some()
+                })
+            })
+            .collect();
+        if !tags.is_empty() {
+            tags.sort_by_key(|tag| tag.line);
+            return Ok(tags);
+        }
+    }
+    let output = Command::new("ctags")
+        .args(["-f", "-", "--fields=+nKs", "--sort=no"])
+        .arg(file)
+        .output()
+        .map_err(|_| "No symbols: there is no tags file and ctags is not installed")?;
+    if !output.status.success() {
+        return Err(format!(
+            "ctags: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
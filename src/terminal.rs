@@ -1,68 +1,241 @@
 use crate::Position;
-use crossterm::event::{read, Event};
 use crossterm::style::{Color, SetBackgroundColor, SetForegroundColor};
 use crossterm::terminal::{size, Clear, ClearType};
 use crossterm::{cursor, queue};
-use std::io::{self, stdout, Write};
+use std::io::{stdout, Write};
+use std::mem;
 
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
+
+// Stands in for the real terminal in tests: every drawn line is kept,
+// without escape codes and with them, until the next frame.
+struct Headless {
+    screen: Vec<String>,
+    styled: Vec<String>,
+    // The escape sequences sent with `send`.
+    sent: Vec<String>,
+    // How many frames were begun, and the screen lines each of them
+    // printed because they changed, one after the other.
+    frames: usize,
+    printed: Vec<usize>,
+}
+
 pub struct Terminal {
     size: Size,
+    headless: Option<Headless>,
+    // What is drawn goes here first and reaches the terminal on `flush`,
+    // unless it is the same as the frame before. Only `flush` can fail.
+    frame: Vec<u8>,
+    last_frame: Vec<u8>,
+    // What each screen line showed last, so unchanged lines are not
+    // cleared and printed again, and the line the frame is on.
+    lines: Vec<String>,
+    line: usize,
 }
 
 impl Terminal {
-    pub fn default() -> Result<Self, std::io::Error> {
+    pub fn new() -> Result<Self, std::io::Error> {
         let (width, height) = size()?;
         let width = width.saturating_add(5);
         let height = height.saturating_sub(3);
         Ok(Self {
             size: Size { width, height },
+            headless: None,
+            frame: Vec::new(),
+            last_frame: Vec::new(),
+            lines: Vec::new(),
+            line: 0,
         })
     }
+    #[cfg(test)]
+    pub fn headless(width: u16, height: u16) -> Self {
+        Self {
+            size: Size { width, height },
+            headless: Some(Headless {
+                screen: Vec::new(),
+                styled: Vec::new(),
+                sent: Vec::new(),
+                frames: 0,
+                printed: Vec::new(),
+            }),
+            frame: Vec::new(),
+            last_frame: Vec::new(),
+            lines: Vec::new(),
+            line: 0,
+        }
+    }
     pub fn size(&self) -> &Size {
         &self.size
     }
-    pub fn clear_screen() {
-        queue!(stdout(), Clear(ClearType::All)).unwrap();
+    pub fn clear_screen(&mut self) {
+        self.lines.clear();
+        if let Some(headless) = &mut self.headless {
+            headless.screen.clear();
+            headless.styled.clear();
+        } else {
+            let _ = queue!(self.frame, Clear(ClearType::All));
+        }
+    }
+    // Starts a new frame at the top left corner.
+    pub fn begin_frame(&mut self) {
+        self.line = 0;
+        if let Some(headless) = &mut self.headless {
+            headless.screen.clear();
+            headless.styled.clear();
+            headless.frames = headless.frames.saturating_add(1);
+        } else {
+            self.cursor_hide();
+            self.cursor_position(&Position::default());
+        }
     }
 
-    pub fn cursor_position(position: &Position) {
+    pub fn cursor_position(&mut self, position: &Position) {
+        if self.headless.is_some() {
+            return;
+        }
         let Position { mut x, y } = position;
         if *y != 0 {
             x = x.saturating_add(5);
         }
-        queue!(stdout(), cursor::MoveTo(x as u16, *y as u16)).unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let _ = queue!(self.frame, cursor::MoveTo(x as u16, *y as u16));
+    }
+    // Like `cursor_position`, but counting columns from the left edge of
+    // the screen rather than from after the gutter.
+    pub fn cursor_to(&mut self, position: &Position) {
+        if self.headless.is_some() {
+            return;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let _ = queue!(
+            self.frame,
+            cursor::MoveTo(position.x as u16, position.y as u16)
+        );
     }
-    pub fn flush() -> Result<(), std::io::Error> {
-        io::stdout().flush()
+    // Sends the frame to the terminal, skipping it when nothing changed.
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        if self.headless.is_some() || self.frame == self.last_frame {
+            self.frame.clear();
+            return Ok(());
+        }
+        let mut stdout = stdout();
+        stdout.write_all(&self.frame)?;
+        stdout.flush()?;
+        mem::swap(&mut self.frame, &mut self.last_frame);
+        self.frame.clear();
+        Ok(())
     }
-    pub fn read_key() -> Result<Event, std::io::Error> {
-        loop {
-            return Ok(read().unwrap());
+    // Writes an escape sequence straight to the terminal, rather than as
+    // part of the frame, which may not be sent.
+    pub fn send(&mut self, sequence: &str) -> Result<(), std::io::Error> {
+        if let Some(headless) = &mut self.headless {
+            headless.sent.push(sequence.to_string());
+            return Ok(());
         }
+        let mut stdout = stdout();
+        stdout.write_all(sequence.as_bytes())?;
+        stdout.flush()
     }
-    pub fn cursor_hide() {
-        queue!(stdout(), cursor::Hide).unwrap();
+    #[cfg(test)]
+    pub fn sent(&self) -> &[String] {
+        self.headless
+            .as_ref()
+            .map_or(&[], |headless| headless.sent.as_slice())
     }
-    pub fn cursor_show() {
-        queue!(stdout(), cursor::Show).unwrap();
+    #[cfg(test)]
+    pub fn frames(&self) -> usize {
+        self.headless.as_ref().map_or(0, |headless| headless.frames)
     }
-    pub fn clear_current_line() {
-        queue!(stdout(), Clear(ClearType::CurrentLine)).unwrap();
+    #[cfg(test)]
+    pub fn printed(&self) -> &[usize] {
+        self.headless
+            .as_ref()
+            .map_or(&[], |headless| headless.printed.as_slice())
     }
-    pub fn set_bg_color(color: Color) {
-        queue!(stdout(), SetBackgroundColor(color)).unwrap();
+    #[cfg(test)]
+    pub fn screen(&self) -> &[String] {
+        self.headless
+            .as_ref()
+            .map_or(&[], |headless| headless.screen.as_slice())
     }
-    pub fn reset_bg_color() {
-        queue!(stdout(), SetBackgroundColor(Color::Reset)).unwrap();
+    // The screen as drawn, colors and all.
+    #[cfg(test)]
+    pub fn styled_screen(&self) -> &[String] {
+        self.headless
+            .as_ref()
+            .map_or(&[], |headless| headless.styled.as_slice())
     }
-    pub fn set_fg_color(color: Color) {
-        queue!(stdout(), SetForegroundColor(color)).unwrap();
+    pub fn write_line(&mut self, line: &str) {
+        self.write(line);
+        if self.headless.is_none() {
+            self.frame.extend_from_slice(b"\r\n");
+        }
     }
-    pub fn reset_fg_color() {
-        queue!(stdout(), SetForegroundColor(Color::Reset)).unwrap();
+    // Writes the last line of the screen, which has no line break after it.
+    pub fn write(&mut self, text: &str) {
+        if let Some(headless) = &mut self.headless {
+            headless.screen.push(strip_escapes(text));
+            headless.styled.push(text.to_string());
+        }
+        let index = self.line;
+        self.line = self.line.saturating_add(1);
+        match self.lines.get_mut(index) {
+            Some(last) if last == text => return,
+            Some(last) => text.clone_into(last),
+            None => self.lines.push(text.to_string()),
+        }
+        if let Some(headless) = &mut self.headless {
+            headless.printed.push(index);
+            return;
+        }
+        let _ = queue!(self.frame, Clear(ClearType::CurrentLine));
+        self.frame.extend_from_slice(text.as_bytes());
+    }
+    pub fn cursor_hide(&mut self) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, cursor::Hide);
+        }
+    }
+    pub fn cursor_show(&mut self) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, cursor::Show);
+        }
+    }
+    pub fn set_bg_color(&mut self, color: Color) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, SetBackgroundColor(color));
+        }
+    }
+    pub fn reset_bg_color(&mut self) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, SetBackgroundColor(Color::Reset));
+        }
+    }
+    pub fn set_fg_color(&mut self, color: Color) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, SetForegroundColor(color));
+        }
+    }
+    pub fn reset_fg_color(&mut self) {
+        if self.headless.is_none() {
+            let _ = queue!(self.frame, SetForegroundColor(Color::Reset));
+        }
+    }
+}
+
+// Drops the CSI sequences (`ESC [ ... letter`) used for colors.
+fn strip_escapes(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            chars.find(char::is_ascii_alphabetic);
+        } else {
+            plain.push(c);
+        }
     }
+    plain
 }
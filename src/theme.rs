@@ -0,0 +1,375 @@
+use crossterm::style::Color::{self, Rgb};
+use rvim_core::highlighting::Type;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// The highlight groups `:highlight` can change, by name.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Normal,
+    Number,
+    Search,
+    String,
+    Character,
+    Comment,
+    // Documentation comments, such as Rust's `///`.
+    DocComment,
+    Keyword,
+    Type,
+    // The names of functions where they are called or defined.
+    Function,
+    // Attributes such as `#[derive(Debug)]`, and lifetimes such as `'a`.
+    Attribute,
+    Lifetime,
+    // Preprocessor directives, such as `#include`.
+    PreProc,
+    // Shell variables and command substitutions, such as `$HOME`.
+    Variable,
+    // Semantic tokens from a language server.
+    Parameter,
+    Property,
+    EnumMember,
+    Macro,
+    // Escape sequences in strings, and the ones the language does not
+    // allow.
+    SpecialChar,
+    Error,
+    StatusLine,
+    // The selection in visual mode.
+    Visual,
+    // Markers that are not part of the text, such as `listchars`.
+    NonText,
+    // The popup menu, and its selected item.
+    Pmenu,
+    PmenuSel,
+    // Diagnostics by severity, for their messages and signs.
+    DiagnosticError,
+    DiagnosticWarn,
+    DiagnosticInfo,
+    DiagnosticHint,
+    // The signs of a line with a breakpoint and of the line the program
+    // being debugged stopped on.
+    BreakpointSign,
+    StoppedSign,
+    // The keys that jump to the other matches of `s{char}{char}`.
+    SneakLabel,
+    // The sign of a line with a bookmark.
+    BookmarkSign,
+    // The text just yanked or put, for a moment.
+    Flash,
+}
+
+const GROUPS: &[(&str, Group)] = &[
+    ("Normal", Group::Normal),
+    ("Number", Group::Number),
+    ("Search", Group::Search),
+    ("String", Group::String),
+    ("Character", Group::Character),
+    ("Comment", Group::Comment),
+    ("DocComment", Group::DocComment),
+    ("Keyword", Group::Keyword),
+    ("Type", Group::Type),
+    ("Function", Group::Function),
+    ("Attribute", Group::Attribute),
+    ("Lifetime", Group::Lifetime),
+    ("PreProc", Group::PreProc),
+    ("Variable", Group::Variable),
+    ("Parameter", Group::Parameter),
+    ("Property", Group::Property),
+    ("EnumMember", Group::EnumMember),
+    ("Macro", Group::Macro),
+    ("SpecialChar", Group::SpecialChar),
+    ("Error", Group::Error),
+    ("StatusLine", Group::StatusLine),
+    ("Visual", Group::Visual),
+    ("NonText", Group::NonText),
+    ("Pmenu", Group::Pmenu),
+    ("PmenuSel", Group::PmenuSel),
+    ("DiagnosticError", Group::DiagnosticError),
+    ("DiagnosticWarn", Group::DiagnosticWarn),
+    ("DiagnosticInfo", Group::DiagnosticInfo),
+    ("DiagnosticHint", Group::DiagnosticHint),
+    ("BreakpointSign", Group::BreakpointSign),
+    ("StoppedSign", Group::StoppedSign),
+    ("SneakLabel", Group::SneakLabel),
+    ("BookmarkSign", Group::BookmarkSign),
+    ("Flash", Group::Flash),
+];
+
+impl Group {
+    pub fn of(hl_type: Type) -> Self {
+        match hl_type {
+            Type::None => Self::Normal,
+            Type::Number => Self::Number,
+            Type::Match => Self::Search,
+            Type::String => Self::String,
+            Type::Escape => Self::SpecialChar,
+            Type::InvalidEscape => Self::Error,
+            Type::Character => Self::Character,
+            Type::Comment | Type::MultilineComment => Self::Comment,
+            Type::DocComment => Self::DocComment,
+            Type::Attribute => Self::Attribute,
+            Type::Lifetime => Self::Lifetime,
+            Type::Preprocessor => Self::PreProc,
+            Type::Variable => Self::Variable,
+            Type::PrimaryKeywords => Self::Keyword,
+            Type::SecondaryKeywords | Type::Type => Self::Type,
+            Type::Function => Self::Function,
+        }
+    }
+    fn name(self) -> &'static str {
+        GROUPS
+            .iter()
+            .find(|(_, group)| *group == self)
+            .map_or("?", |(name, _)| name)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+// The colors of every highlight group: the built-in defaults, then the
+// theme file, then whatever `:highlight` changed at runtime. The theme
+// file is re-read whenever it changes on disk.
+pub struct Theme {
+    styles: HashMap<Group, Style>,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    error: Option<String>,
+}
+
+impl Theme {
+    // Starts out with the defaults; the file is read by the first
+    // `reload_if_changed`.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            styles: defaults(),
+            path,
+            modified: None,
+            error: None,
+        }
+    }
+    pub fn style(&self, group: Group) -> Style {
+        self.styles.get(&group).copied().unwrap_or_default()
+    }
+    // Re-reads the theme file if it changed since it was last read. Returns
+    // whether it did, or the first bad line.
+    pub fn reload_if_changed(&mut self) -> Result<bool, String> {
+        let modified = self
+            .path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|metadata| metadata.modified().ok());
+        if modified == self.modified {
+            return Ok(false);
+        }
+        let result = self.reload();
+        self.error = result.as_ref().err().cloned();
+        result.map(|()| true)
+    }
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+    // The first bad line the last time the file was read.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+    fn reload(&mut self) -> Result<(), String> {
+        self.styles = defaults();
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.modified = fs::metadata(path)
+            .ok()
+            .and_then(|metadata| metadata.modified().ok());
+        let Ok(contents) = fs::read_to_string(path) else {
+            return Ok(());
+        };
+        let name = path.display().to_string();
+        let mut result = Ok(());
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('"') {
+                continue;
+            }
+            let arguments: Vec<String> = line
+                .split_whitespace()
+                .skip_while(|word| matches!(*word, "hi" | "highlight"))
+                .map(str::to_string)
+                .collect();
+            if let Err(error) = self.highlight(&arguments) {
+                if result.is_ok() {
+                    result = Err(format!("{name}:{}: {error}", number.saturating_add(1)));
+                }
+            }
+        }
+        result
+    }
+    // `:highlight {Group} [fg={color}] [bg={color}]`. With only a group name,
+    // describes it; with no arguments, describes every group.
+    pub fn highlight(&mut self, arguments: &[String]) -> Result<Option<String>, String> {
+        let Some((name, attributes)) = arguments.split_first() else {
+            let all: Vec<String> = GROUPS
+                .iter()
+                .map(|(_, group)| self.describe(*group))
+                .collect();
+            return Ok(Some(all.join(" | ")));
+        };
+        let group = GROUPS
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|(_, group)| *group)
+            .ok_or_else(|| format!("Unknown highlight group: {name}"))?;
+        if attributes.is_empty() {
+            return Ok(Some(self.describe(group)));
+        }
+        let mut style = self.style(group);
+        for attribute in attributes {
+            match attribute.split_once('=') {
+                Some(("fg" | "guifg", value)) => style.fg = parse_color(value)?,
+                Some(("bg" | "guibg", value)) => style.bg = parse_color(value)?,
+                _ => return Err(format!("Invalid attribute: {attribute}")),
+            }
+        }
+        self.styles.insert(group, style);
+        Ok(None)
+    }
+
+    fn describe(&self, group: Group) -> String {
+        let Style { fg, bg } = self.style(group);
+        format!(
+            "{} fg={} bg={}",
+            group.name(),
+            color_name(fg),
+            color_name(bg)
+        )
+    }
+}
+
+fn defaults() -> HashMap<Group, Style> {
+    let fg = |r, g, b| Style {
+        fg: Some(Rgb { r, g, b }),
+        bg: None,
+    };
+    let on = |fg: Style, r, g, b| Style {
+        bg: Some(Rgb { r, g, b }),
+        ..fg
+    };
+    HashMap::from([
+        (Group::Normal, fg(255, 255, 255)),
+        (Group::Number, fg(220, 163, 163)),
+        (Group::Search, fg(38, 139, 210)),
+        (Group::String, fg(211, 54, 130)),
+        (Group::Character, fg(108, 113, 196)),
+        (Group::Comment, fg(133, 153, 0)),
+        (Group::DocComment, fg(147, 161, 161)),
+        (Group::Keyword, fg(181, 137, 0)),
+        (Group::Type, fg(42, 161, 152)),
+        (Group::Function, fg(38, 139, 210)),
+        (Group::Attribute, fg(203, 75, 22)),
+        (Group::Lifetime, fg(108, 113, 196)),
+        (Group::PreProc, fg(203, 75, 22)),
+        (Group::Variable, fg(38, 139, 210)),
+        (Group::Parameter, fg(238, 232, 213)),
+        (Group::Property, fg(42, 161, 152)),
+        (Group::EnumMember, fg(108, 113, 196)),
+        (Group::Macro, fg(203, 75, 22)),
+        (Group::SpecialChar, fg(203, 75, 22)),
+        (Group::Error, on(fg(253, 246, 227), 220, 50, 47)),
+        (
+            Group::Visual,
+            Style {
+                fg: None,
+                bg: Some(Rgb {
+                    r: 88,
+                    g: 110,
+                    b: 117,
+                }),
+            },
+        ),
+        (Group::NonText, fg(88, 110, 117)),
+        (Group::Pmenu, on(fg(238, 232, 213), 7, 54, 66)),
+        (Group::PmenuSel, on(fg(7, 54, 66), 147, 161, 161)),
+        (Group::DiagnosticError, fg(220, 50, 47)),
+        (Group::DiagnosticWarn, fg(203, 75, 22)),
+        (Group::DiagnosticInfo, fg(38, 139, 210)),
+        (Group::DiagnosticHint, fg(88, 110, 117)),
+        (Group::BreakpointSign, fg(220, 50, 47)),
+        (Group::StoppedSign, fg(133, 153, 0)),
+        (Group::SneakLabel, on(fg(253, 246, 227), 211, 54, 130)),
+        (Group::BookmarkSign, fg(181, 137, 0)),
+        (Group::Flash, on(fg(7, 54, 66), 181, 137, 0)),
+        (
+            Group::StatusLine,
+            Style {
+                fg: Some(Rgb {
+                    r: 63,
+                    g: 63,
+                    b: 63,
+                }),
+                bg: Some(Rgb {
+                    r: 239,
+                    g: 239,
+                    b: 239,
+                }),
+            },
+        ),
+    ])
+}
+
+const COLOR_NAMES: &[(&str, Color)] = &[
+    ("black", Color::Black),
+    ("darkgrey", Color::DarkGrey),
+    ("red", Color::Red),
+    ("darkred", Color::DarkRed),
+    ("green", Color::Green),
+    ("darkgreen", Color::DarkGreen),
+    ("yellow", Color::Yellow),
+    ("darkyellow", Color::DarkYellow),
+    ("blue", Color::Blue),
+    ("darkblue", Color::DarkBlue),
+    ("magenta", Color::Magenta),
+    ("darkmagenta", Color::DarkMagenta),
+    ("cyan", Color::Cyan),
+    ("darkcyan", Color::DarkCyan),
+    ("white", Color::White),
+    ("grey", Color::Grey),
+];
+
+// `#rrggbb`, one of the terminal color names, or `NONE` for the terminal's
+// default.
+fn parse_color(value: &str) -> Result<Option<Color>, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    if let Some(hex) = value.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |index: usize| {
+            hex.get(index..index.saturating_add(2))
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+        if let (Some(r), Some(g), Some(b)) = (channel(0), channel(2), channel(4)) {
+            return Ok(Some(Rgb { r, g, b }));
+        }
+    }
+    COLOR_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(value))
+        .map(|(_, color)| Some(*color))
+        .ok_or_else(|| format!("Invalid color: {value}"))
+}
+
+fn color_name(color: Option<Color>) -> String {
+    match color {
+        None => "NONE".to_string(),
+        Some(Rgb { r, g, b }) => format!("#{r:02x}{g:02x}{b:02x}"),
+        Some(color) => COLOR_NAMES
+            .iter()
+            .find(|(_, other)| *other == color)
+            .map_or_else(|| format!("{color:?}"), |(name, _)| (*name).to_string()),
+    }
+}
@@ -0,0 +1,220 @@
+// The files of a workspace, for commands that search through it. Files
+// `.gitignore` leaves out are skipped, as are hidden ones and those over a
+// size limit unless asked for. Directory listings are kept between walks
+// and only read again once the directory or its `.gitignore` changes.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Settings {
+    // Walk into hidden files and directories, those starting with `.`.
+    pub hidden: bool,
+    // Files larger than this many bytes are left out; 0 for no limit.
+    pub max_file_size: u64,
+}
+
+// One line of a `.gitignore`.
+struct Rule {
+    pattern: String,
+    // `!pattern` lets back in what an earlier rule left out.
+    negated: bool,
+    // `pattern/` only matches directories.
+    directory_only: bool,
+    // A pattern with a `/` before its end is matched against the path from
+    // the `.gitignore`; one without against the name alone, at any depth.
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.strip_prefix('/').unwrap_or(line).to_string();
+        (!pattern.is_empty()).then_some(Self {
+            pattern,
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+    // `relative` is the path from the `.gitignore`'s directory, with `/`
+    // between its parts.
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let pattern: Vec<char> = self.pattern.chars().collect();
+        if self.anchored {
+            let path: Vec<char> = relative.chars().collect();
+            return glob(&pattern, &path);
+        }
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        glob(&pattern, &name.chars().collect::<Vec<char>>())
+    }
+}
+
+// Shell-style matching: `?` is any one character and `*` any run of them,
+// neither crossing a `/`; `**` crosses them too, and `**/` may match
+// nothing at all.
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            glob(rest, text)
+                || text.iter().enumerate().any(|(index, &c)| {
+                    c == '/' && glob(rest, text.get(index + 1..).unwrap_or_default())
+                })
+        }
+        ['*', '*', rest @ ..] => {
+            (0..=text.len()).any(|index| glob(rest, text.get(index..).unwrap_or_default()))
+        }
+        ['*', rest @ ..] => {
+            let run = text.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|index| glob(rest, text.get(index..).unwrap_or_default()))
+        }
+        ['?', rest @ ..] => matches!(text, [c, text @ ..] if *c != '/' && glob(rest, text)),
+        [p, rest @ ..] => matches!(text, [c, text @ ..] if c == p && glob(rest, text)),
+    }
+}
+
+// A directory as last read.
+struct Listing {
+    modified: Option<SystemTime>,
+    ignore_modified: Option<SystemTime>,
+    rules: Vec<Rule>,
+    // Its entries, as paths, whether they are directories, and names.
+    entries: Vec<(PathBuf, bool, String)>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+impl Listing {
+    fn read(dir: &Path) -> Self {
+        let ignore = dir.join(".gitignore");
+        let rules = fs::read_to_string(&ignore)
+            .map(|contents| contents.lines().filter_map(Rule::parse).collect())
+            .unwrap_or_default();
+        let mut entries: Vec<(PathBuf, bool, String)> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.to_string();
+                let metadata = fs::metadata(entry.path()).ok()?;
+                // linked directories could lead back up the tree
+                if metadata.is_dir() && entry.file_type().ok()?.is_symlink() {
+                    return None;
+                }
+                Some((entry.path(), metadata.is_dir(), name))
+            })
+            .collect();
+        entries.sort_by(|a, b| a.2.cmp(&b.2));
+        Self {
+            modified: modified(dir),
+            ignore_modified: modified(&ignore),
+            rules,
+            entries,
+        }
+    }
+    fn is_current(&self, dir: &Path) -> bool {
+        self.modified == modified(dir) && self.ignore_modified == modified(&dir.join(".gitignore"))
+    }
+}
+
+#[derive(Default)]
+pub struct Walker {
+    listings: HashMap<PathBuf, Listing>,
+}
+
+impl Walker {
+    // Every file under `root` that is not left out, sorted by path. Only the
+    // directories that changed since the last walk are read again.
+    pub fn files(&mut self, root: &Path, settings: Settings) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        // each directory to walk, with those above it that have a
+        // `.gitignore`
+        let mut pending: Vec<(PathBuf, Vec<PathBuf>)> = vec![(root.to_path_buf(), Vec::new())];
+        let mut seen = HashSet::new();
+        while let Some((dir, mut scopes)) = pending.pop() {
+            let stale = !self
+                .listings
+                .get(&dir)
+                .is_some_and(|listing| listing.is_current(&dir));
+            if stale {
+                self.listings.insert(dir.clone(), Listing::read(&dir));
+            }
+            seen.insert(dir.clone());
+            let Some(listing) = self.listings.get(&dir) else {
+                continue;
+            };
+            if !listing.rules.is_empty() {
+                scopes.push(dir.clone());
+            }
+            for (path, is_dir, name) in &listing.entries {
+                if name == ".git" || (!settings.hidden && name.starts_with('.')) {
+                    continue;
+                }
+                if self.is_ignored(path, *is_dir, &scopes) {
+                    continue;
+                }
+                if *is_dir {
+                    pending.push((path.clone(), scopes.clone()));
+                    continue;
+                }
+                // sizes change without the directory changing, so they are
+                // looked at every time
+                let small = settings.max_file_size == 0
+                    || fs::metadata(path)
+                        .is_ok_and(|metadata| metadata.len() <= settings.max_file_size);
+                if small {
+                    files.push(path.clone());
+                }
+            }
+        }
+        // directories gone since the last walk
+        self.listings
+            .retain(|dir, _| seen.contains(dir) || !dir.starts_with(root));
+        files.sort();
+        files
+    }
+    // The last rule to match a path decides, and deeper `.gitignore`s come
+    // after those above them.
+    fn is_ignored(&self, path: &Path, is_dir: bool, scopes: &[PathBuf]) -> bool {
+        let mut ignored = false;
+        for dir in scopes {
+            let Some(rules) = self.listings.get(dir).map(|listing| &listing.rules) else {
+                continue;
+            };
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative: Vec<String> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let relative = relative.join("/");
+            for rule in rules {
+                if rule.negated == ignored && rule.matches(&relative, is_dir) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
@@ -0,0 +1,100 @@
+use crate::Options;
+use crate::Row;
+
+// How a row is split over screen lines when `wrap` is on. The first line
+// holds `width` graphemes; each continuation line starts with the row's
+// indentation (with `breakindent`) and `showbreak`, and holds what is left.
+pub struct Layout {
+    width: usize,
+    indent: usize,
+    showbreak: usize,
+}
+
+impl Layout {
+    pub fn new(options: &Options, width: usize, row: &Row) -> Self {
+        let width = width.max(1);
+        let indent = if options.breakindent {
+            row.as_str()
+                .chars()
+                .map_while(|c| match c {
+                    ' ' => Some(1),
+                    // tabs are drawn two columns wide
+                    '\t' => Some(2),
+                    _ => None,
+                })
+                .sum()
+        } else {
+            0
+        };
+        // continuation lines keep at least half the width for text
+        let showbreak = options.showbreak.chars().count().min(width / 2);
+        let indent = indent.min((width / 2).saturating_sub(showbreak));
+        Self {
+            width,
+            indent,
+            showbreak,
+        }
+    }
+    // The indentation, then `showbreak`, in front of continuation lines.
+    pub fn indent(&self) -> usize {
+        self.indent
+    }
+    pub fn showbreak(&self) -> usize {
+        self.showbreak
+    }
+    fn prefix(&self) -> usize {
+        self.indent.saturating_add(self.showbreak)
+    }
+    // How many graphemes `line` holds.
+    pub fn line_width(&self, line: usize) -> usize {
+        if line == 0 {
+            self.width
+        } else {
+            self.width.saturating_sub(self.prefix()).max(1)
+        }
+    }
+    // The first grapheme on `line`.
+    pub fn line_start(&self, line: usize) -> usize {
+        match line.checked_sub(1) {
+            None => 0,
+            Some(continuation) => self
+                .width
+                .saturating_add(continuation.saturating_mul(self.line_width(1))),
+        }
+    }
+    // The screen line grapheme `x` is on.
+    pub fn line_of(&self, x: usize) -> usize {
+        match x.checked_sub(self.width) {
+            None => 0,
+            Some(rest) => (rest / self.line_width(1)).saturating_add(1),
+        }
+    }
+    // How many screen lines a row of `len` graphemes takes.
+    pub fn height(&self, len: usize) -> usize {
+        if len <= self.width {
+            1
+        } else {
+            self.line_of(len.saturating_sub(1)).saturating_add(1)
+        }
+    }
+    // The screen column grapheme `x` is drawn in.
+    pub fn column(&self, x: usize) -> usize {
+        let line = self.line_of(x);
+        let within = x.saturating_sub(self.line_start(line));
+        if line == 0 {
+            within
+        } else {
+            within.saturating_add(self.prefix())
+        }
+    }
+    // The grapheme drawn at screen `column` of `line`, or the nearest one.
+    pub fn at(&self, line: usize, column: usize) -> usize {
+        let within = if line == 0 {
+            column
+        } else {
+            column.saturating_sub(self.prefix())
+        };
+        self.line_start(line)
+            .saturating_add(within.min(self.line_width(line).saturating_sub(1)))
+    }
+}